@@ -29,6 +29,15 @@
         nativeBuildInputs = [ pkgs.pkg-config ];
         buildInputs = [ pkgs.openssl ];
 
+        # Most tests live in the runner-controller-core library crate
+        cargoTestFlags = [ "--workspace" ];
+
+        # Reported by GET /version
+        GIT_COMMIT = inputs.self.shortRev or inputs.self.dirtyShortRev or "unknown";
+        BUILD_DATE = with builtins;
+          let d = inputs.self.lastModifiedDate or "19700101";
+          in "${substring 0 4 d}-${substring 4 2 d}-${substring 6 2 d}";
+
         meta = {
           description = "GitHub Actions Runner Controller for NixOS containers";
           license = pkgs.lib.licenses.mit;
@@ -99,6 +99,34 @@ let
       nix.settings = {
         experimental-features = [ "nix-command" "flakes" ];
         trusted-users = [ "github-runner" ];
+        post-build-hook = "/etc/nix/push-to-cache";
+      };
+
+      # Push build results to the team binary cache when the controller
+      # provisioned one (CACHE_PUSH_TARGET). A failed push must not fail the build.
+      environment.etc."nix/push-to-cache" = {
+        mode = "0755";
+        text = '''
+          #!''${pkgs.runtimeShell}
+          export PATH=''${lib.makeBinPath [ pkgs.nix pkgs.coreutils ]}:$PATH
+          [ -s /var/lib/nix-cache/push.env ] || exit 0
+          . /var/lib/nix-cache/push.env
+          if [ -s /var/lib/nix-cache/credentials.env ]; then
+            set -a
+            . /var/lib/nix-cache/credentials.env
+            set +a
+          fi
+          if [ -s /var/lib/nix-cache/signing-key ]; then
+            nix store sign --key-file /var/lib/nix-cache/signing-key $OUT_PATHS \
+              || echo "push-to-cache: signing failed" >&2
+          fi
+          if [ -n "$CACHE_PUSH_COMMAND" ]; then
+            $CACHE_PUSH_COMMAND $OUT_PATHS || echo "push-to-cache: push failed" >&2
+          else
+            nix copy --to "$CACHE_PUSH_TARGET" $OUT_PATHS || echo "push-to-cache: push failed" >&2
+          fi
+          exit 0
+        ''';
       };
 
       # GitHub Actions Runner - using github-runner from nixpkgs
@@ -123,9 +151,14 @@ let
         script = '''
           set -euo pipefail
 
+          # The controller may write a host-unique runner name; default to the container name
           RUNNER_NAME="$(hostname)"
+          if [ -s /var/lib/github-runner-name ]; then
+            RUNNER_NAME=$(cat /var/lib/github-runner-name)
+          fi
           GITHUB_REPO="${githubRepo}"
-          TOKEN_FILE="/var/lib/github-runner-token"
+          # Loaded from /var/lib/github-runner-token (root only) by LoadCredential
+          TOKEN_FILE="$CREDENTIALS_DIRECTORY/registration-token"
           STATE_DIR="/var/lib/github-runner"
           WORK_DIR="/var/lib/github-runner-work"
           LOGS_DIR="/var/log/github-runner"
@@ -156,22 +189,36 @@ let
 
           REG_TOKEN=$(cat "$TOKEN_FILE")
 
+          # Labels are written by the controller; fall back to the defaults
+          LABELS_FILE="/var/lib/github-runner-labels"
+          RUNNER_LABELS="${lib.concatStringsSep "," runnerLabels}"
+          if [ -s "$LABELS_FILE" ]; then
+            RUNNER_LABELS=$(cat "$LABELS_FILE")
+          fi
+
+          # Pool environment (and RUNNER_SECRETS_DIR), inherited by every job step
+          if [ -f /var/lib/github-runner-env ]; then
+            set -a
+            . /var/lib/github-runner-env
+            set +a
+          fi
+
           # Clean state for ephemeral runner
           find "$STATE_DIR/" -mindepth 1 -delete 2>/dev/null || true
           find "$WORK_DIR/" -mindepth 1 -delete 2>/dev/null || true
 
           echo "Configuring runner $RUNNER_NAME..."
           cd "$STATE_DIR"
-          config.sh \
+          # The token goes in through the environment, out of sight of ps
+          ACTIONS_RUNNER_INPUT_TOKEN="$REG_TOKEN" config.sh \
             --unattended \
             --disableupdate \
             --work "$WORK_DIR" \
             --url "https://github.com/$GITHUB_REPO" \
-            --labels "self-hosted,ci,nix,x64,Linux" \
+            --labels "$RUNNER_LABELS" \
             --name "$RUNNER_NAME" \
             --replace \
-            --ephemeral \
-            --token "$REG_TOKEN"
+            --ephemeral
 
           # Move _diag to logs dir
           mkdir -p "$LOGS_DIR"
@@ -193,6 +240,7 @@ let
           LogsDirectory = "github-runner";
           RuntimeDirectory = "github-runner";
           KillSignal = "SIGINT";
+          LoadCredential = [ "registration-token:/var/lib/github-runner-token" ];
         };
       };
     }
@@ -464,24 +512,19 @@ in {
 
     environment = {
       GITHUB_REPO = githubRepo;
-      GITHUB_TOKEN_FILE = "/run/secrets/github-runner/token";
       MAX_CONCURRENT = toString maxConcurrentJobs;
       POLL_INTERVAL = toString pollIntervalSeconds;
       JOB_TIMEOUT = toString jobTimeoutSeconds;
       RUNNER_LABELS = lib.concatStringsSep "," runnerLabels;
+      # Containers are built from the host's nixpkgs, so they ship this version
+      RUNNER_VERSION = pkgs.github-runner.version;
       STATE_DIR = "/var/lib/runner-controller";
       HTTP_PORT = "8080";
+      HTTP_SOCKET = "/run/runner-controller/api.sock";
       RUST_LOG = "info";
       NIX_PATH = builtins.concatStringsSep ":" config.nix.nixPath;
     };
 
-    preStart = ''
-      while [ ! -f /run/secrets/github-runner/token ]; do
-        echo "Waiting for GitHub runner token..."
-        sleep 1
-      done
-    '';
-
     serviceConfig = {
       Type = "simple";
       ExecStart = "${runnerController}/bin/runner-controller";
@@ -489,6 +532,11 @@ in {
       RestartSec = "10s";
       StateDirectory = "runner-controller";
       StateDirectoryMode = "0755";
+      RuntimeDirectory = "runner-controller";
+      RuntimeDirectoryMode = "0700";
+      # Read as $CREDENTIALS_DIRECTORY/github-token; until agenix has
+      # decrypted the token the start fails and is retried
+      LoadCredential = [ "github-token:/run/secrets/github-runner/token" ];
     };
   };
 
@@ -496,7 +544,7 @@ in {
   age.secrets.github-runner-token = {
     file = ../../secrets/github-runner.age;
     path = "/run/secrets/github-runner/token";
-    mode = "0644";
+    mode = "0400";
     owner = "root";
   };
 }
@@ -0,0 +1,52 @@
+//! Embeds the git commit and build date reported by `GET /version`.
+//!
+//! Nix builds have no `.git` and pass both through `GIT_COMMIT` and `BUILD_DATE`.
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=BUILD_DATE");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+
+    let commit = std::env::var("GIT_COMMIT")
+        .ok()
+        .or_else(git_commit)
+        .unwrap_or_else(|| "unknown".to_string());
+    let date = std::env::var("BUILD_DATE").unwrap_or_else(|_| today());
+
+    println!("cargo:rustc-env=RUNNER_CONTROLLER_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=RUNNER_CONTROLLER_BUILD_DATE={}", date);
+}
+
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()?;
+    let commit = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !commit.trim().is_empty()).then(|| commit.trim().to_string())
+}
+
+/// Current UTC date as `YYYY-MM-DD`
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let days = (secs / 86400) as i64;
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
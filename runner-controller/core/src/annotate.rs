@@ -0,0 +1,338 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::github::{CheckRunOutput, WorkflowJob};
+
+/// Keeps a check run on each queued job's commit showing its place in the
+/// self-hosted queue, so developers see progress in the pull request instead
+/// of a silent wait.
+#[derive(Debug, Default)]
+pub struct QueueAnnotator {
+    enabled: bool,
+    /// Check run and last posted position per annotated job
+    runs: HashMap<u64, (u64, usize)>,
+    /// Jobs whose check run was completed while they were still queued
+    done: HashSet<u64>,
+    /// Jobs queued on the previous cycle
+    seen: HashSet<u64>,
+    /// Smoothed seconds between jobs leaving the queue
+    drain_secs: Option<f64>,
+    last_drain: Option<Instant>,
+}
+
+/// A check run change to make on GitHub
+#[derive(Debug, Clone, PartialEq)]
+pub enum Annotation {
+    Create {
+        job_id: u64,
+        head_sha: String,
+        position: usize,
+        output: CheckRunOutput,
+    },
+    Update {
+        check_run_id: u64,
+        output: CheckRunOutput,
+    },
+    Complete {
+        check_run_id: u64,
+        output: CheckRunOutput,
+        conclusion: &'static str,
+    },
+}
+
+/// Weight of the newest observation in the smoothed drain interval
+const DRAIN_SMOOTHING: f64 = 0.3;
+
+impl QueueAnnotator {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Check run changes for this cycle's queue, which must be ordered
+    /// oldest first. Only changed positions are posted again.
+    pub fn plan(&mut self, queued: &[WorkflowJob]) -> Vec<Annotation> {
+        let now = Instant::now();
+        let ids: HashSet<u64> = queued.iter().map(|j| j.id).collect();
+        self.observe_drain(&ids, now);
+
+        let mut changes = Vec::new();
+        self.runs.retain(|id, (check_run_id, _)| {
+            let queued = ids.contains(id);
+            if !queued {
+                changes.push(Annotation::Complete {
+                    check_run_id: *check_run_id,
+                    output: CheckRunOutput {
+                        title: "No longer queued".to_string(),
+                        summary: "The job left the self-hosted queue.".to_string(),
+                    },
+                    conclusion: "neutral",
+                });
+            }
+            queued
+        });
+        self.done.retain(|id| ids.contains(id));
+
+        for (index, job) in queued.iter().enumerate() {
+            let position = index + 1;
+            if self.done.contains(&job.id) {
+                continue;
+            }
+            let output = self.output(position);
+            match self.runs.get_mut(&job.id) {
+                Some((_, posted)) if *posted == position => {}
+                Some((check_run_id, posted)) => {
+                    *posted = position;
+                    changes.push(Annotation::Update {
+                        check_run_id: *check_run_id,
+                        output,
+                    });
+                }
+                None if !job.head_sha.is_empty() => changes.push(Annotation::Create {
+                    job_id: job.id,
+                    head_sha: job.head_sha.clone(),
+                    position,
+                    output,
+                }),
+                None => {}
+            }
+        }
+        changes
+    }
+
+    /// Remember the check run created for a queued job
+    pub fn created(&mut self, job_id: u64, check_run_id: u64, position: usize) {
+        self.runs.insert(job_id, (check_run_id, position));
+    }
+
+    /// Stop annotating a job whose runner container was spawned, returning
+    /// its check run to complete
+    pub fn complete(&mut self, job_id: u64) -> Option<u64> {
+        self.done.insert(job_id);
+        self.runs.remove(&job_id).map(|(check_run_id, _)| check_run_id)
+    }
+
+    /// Update the drain interval from the jobs that left the queue
+    fn observe_drain(&mut self, ids: &HashSet<u64>, now: Instant) {
+        let left = self.seen.difference(ids).count();
+        self.seen = ids.clone();
+
+        let Some(last) = self.last_drain else {
+            self.last_drain = Some(now);
+            return;
+        };
+        if left == 0 {
+            return;
+        }
+
+        let interval = now.duration_since(last).as_secs_f64() / left as f64;
+        self.drain_secs = Some(match self.drain_secs {
+            Some(secs) => secs * (1.0 - DRAIN_SMOOTHING) + interval * DRAIN_SMOOTHING,
+            None => interval,
+        });
+        self.last_drain = Some(now);
+    }
+
+    fn output(&self, position: usize) -> CheckRunOutput {
+        let summary = match self.drain_secs {
+            Some(secs) => {
+                let minutes = (secs * position as f64 / 60.0).ceil().max(1.0);
+                format!("Estimated wait: ~{} min, based on how fast the queue drains.", minutes)
+            }
+            None => "Waiting for a free runner; no estimate yet.".to_string(),
+        };
+        CheckRunOutput {
+            title: format!("Queued on self-hosted infra, position {}", position),
+            summary,
+        }
+    }
+}
+
+/// Where provisioning failures are reported to the job's developers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureAnnotation {
+    #[default]
+    Off,
+    /// A check run on the job's commit; needs a GitHub App token
+    CheckRun,
+    /// A commit status on the job's commit
+    Status,
+}
+
+impl std::str::FromStr for FailureAnnotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => Ok(Self::Off),
+            "check_run" => Ok(Self::CheckRun),
+            "status" => Ok(Self::Status),
+            other => anyhow::bail!("unknown provisioning failure annotation '{}'", other),
+        }
+    }
+}
+
+/// What was posted on a job's commit about its provisioning failures
+#[derive(Debug, Clone, PartialEq)]
+pub enum Posted {
+    CheckRun(u64),
+    Status { head_sha: String },
+}
+
+/// Counts failed attempts to provision a runner per job, and decides when the
+/// job's developers are told the infrastructure is at fault
+#[derive(Debug)]
+pub struct ProvisionFailures {
+    mode: FailureAnnotation,
+    /// Consecutive failed attempts before a job is annotated
+    threshold: u32,
+    jobs: HashMap<u64, FailedJob>,
+}
+
+#[derive(Debug)]
+struct FailedJob {
+    attempts: u32,
+    last_attempt: Instant,
+    posted: Option<Posted>,
+}
+
+impl ProvisionFailures {
+    pub fn new(mode: FailureAnnotation, threshold: u32) -> Self {
+        Self {
+            mode,
+            threshold,
+            jobs: HashMap::new(),
+        }
+    }
+
+    pub fn mode(&self) -> FailureAnnotation {
+        self.mode
+    }
+
+    /// Count a failed attempt for `job_id`, returning the attempts so far once
+    /// the job is due an annotation, and what was already posted for it
+    pub fn failed(&mut self, job_id: u64) -> Option<(u32, Option<Posted>)> {
+        if self.mode == FailureAnnotation::Off {
+            return None;
+        }
+        let job = self.jobs.entry(job_id).or_insert(FailedJob {
+            attempts: 0,
+            last_attempt: Instant::now(),
+            posted: None,
+        });
+        job.attempts += 1;
+        job.last_attempt = Instant::now();
+        (job.attempts >= self.threshold).then(|| (job.attempts, job.posted.clone()))
+    }
+
+    /// Remember the annotation posted for a job
+    pub fn posted(&mut self, job_id: u64, posted: Posted) {
+        if let Some(job) = self.jobs.get_mut(&job_id) {
+            job.posted = Some(posted);
+        }
+    }
+
+    /// A runner was provisioned for `job_id`: forget its failures, returning
+    /// the annotation to resolve and how many attempts had failed
+    pub fn succeeded(&mut self, job_id: u64) -> Option<(Posted, u32)> {
+        let job = self.jobs.remove(&job_id)?;
+        Some((job.posted?, job.attempts))
+    }
+
+    /// Forget jobs not retried for `max_age`, returning their annotations
+    pub fn abandoned(&mut self, max_age: Duration) -> Vec<Posted> {
+        let mut abandoned = Vec::new();
+        self.jobs.retain(|_, job| {
+            let keep = job.last_attempt.elapsed() < max_age;
+            if !keep {
+                abandoned.extend(job.posted.take());
+            }
+            keep
+        });
+        abandoned
+    }
+}
+
+/// Check run output explaining failed provisioning attempts
+pub fn provision_failure_output(attempts: u32, error: &str) -> CheckRunOutput {
+    CheckRunOutput {
+        title: format!("Runner provisioning failed {} times", attempts),
+        summary: format!(
+            "The self-hosted runner controller could not start a runner for this job: {}\n\n\
+             This is an infrastructure problem, not a problem with your change. The \
+             controller keeps retrying while the job is queued; its operators have the \
+             details.",
+            error
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_tracks_positions() {
+        let job = |id| WorkflowJob {
+            id,
+            head_sha: "abc123".to_string(),
+            ..Default::default()
+        };
+        let mut annotator = QueueAnnotator::new(true);
+
+        let changes = annotator.plan(&[job(1), job(2)]);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(changes[1], Annotation::Create { job_id: 2, position: 2, .. }));
+        annotator.created(1, 100, 1);
+        annotator.created(2, 200, 2);
+
+        // Nothing moved: nothing to post
+        assert!(annotator.plan(&[job(1), job(2)]).is_empty());
+
+        // Job 1 picked up: its check run completes, job 2 moves up
+        let changes = annotator.plan(&[job(2)]);
+        assert!(matches!(changes[0], Annotation::Complete { check_run_id: 100, .. }));
+        match &changes[1] {
+            Annotation::Update { check_run_id, output } => {
+                assert_eq!(*check_run_id, 200);
+                assert!(output.title.ends_with("position 1"));
+                assert!(output.summary.contains("~1 min"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Completed on spawn: not recreated while GitHub still lists it as queued
+        assert_eq!(annotator.complete(2), Some(200));
+        assert!(annotator.plan(&[job(2)]).is_empty());
+    }
+
+    #[test]
+    fn test_provision_failures() {
+        let mut off = ProvisionFailures::new(FailureAnnotation::Off, 1);
+        assert_eq!(off.failed(1), None);
+
+        let mut failures = ProvisionFailures::new(FailureAnnotation::CheckRun, 2);
+        assert_eq!(failures.failed(1), None);
+        assert_eq!(failures.failed(1), Some((2, None)));
+        failures.posted(1, Posted::CheckRun(100));
+        assert_eq!(failures.failed(1), Some((3, Some(Posted::CheckRun(100)))));
+        assert_eq!(failures.succeeded(1), Some((Posted::CheckRun(100), 3)));
+        assert_eq!(failures.succeeded(1), None);
+
+        failures.failed(2);
+        failures.failed(2);
+        failures.posted(2, Posted::CheckRun(200));
+        assert!(failures.abandoned(Duration::from_secs(60)).is_empty());
+        assert_eq!(failures.abandoned(Duration::ZERO), [Posted::CheckRun(200)]);
+    }
+}
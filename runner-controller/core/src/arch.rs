@@ -0,0 +1,248 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::state::ContainerState;
+
+/// Pool slots of architecture pools start at multiples of this, after the
+/// native pool's slots
+pub const ARCH_SLOT_BASE: usize = 1000;
+
+/// CPU architecture a job can ask for with a `runs-on` label
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    X64,
+    Arm64,
+}
+
+impl Arch {
+    /// Architecture of this host
+    pub fn native() -> Self {
+        match std::env::consts::ARCH {
+            "aarch64" => Self::Arm64,
+            _ => Self::X64,
+        }
+    }
+
+    /// Label GitHub gives runners of this architecture
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::X64 => "X64",
+            Self::Arm64 => "ARM64",
+        }
+    }
+
+    /// Architecture a runner label stands for, in GitHub's spelling or the
+    /// usual aliases
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.to_ascii_lowercase().as_str() {
+            "x64" | "x86_64" | "amd64" => Some(Self::X64),
+            "arm64" | "aarch64" => Some(Self::Arm64),
+            _ => None,
+        }
+    }
+
+    /// Architecture requested by a job's labels, if any
+    pub fn of(labels: &[String]) -> Option<Self> {
+        labels.iter().find_map(|l| Self::from_label(l))
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::X64 => "x64",
+            Self::Arm64 => "arm64",
+        })
+    }
+}
+
+impl FromStr for Arch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Self::from_label(s.trim())
+            .ok_or_else(|| anyhow::anyhow!("unknown architecture '{}', expected x64 or arm64", s))
+    }
+}
+
+/// Runners for a foreign architecture, run under emulation from their own
+/// template (`ARCH_TEMPLATES`)
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchPool {
+    pub arch: Arch,
+    /// Container configuration or image built for `arch`
+    pub template: String,
+    /// Warm runners kept for the architecture
+    pub size: usize,
+    /// Containers of the architecture at once, pool and external; `None`
+    /// leaves only `MAX_EXTERNAL_RUNNERS`
+    pub max_runners: Option<usize>,
+}
+
+impl ArchPool {
+    /// `labels` with their architecture label replaced by this pool's
+    pub fn labels(&self, labels: &[String]) -> Vec<String> {
+        labels
+            .iter()
+            .filter(|l| Arch::from_label(l).is_none())
+            .cloned()
+            .chain([self.arch.label().to_string()])
+            .collect()
+    }
+}
+
+/// A job for an architecture this controller has no pool for
+#[derive(Debug, Clone, Error)]
+#[error("no runners for architecture {arch} on this host")]
+pub struct UnsupportedArch {
+    pub arch: Arch,
+}
+
+/// Runners of one architecture against its limit
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ArchCapacity {
+    pub arch: Arch,
+    /// Whether the host runs the architecture natively
+    pub native: bool,
+    /// Warm runners kept
+    pub pool_size: usize,
+    /// Containers running for the architecture
+    pub running: usize,
+    /// Limit on `running`; `None` is only bounded by the overall limits
+    pub max_runners: Option<usize>,
+}
+
+/// Routes jobs to the native pool or an architecture pool by their labels
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ArchRoutes {
+    pools: Vec<ArchPool>,
+}
+
+impl ArchRoutes {
+    /// Pools for foreign architectures; one for the native architecture is
+    /// refused, as that is what the regular pool is for
+    pub fn new(pools: Vec<ArchPool>) -> anyhow::Result<Self> {
+        let native = Arch::native();
+        if let Some(pool) = pools.iter().find(|p| p.arch == native) {
+            anyhow::bail!("{} is this host's native architecture", pool.arch);
+        }
+        Ok(Self { pools })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    pub fn pools(&self) -> &[ArchPool] {
+        &self.pools
+    }
+
+    /// Pool for a job with `labels`: `None` for the native architecture
+    pub fn route(&self, labels: &[String]) -> Result<Option<&ArchPool>, UnsupportedArch> {
+        match Arch::of(labels) {
+            Some(arch) if arch != Arch::native() => self
+                .pools
+                .iter()
+                .find(|p| p.arch == arch)
+                .map(Some)
+                .ok_or(UnsupportedArch { arch }),
+            _ => Ok(None),
+        }
+    }
+
+    /// Warm pool slots of the architecture pools
+    pub fn slots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pools
+            .iter()
+            .enumerate()
+            .flat_map(|(i, pool)| (0..pool.size).map(move |n| ARCH_SLOT_BASE * (i + 1) + n))
+    }
+
+    /// Architecture pool a pool slot belongs to; `None` for native slots
+    pub fn pool_of_slot(&self, slot: usize) -> Option<&ArchPool> {
+        let index = (slot / ARCH_SLOT_BASE).checked_sub(1)?;
+        self.pools.get(index)
+    }
+
+    /// Containers per architecture against the limits, native first
+    pub fn capacity<'a>(
+        &self,
+        native_pool_size: usize,
+        containers: impl IntoIterator<Item = &'a ContainerState>,
+    ) -> Vec<ArchCapacity> {
+        let native = Arch::native();
+        let mut capacity: Vec<ArchCapacity> = [ArchCapacity {
+            arch: native,
+            native: true,
+            pool_size: native_pool_size,
+            running: 0,
+            max_runners: None,
+        }]
+        .into_iter()
+        .chain(self.pools.iter().map(|pool| ArchCapacity {
+            arch: pool.arch,
+            native: false,
+            pool_size: pool.size,
+            running: 0,
+            max_runners: pool.max_runners,
+        }))
+        .collect();
+        for state in containers {
+            let arch = Arch::of(&state.labels).unwrap_or(native);
+            if let Some(entry) = capacity.iter_mut().find(|c| c.arch == arch) {
+                entry.running += 1;
+            }
+        }
+        capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arch_routes() {
+        let foreign = match Arch::native() {
+            Arch::X64 => Arch::Arm64,
+            Arch::Arm64 => Arch::X64,
+        };
+        let pool = ArchPool {
+            arch: foreign,
+            template: "/etc/nixos/ci-foreign.nix".to_string(),
+            size: 2,
+            max_runners: Some(3),
+        };
+        assert!(ArchRoutes::new(vec![ArchPool {
+            arch: Arch::native(),
+            ..pool.clone()
+        }])
+        .is_err());
+        let routes = ArchRoutes::new(vec![pool]).unwrap();
+        let labels = |arch: Arch| vec!["self-hosted".to_string(), arch.label().to_lowercase()];
+
+        assert!(routes.route(&["self-hosted".to_string()]).unwrap().is_none());
+        assert!(routes.route(&labels(Arch::native())).unwrap().is_none());
+        let routed = routes.route(&labels(foreign)).unwrap().unwrap();
+        assert_eq!(routed.labels(&labels(Arch::native()))[1], foreign.label());
+        assert!(ArchRoutes::default().route(&labels(foreign)).is_err());
+
+        let slots: Vec<usize> = routes.slots().collect();
+        assert_eq!(slots, [ARCH_SLOT_BASE, ARCH_SLOT_BASE + 1]);
+        assert!(routes.pool_of_slot(3).is_none());
+        assert_eq!(routes.pool_of_slot(ARCH_SLOT_BASE + 1).unwrap().arch, foreign);
+
+        let mut state = ContainerState::new(ARCH_SLOT_BASE);
+        state.labels = labels(foreign);
+        let capacity = routes.capacity(4, [&state, &ContainerState::new(0)]);
+        assert_eq!((capacity[0].running, capacity[0].pool_size), (1, 4));
+        assert_eq!((capacity[1].running, capacity[1].max_runners), (1, Some(3)));
+    }
+}
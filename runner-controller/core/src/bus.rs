@@ -0,0 +1,339 @@
+use std::collections::HashSet;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, StreamExt};
+use tokio::sync::{mpsc, watch, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::debug;
+
+use crate::container::Backend;
+
+/// Events queued for the scheduler before producers have to wait
+const BUS_CAPACITY: usize = 64;
+
+/// Containers the watcher checks at once
+const WATCH_CONCURRENCY: usize = 8;
+
+/// How long a requested tick waits for further requests to share it
+const TICK_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Stands in for a deadline past what an instant can hold (`Duration::MAX`
+/// while idle): about 30 years, as tokio's own sleeps do
+const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+
+/// Something the pool controller reacts to. Events are hints: the
+/// controller looks at the actual state before acting on one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A dispatch pass is due: refill the pool and look at the queue
+    Poll,
+    /// A sweep over external containers and GitHub runners is due
+    Reconcile,
+    /// The runner of a container finished
+    ContainerExited { name: String },
+}
+
+impl Event {
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::Poll => "poll",
+            Event::Reconcile => "reconcile",
+            Event::ContainerExited { .. } => "container_exited",
+        }
+    }
+}
+
+/// Sends one event at a period the scheduler can change, e.g. when it goes
+/// idle. A tick the scheduler has not picked up yet isn't sent again, so
+/// ticks never pile up behind a slow pass. A tick can be brought forward to
+/// `TICK_DEBOUNCE` from the first request, which the requests until then share.
+struct Ticker {
+    period: watch::Sender<Duration>,
+    pending: Arc<AtomicBool>,
+    soon: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl Ticker {
+    fn start(event: Event, period: Duration, jitter: f64, tx: mpsc::Sender<Event>) -> Self {
+        let (period, mut period_rx) = watch::channel(period);
+        let pending = Arc::new(AtomicBool::new(false));
+        let soon = Arc::new(Notify::new());
+        let sent = Arc::clone(&pending);
+        let requested = Arc::clone(&soon);
+        let task = tokio::spawn(async move {
+            loop {
+                let period = jittered(*period_rx.borrow_and_update(), jitter);
+                let sleep = tokio::time::sleep_until(deadline(period));
+                tokio::pin!(sleep);
+                loop {
+                    tokio::select! {
+                        _ = &mut sleep => break,
+                        changed = period_rx.changed() => {
+                            if changed.is_err() {
+                                return;
+                            }
+                            let period = jittered(*period_rx.borrow_and_update(), jitter);
+                            sleep.as_mut().reset(deadline(period));
+                        }
+                        _ = requested.notified() => {
+                            let soon = tokio::time::Instant::now() + TICK_DEBOUNCE;
+                            if soon < sleep.deadline() {
+                                sleep.as_mut().reset(soon);
+                            }
+                        }
+                    }
+                }
+                let queued = sent.swap(true, Ordering::AcqRel);
+                if !queued && tx.send(event.clone()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            period,
+            pending,
+            soon,
+            task,
+        }
+    }
+}
+
+/// The producers feeding the scheduler: a poll and a reconcile timer and a
+/// watcher reporting finished runners. Webhooks and API requests arrive on
+/// the controller's control channel instead. The producers stop when this
+/// is dropped.
+pub struct Bus {
+    rx: mpsc::Receiver<Event>,
+    poll: Ticker,
+    reconcile: Option<Ticker>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+impl Bus {
+    /// Start the producers: polls every `poll_period`, reconciles every
+    /// `reconcile_period` (none without one), both shifted by up to `jitter`
+    /// of the period, and a look at the runners of `containers` every
+    /// `watch_interval`
+    pub fn start(
+        containers: Arc<dyn Backend>,
+        poll_period: Duration,
+        reconcile_period: Option<Duration>,
+        jitter: f64,
+        watch_interval: Option<Duration>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(BUS_CAPACITY);
+        Self {
+            poll: Ticker::start(Event::Poll, poll_period, jitter, tx.clone()),
+            reconcile: reconcile_period
+                .map(|period| Ticker::start(Event::Reconcile, period, jitter, tx.clone())),
+            watcher: watch_interval.map(|interval| spawn_watcher(containers, interval, tx)),
+            rx,
+        }
+    }
+
+    /// Next event; its producer may send the next tick from now on
+    pub async fn recv(&mut self) -> Option<Event> {
+        let event = self.rx.recv().await?;
+        match &event {
+            Event::Poll => self.poll.pending.store(false, Ordering::Release),
+            Event::Reconcile => {
+                if let Some(reconcile) = &self.reconcile {
+                    reconcile.pending.store(false, Ordering::Release);
+                }
+            }
+            Event::ContainerExited { .. } => {}
+        }
+        metrics::counter!("runner_controller_events_total", "event" => event.kind()).increment(1);
+        Some(event)
+    }
+
+    /// Restart the poll timer with `period`
+    pub fn set_poll_period(&self, period: Duration) {
+        self.poll.period.send_replace(period);
+    }
+
+    /// Poll in `TICK_DEBOUNCE` unless one is due sooner, so a burst of
+    /// requests is handled by a single poll
+    pub fn poll_soon(&self) {
+        self.poll.soon.notify_one();
+    }
+
+    /// Restart the reconcile timer with `period`
+    pub fn set_reconcile_period(&self, period: Duration) {
+        if let Some(reconcile) = &self.reconcile {
+            reconcile.period.send_replace(period);
+        }
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        self.poll.task.abort();
+        if let Some(reconcile) = &self.reconcile {
+            reconcile.task.abort();
+        }
+        if let Some(watcher) = &self.watcher {
+            watcher.abort();
+        }
+    }
+}
+
+/// Report pool and external containers whose runner finished, once each
+/// until it runs again. Failed checks are left to the poll.
+fn spawn_watcher(
+    containers: Arc<dyn Backend>,
+    interval: Duration,
+    tx: mpsc::Sender<Event>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut exited: HashSet<String> = HashSet::new();
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let names = match (containers.list().await, containers.list_external().await) {
+                (Ok(pool), Ok(external)) => pool.into_iter().chain(external),
+                (Err(e), _) | (_, Err(e)) => {
+                    debug!(error = %e, "Container watcher failed to list containers");
+                    continue;
+                }
+            };
+            let finished: Vec<String> = stream::iter(names)
+                .map(|name| {
+                    let containers = Arc::clone(&containers);
+                    async move {
+                        let completed = containers.is_runner_completed(&name).await;
+                        matches!(completed, Ok(true)).then_some(name)
+                    }
+                })
+                .buffer_unordered(WATCH_CONCURRENCY)
+                .filter_map(|name| async move { name })
+                .collect()
+                .await;
+
+            for name in &finished {
+                if exited.contains(name) {
+                    continue;
+                }
+                let event = Event::ContainerExited { name: name.clone() };
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+            exited = finished.into_iter().collect();
+        }
+    })
+}
+
+/// When a sleep of `period` from now ends, far in the future if it would
+/// overflow
+fn deadline(period: Duration) -> tokio::time::Instant {
+    let now = tokio::time::Instant::now();
+    now.checked_add(period).unwrap_or(now + FAR_FUTURE)
+}
+
+/// Uniformly random duration in `[0, max)`
+pub(crate) fn random_jitter(max: Duration) -> Duration {
+    let random = RandomState::new().hash_one(Instant::now());
+    Duration::from_millis(random % max.as_millis().max(1) as u64)
+}
+
+/// `interval` shifted by a random amount of up to `fraction` of it either
+/// way; the mean stays `interval`
+fn jittered(interval: Duration, fraction: f64) -> Duration {
+    if fraction <= 0.0 || interval == Duration::MAX {
+        return interval;
+    }
+    let spread = interval.mul_f64(fraction);
+    interval - spread + random_jitter(spread * 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{FakeBackend, RunnerNaming};
+
+    #[test]
+    fn test_jittered_poll_interval() {
+        let interval = Duration::from_secs(10);
+        assert_eq!(jittered(interval, 0.0), interval);
+        assert_eq!(jittered(Duration::MAX, 0.5), Duration::MAX);
+        for _ in 0..100 {
+            let sleep = jittered(interval, 0.2);
+            assert!(sleep >= Duration::from_secs(8) && sleep < Duration::from_secs(12));
+        }
+    }
+
+    #[test]
+    fn test_bus_producers() {
+        let backend = Arc::new(FakeBackend::new(RunnerNaming::default()));
+        backend.insert("r0000");
+        backend.insert("x0000001");
+        backend.complete("x0000001");
+
+        tokio_test::block_on(async {
+            let tick = Duration::from_millis(10);
+            let mut bus = Bus::start(backend.clone(), tick, None, 0.0, Some(tick));
+            let mut events = Vec::new();
+            while events.len() < 4 {
+                events.push(bus.recv().await.unwrap());
+            }
+            let exited = Event::ContainerExited {
+                name: "x0000001".to_string(),
+            };
+            // Reported once, and ticks don't pile up while nobody listens
+            assert_eq!(events.iter().filter(|e| **e == exited).count(), 1);
+            tokio::time::sleep(tick * 5).await;
+            let mut polls = 0;
+            while let Ok(event) = bus.rx.try_recv() {
+                polls += usize::from(event == Event::Poll);
+            }
+            assert!(polls <= 1, "{} polls queued", polls);
+        });
+    }
+
+    #[test]
+    fn test_poll_soon_is_debounced() {
+        let backend = Arc::new(FakeBackend::new(RunnerNaming::default()));
+        tokio_test::block_on(async {
+            tokio::time::pause();
+            let hour = Duration::from_secs(3600);
+            let mut bus = Bus::start(backend, hour, None, 0.0, None);
+            let start = tokio::time::Instant::now();
+            for _ in 0..3 {
+                bus.poll_soon();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+
+            // One poll for the burst, a second after the first request
+            assert_eq!(bus.recv().await, Some(Event::Poll));
+            assert_eq!(start.elapsed().as_secs(), 1);
+            let next = tokio::time::timeout(hour / 2, bus.recv()).await;
+            assert!(next.is_err(), "{:?}", next);
+        });
+    }
+
+    #[test]
+    fn test_idle_poll_period() {
+        let backend = Arc::new(FakeBackend::new(RunnerNaming::default()));
+        tokio_test::block_on(async {
+            tokio::time::pause();
+            // Idle with webhooks: no polls until the period is set again
+            let mut bus = Bus::start(backend, Duration::from_secs(10), None, 0.5, None);
+            // The ticker is already waiting when the period changes
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            bus.set_poll_period(Duration::MAX);
+            let idle = tokio::time::timeout(Duration::from_secs(3600), bus.recv()).await;
+            assert!(idle.is_err(), "{:?}", idle);
+
+            bus.set_poll_period(Duration::from_secs(10));
+            let next = tokio::time::timeout(Duration::from_secs(20), bus.recv()).await;
+            assert_eq!(next.unwrap(), Some(Event::Poll));
+        });
+    }
+}
@@ -0,0 +1,248 @@
+use std::path::Path;
+
+use tracing::debug;
+
+/// Host capabilities detected at startup, used to derive extra runner labels
+#[derive(Debug, Clone, Default)]
+pub struct HostCapabilities {
+    pub kvm: bool,
+    pub cpu_features: Vec<String>,
+    pub gpu_vendors: Vec<String>,
+    /// Label-friendly GPU models, e.g. `geforce-rtx-4090`, or `amd-744c` by PCI device ID
+    pub gpu_models: Vec<String>,
+    pub ram_gb: Option<u64>,
+}
+
+/// CPU flags from /proc/cpuinfo that are worth advertising as labels
+const INTERESTING_CPU_FLAGS: &[(&str, &str)] = &[
+    ("avx2", "avx2"),
+    ("avx512f", "avx512"),
+    ("sha_ni", "sha-ni"),
+    ("aes", "aes-ni"),
+];
+
+impl HostCapabilities {
+    /// Probe the local host
+    pub fn detect() -> Self {
+        let kvm = Path::new("/dev/kvm").exists();
+
+        let cpu_features = std::fs::read_to_string("/proc/cpuinfo")
+            .map(|s| parse_cpu_features(&s))
+            .unwrap_or_default();
+
+        let ram_gb = std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|s| parse_mem_total_gb(&s));
+
+        let (gpu_vendors, gpu_models) = detect_gpus();
+
+        let caps = Self {
+            kvm,
+            cpu_features,
+            gpu_vendors,
+            gpu_models,
+            ram_gb,
+        };
+        debug!(capabilities = ?caps, "Detected host capabilities");
+        caps
+    }
+
+    /// Labels describing these capabilities (e.g. `kvm`, `avx512`, `gpu-nvidia`,
+    /// `gpu-geforce-rtx-4090`, `ram-64g`)
+    pub fn labels(&self) -> Vec<String> {
+        let mut labels = Vec::new();
+
+        if self.kvm {
+            labels.push("kvm".to_string());
+        }
+
+        labels.extend(self.cpu_features.iter().cloned());
+
+        if !self.gpu_vendors.is_empty() {
+            labels.push("gpu".to_string());
+            labels.extend(self.gpu_vendors.iter().map(|v| format!("gpu-{}", v)));
+            labels.extend(self.gpu_models.iter().map(|m| format!("gpu-{}", m)));
+        }
+
+        if let Some(gb) = self.ram_gb {
+            labels.push(format!("ram-{}g", gb));
+        }
+
+        labels
+    }
+}
+
+/// Map the first `flags` line of /proc/cpuinfo to label names
+fn parse_cpu_features(cpuinfo: &str) -> Vec<String> {
+    let Some(flags) = cpuinfo
+        .lines()
+        .find(|l| l.starts_with("flags"))
+        .and_then(|l| l.split_once(':'))
+        .map(|(_, flags)| flags.split_whitespace().collect::<Vec<_>>())
+    else {
+        return Vec::new();
+    };
+
+    INTERESTING_CPU_FLAGS
+        .iter()
+        .filter(|(flag, _)| flags.contains(flag))
+        .map(|(_, label)| label.to_string())
+        .collect()
+}
+
+/// Parse `MemTotal` from /proc/meminfo, rounded to the nearest GiB
+fn parse_mem_total_gb(meminfo: &str) -> Option<u64> {
+    let kb: u64 = meminfo
+        .lines()
+        .find(|l| l.starts_with("MemTotal:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()?;
+
+    Some((kb + 512 * 1024) / (1024 * 1024))
+}
+
+/// `Model:` of a /proc/driver/nvidia/gpus/*/information file as a label,
+/// e.g. `NVIDIA GeForce RTX 4090` becomes `geforce-rtx-4090`
+fn parse_nvidia_model(information: &str) -> Option<String> {
+    let model = information
+        .lines()
+        .find_map(|l| l.strip_prefix("Model:"))?
+        .trim();
+    let model = model.strip_prefix("NVIDIA ").unwrap_or(model).to_lowercase();
+
+    let label = model
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    (!label.is_empty()).then_some(label)
+}
+
+/// GPU vendors from the PCI vendor IDs of DRM devices, and GPU models: named
+/// by the NVIDIA driver where it is loaded, otherwise `<vendor>-<device ID>`
+fn detect_gpus() -> (Vec<String>, Vec<String>) {
+    let mut models: Vec<String> = std::fs::read_dir("/proc/driver/nvidia/gpus")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| std::fs::read_to_string(e.path().join("information")).ok())
+        .filter_map(|information| parse_nvidia_model(&information))
+        .collect();
+    let nvidia_models = !models.is_empty();
+
+    let mut vendors = Vec::new();
+    for entry in std::fs::read_dir("/sys/class/drm").into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Only card nodes (card0), not connectors (card0-HDMI-A-1)
+        if !name.starts_with("card") || name.contains('-') {
+            continue;
+        }
+
+        let vendor =
+            std::fs::read_to_string(entry.path().join("device/vendor")).unwrap_or_default();
+        let vendor = match vendor.trim() {
+            "0x10de" => "nvidia",
+            "0x1002" => "amd",
+            "0x8086" => "intel",
+            _ => continue,
+        };
+
+        if !vendors.iter().any(|v| v == vendor) {
+            vendors.push(vendor.to_string());
+        }
+
+        if vendor != "nvidia" || !nvidia_models {
+            let device =
+                std::fs::read_to_string(entry.path().join("device/device")).unwrap_or_default();
+            if let Some(id) = device.trim().strip_prefix("0x") {
+                models.push(format!("{}-{}", vendor, id));
+            }
+        }
+    }
+
+    models.sort();
+    models.dedup();
+    (vendors, models)
+}
+
+/// Device nodes of each GPU on this host: `/dev/nvidiaN` when the NVIDIA
+/// driver is loaded, otherwise the DRM card and render nodes of each card
+pub fn detect_gpu_devices() -> Vec<Vec<String>> {
+    let numbered = |name: &str, prefix: &str| {
+        name.strip_prefix(prefix)
+            .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|n| n.parse::<u32>().ok())
+    };
+
+    let mut nvidia: Vec<u32> = std::fs::read_dir("/dev")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| numbered(&e.file_name().to_string_lossy(), "nvidia"))
+        .collect();
+    if !nvidia.is_empty() {
+        nvidia.sort_unstable();
+        return nvidia
+            .into_iter()
+            .map(|n| vec![format!("/dev/nvidia{}", n)])
+            .collect();
+    }
+
+    let mut cards: Vec<u32> = std::fs::read_dir("/sys/class/drm")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| numbered(&e.file_name().to_string_lossy(), "card"))
+        .collect();
+    cards.sort_unstable();
+    cards
+        .into_iter()
+        .map(|card| {
+            // The card's render node is listed next to it under the PCI device
+            let dir = format!("/sys/class/drm/card{}/device/drm", card);
+            let mut nodes: Vec<String> = std::fs::read_dir(dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|e| format!("/dev/dri/{}", e.file_name().to_string_lossy()))
+                .collect();
+            if nodes.is_empty() {
+                nodes.push(format!("/dev/dri/card{}", card));
+            }
+            nodes.sort();
+            nodes
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_host_info() {
+        let cpuinfo = "processor\t: 0\nflags\t\t: fpu sse avx2 avx512f aes\n";
+        assert_eq!(
+            parse_cpu_features(cpuinfo),
+            vec!["avx2", "avx512", "aes-ni"]
+        );
+
+        let meminfo = "MemTotal:       65536000 kB\nMemFree:         1000 kB\n";
+        assert_eq!(parse_mem_total_gb(meminfo), Some(63));
+
+        let information = "Model: \t\t NVIDIA GeForce RTX 4090\nIRQ:   \t\t 187\n";
+        assert_eq!(parse_nvidia_model(information).as_deref(), Some("geforce-rtx-4090"));
+        let information = "Model: \t\t NVIDIA A100-SXM4-40GB\n";
+        assert_eq!(parse_nvidia_model(information).as_deref(), Some("a100-sxm4-40gb"));
+        assert_eq!(parse_nvidia_model("IRQ: 187\n"), None);
+
+        let caps = HostCapabilities {
+            gpu_vendors: vec!["nvidia".to_string()],
+            gpu_models: vec!["a100-sxm4-40gb".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(caps.labels(), ["gpu", "gpu-nvidia", "gpu-a100-sxm4-40gb"]);
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::arch::Arch;
+use crate::config::{Config, ContainerBackend};
+use crate::container::{check_nspawn_image, DEFAULT_CONTAINER_TEMPLATE, NIXOS_CONTAINER_BIN};
+use crate::github::{GitHubClient, GitHubError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Ok,
+    Info,
+    Warn,
+    Fail,
+}
+
+impl Level {
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Ok => "[ok]   ",
+            Self::Info => "[info] ",
+            Self::Warn => "[warn] ",
+            Self::Fail => "[fail] ",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Finding {
+    level: Level,
+    message: String,
+}
+
+impl Finding {
+    fn new(level: Level, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+        }
+    }
+}
+
+/// Validate a loaded configuration against this host and GitHub and print a
+/// report. Returns true if nothing failed; warnings don't fail the check.
+pub async fn run(config: &Config, github: &GitHubClient) -> bool {
+    println!("runner-controller configuration check");
+    println!("  repo: {}", config.github_repo);
+    println!("  backend: {}", config.container_backend.name());
+    println!("  hash: {}", config.hash());
+    println!();
+
+    let mut findings = local_checks(config);
+    findings.extend(github_checks(config, github).await);
+    for finding in &findings {
+        println!("{}{}", finding.level.prefix(), finding.message);
+    }
+
+    let count = |level| findings.iter().filter(|f| f.level == level).count();
+    let (fails, warnings) = (count(Level::Fail), count(Level::Warn));
+    println!();
+    println!("{} problem(s), {} warning(s)", fails, warnings);
+    fails == 0
+}
+
+/// Checks that need nothing but the host
+fn local_checks(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    match (&config.github_token_file, &config.vault) {
+        (Some(file), _) if config.github_token.is_empty() => findings.push(Finding::new(
+            Level::Fail,
+            format!("token file {} is empty", file.display()),
+        )),
+        (Some(file), _) => {
+            findings.push(Finding::new(Level::Ok, format!("token read from {}", file.display())))
+        }
+        // Fetched before the checks ran, or they wouldn't run
+        (None, Some(vault)) => findings.push(Finding::new(
+            Level::Ok,
+            format!("token fetched from Vault at {} ({})", vault.addr, vault.secret_path),
+        )),
+        (None, None) => {}
+    }
+
+    let parts: Vec<&str> = config.github_repo.split('/').collect();
+    if parts.len() != 2 || parts.iter().any(|p| p.trim().is_empty()) {
+        findings.push(Finding::new(
+            Level::Fail,
+            format!("GITHUB_REPO '{}' is not owner/repo", config.github_repo),
+        ));
+    }
+
+    let state_dir = config.state_dir.display();
+    findings.push(match check_writable(&config.state_dir) {
+        Ok(()) => Finding::new(Level::Ok, format!("STATE_DIR {} is writable", state_dir)),
+        Err(e) => Finding::new(
+            Level::Fail,
+            format!("STATE_DIR {} is not writable (it holds the state database): {}", state_dir, e),
+        ),
+    });
+
+    findings.extend(backend_checks(config));
+    findings.extend(label_checks(&config.runner_labels));
+    findings
+}
+
+/// Create `dir` if needed and write and remove a file in it
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(format!(".check-config-{}", std::process::id()));
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
+}
+
+/// Whether `program` is an executable file in a directory of `PATH`
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+fn backend_checks(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    match &config.container_backend {
+        ContainerBackend::Nixos => {
+            findings.push(if Path::new(NIXOS_CONTAINER_BIN).is_file() {
+                Finding::new(Level::Ok, format!("nixos-container found at {}", NIXOS_CONTAINER_BIN))
+            } else {
+                Finding::new(
+                    Level::Fail,
+                    format!(
+                        "nixos-container not found at {}; the nixos backend needs a NixOS host \
+                         (or set CONTAINER_BACKEND=nspawn)",
+                        NIXOS_CONTAINER_BIN
+                    ),
+                )
+            });
+
+            // Flake references are only resolved when a container is built
+            let templates = [(DEFAULT_CONTAINER_TEMPLATE, "the default".to_string())]
+                .into_iter()
+                .chain(config.label_templates.iter().map(|(label, template)| {
+                    (template.as_str(), format!("LABEL_TEMPLATES (label {})", label))
+                }))
+                .chain(config.arch_routes.pools().iter().map(|pool| {
+                    (pool.template.as_str(), format!("ARCH_TEMPLATES ({})", pool.arch))
+                }));
+            for (template, source) in templates.filter(|(t, _)| !t.contains('#')) {
+                findings.push(if Path::new(template).exists() {
+                    Finding::new(Level::Ok, format!("container template {}", template))
+                } else {
+                    Finding::new(
+                        Level::Fail,
+                        format!("container template {} from {} does not exist", template, source),
+                    )
+                });
+            }
+        }
+        ContainerBackend::Nspawn { image } => {
+            for tool in ["machinectl", "systemd-run"] {
+                findings.push(if on_path(tool) {
+                    Finding::new(Level::Ok, format!("{} found", tool))
+                } else {
+                    Finding::new(
+                        Level::Fail,
+                        format!("{} not found on PATH; the nspawn backend needs systemd", tool),
+                    )
+                });
+            }
+            // Anything but a path names an image machinectl already has
+            if image.starts_with('/') && !Path::new(image).exists() {
+                findings.push(Finding::new(
+                    Level::Fail,
+                    format!("NSPAWN_IMAGE {} does not exist", image),
+                ));
+            }
+            let machines = config.container_backend.containers_dir();
+            let templates = config.label_templates.iter().map(|(_, template)| template);
+            for image in std::iter::once(image).chain(templates) {
+                if let Err(e) = check_nspawn_image(image, machines) {
+                    findings.push(Finding::new(Level::Fail, e.to_string()));
+                }
+            }
+        }
+    }
+
+    if let Some(remote) = &config.remote {
+        if !on_path("ssh") {
+            findings.push(Finding::new(
+                Level::Fail,
+                "ssh not found on PATH; REMOTE_HOSTS are driven over ssh",
+            ));
+        }
+        if let Some(key) = &remote.ssh_key {
+            if let Err(e) = std::fs::File::open(key) {
+                findings.push(Finding::new(
+                    Level::Fail,
+                    format!("REMOTE_SSH_KEY {} is not readable: {}", key.display(), e),
+                ));
+            }
+        }
+        findings.push(Finding::new(
+            Level::Info,
+            format!(
+                "{} remote host(s); reachability is checked by the running controller",
+                remote.hosts.len()
+            ),
+        ));
+    }
+    findings
+}
+
+fn label_checks(labels: &[String]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if labels.is_empty() {
+        findings.push(Finding::new(
+            Level::Fail,
+            "RUNNER_LABELS is empty; no job could target the runners",
+        ));
+    }
+    for label in labels.iter().filter(|l| l.chars().any(char::is_whitespace)) {
+        findings.push(Finding::new(
+            Level::Fail,
+            format!("label '{}' contains whitespace, which GitHub rejects", label),
+        ));
+    }
+
+    // GitHub compares labels case-insensitively
+    let mut seen = HashSet::new();
+    for label in labels {
+        if !seen.insert(label.to_ascii_lowercase()) {
+            findings.push(Finding::new(
+                Level::Warn,
+                format!("label '{}' is listed more than once (labels are case-insensitive)", label),
+            ));
+        }
+    }
+
+    let native = Arch::native();
+    if let Some(arch) = Arch::of(labels).filter(|&arch| arch != native) {
+        findings.push(Finding::new(
+            Level::Warn,
+            format!(
+                "RUNNER_LABELS advertises {} but this host is {}; use ARCH_TEMPLATES for \
+                 other architectures",
+                arch, native
+            ),
+        ));
+    }
+
+    if findings.is_empty() {
+        findings.push(Finding::new(Level::Ok, format!("labels: {}", labels.join(", "))));
+    }
+    findings
+}
+
+/// Token scopes, repository access and the permissions the controller uses
+async fn github_checks(config: &Config, github: &GitHubClient) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    match github.token_scopes().await {
+        Ok(Some(scopes)) if scopes.iter().any(|s| s == "repo") => {
+            findings.push(Finding::new(Level::Ok, "token has the repo scope"));
+        }
+        Ok(Some(scopes)) => {
+            let scopes = if scopes.is_empty() { "none".to_string() } else { scopes.join(", ") };
+            findings.push(Finding::new(
+                Level::Fail,
+                format!("token lacks the repo scope runners register with (has: {})", scopes),
+            ));
+        }
+        Ok(None) => findings.push(Finding::new(
+            Level::Info,
+            "fine-grained or installation token; permissions are checked per request below",
+        )),
+        Err(GitHubError::Unauthorized) => {
+            findings.push(Finding::new(
+                Level::Fail,
+                "GitHub rejected the token; it is expired, revoked or mistyped",
+            ));
+            return findings;
+        }
+        Err(e) => {
+            findings.push(Finding::new(Level::Fail, format!("GitHub API unreachable: {}", e)));
+            findings.push(Finding::new(
+                Level::Info,
+                "run `runner-controller doctor` to diagnose connectivity",
+            ));
+            return findings;
+        }
+    }
+
+    match github.repo_metadata(&config.github_repo).await {
+        Ok(repo) => {
+            findings.push(Finding::new(
+                Level::Ok,
+                format!("repository {} ({})", config.github_repo, repo.visibility),
+            ));
+            match repo.actions_enabled {
+                Some(false) => findings.push(Finding::new(
+                    Level::Fail,
+                    format!("GitHub Actions is disabled for {}", config.github_repo),
+                )),
+                None => findings.push(Finding::new(
+                    Level::Warn,
+                    "the token can't read whether Actions is enabled (needs administration read)",
+                )),
+                Some(true) => {}
+            }
+        }
+        Err(GitHubError::NotFound(_)) => {
+            findings.push(Finding::new(
+                Level::Fail,
+                format!(
+                    "repository {} not found; check GITHUB_REPO and that the token can see it",
+                    config.github_repo
+                ),
+            ));
+            return findings;
+        }
+        Err(e) => findings.push(Finding::new(Level::Fail, format!("repository: {}", e))),
+    }
+
+    match github.list_runners().await {
+        Ok(runners) => findings.push(Finding::new(
+            Level::Ok,
+            format!("listed {} self-hosted runner(s)", runners.len()),
+        )),
+        Err(e) => findings.push(Finding::new(
+            Level::Fail,
+            format!("listing runners failed (needs administration read): {}", e),
+        )),
+    }
+    // Registration tokens expire unused after an hour
+    match github.get_registration_token().await {
+        Ok(_) => findings.push(Finding::new(Level::Ok, "registration token issued")),
+        Err(e) => findings.push(Finding::new(
+            Level::Fail,
+            format!("registration token failed (needs administration write): {}", e),
+        )),
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_local_checks() {
+        let dir = std::env::temp_dir()
+            .join(format!("runner-controller-check-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let token_file = dir.join("token");
+        std::fs::write(&token_file, "test-token").unwrap();
+
+        let foreign = match Arch::native() {
+            Arch::X64 => "ARM64",
+            Arch::Arm64 => "X64",
+        };
+        let vars: HashMap<&str, String> = [
+            ("GITHUB_REPO", "owner/repo".to_string()),
+            ("GITHUB_TOKEN_FILE", token_file.display().to_string()),
+            ("STATE_DIR", dir.join("state").display().to_string()),
+            ("RUNNER_LABELS", format!("self-hosted,ci,CI,big box,{}", foreign)),
+        ]
+        .into_iter()
+        .collect();
+        let config = Config::from_vars(|name| vars.get(name).cloned()).unwrap();
+
+        let findings = local_checks(&config);
+        let has = |level, text: &str| {
+            findings.iter().any(|f| f.level == level && f.message.contains(text))
+        };
+        assert!(has(Level::Ok, "token read from"));
+        assert!(has(Level::Ok, "is writable"));
+        assert!(has(Level::Fail, "'big box' contains whitespace"));
+        assert!(has(Level::Warn, "'CI' is listed more than once"));
+        assert!(has(Level::Warn, "advertises"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1429 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use redb::Durability;
+use serde::{Serialize, Serializer};
+use zeroize::Zeroizing;
+
+use crate::annotate::FailureAnnotation;
+use crate::arch::{Arch, ArchPool, ArchRoutes, ARCH_SLOT_BASE};
+use crate::archive::ArchiveConfig;
+use crate::consistency::StartupAudit;
+use crate::container::{
+    check_secret_name, BindMount, CachePush, EnvVar, GpuConfig, RemoteConfig, RemoteRuntime,
+};
+use crate::egress::EgressMode;
+use crate::github::{RetryPolicy, RetrySettings};
+use crate::hooks::HookConfig;
+use crate::leader::LeaseConfig;
+use crate::load::{DiskGc, LoadLimits};
+use crate::maintenance::MaintenanceWindow;
+use crate::notify::WebhookFormat;
+use crate::policy::ForkPolicy;
+use crate::prewarm::ScheduleEntry;
+use crate::runner_version::RunnerVersion;
+use crate::secrets::{SecretString, VaultAuth, VaultConfig};
+use crate::store::RedisConfig;
+use crate::tenant::{load_tenants, Tenant};
+
+/// Hosts a runner needs to register, fetch actions and upload results, plus
+/// the Nix binary cache
+const DEFAULT_EGRESS_ALLOWLIST: &str = "github.com,api.github.com,codeload.github.com,\
+    objects.githubusercontent.com,pipelines.actions.githubusercontent.com,\
+    results-receiver.actions.githubusercontent.com,ghcr.io,cache.nixos.org";
+
+/// GitHub App credentials for minting job-scoped installation tokens
+#[derive(Debug, Clone, Serialize)]
+pub struct GitHubAppConfig {
+    pub app_id: String,
+    pub installation_id: u64,
+    /// `None` when the key comes from Vault
+    pub private_key_file: Option<PathBuf>,
+}
+
+/// Address family used for GitHub API connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    /// Both families, racing connections (happy eyeballs)
+    Auto,
+    Ipv4,
+    Ipv6,
+}
+
+impl std::str::FromStr for IpFamily {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "ipv4" => Ok(Self::Ipv4),
+            "ipv6" => Ok(Self::Ipv6),
+            other => anyhow::bail!("unknown IP family '{}'", other),
+        }
+    }
+}
+
+/// Runtime used to create and manage runner containers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase", tag = "kind")]
+pub enum ContainerBackend {
+    /// NixOS containers managed with `nixos-container`
+    Nixos,
+    /// Plain systemd-nspawn machines managed with `machinectl`.
+    /// `image` is a tarball, an OS tree directory, or the name of an existing machine image.
+    Nspawn { image: String },
+}
+
+impl ContainerBackend {
+    /// Backends this build can drive, as accepted by `CONTAINER_BACKEND`
+    pub const SUPPORTED: &'static [&'static str] = &["nixos", "nspawn"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Nixos => "nixos",
+            Self::Nspawn { .. } => "nspawn",
+        }
+    }
+
+    /// Where container root filesystems live on the host
+    pub fn containers_dir(&self) -> &'static Path {
+        match self {
+            Self::Nixos => Path::new("/var/lib/nixos-containers"),
+            Self::Nspawn { .. } => Path::new("/var/lib/machines"),
+        }
+    }
+}
+
+/// Network settings for the GitHub API client
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkConfig {
+    pub ip_family: IpFamily,
+    /// Fixed addresses for api.github.com, bypassing system DNS
+    pub resolve: Vec<IpAddr>,
+    pub connect_timeout: Duration,
+    /// Proxy for GitHub API requests; may embed credentials, so never recorded.
+    /// Unset falls back to the standard `HTTPS_PROXY`/`NO_PROXY` variables.
+    #[serde(skip)]
+    pub proxy: Option<String>,
+    /// Extra PEM CA certificates to trust, e.g. for an intercepting proxy
+    pub ca_file: Option<PathBuf>,
+}
+
+impl NetworkConfig {
+    /// Whether GitHub API requests go through a proxy
+    pub fn uses_proxy(&self) -> bool {
+        self.proxy.is_some()
+            || ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+                .iter()
+                .any(|var| std::env::var(var).is_ok_and(|v| !v.is_empty()))
+    }
+}
+
+/// Effective controller configuration.
+///
+/// Serializes to a snapshot with secrets omitted, which is hashed and recorded
+/// in the state DB so history can be correlated with configuration changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    pub github_repo: String,
+    #[serde(skip)]
+    pub github_token: SecretString,
+    /// Watched for rotation while running; `None` when the token comes
+    /// from Vault
+    pub github_token_file: Option<PathBuf>,
+    pub max_concurrent_jobs: usize,
+    /// Between dispatch passes: queued jobs, pool refills and runner startup
+    pub poll_interval: Duration,
+    /// Fraction by which each poll sleep randomly deviates, from 0.0 (none)
+    /// to 1.0, so controllers deployed together don't poll in lockstep
+    pub poll_jitter: f64,
+    /// Between full reconcile passes listing every container and runner;
+    /// never shorter than `poll_interval` in practice
+    pub reconcile_interval: Duration,
+    /// How often finished runners are looked for between polls, so their
+    /// slots are refilled at once; `None` leaves them to the next poll
+    pub container_watch_interval: Option<Duration>,
+    /// Quiet period after which the controller goes idle; `None` disables idle mode
+    pub idle_after: Option<Duration>,
+    /// Poll interval while idle; `None` stops polling until woken up
+    pub idle_poll_interval: Option<Duration>,
+    /// Verifies `X-Hub-Signature-256` on `POST /webhook`; unset disables the endpoint
+    #[serde(skip)]
+    pub webhook_secret: Option<SecretString>,
+    pub job_timeout: Duration,
+    /// How long a new container may take to register its runner; `None` disables
+    pub startup_timeout: Option<Duration>,
+    pub runner_labels: Vec<String>,
+    pub state_dir: PathBuf,
+    /// `None` with `HTTP_PORT=off`, serving the API on `http_socket` only
+    pub http_port: Option<u16>,
+    /// Unix socket the API is also served on; its file mode is the access
+    /// control, so connections get admin access without a token
+    pub http_socket: Option<PathBuf>,
+    pub http_socket_mode: u32,
+    #[serde(skip)]
+    pub api_token: Option<SecretString>,
+    pub max_external_runners: usize,
+    #[serde(serialize_with = "serialize_durability")]
+    pub state_durability: Durability,
+    /// How often free space in the state database is given back; `None` never
+    pub state_compact_interval: Option<Duration>,
+    pub reaper_interval: Option<Duration>,
+    /// Only offline runners with this name prefix are reaped; defaults to
+    /// the prefix of `RUNNER_NAME_TEMPLATE`
+    pub reaper_name_prefix: Option<String>,
+    pub runner_name_template: String,
+    /// Fixed controller instance ID; generated and persisted when unset
+    pub instance_id: Option<String>,
+    /// Lease shared with a standby controller; `None` always dispatches
+    pub leader_lease: Option<LeaseConfig>,
+    /// Redis the replicas of this controller share job claims through;
+    /// `None` keeps them in memory
+    pub redis: Option<RedisConfig>,
+    pub auto_labels: bool,
+    #[serde(skip)]
+    pub notify_webhook_url: Option<String>,
+    pub notify_format: WebhookFormat,
+    pub notify_digest_interval: Option<Duration>,
+    pub notify_email_to: Vec<String>,
+    pub notify_sendmail: PathBuf,
+    pub notify_template_dir: Option<PathBuf>,
+    pub github_app: Option<GitHubAppConfig>,
+    pub job_token_permissions: BTreeMap<String, String>,
+    pub network: NetworkConfig,
+    pub deny_actors: Vec<String>,
+    pub deny_repos: Vec<String>,
+    pub fork_policy: ForkPolicy,
+    /// Refuse jobs from public repositories
+    pub private_repos_only: bool,
+    /// Glob allowlists for workflow files, refs and actors; empty allows all
+    pub allow_workflows: Vec<String>,
+    pub allow_refs: Vec<String>,
+    pub allow_actors: Vec<String>,
+    pub quarantine_on_timeout: bool,
+    pub hooks: HookConfig,
+    /// Watts a fully busy CPU core draws, for energy estimates of jobs
+    pub cpu_watts: Option<f64>,
+    pub claim_jitter: Duration,
+    pub starvation_cycles: u32,
+    /// Poll cycles a job no runner could ever take may stay queued before
+    /// it is reported; 0 disables
+    pub unmatched_queue_cycles: u32,
+    /// Also send a notification for such jobs, not just a `/status` warning
+    pub unmatched_queue_notify: bool,
+    /// Post a check run with each queued job's place in the queue
+    pub queue_annotations: bool,
+    /// Where jobs whose runners keep failing to start are annotated
+    pub provision_failure_annotation: FailureAnnotation,
+    /// Consecutive failed `/spawn` attempts for a job before it is annotated
+    pub provision_failure_attempts: u32,
+    /// Consecutive failed GitHub requests before polling pauses; 0 disables
+    pub circuit_breaker_threshold: u32,
+    pub circuit_breaker_cooldown: Duration,
+    pub github_retry: RetrySettings,
+    /// Requests kept for registration and cleanup once the rate limit runs low
+    pub github_rate_reserve: u64,
+    /// Requests per repository and rate limit window; `None` is unlimited
+    pub github_repo_rate_budget: Option<u64>,
+    pub startup_audit: StartupAudit,
+    /// Spawns per poll cycle double every interval after startup or an outage
+    pub spawn_ramp_interval: Option<Duration>,
+    /// Spawns per hour across all repositories (0 = unlimited)
+    pub max_jobs_per_hour: u32,
+    /// Spawns per hour for a single repository (0 = unlimited)
+    pub max_jobs_per_hour_per_repo: u32,
+    /// Split external runner slots between competing repositories by weight
+    pub fair_share: bool,
+    /// `owner/repo=weight` pairs; unlisted repositories weigh 1
+    pub repo_weights: Vec<(String, u32)>,
+    /// How long a spawn request keeps its repository competing for slots
+    pub fair_share_window: Duration,
+    pub tenants: Vec<Tenant>,
+    pub container_backend: ContainerBackend,
+    /// Remote hosts external runners are placed on over ssh; `None` runs
+    /// everything on this host
+    pub remote: Option<RemoteConfig>,
+    /// Vault the GitHub credentials are fetched from instead of files
+    pub vault: Option<VaultConfig>,
+    /// `label=template` pairs: runners whose labels include `label` are created
+    /// from `template` (config path or flake reference); the first match wins
+    pub label_templates: Vec<(String, String)>,
+    /// Pools for foreign architectures, run under emulation
+    pub arch_routes: ArchRoutes,
+    /// Size of the tmpfs each container's writable root lives on (e.g. `8G`);
+    /// `None` keeps roots on disk
+    pub root_tmpfs_size: Option<String>,
+    /// Host directories extra bind mounts may come from
+    pub bind_allowlist: Vec<PathBuf>,
+    /// Extra bind mounts for the warm pool's containers
+    pub pool_binds: Vec<BindMount>,
+    /// Environment variables for the warm pool's runner processes; values
+    /// may be credentials, so only names are recorded
+    #[serde(serialize_with = "serialize_env_names")]
+    pub pool_env: Vec<EnvVar>,
+    /// Files from `secrets_dir` provisioned into the warm pool's containers
+    pub pool_secrets: Vec<String>,
+    /// Host directory pool secret files are read from
+    pub secrets_dir: Option<PathBuf>,
+    /// Where runner containers may connect to; the pool's mode can be changed at runtime
+    pub egress_mode: EgressMode,
+    /// Host names, addresses and networks reachable in `EgressMode::Allowlist`
+    pub egress_allowlist: Vec<String>,
+    /// Jobs a pool container serves before it is recycled; 1 disables reuse
+    pub reuse_jobs: u32,
+    /// Age after which a reused pool container is recycled
+    pub reuse_max_age: Duration,
+    pub load_limits: LoadLimits,
+    /// Garbage collection when disk space runs low; `None` disables it
+    pub disk_gc: Option<DiskGc>,
+    /// Most standby containers pre-warmed ahead of expected demand (0 disables)
+    pub prewarm_max: usize,
+    /// How far ahead expected demand is looked up
+    pub prewarm_lead: Duration,
+    /// How much job history demand is learned from
+    pub prewarm_lookback: Duration,
+    /// Fixed pool sizes for some hours of the week, overriding learned demand
+    pub prewarm_schedule: Vec<ScheduleEntry>,
+    /// Weekly windows in which no runners are spawned; running jobs finish
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    /// S3-compatible bucket job logs are archived to; `None` disables archiving
+    pub archive: Option<ArchiveConfig>,
+    /// How long runner logs captured at cleanup are kept in `STATE_DIR/logs`;
+    /// `None` keeps them forever
+    pub captured_log_retention: Option<Duration>,
+    /// Binary cache trusted containers push build results to; `None` disables pushing
+    pub cache_push: Option<CachePush>,
+    /// GPUs passed through to GPU-labeled runners; `None` when the host has none
+    pub gpus: Option<GpuConfig>,
+    /// actions-runner version the container template ships; `None` disables
+    /// upgrade checks
+    pub runner_version: Option<String>,
+    pub runner_version_check_interval: Duration,
+    /// Let runners update themselves once a newer release is out
+    pub runner_auto_upgrade: bool,
+    /// Pool containers provisioned at the same time
+    pub spawn_concurrency: usize,
+}
+
+impl Config {
+    /// Load configuration from environment variables
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Load configuration from variables looked up by name instead of the
+    /// environment, e.g. a fixed set in tests
+    pub fn from_vars(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| lookup(name).ok_or(std::env::VarError::NotPresent);
+        let env_list = |name: &str, default: &str| {
+            split_list(&var(name).unwrap_or_else(|_| default.to_string()))
+        };
+        // Intervals and timeouts in seconds, bare or as a duration like `2h30m`
+        let secs = |name: &str, default: &str| {
+            parse_secs(&var(name).unwrap_or_else(|_| default.to_string())).with_context(|| {
+                format!("{} must be a number of seconds or a duration like 90s or 2h30m", name)
+            })
+        };
+        let github_repo = var("GITHUB_REPO")
+            .context("GITHUB_REPO environment variable is required")?;
+
+        // systemd credentials (LoadCredential=) take precedence over the
+        // *_FILE variables
+        let credentials_dir = var("CREDENTIALS_DIRECTORY").ok().map(PathBuf::from);
+        let secret_file = |credential: &str, name: &str| {
+            credentials_dir
+                .as_ref()
+                .map(|dir| dir.join(credential))
+                .filter(|path| path.exists())
+                .map(|path| path.display().to_string())
+                .or_else(|| var(name).ok())
+        };
+
+        // GitHub credentials from HashiCorp Vault instead of files
+        let vault_refresh_secs = secs("VAULT_REFRESH_INTERVAL", "300")?;
+        if vault_refresh_secs == 0 {
+            anyhow::bail!("VAULT_REFRESH_INTERVAL must be at least 1 second");
+        }
+        let vault = match var("VAULT_ADDR") {
+            Ok(addr) => {
+                let auth = match secret_file("vault-token", "VAULT_TOKEN_FILE") {
+                    Some(file) => VaultAuth::Token { file: file.into() },
+                    None => VaultAuth::AppRole {
+                        role_id: var("VAULT_ROLE_ID")
+                            .context("VAULT_ADDR needs VAULT_TOKEN_FILE or VAULT_ROLE_ID")?,
+                        secret_id_file: secret_file("vault-secret-id", "VAULT_SECRET_ID_FILE")
+                            .context("VAULT_ROLE_ID needs VAULT_SECRET_ID_FILE")?
+                            .into(),
+                    },
+                };
+                Some(VaultConfig {
+                    addr,
+                    auth,
+                    secret_path: var("VAULT_SECRET_PATH")
+                        .context("VAULT_SECRET_PATH is required with VAULT_ADDR")?,
+                    token_field: var("VAULT_TOKEN_FIELD")
+                        .unwrap_or_else(|_| "github_token".to_string()),
+                    app_key_field: var("VAULT_APP_KEY_FIELD")
+                        .unwrap_or_else(|_| "github_app_key".to_string()),
+                    refresh_interval: Duration::from_secs(vault_refresh_secs),
+                })
+            }
+            Err(_) => None,
+        };
+
+        // With Vault the token is fetched at startup and never read from disk
+        let github_token_file = match vault {
+            Some(_) => None,
+            None => Some(
+                secret_file("github-token", "GITHUB_TOKEN_FILE")
+                    .context("GITHUB_TOKEN_FILE or a github-token credential is required")?,
+            ),
+        };
+        let github_token = match &github_token_file {
+            Some(path) => read_secret_file(path)
+                .with_context(|| format!("Failed to read GitHub token from {}", path))?,
+            None => SecretString::default(),
+        };
+
+        let max_concurrent_jobs = var("MAX_CONCURRENT")
+            .unwrap_or_else(|_| "7".to_string())
+            .parse()
+            .context("MAX_CONCURRENT must be a valid number")?;
+
+        let poll_interval_secs = secs("POLL_INTERVAL", "10")?;
+        let poll_jitter: f64 = var("POLL_JITTER")
+            .unwrap_or_else(|_| "0.1".to_string())
+            .parse()
+            .context("POLL_JITTER must be a number between 0 and 1")?;
+        if !(0.0..=1.0).contains(&poll_jitter) {
+            anyhow::bail!("POLL_JITTER must be a number between 0 and 1");
+        }
+
+        let reconcile_interval_secs = secs("RECONCILE_INTERVAL", "60")?;
+        let container_watch_secs = secs("CONTAINER_WATCH_INTERVAL", "5")?;
+
+        let idle_after_secs = secs("IDLE_AFTER", "0")?;
+
+        let webhook_secret = match secret_file("webhook-secret", "GITHUB_WEBHOOK_SECRET_FILE") {
+            Some(path) => Some(
+                read_secret_file(&path)
+                    .with_context(|| format!("Failed to read webhook secret from {}", path))?,
+            ),
+            None => None,
+        };
+
+        // With webhooks, an idle controller needn't poll at all
+        let idle_poll_interval_secs =
+            secs("IDLE_POLL_INTERVAL", if webhook_secret.is_some() { "0" } else { "300" })?;
+
+        let job_timeout_secs = secs("JOB_TIMEOUT", "7200")?;
+
+        let startup_timeout_secs = secs("STARTUP_TIMEOUT", "600")?;
+
+        let runner_labels = env_list("RUNNER_LABELS", "self-hosted,ci,nix,x64,Linux");
+
+        let state_dir: PathBuf = var("STATE_DIR")
+            .unwrap_or_else(|_| "/var/lib/runner-controller".to_string())
+            .into();
+
+        let http_port = match var("HTTP_PORT").as_deref() {
+            Ok("off") => None,
+            Ok(port) => Some(
+                port.parse()
+                    .context("HTTP_PORT must be a valid port number or 'off'")?,
+            ),
+            Err(_) => Some(8080),
+        };
+        let http_socket = var("HTTP_SOCKET")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        let http_socket_mode = u32::from_str_radix(
+            &var("HTTP_SOCKET_MODE").unwrap_or_else(|_| "660".to_string()),
+            8,
+        )
+        .context("HTTP_SOCKET_MODE must be an octal file mode")?;
+        if http_port.is_none() && http_socket.is_none() {
+            anyhow::bail!("HTTP_PORT=off requires HTTP_SOCKET");
+        }
+
+        // Optional bearer token guarding the external scheduler endpoints
+        let api_token = match secret_file("api-token", "API_TOKEN_FILE") {
+            Some(path) => Some(
+                read_secret_file(&path)
+                    .with_context(|| format!("Failed to read API token from {}", path))?,
+            ),
+            None => None,
+        };
+
+        let max_external_runners = var("MAX_EXTERNAL_RUNNERS")
+            .unwrap_or_else(|_| "4".to_string())
+            .parse()
+            .context("MAX_EXTERNAL_RUNNERS must be a valid number")?;
+
+        // Durability for low-value state writes; container lifecycle writes are always immediate
+        let state_durability = match var("STATE_DURABILITY")
+            .unwrap_or_else(|_| "eventual".to_string())
+            .as_str()
+        {
+            "eventual" => Durability::Eventual,
+            "immediate" => Durability::Immediate,
+            other => anyhow::bail!(
+                "STATE_DURABILITY must be 'eventual' or 'immediate', got '{}'",
+                other
+            ),
+        };
+
+        // 0 disables compaction
+        let state_compact_interval_secs = secs("STATE_COMPACT_INTERVAL", "86400")?;
+
+        // 0 disables the stale runner reaper
+        let reaper_interval_secs = secs("REAPER_INTERVAL", "600")?;
+
+        let reaper_name_prefix = var("REAPER_NAME_PREFIX").ok().filter(|p| !p.is_empty());
+
+        let auto_labels = var("AUTO_LABELS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("AUTO_LABELS must be true or false")?;
+
+        let notify_webhook_url = match var("NOTIFY_WEBHOOK_FILE") {
+            Ok(path) => Some(
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read webhook URL from {}", path))?
+                    .trim()
+                    .to_string(),
+            ),
+            Err(_) => None,
+        };
+
+        let notify_format = var("NOTIFY_FORMAT")
+            .unwrap_or_else(|_| "generic".to_string())
+            .parse()
+            .context("NOTIFY_FORMAT must be slack, discord or generic")?;
+
+        // 0 sends every notification immediately
+        let notify_digest_secs = secs("NOTIFY_DIGEST_INTERVAL", "0")?;
+
+        // Email notifications are piped to a sendmail-compatible binary
+        let notify_email_to = env_list("NOTIFY_EMAIL_TO", "");
+        let notify_sendmail = var("NOTIFY_SENDMAIL")
+            .unwrap_or_else(|_| "sendmail".to_string())
+            .into();
+        let notify_template_dir = var("NOTIFY_TEMPLATE_DIR").ok().map(PathBuf::from);
+
+        // Job-scoped tokens are enabled when all GitHub App settings are
+        // present; with Vault the key may come from there instead
+        let github_app_key_file = var("GITHUB_APP_KEY_FILE").ok().map(PathBuf::from);
+        let github_app = match (var("GITHUB_APP_ID"), var("GITHUB_APP_INSTALLATION_ID")) {
+            (Ok(app_id), Ok(installation_id))
+                if github_app_key_file.is_some() || vault.is_some() =>
+            {
+                Some(GitHubAppConfig {
+                    app_id,
+                    installation_id: installation_id
+                        .parse()
+                        .context("GITHUB_APP_INSTALLATION_ID must be a valid number")?,
+                    private_key_file: github_app_key_file,
+                })
+            }
+            _ => None,
+        };
+
+        let job_token_permissions = parse_permissions(
+            &var("JOB_TOKEN_PERMISSIONS").unwrap_or_else(|_| "contents=read".to_string()),
+        )?;
+
+        let ip_family = var("GITHUB_IP_FAMILY")
+            .unwrap_or_else(|_| "auto".to_string())
+            .parse()
+            .context("GITHUB_IP_FAMILY must be auto, ipv4 or ipv6")?;
+
+        let resolve = var("GITHUB_API_RESOLVE")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<IpAddr>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("GITHUB_API_RESOLVE must be a comma-separated list of IP addresses")?;
+
+        let connect_timeout_secs = secs("GITHUB_CONNECT_TIMEOUT", "10")?;
+
+        let proxy = var("GITHUB_PROXY").ok().filter(|p| !p.is_empty());
+        let ca_file = var("GITHUB_CA_FILE").ok().map(PathBuf::from);
+
+        // Runner-usage policies (see policy.rs)
+        let deny_actors = env_list("DENY_ACTORS", "");
+        let deny_repos = env_list("DENY_REPOS", "");
+        let allow_workflows = env_list("ALLOW_WORKFLOWS", "");
+        let allow_refs = env_list("ALLOW_REFS", "");
+        let allow_actors = env_list("ALLOW_ACTORS", "");
+        let fork_policy = var("FORK_POLICY")
+            .unwrap_or_else(|_| "deny".to_string())
+            .parse()
+            .context("FORK_POLICY must be allow, deny or isolate")?;
+
+        let private_repos_only = var("PRIVATE_REPOS_ONLY")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("PRIVATE_REPOS_ONLY must be true or false")?;
+
+        let queue_annotations = var("QUEUE_ANNOTATIONS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("QUEUE_ANNOTATIONS must be true or false")?;
+
+        let provision_failure_annotation = var("PROVISION_FAILURE_ANNOTATION")
+            .unwrap_or_else(|_| "off".to_string())
+            .parse()
+            .context("PROVISION_FAILURE_ANNOTATION must be off, check_run or status")?;
+        let provision_failure_attempts: u32 = var("PROVISION_FAILURE_ATTEMPTS")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse()
+            .context("PROVISION_FAILURE_ATTEMPTS must be a valid number")?;
+        if provision_failure_attempts == 0 {
+            anyhow::bail!("PROVISION_FAILURE_ATTEMPTS must be at least 1");
+        }
+
+        let container_backend = match var("CONTAINER_BACKEND")
+            .unwrap_or_else(|_| "nixos".to_string())
+            .as_str()
+        {
+            "nixos" => ContainerBackend::Nixos,
+            "nspawn" => ContainerBackend::Nspawn {
+                image: var("NSPAWN_IMAGE")
+                    .context("NSPAWN_IMAGE is required when CONTAINER_BACKEND=nspawn")?,
+            },
+            other => anyhow::bail!("CONTAINER_BACKEND must be nixos or nspawn, got '{}'", other),
+        };
+
+        // Remote hosts as name=destination:capacity, all driven the same way
+        let remote_hosts = env_list("REMOTE_HOSTS", "")
+            .iter()
+            .map(|host| host.parse())
+            .collect::<Result<Vec<_>>>()
+            .context("Invalid REMOTE_HOSTS")?;
+        let remote = if remote_hosts.is_empty() {
+            None
+        } else {
+            let runtime = RemoteRuntime::new(
+                &var("REMOTE_RUNTIME").unwrap_or_else(|_| "nixos".to_string()),
+                var("REMOTE_TEMPLATE").ok().filter(|t| !t.is_empty()),
+            )
+            .context("Invalid REMOTE_RUNTIME or REMOTE_TEMPLATE")?;
+            Some(RemoteConfig {
+                hosts: remote_hosts,
+                runtime,
+                ssh_key: var("REMOTE_SSH_KEY").ok().filter(|k| !k.is_empty()).map(PathBuf::from),
+                local_capacity: var("LOCAL_EXTERNAL_CAPACITY")
+                    .unwrap_or_else(|_| "0".to_string())
+                    .parse()
+                    .context("LOCAL_EXTERNAL_CAPACITY must be a valid number")?,
+            })
+        };
+
+        let label_templates = parse_label_templates(
+            &var("LABEL_TEMPLATES").unwrap_or_default(),
+        )?;
+
+        let root_tmpfs_size = var("ROOT_TMPFS_SIZE").ok().filter(|s| !s.is_empty());
+        if let Some(size) = &root_tmpfs_size {
+            let digits = size.trim_end_matches(['k', 'K', 'm', 'M', 'g', 'G', '%']);
+            let valid = !digits.is_empty()
+                && digits.chars().all(|c| c.is_ascii_digit())
+                && size.len() <= digits.len() + 1;
+            if !valid {
+                anyhow::bail!("ROOT_TMPFS_SIZE must be a size like 8G or 50%, got '{}'", size);
+            }
+        }
+
+        let egress_mode: EgressMode = var("EGRESS_MODE")
+            .unwrap_or_else(|_| "full".to_string())
+            .parse()
+            .context("EGRESS_MODE must be full, allowlist or isolated")?;
+        let egress_allowlist = env_list("EGRESS_ALLOWLIST", DEFAULT_EGRESS_ALLOWLIST);
+
+        // Container reuse: a pool container's runner re-registers for further jobs
+        let reuse_jobs: u32 = var("CONTAINER_REUSE_JOBS")
+            .unwrap_or_else(|_| "1".to_string())
+            .parse()
+            .context("Invalid CONTAINER_REUSE_JOBS")?;
+        let reuse_max_age_secs = secs("CONTAINER_REUSE_MAX_AGE", "3600")?;
+
+        let bind_allowlist: Vec<PathBuf> = env_list("BIND_ALLOWLIST", "")
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let pool_binds = env_list("POOL_BINDS", "")
+            .iter()
+            .map(|bind| {
+                let bind: BindMount = bind.parse()?;
+                bind.check_allowed(&bind_allowlist).context("Invalid POOL_BINDS")?;
+                Ok(bind)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Runner environment and secret files for the pool, e.g. a shared cache endpoint
+        let pool_env = env_list("POOL_ENV", "")
+            .iter()
+            .map(|var| var.parse::<EnvVar>().context("Invalid POOL_ENV"))
+            .collect::<Result<Vec<_>>>()?;
+        let secrets_dir = var("SECRETS_DIR").ok().map(PathBuf::from);
+        let pool_secrets = env_list("POOL_SECRETS", "");
+        for name in &pool_secrets {
+            check_secret_name(name).context("Invalid POOL_SECRETS")?;
+        }
+        if !pool_secrets.is_empty() && secrets_dir.is_none() {
+            anyhow::bail!("POOL_SECRETS requires SECRETS_DIR");
+        }
+
+        // Host saturation thresholds; unset or 0 disables each check
+        let cpu_watts: f64 = var("CPU_WATTS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("CPU_WATTS must be a valid number")?;
+        if cpu_watts < 0.0 {
+            anyhow::bail!("CPU_WATTS must not be negative");
+        }
+
+        let max_load_per_cpu: f64 = var("MAX_LOAD_PER_CPU")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("MAX_LOAD_PER_CPU must be a valid number")?;
+
+        let min_free_memory_mb: u64 = var("MIN_FREE_MEMORY_MB")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("MIN_FREE_MEMORY_MB must be a valid number")?;
+
+        let min_free_disk_gb: u64 = var("MIN_FREE_DISK_GB")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("MIN_FREE_DISK_GB must be a valid number")?;
+
+        let load_limits = LoadLimits {
+            max_load_per_cpu: (max_load_per_cpu > 0.0).then_some(max_load_per_cpu),
+            min_free_memory_mb: (min_free_memory_mb > 0).then_some(min_free_memory_mb),
+            min_free_disk_gb: (min_free_disk_gb > 0).then_some(min_free_disk_gb),
+            disk_paths: vec![
+                state_dir.clone(),
+                PathBuf::from("/nix/store"),
+                container_backend.containers_dir().to_path_buf(),
+            ],
+        };
+
+        // Garbage collection when free disk space runs low
+        let disk_gc_threshold_gb: u64 = var("DISK_GC_THRESHOLD_GB")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("DISK_GC_THRESHOLD_GB must be a valid number")?;
+
+        let disk_gc_interval_secs = secs("DISK_GC_INTERVAL", "3600")?;
+
+        let disk_gc_command: Vec<String> = var("DISK_GC_COMMAND")
+            .unwrap_or_else(|_| "nix-collect-garbage".to_string())
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        if disk_gc_threshold_gb > 0 && disk_gc_command.is_empty() {
+            anyhow::bail!("DISK_GC_COMMAND must not be empty");
+        }
+
+        let disk_gc = (disk_gc_threshold_gb > 0).then(|| DiskGc {
+            threshold_gb: disk_gc_threshold_gb,
+            command: disk_gc_command,
+            interval: Duration::from_secs(disk_gc_interval_secs),
+        });
+
+        // Preserve timed-out containers for investigation instead of destroying them
+        let quarantine_on_timeout = var("QUARANTINE_ON_TIMEOUT")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("QUARANTINE_ON_TIMEOUT must be true or false")?;
+
+        // Site-specific integrations, run through `sh -c`
+        let hook = |name: &str| var(name).ok().filter(|c| !c.trim().is_empty());
+        let hook_timeout_secs = secs("HOOK_TIMEOUT", "60")?;
+        let hooks = HookConfig {
+            on_spawn: hook("HOOK_ON_SPAWN"),
+            on_complete: hook("HOOK_ON_COMPLETE"),
+            on_timeout: hook("HOOK_ON_TIMEOUT"),
+            on_cleanup_failed: hook("HOOK_ON_CLEANUP_FAILED"),
+            timeout: Duration::from_secs(hook_timeout_secs),
+        };
+
+        let runner_name_template = var("RUNNER_NAME_TEMPLATE")
+            .unwrap_or_else(|_| "{container}".to_string());
+
+        let instance_id = var("CONTROLLER_INSTANCE_ID")
+            .ok()
+            .filter(|id| !id.is_empty());
+
+        // Active/standby pairs share a lease; only its holder dispatches
+        let leader_lease_ttl_secs = secs("LEADER_LEASE_TTL", "30")?;
+        if leader_lease_ttl_secs < 3 {
+            anyhow::bail!("LEADER_LEASE_TTL must be at least 3 seconds");
+        }
+        let leader_lease = var("LEADER_LEASE_FILE")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(|path| LeaseConfig {
+                path: PathBuf::from(path),
+                ttl: Duration::from_secs(leader_lease_ttl_secs),
+            });
+
+        // Replicas behind a load balancer share job claims through Redis
+        let redis_url = match secret_file("redis-url", "REDIS_URL_FILE") {
+            Some(path) => Some(
+                read_secret_file(&path)
+                    .with_context(|| format!("Failed to read Redis URL from {}", path))?,
+            ),
+            None => var("REDIS_URL").ok().filter(|url| !url.is_empty()).map(SecretString::from),
+        };
+        let redis = redis_url.map(|url| RedisConfig {
+            url,
+            key_prefix: var("REDIS_KEY_PREFIX")
+                .unwrap_or_else(|_| "runner-controller".to_string()),
+        });
+
+        // Random delay before claiming a job, so racing controllers see each other's claims
+        let claim_jitter_ms: u64 = var("CLAIM_JITTER_MS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("CLAIM_JITTER_MS must be a valid number")?;
+
+        // Poll cycles a matching job may stay queued with idle runners before alerting
+        let starvation_cycles = var("STARVATION_CYCLES")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("STARVATION_CYCLES must be a valid number")?;
+
+        // Poll cycles a job whose labels match no runner may stay queued before warning
+        let unmatched_queue_cycles = var("UNMATCHED_QUEUE_CYCLES")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("UNMATCHED_QUEUE_CYCLES must be a valid number")?;
+        let unmatched_queue_notify = var("UNMATCHED_QUEUE_NOTIFY")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("UNMATCHED_QUEUE_NOTIFY must be true or false")?;
+
+        // Pause polling during GitHub outages instead of retrying every cycle
+        let circuit_breaker_threshold = var("CIRCUIT_BREAKER_THRESHOLD")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse()
+            .context("CIRCUIT_BREAKER_THRESHOLD must be a valid number")?;
+
+        let circuit_breaker_cooldown_secs = secs("CIRCUIT_BREAKER_COOLDOWN", "60")?;
+
+        let github_rate_reserve = var("GITHUB_RATE_RESERVE")
+            .unwrap_or_else(|_| "500".to_string())
+            .parse()
+            .context("GITHUB_RATE_RESERVE must be a valid number")?;
+        let github_repo_rate_budget: u64 = var("GITHUB_REPO_RATE_BUDGET")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("GITHUB_REPO_RATE_BUDGET must be a valid number")?;
+
+        // Retries of failed GitHub requests, jittered so controllers spread out
+        let retry_attempts: u32 = var("GITHUB_RETRY_ATTEMPTS")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse()
+            .context("GITHUB_RETRY_ATTEMPTS must be a valid number")?;
+        if retry_attempts == 0 {
+            anyhow::bail!("GITHUB_RETRY_ATTEMPTS must be at least 1");
+        }
+        let retry_initial_ms: u64 = var("GITHUB_RETRY_INITIAL_MS")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse()
+            .context("GITHUB_RETRY_INITIAL_MS must be a valid number")?;
+        let retry_max_ms: u64 = var("GITHUB_RETRY_MAX_MS")
+            .unwrap_or_else(|_| "30000".to_string())
+            .parse()
+            .context("GITHUB_RETRY_MAX_MS must be a valid number")?;
+        let retry_jitter: f64 = var("GITHUB_RETRY_JITTER")
+            .unwrap_or_else(|_| "0.5".to_string())
+            .parse()
+            .context("GITHUB_RETRY_JITTER must be a number between 0 and 1")?;
+        if !(0.0..=1.0).contains(&retry_jitter) {
+            anyhow::bail!("GITHUB_RETRY_JITTER must be a number between 0 and 1");
+        }
+        let retry_deadline_secs = secs("GITHUB_RETRY_DEADLINE", "0")?;
+        let github_retry = RetrySettings {
+            policy: RetryPolicy {
+                max_attempts: retry_attempts,
+                initial_backoff: Duration::from_millis(retry_initial_ms),
+                max_backoff: Duration::from_millis(retry_max_ms),
+                jitter: retry_jitter,
+                deadline: (retry_deadline_secs > 0)
+                    .then(|| Duration::from_secs(retry_deadline_secs)),
+            },
+            overrides: env_list("GITHUB_RETRY_OVERRIDES", "")
+                .iter()
+                .map(|o| o.parse())
+                .collect::<Result<_>>()?,
+        };
+
+        // Compare containers, state and GitHub on startup
+        let startup_audit = var("STARTUP_AUDIT")
+            .unwrap_or_else(|_| "off".to_string())
+            .parse()
+            .context("STARTUP_AUDIT must be off, report or fix")?;
+
+        let spawn_ramp_secs = secs("SPAWN_RAMP_INTERVAL", "30")?;
+
+        // Hourly spawn budgets against matrix explosions and retry storms
+        let max_jobs_per_hour = var("MAX_JOBS_PER_HOUR")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("MAX_JOBS_PER_HOUR must be a valid number")?;
+
+        let max_jobs_per_hour_per_repo = var("MAX_JOBS_PER_HOUR_PER_REPO")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("MAX_JOBS_PER_HOUR_PER_REPO must be a valid number")?;
+
+        let fair_share = var("FAIR_SHARE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("FAIR_SHARE must be true or false")?;
+
+        let repo_weights = env_list("REPO_WEIGHTS", "")
+            .iter()
+            .map(|pair| {
+                let (repo, weight) = pair
+                    .split_once('=')
+                    .with_context(|| format!("'{}' is not repo=weight", pair))?;
+                let weight: u32 = weight
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|w| *w > 0)
+                    .with_context(|| format!("Weight for {} must be a positive number", repo))?;
+                Ok((repo.trim().to_string(), weight))
+            })
+            .collect::<Result<Vec<_>>>()
+            .context("Invalid REPO_WEIGHTS")?;
+
+        // Foreign architectures served under emulation, with their template,
+        // warm pool size and runner limit
+        let arch_pairs = |name: &str| -> Result<Vec<(Arch, String)>> {
+            env_list(name, "")
+                .iter()
+                .map(|pair| {
+                    let (arch, value) = pair
+                        .split_once('=')
+                        .with_context(|| format!("'{}' is not arch=value", pair))?;
+                    Ok((arch.parse()?, value.trim().to_string()))
+                })
+                .collect::<Result<_>>()
+                .with_context(|| format!("Invalid {}", name))
+        };
+        let arch_number = |name: &str, arch: Arch| -> Result<Option<usize>> {
+            arch_pairs(name)?
+                .into_iter()
+                .find(|(a, _)| *a == arch)
+                .map(|(_, n)| n.parse())
+                .transpose()
+                .with_context(|| format!("{} for {} must be a valid number", name, arch))
+        };
+        let arch_routes = arch_pairs("ARCH_TEMPLATES")?
+            .into_iter()
+            .map(|(arch, template)| {
+                Ok(ArchPool {
+                    arch,
+                    template,
+                    size: arch_number("ARCH_POOL_SIZES", arch)?.unwrap_or(0),
+                    max_runners: arch_number("ARCH_MAX_RUNNERS", arch)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .and_then(ArchRoutes::new)
+            .context("Invalid ARCH_TEMPLATES")?;
+        if !arch_routes.is_empty() && max_concurrent_jobs >= ARCH_SLOT_BASE {
+            anyhow::bail!("MAX_CONCURRENT must be below {} with ARCH_TEMPLATES", ARCH_SLOT_BASE);
+        }
+
+        let fair_share_window_secs = secs("FAIR_SHARE_WINDOW", "600")?;
+
+        // Tenant-scoped read tokens for the HTTP API (multi-tenant mode)
+        let tenants = match var("TENANTS_FILE") {
+            Ok(path) => load_tenants(path.as_ref())?,
+            Err(_) => Vec::new(),
+        };
+
+        let prewarm_max = var("PREWARM_MAX")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("PREWARM_MAX must be a valid number")?;
+
+        let prewarm_lead_secs = secs("PREWARM_LEAD", "900")?;
+
+        let prewarm_lookback_days: u64 = var("PREWARM_LOOKBACK_DAYS")
+            .unwrap_or_else(|_| "28".to_string())
+            .parse()
+            .context("PREWARM_LOOKBACK_DAYS must be a valid number")?;
+
+        let prewarm_schedule = env_list("PREWARM_SCHEDULE", "")
+            .iter()
+            .map(|entry| entry.parse())
+            .collect::<Result<Vec<ScheduleEntry>>>()
+            .context("Invalid PREWARM_SCHEDULE")?;
+
+        let maintenance_windows = env_list("MAINTENANCE_WINDOWS", "")
+            .iter()
+            .map(|window| window.parse())
+            .collect::<Result<Vec<MaintenanceWindow>>>()
+            .context("Invalid MAINTENANCE_WINDOWS")?;
+
+        // GitHub refuses runners that skipped updates for too long
+        let runner_version = match var("RUNNER_VERSION") {
+            Ok(version) => {
+                version
+                    .parse::<RunnerVersion>()
+                    .context("Invalid RUNNER_VERSION")?;
+                Some(version.trim().trim_start_matches('v').to_string())
+            }
+            Err(_) => None,
+        };
+        let runner_version_check_secs = secs("RUNNER_VERSION_CHECK_INTERVAL", "21600")?;
+        let runner_auto_upgrade = var("RUNNER_AUTO_UPGRADE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("RUNNER_AUTO_UPGRADE must be true or false")?;
+
+        // Boot several pool containers at once instead of one after another
+        let spawn_concurrency: usize = var("SPAWN_CONCURRENCY")
+            .unwrap_or_else(|_| "4".to_string())
+            .parse()
+            .context("SPAWN_CONCURRENCY must be a valid number")?;
+
+        let archive = match var("ARCHIVE_S3_BUCKET") {
+            Ok(bucket) => {
+                let endpoint = var("ARCHIVE_S3_ENDPOINT")
+                    .context("ARCHIVE_S3_ENDPOINT is required with ARCHIVE_S3_BUCKET")?;
+                reqwest::Url::parse(&endpoint).context("ARCHIVE_S3_ENDPOINT must be a URL")?;
+                let access_key_id = var("ARCHIVE_S3_ACCESS_KEY_ID")
+                    .context("ARCHIVE_S3_ACCESS_KEY_ID is required with ARCHIVE_S3_BUCKET")?;
+                let secret_file = var("ARCHIVE_S3_SECRET_KEY_FILE")
+                    .context("ARCHIVE_S3_SECRET_KEY_FILE is required with ARCHIVE_S3_BUCKET")?;
+                let secret_access_key = read_secret_file(&secret_file)
+                    .with_context(|| format!("Failed to read S3 secret key from {}", secret_file))?;
+                let retention_days: u64 = var("ARCHIVE_RETENTION_DAYS")
+                    .unwrap_or_else(|_| "0".to_string())
+                    .parse()
+                    .context("ARCHIVE_RETENTION_DAYS must be a valid number")?;
+
+                Some(ArchiveConfig {
+                    endpoint,
+                    bucket,
+                    region: var("ARCHIVE_S3_REGION")
+                        .unwrap_or_else(|_| "us-east-1".to_string()),
+                    prefix: var("ARCHIVE_S3_PREFIX").unwrap_or_default(),
+                    access_key_id,
+                    secret_access_key,
+                    retention: (retention_days > 0)
+                        .then(|| Duration::from_secs(retention_days * 86400)),
+                })
+            }
+            Err(_) => None,
+        };
+
+        let captured_log_retention_days: u64 = var("CAPTURED_LOG_RETENTION_DAYS")
+            .unwrap_or_else(|_| "14".to_string())
+            .parse()
+            .context("CAPTURED_LOG_RETENTION_DAYS must be a valid number")?;
+
+        // Binary cache push from trusted runner containers
+        let read_secret = |name: &str| -> Result<Option<SecretString>> {
+            match var(name) {
+                Ok(path) => read_secret_file(&path)
+                    .with_context(|| format!("Failed to read {} from {}", name, path))
+                    .map(Some),
+                Err(_) => Ok(None),
+            }
+        };
+        let cache_push = match var("CACHE_PUSH_TARGET") {
+            Ok(target) => Some(CachePush {
+                target,
+                command: var("CACHE_PUSH_COMMAND").ok().filter(|c| !c.is_empty()),
+                signing_key: read_secret("CACHE_SIGNING_KEY_FILE")?,
+                credentials: read_secret("CACHE_CREDENTIALS_FILE")?,
+            }),
+            Err(_) => None,
+        };
+
+        // GPU passthrough: one entry per GPU, a GPU's device nodes joined by `+`
+        let gpu_devices: Vec<Vec<String>> = match var("GPU_DEVICES") {
+            Ok(devices) => devices
+                .split(',')
+                .map(|gpu| {
+                    gpu.split('+')
+                        .map(|node| node.trim().to_string())
+                        .filter(|node| !node.is_empty())
+                        .collect::<Vec<_>>()
+                })
+                .filter(|nodes| !nodes.is_empty())
+                .collect(),
+            Err(_) => crate::capabilities::detect_gpu_devices(),
+        };
+        let gpus = (!gpu_devices.is_empty()).then(|| GpuConfig {
+            label: var("GPU_LABEL").unwrap_or_else(|_| "gpu".to_string()),
+            devices: gpu_devices,
+            shared_devices: env_list(
+                "GPU_SHARED_DEVICES",
+                "/dev/nvidiactl,/dev/nvidia-uvm,/dev/nvidia-uvm-tools,/dev/nvidia-modeset",
+            ),
+            driver_paths: env_list("GPU_DRIVER_PATHS", "/run/opengl-driver"),
+        });
+
+        Ok(Config {
+            github_repo,
+            github_token,
+            github_token_file: github_token_file.map(PathBuf::from),
+            max_concurrent_jobs,
+            poll_interval: Duration::from_secs(poll_interval_secs),
+            poll_jitter,
+            reconcile_interval: Duration::from_secs(reconcile_interval_secs),
+            container_watch_interval: (container_watch_secs > 0)
+                .then(|| Duration::from_secs(container_watch_secs)),
+            idle_after: (idle_after_secs > 0).then(|| Duration::from_secs(idle_after_secs)),
+            idle_poll_interval: (idle_poll_interval_secs > 0)
+                .then(|| Duration::from_secs(idle_poll_interval_secs)),
+            webhook_secret,
+            job_timeout: Duration::from_secs(job_timeout_secs),
+            startup_timeout: (startup_timeout_secs > 0)
+                .then(|| Duration::from_secs(startup_timeout_secs)),
+            runner_labels,
+            state_dir,
+            http_port,
+            http_socket,
+            http_socket_mode,
+            api_token,
+            max_external_runners,
+            state_durability,
+            state_compact_interval: (state_compact_interval_secs > 0)
+                .then(|| Duration::from_secs(state_compact_interval_secs)),
+            reaper_interval: (reaper_interval_secs > 0)
+                .then(|| Duration::from_secs(reaper_interval_secs)),
+            reaper_name_prefix,
+            runner_name_template,
+            instance_id,
+            leader_lease,
+            redis,
+            auto_labels,
+            notify_webhook_url,
+            notify_format,
+            notify_digest_interval: (notify_digest_secs > 0)
+                .then(|| Duration::from_secs(notify_digest_secs)),
+            notify_email_to,
+            notify_sendmail,
+            notify_template_dir,
+            github_app,
+            job_token_permissions,
+            network: NetworkConfig {
+                ip_family,
+                resolve,
+                connect_timeout: Duration::from_secs(connect_timeout_secs),
+                proxy,
+                ca_file,
+            },
+            deny_actors,
+            deny_repos,
+            fork_policy,
+            private_repos_only,
+            allow_workflows,
+            allow_refs,
+            allow_actors,
+            quarantine_on_timeout,
+            hooks,
+            cpu_watts: (cpu_watts > 0.0).then_some(cpu_watts),
+            claim_jitter: Duration::from_millis(claim_jitter_ms),
+            starvation_cycles,
+            unmatched_queue_cycles,
+            unmatched_queue_notify,
+            queue_annotations,
+            provision_failure_annotation,
+            provision_failure_attempts,
+            circuit_breaker_threshold,
+            circuit_breaker_cooldown: Duration::from_secs(circuit_breaker_cooldown_secs),
+            github_retry,
+            github_rate_reserve,
+            github_repo_rate_budget: (github_repo_rate_budget > 0)
+                .then_some(github_repo_rate_budget),
+            startup_audit,
+            spawn_ramp_interval: (spawn_ramp_secs > 0).then(|| Duration::from_secs(spawn_ramp_secs)),
+            max_jobs_per_hour,
+            max_jobs_per_hour_per_repo,
+            fair_share,
+            repo_weights,
+            fair_share_window: Duration::from_secs(fair_share_window_secs),
+            tenants,
+            container_backend,
+            remote,
+            vault,
+            label_templates,
+            arch_routes,
+            root_tmpfs_size,
+            bind_allowlist,
+            pool_binds,
+            pool_env,
+            pool_secrets,
+            secrets_dir,
+            egress_mode,
+            egress_allowlist,
+            reuse_jobs: reuse_jobs.max(1),
+            reuse_max_age: Duration::from_secs(reuse_max_age_secs),
+            load_limits,
+            disk_gc,
+            prewarm_max,
+            prewarm_lead: Duration::from_secs(prewarm_lead_secs),
+            prewarm_lookback: Duration::from_secs(prewarm_lookback_days * 86400),
+            prewarm_schedule,
+            maintenance_windows,
+            archive,
+            captured_log_retention: (captured_log_retention_days > 0)
+                .then(|| Duration::from_secs(captured_log_retention_days * 86400)),
+            cache_push,
+            gpus,
+            runner_version,
+            runner_version_check_interval: Duration::from_secs(runner_version_check_secs),
+            runner_auto_upgrade,
+            spawn_concurrency: spawn_concurrency.max(1),
+        })
+    }
+}
+
+impl Config {
+    /// Template for a runner with `labels`, from `LABEL_TEMPLATES`; `None`
+    /// means the backend's default template or image
+    pub fn template_for(&self, labels: &[String]) -> Option<&str> {
+        self.label_templates
+            .iter()
+            .find(|(label, _)| labels.iter().any(|l| l.eq_ignore_ascii_case(label)))
+            .map(|(_, template)| template.as_str())
+    }
+
+    /// Stable hash of the (secret-free) configuration snapshot. Pool
+    /// environment values are left out of the snapshot but still hashed, so
+    /// changing one reseeds the pool.
+    pub fn hash(&self) -> String {
+        let mut snapshot = serde_json::to_string(self).unwrap_or_default();
+        for var in &self.pool_env {
+            snapshot.push('\0');
+            snapshot.push_str(&var.value);
+        }
+        format!("{:016x}", fnv1a(snapshot.as_bytes()))
+    }
+}
+
+fn serialize_durability<S: Serializer>(d: &Durability, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(match d {
+        Durability::Immediate => "immediate",
+        _ => "eventual",
+    })
+}
+
+fn serialize_env_names<S: Serializer>(env: &[EnvVar], s: S) -> Result<S::Ok, S::Error> {
+    s.collect_seq(env.iter().map(|var| var.name.as_str()))
+}
+
+/// 64-bit FNV-1a; unlike `DefaultHasher` it is stable across Rust releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Split a comma-separated list, dropping empty entries
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Parse an environment file (`KEY=value` lines, as systemd's
+/// `EnvironmentFile=`): blank lines and `#` comments are skipped, an
+/// `export ` prefix and quotes around the value are dropped
+pub fn parse_env_file(contents: &str) -> Result<BTreeMap<String, String>> {
+    let mut vars = BTreeMap::new();
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("line {}: expected KEY=value", n + 1))?;
+        let value = value.trim();
+        let unquoted = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q))
+            .unwrap_or(value);
+        vars.insert(key.trim().to_string(), unquoted.to_string());
+    }
+    Ok(vars)
+}
+
+/// Trimmed contents of a token or key file; the untrimmed copy is wiped too
+fn read_secret_file(path: impl AsRef<Path>) -> std::io::Result<SecretString> {
+    let contents = Zeroizing::new(std::fs::read_to_string(path)?);
+    Ok(SecretString::from(contents.trim()))
+}
+
+/// Whole seconds from a bare number or a humantime duration (`15s`, `2h30m`)
+fn parse_secs(s: &str) -> Result<u64> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse() {
+        return Ok(secs);
+    }
+    let duration = humantime::parse_duration(s)?;
+    if duration.subsec_nanos() != 0 {
+        anyhow::bail!("'{}' is not a whole number of seconds", s);
+    }
+    Ok(duration.as_secs())
+}
+
+/// Parse `label=template` pairs, e.g. `nix=/etc/nixos/nix.nix,docker=github:org/ci#docker`
+fn parse_label_templates(s: &str) -> Result<Vec<(String, String)>> {
+    s.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|pair| {
+            let (label, template) = pair.split_once('=').with_context(|| {
+                format!("Invalid label template '{}', expected label=template", pair)
+            })?;
+            Ok((label.trim().to_string(), template.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Parse `scope=level` pairs, e.g. `contents=read,pull_requests=write`
+fn parse_permissions(s: &str) -> Result<BTreeMap<String, String>> {
+    s.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|pair| {
+            let (scope, level) = pair
+                .split_once('=')
+                .with_context(|| format!("Invalid permission '{}', expected scope=level", pair))?;
+            Ok((scope.trim().to_string(), level.trim().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::OnceLock;
+
+    /// Configuration from `vars`, with a repository and token file unless given
+    fn load(vars: &[(&str, &str)]) -> Result<Config> {
+        static TOKEN_FILE: OnceLock<PathBuf> = OnceLock::new();
+        let token_file = TOKEN_FILE.get_or_init(|| {
+            let path = std::env::temp_dir()
+                .join(format!("runner-controller-config-{}", std::process::id()));
+            std::fs::write(&path, "test-token").unwrap();
+            path
+        });
+        let mut all: HashMap<&str, String> = HashMap::from([
+            ("GITHUB_REPO", "owner/repo".to_string()),
+            ("GITHUB_TOKEN_FILE", token_file.display().to_string()),
+        ]);
+        all.extend(vars.iter().map(|(name, value)| (*name, value.to_string())));
+        Config::from_vars(|name| all.get(name).cloned())
+    }
+
+    #[test]
+    fn test_config_file() {
+        let file = parse_env_file(
+            "# runner-controller\n\
+             export GITHUB_REPO=\"owner/file\"\n\
+             \n\
+             MAX_CONCURRENT = '3'\n\
+             RUNNER_LABELS=self-hosted,from-file\n",
+        )
+        .unwrap();
+        assert_eq!(file["GITHUB_REPO"], "owner/file");
+        assert_eq!(file["MAX_CONCURRENT"], "3");
+        assert!(parse_env_file("GITHUB_REPO").is_err());
+
+        // Flags beat the environment, which beats the file, as in `--config`
+        let mut vars: Vec<(&str, &str)> =
+            file.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+        vars.push(("MAX_CONCURRENT", "5"));
+        vars.push(("GITHUB_REPO", "owner/flag"));
+        let config = load(&vars).unwrap();
+        assert_eq!(config.github_repo, "owner/flag");
+        assert_eq!(config.max_concurrent_jobs, 5);
+        assert_eq!(config.runner_labels, ["self-hosted", "from-file"]);
+    }
+
+    #[test]
+    fn test_durations() {
+        let config = load(&[("POLL_INTERVAL", "2m"), ("JOB_TIMEOUT", "2h 30m")]).unwrap();
+        assert_eq!(config.poll_interval, Duration::from_secs(120));
+        assert_eq!(config.job_timeout, Duration::from_secs(9000));
+        // Bare numbers are still seconds
+        let config = load(&[("POLL_INTERVAL", " 30 ")]).unwrap();
+        assert_eq!(config.poll_interval, Duration::from_secs(30));
+
+        for invalid in ["1.5s", "500ms", "soon", "-5"] {
+            let err = load(&[("POLL_INTERVAL", invalid)]).unwrap_err();
+            assert!(err.to_string().starts_with("POLL_INTERVAL must be"), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_pool_env_values_left_out_of_snapshot() {
+        let config = load(&[("POOL_ENV", "REGISTRY_PASSWORD=hunter2")]).unwrap();
+        let snapshot = serde_json::to_value(&config).unwrap();
+        assert_eq!(snapshot["pool_env"], serde_json::json!(["REGISTRY_PASSWORD"]));
+        assert!(!snapshot.to_string().contains("hunter2"));
+
+        // A new value still changes the hash, so the pool is reseeded
+        let rotated = load(&[("POOL_ENV", "REGISTRY_PASSWORD=hunter3")]).unwrap();
+        assert_ne!(config.hash(), rotated.hash());
+    }
+
+    #[test]
+    fn test_vault_refresh_interval() {
+        let err = load(&[("VAULT_REFRESH_INTERVAL", "0")]).unwrap_err();
+        assert!(err.to_string().contains("at least 1 second"));
+    }
+
+    #[test]
+    fn test_systemd_credentials() {
+        let dir = std::env::temp_dir()
+            .join(format!("runner-controller-credentials-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let credentials = dir.join("credentials");
+        std::fs::create_dir_all(&credentials).unwrap();
+        std::fs::write(credentials.join("github-token"), "from-credential\n").unwrap();
+        std::fs::write(dir.join("token"), "from-file").unwrap();
+        std::fs::write(dir.join("webhook"), "webhook-from-file").unwrap();
+
+        let config = load(&[
+            ("CREDENTIALS_DIRECTORY", &credentials.display().to_string()),
+            ("GITHUB_TOKEN_FILE", &dir.join("token").display().to_string()),
+            ("GITHUB_WEBHOOK_SECRET_FILE", &dir.join("webhook").display().to_string()),
+        ])
+        .unwrap();
+
+        // The credential wins over GITHUB_TOKEN_FILE; without a
+        // webhook-secret credential the *_FILE variable is used
+        assert_eq!(config.github_token.expose(), "from-credential");
+        assert_eq!(config.github_token_file, Some(credentials.join("github-token")));
+        assert_eq!(config.webhook_secret.unwrap().expose(), "webhook-from-file");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::container::{ContainerManager, RunnerNaming};
+use crate::github::{Runner, WorkflowJob};
+
+/// What to do with the startup consistency audit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupAudit {
+    #[default]
+    Off,
+    /// Log and record deviations only
+    Report,
+    /// Also repair what can be repaired
+    Fix,
+}
+
+impl FromStr for StartupAudit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "off" => Ok(Self::Off),
+            "report" => Ok(Self::Report),
+            "fix" => Ok(Self::Fix),
+            other => anyhow::bail!("unknown startup audit mode '{}'", other),
+        }
+    }
+}
+
+/// Kind of drift between local containers, the state database and GitHub
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviationKind {
+    /// Container exists but the state database has no entry for it
+    ContainerWithoutState,
+    /// State entry for a container that no longer exists
+    StateWithoutContainer,
+    /// Container whose runner is not registered on GitHub
+    ContainerWithoutRunner,
+    /// Runner owned by this instance but with no local container (ghost runner)
+    RunnerWithoutContainer,
+    /// In-progress job assigned to one of our runners whose container is gone
+    OrphanedJob,
+}
+
+impl DeviationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ContainerWithoutState => "container_without_state",
+            Self::StateWithoutContainer => "state_without_container",
+            Self::ContainerWithoutRunner => "container_without_runner",
+            Self::RunnerWithoutContainer => "runner_without_container",
+            Self::OrphanedJob => "orphaned_job",
+        }
+    }
+}
+
+/// A single inconsistency found by the audit
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Deviation {
+    pub kind: DeviationKind,
+    /// Container name, or the runner name for ghost runners
+    pub subject: String,
+    pub detail: String,
+    /// GitHub runner ID, for ghost runners
+    #[serde(skip)]
+    pub runner_id: Option<u64>,
+}
+
+/// Everything the audit compares
+pub struct Observed<'a> {
+    pub containers: &'a HashSet<String>,
+    pub state: &'a HashSet<String>,
+    pub runners: &'a [Runner],
+    pub in_progress_jobs: &'a [WorkflowJob],
+    pub naming: &'a RunnerNaming,
+}
+
+/// Compare local containers, the state database and GitHub, listing every deviation
+pub fn find_deviations(observed: &Observed) -> Vec<Deviation> {
+    let Observed {
+        containers,
+        state,
+        runners,
+        in_progress_jobs,
+        naming,
+    } = observed;
+    let mut deviations = Vec::new();
+
+    let mut names: Vec<&String> = containers.iter().collect();
+    names.sort();
+    for name in names {
+        if !state.contains(name) {
+            deviations.push(Deviation {
+                kind: DeviationKind::ContainerWithoutState,
+                subject: name.clone(),
+                detail: "container has no state entry".to_string(),
+                runner_id: None,
+            });
+        }
+
+        let runner_name = naming.runner_name(name);
+        if !runners.iter().any(|r| r.name == runner_name) {
+            deviations.push(Deviation {
+                kind: DeviationKind::ContainerWithoutRunner,
+                subject: name.clone(),
+                detail: format!("runner {} is not registered", runner_name),
+                runner_id: None,
+            });
+        }
+    }
+
+    let mut stale: Vec<&String> = state.difference(containers).collect();
+    stale.sort();
+    for name in stale {
+        deviations.push(Deviation {
+            kind: DeviationKind::StateWithoutContainer,
+            subject: name.clone(),
+            detail: "state entry has no container".to_string(),
+            runner_id: None,
+        });
+    }
+
+    // A runner name of ours whose container does not exist. Registered runners
+    // must also carry this instance's label to count as ours.
+    let missing = |name: &str| {
+        let owned = match runners.iter().find(|r| r.name == name) {
+            Some(runner) => naming.owns(runner),
+            None => naming
+                .container_name(name)
+                .is_some_and(ContainerManager::is_runner_name),
+        };
+        owned && naming.container_name(name).is_some_and(|c| !containers.contains(c))
+    };
+
+    for runner in runners.iter() {
+        if missing(&runner.name) {
+            deviations.push(Deviation {
+                kind: DeviationKind::RunnerWithoutContainer,
+                subject: runner.name.clone(),
+                detail: format!("runner {} is {}", runner.id, runner.status),
+                runner_id: Some(runner.id),
+            });
+        }
+    }
+
+    for job in in_progress_jobs.iter() {
+        let Some(runner) = job.runner_name.as_deref() else {
+            continue;
+        };
+        if missing(runner) {
+            deviations.push(Deviation {
+                kind: DeviationKind::OrphanedJob,
+                subject: runner.to_string(),
+                detail: format!("job {} ({}) runs on a missing container", job.id, job.name),
+                runner_id: None,
+            });
+        }
+    }
+
+    deviations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_deviations() {
+        let set = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<_>>();
+        let runner = |id, name: &str| Runner {
+            id,
+            name: name.to_string(),
+            status: "online".to_string(),
+            ..Default::default()
+        };
+
+        let containers = set(&["r0", "r1"]);
+        let state = set(&["r0", "r2"]);
+        let runners = vec![runner(1, "r0"), runner(2, "r3"), runner(3, "laptop")];
+        let jobs = vec![WorkflowJob {
+            id: 42,
+            name: "build".to_string(),
+            runner_name: Some("r3".to_string()),
+            ..Default::default()
+        }];
+        let naming = RunnerNaming::default();
+
+        let kinds: Vec<(DeviationKind, String)> = find_deviations(&Observed {
+            containers: &containers,
+            state: &state,
+            runners: &runners,
+            in_progress_jobs: &jobs,
+            naming: &naming,
+        })
+        .into_iter()
+        .map(|d| (d.kind, d.subject))
+        .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                (DeviationKind::ContainerWithoutState, "r1".to_string()),
+                (DeviationKind::ContainerWithoutRunner, "r1".to_string()),
+                (DeviationKind::StateWithoutContainer, "r2".to_string()),
+                (DeviationKind::RunnerWithoutContainer, "r3".to_string()),
+                (DeviationKind::OrphanedJob, "r3".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1980 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::process::{Child, Command};
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+
+use crate::config::ContainerBackend;
+use crate::egress::{self, EgressMode};
+use crate::github::Runner;
+use crate::secrets::SecretString;
+use crate::state::unix_now;
+
+mod fake;
+mod remote;
+
+pub use fake::{BackendCall, FakeBackend};
+pub use remote::{Fleet, HostCapacity, RemoteConfig, RemoteHostConfig, RemoteRuntime};
+
+/// Path (inside the container) of the job-scoped GitHub token made available to workflows
+pub const JOB_TOKEN_PATH: &str = "var/lib/github-job-token";
+
+/// Binary cache settings read by the container template's post-build hook
+const CACHE_PUSH_ENV_PATH: &str = "var/lib/nix-cache/push.env";
+const CACHE_SIGNING_KEY_PATH: &str = "var/lib/nix-cache/signing-key";
+const CACHE_CREDENTIALS_PATH: &str = "var/lib/nix-cache/credentials.env";
+
+/// Pool environment variables, sourced by the runner service before it starts
+const RUNNER_ENV_PATH: &str = "var/lib/github-runner-env";
+/// Present when the runner may update itself (registered without `--disableupdate`)
+pub const RUNNER_ALLOW_UPDATE_PATH: &str = "var/lib/github-runner-allow-update";
+/// Directory (inside the container) pool secret files are provisioned into
+pub const RUNNER_SECRETS_DIR: &str = "var/lib/github-runner-secrets";
+
+/// Variables the runner service script sets or relies on; a pool variable
+/// overriding one would change where the runner registers or what it deletes
+const RESERVED_ENV: &[&str] = &[
+    "ACTIONS_RUNNER_INPUT_TOKEN",
+    "CREDENTIALS_DIRECTORY",
+    "GITHUB_REPO",
+    "HOME",
+    "IFS",
+    "LABELS_FILE",
+    "LOGS_DIR",
+    "PATH",
+    "REG_TOKEN",
+    "RUNNER_LABELS",
+    "RUNNER_NAME",
+    "RUNNER_ROOT",
+    "RUNNER_SECRETS_DIR",
+    "STATE_DIR",
+    "TOKEN_FILE",
+    "WORK_DIR",
+];
+
+/// Registration token, labels and GitHub name read by the runner service on configure
+const RUNNER_TOKEN_PATH: &str = "var/lib/github-runner-token";
+const RUNNER_LABELS_PATH: &str = "var/lib/github-runner-labels";
+const RUNNER_NAME_PATH: &str = "var/lib/github-runner-name";
+
+/// Where an nspawn image may install `github-runner.service`
+const RUNNER_UNIT_DIRS: [&str; 3] =
+    ["etc/systemd/system", "usr/lib/systemd/system", "lib/systemd/system"];
+
+/// Runner diagnostic logs inside the container root (`$RUNNER_ROOT/_diag`)
+const RUNNER_DIAG_DIR: &str = "var/lib/github-runner/_diag";
+
+/// uid of the `github-runner` user jobs run as in the container template
+pub const RUNNER_UID: u32 = 1000;
+
+/// Who may read a file written into a container
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileMode {
+    /// Readable by everyone (0644): labels, runner name, pool environment
+    Public,
+    /// Readable by the runner user only (0600, owned by `RUNNER_UID`): the job
+    /// token and pool secrets workflows read
+    Runner,
+    /// Readable by root only (0600): the registration token and cache credentials
+    Root,
+}
+
+impl FileMode {
+    pub fn permissions(self) -> u32 {
+        match self {
+            Self::Public => 0o644,
+            Self::Runner | Self::Root => 0o600,
+        }
+    }
+
+    pub fn owner(self) -> Option<u32> {
+        match self {
+            Self::Runner => Some(RUNNER_UID),
+            Self::Public | Self::Root => None,
+        }
+    }
+}
+
+/// Write `contents` to `path` with `mode`, creating its directory. The mode is
+/// set before anything is written, and also on a file an earlier write left.
+fn write_file(path: &Path, contents: &str, mode: FileMode) -> Result<(), BackendError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(BackendError::io("create", parent))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode.permissions())
+        .open(path)
+        .map_err(BackendError::io("write", path))?;
+    file.set_permissions(std::fs::Permissions::from_mode(mode.permissions()))
+        .and_then(|()| match mode.owner() {
+            Some(uid) => std::os::unix::fs::fchown(&file, Some(uid), None),
+            None => Ok(()),
+        })
+        .and_then(|()| file.write_all(contents.as_bytes()))
+        .map_err(BackendError::io("write", path))
+}
+
+/// nixos-container binary and the default container configuration of the
+/// nixos backend
+pub const NIXOS_CONTAINER_BIN: &str = "/run/current-system/sw/bin/nixos-container";
+pub const DEFAULT_CONTAINER_TEMPLATE: &str = "/etc/nixos/ci-container-template.nix";
+
+/// zstd level for captured logs; runner journals compress ~10x at this level
+const LOG_ZSTD_LEVEL: i32 = 3;
+
+const NSPAWN_CONFIG_TEMPLATE: &str = r#"[Exec]
+SystemCallFilter=add_key keyctl bpf
+Capability=all
+
+[Files]
+Bind=/sys/fs/bpf
+BindReadOnly=/sys/module
+BindReadOnly=/lib/modules
+BindReadOnly=/run/secrets
+BindReadOnly=/run/agenix
+"#;
+
+/// Same as `NSPAWN_CONFIG_TEMPLATE` without the host secret mounts, for untrusted jobs
+const NSPAWN_ISOLATED_CONFIG_TEMPLATE: &str = r#"[Exec]
+SystemCallFilter=add_key keyctl bpf
+Capability=all
+
+[Files]
+Bind=/sys/fs/bpf
+BindReadOnly=/sys/module
+BindReadOnly=/lib/modules
+"#;
+
+/// nspawn settings for generic (non-NixOS) machines; networking comes from a
+/// veth pair that the host's systemd-networkd configures via DHCP
+const NSPAWN_MACHINE_TEMPLATE: &str = r#"[Exec]
+SystemCallFilter=add_key keyctl bpf
+Capability=all
+
+[Files]
+Bind=/sys/fs/bpf
+BindReadOnly=/sys/module
+BindReadOnly=/lib/modules
+
+[Network]
+VirtualEthernet=yes
+"#;
+
+/// Why a container backend operation failed. A tool that can't be run at all
+/// or a host path that can't be written means the host needs an operator; a
+/// command that ran and failed may succeed next time.
+#[derive(Debug, Error)]
+pub enum BackendError {
+    /// A management tool couldn't be started (missing binary, permissions)
+    #[error("failed to execute {program}: {source}")]
+    Exec {
+        program: String,
+        source: std::io::Error,
+    },
+    /// A management command ran and exited unsuccessfully
+    #[error("{command} failed: {stderr}")]
+    Command { command: String, stderr: String },
+    /// Preparing or preserving container files on the host failed
+    #[error("failed to {action} {path:?}: {source}")]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// Every host a container could be placed on is full
+    #[error("no host has capacity for {name}")]
+    NoCapacity { name: String },
+    /// An nspawn image can't run a runner: it has no `github-runner.service`
+    #[error("nspawn image {image} has no github-runner.service unit")]
+    MissingRunnerUnit { image: String },
+}
+
+impl BackendError {
+    /// `map_err` adapter for a failed filesystem operation on `path`
+    fn io(action: &'static str, path: impl AsRef<Path>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        move |source| Self::Io {
+            action,
+            path,
+            source,
+        }
+    }
+}
+
+/// Maps container names to the names runners register with on GitHub.
+///
+/// Built from a template such as `{hostname}-{container}`, so several controllers
+/// sharing a repository register distinct runner names and only touch their own.
+#[derive(Debug, Clone, Default)]
+pub struct RunnerNaming {
+    prefix: String,
+    suffix: String,
+    /// Label added to every runner this controller instance registers
+    instance_label: Option<String>,
+}
+
+impl RunnerNaming {
+    /// Parse a template; `{container}` is required, `{hostname}` is optional
+    pub fn new(template: &str, hostname: &str) -> Result<Self> {
+        let template = template.replace("{hostname}", hostname);
+        let Some((prefix, suffix)) = template.split_once("{container}") else {
+            anyhow::bail!("Runner name template must contain {{container}}: {}", template);
+        };
+
+        Ok(Self {
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+            instance_label: None,
+        })
+    }
+
+    /// GitHub runner name for a container
+    pub fn runner_name(&self, container: &str) -> String {
+        format!("{}{}{}", self.prefix, container, self.suffix)
+    }
+
+    /// What every runner name of this host starts with, e.g. `ci1-`
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Container name for a GitHub runner, if the runner follows this naming
+    pub fn container_name<'a>(&self, runner: &'a str) -> Option<&'a str> {
+        runner
+            .strip_prefix(self.prefix.as_str())?
+            .strip_suffix(self.suffix.as_str())
+    }
+
+    /// Tag runners with this controller instance's ID
+    pub fn with_instance(mut self, instance_id: &str) -> Self {
+        self.instance_label = Some(format!("rc-{}", instance_id));
+        self
+    }
+
+    pub fn instance_label(&self) -> Option<&str> {
+        self.instance_label.as_deref()
+    }
+
+    /// Whether a GitHub runner was registered by this controller instance:
+    /// it follows our naming and carries our instance label. Runners of other
+    /// controllers and manually added runners are never ours.
+    pub fn owns(&self, runner: &Runner) -> bool {
+        let named = self
+            .container_name(&runner.name)
+            .is_some_and(ContainerManager::is_runner_name);
+        let tagged = self
+            .instance_label
+            .as_deref()
+            .is_none_or(|label| runner.has_label(label));
+        named && tagged
+    }
+}
+
+/// Binary cache that trusted runner containers push their build results to
+#[derive(Debug, Clone, Serialize)]
+pub struct CachePush {
+    /// Store URI for `nix copy --to`, e.g. `s3://ci-cache?endpoint=minio:9000`
+    pub target: String,
+    /// Push command used instead of `nix copy`, e.g. `attic push ci`; the
+    /// built store paths are appended
+    pub command: Option<String>,
+    /// Nix secret key the paths are signed with before pushing
+    #[serde(skip)]
+    pub signing_key: Option<SecretString>,
+    /// `KEY=value` lines exported for the push (S3 or attic credentials)
+    #[serde(skip)]
+    pub credentials: Option<SecretString>,
+}
+
+impl CachePush {
+    /// Files to provision into a container for the post-build hook
+    pub fn files(&self) -> Vec<(&'static str, String, FileMode)> {
+        let env = format!(
+            "CACHE_PUSH_TARGET={}\nCACHE_PUSH_COMMAND={}\n",
+            shell_quote(&self.target),
+            shell_quote(self.command.as_deref().unwrap_or_default())
+        );
+        let mut files = vec![(CACHE_PUSH_ENV_PATH, env, FileMode::Public)];
+        // The post-build hook runs as root, in the nix daemon
+        if let Some(key) = &self.signing_key {
+            files.push((CACHE_SIGNING_KEY_PATH, key.expose().to_string(), FileMode::Root));
+        }
+        if let Some(credentials) = &self.credentials {
+            let credentials = credentials.expose().to_string();
+            files.push((CACHE_CREDENTIALS_PATH, credentials, FileMode::Root));
+        }
+        files
+    }
+}
+
+/// Environment variable for a pool's runner process, written `NAME=value`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+}
+
+impl EnvVar {
+    /// Refuse names the runner service couldn't export or uses itself, and
+    /// multi-line values
+    pub fn validate(&self) -> Result<()> {
+        let mut chars = self.name.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            anyhow::bail!("invalid environment variable name '{}'", self.name);
+        }
+        if RESERVED_ENV.contains(&self.name.as_str()) {
+            anyhow::bail!("{} is set by the runner service and can't be overridden", self.name);
+        }
+        if self.value.contains(['\n', '\0']) {
+            anyhow::bail!("value of {} contains a newline or NUL", self.name);
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for EnvVar {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((name, value)) = s.trim().split_once('=') else {
+            anyhow::bail!("invalid environment variable '{}', expected NAME=value", s);
+        };
+        let var = Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        var.validate()?;
+        Ok(var)
+    }
+}
+
+/// Refuse secret names that aren't plain file names in the secrets directory
+pub fn check_secret_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', ',', '\n']) {
+        anyhow::bail!("invalid secret name '{}', expected a file name", name);
+    }
+    Ok(())
+}
+
+/// Files provisioning a pool's environment into a container: the environment
+/// file the runner service sources and one file per secret, read from
+/// `secrets_dir` on the host. Secrets are re-read on every spawn, so rotated
+/// files reach the next container.
+pub fn runner_env_files(
+    env: &[EnvVar],
+    secrets: &[String],
+    secrets_dir: Option<&Path>,
+) -> Result<Vec<(String, String, FileMode)>> {
+    let mut files = Vec::new();
+    // Specs stored before a name was reserved aren't validated otherwise
+    let mut vars: Vec<String> = env
+        .iter()
+        .map(|var| {
+            var.validate()?;
+            Ok(format!("{}={}\n", var.name, shell_quote(&var.value)))
+        })
+        .collect::<Result<_>>()?;
+
+    if !secrets.is_empty() {
+        let Some(dir) = secrets_dir else {
+            anyhow::bail!("pool secrets need SECRETS_DIR");
+        };
+        for name in secrets {
+            check_secret_name(name)?;
+            let path = dir.join(name);
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read secret {}", path.display()))?;
+            let path = format!("{}/{}", RUNNER_SECRETS_DIR, name);
+            files.push((path, contents, FileMode::Runner));
+        }
+        vars.push(format!("RUNNER_SECRETS_DIR=/{}\n", RUNNER_SECRETS_DIR));
+    }
+
+    if !vars.is_empty() {
+        files.push((RUNNER_ENV_PATH.to_string(), vars.concat(), FileMode::Public));
+    }
+    Ok(files)
+}
+
+/// A host path bound into runner containers, written `source[:target][:ro|rw]`
+/// (read-only unless `rw`; the target defaults to the source)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BindMount {
+    pub source: String,
+    pub target: String,
+    #[serde(default = "default_read_only")]
+    pub read_only: bool,
+}
+
+fn default_read_only() -> bool {
+    true
+}
+
+impl BindMount {
+    pub fn read_only(path: &str) -> Self {
+        Self {
+            source: path.to_string(),
+            target: path.to_string(),
+            read_only: true,
+        }
+    }
+
+    /// Refuse binds whose source isn't under one of the `allowed` host
+    /// directories, so a pool change can't hand jobs `/` or `/etc`
+    pub fn check_allowed(&self, allowed: &[PathBuf]) -> Result<()> {
+        for path in [&self.source, &self.target] {
+            let path = Path::new(path);
+            if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+                anyhow::bail!("bind mount paths must be absolute without '..': {}", path.display());
+            }
+            if path.to_str().is_some_and(|p| p.contains([':', ',', '\n'])) {
+                anyhow::bail!("bind mount path contains ':', ',' or a newline: {}", path.display());
+            }
+        }
+        // Resolve symlinks, so a link under an allowed directory can't point
+        // the bind elsewhere
+        let source = std::fs::canonicalize(&self.source)
+            .with_context(|| format!("Failed to resolve bind mount source {}", self.source))?;
+        let allowed = allowed.iter().map(|dir| std::fs::canonicalize(dir).unwrap_or(dir.clone()));
+        if !allowed.into_iter().any(|dir| source.starts_with(dir)) {
+            anyhow::bail!("bind mount source {} is not in BIND_ALLOWLIST", self.source);
+        }
+        Ok(())
+    }
+
+    /// `Bind=`/`BindReadOnly=` line for an nspawn config
+    fn nspawn_line(&self) -> String {
+        let key = if self.read_only { "BindReadOnly" } else { "Bind" };
+        if self.source == self.target {
+            format!("{}={}\n", key, self.source)
+        } else {
+            format!("{}={}:{}\n", key, self.source, self.target)
+        }
+    }
+}
+
+impl FromStr for BindMount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = s.trim().split(':').collect();
+        let read_only = match parts.last().copied() {
+            Some("ro") if parts.len() > 1 => {
+                parts.pop();
+                true
+            }
+            Some("rw") if parts.len() > 1 => {
+                parts.pop();
+                false
+            }
+            _ => true,
+        };
+        let (source, target) = match parts.as_slice() {
+            [source] => (*source, *source),
+            [source, target] => (*source, *target),
+            _ => anyhow::bail!("invalid bind mount '{}', expected source[:target][:ro|rw]", s),
+        };
+        if source.is_empty() || target.is_empty() {
+            anyhow::bail!("invalid bind mount '{}', expected source[:target][:ro|rw]", s);
+        }
+        Ok(Self {
+            source: source.to_string(),
+            target: target.to_string(),
+            read_only,
+        })
+    }
+}
+
+/// Host GPUs handed to jobs that ask for one by label. Each GPU serves one
+/// container at a time.
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuConfig {
+    /// Runner label that requests a GPU
+    pub label: String,
+    /// Device nodes of each GPU, e.g. `/dev/nvidia0` or `/dev/dri/card1` and
+    /// `/dev/dri/renderD129`
+    pub devices: Vec<Vec<String>>,
+    /// Device nodes every GPU container needs besides its own (NVIDIA control
+    /// and UVM devices); missing ones are skipped
+    pub shared_devices: Vec<String>,
+    /// Host driver directories bound read-only, e.g. `/run/opengl-driver`;
+    /// missing ones are skipped
+    pub driver_paths: Vec<String>,
+}
+
+impl GpuConfig {
+    pub fn count(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Whether a runner with these labels gets a GPU
+    pub fn requested(&self, labels: &[String]) -> bool {
+        labels.iter().any(|l| l.eq_ignore_ascii_case(&self.label))
+    }
+
+    /// Device nodes to pass into a container holding GPU `index`
+    pub fn device_nodes(&self, index: usize) -> Vec<String> {
+        let shared = self
+            .shared_devices
+            .iter()
+            .filter(|path| Path::new(path).exists());
+        self.devices
+            .get(index)
+            .into_iter()
+            .flatten()
+            .chain(shared)
+            .cloned()
+            .collect()
+    }
+
+    /// Driver directories that exist on this host
+    pub fn driver_binds(&self) -> Vec<BindMount> {
+        self.driver_paths
+            .iter()
+            .filter(|path| Path::new(path).exists())
+            .map(|path| BindMount::read_only(path))
+            .collect()
+    }
+}
+
+/// Resource usage of a container's unit, as accounted by systemd
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct ResourceUsage {
+    pub memory_bytes: Option<u64>,
+    pub cpu_seconds: Option<f64>,
+    pub tasks: Option<u64>,
+}
+
+impl ResourceUsage {
+    /// Parse `systemctl show -p MemoryCurrent -p CPUUsageNSec -p TasksCurrent`
+    /// output; properties without accounting read `[not set]` or `u64::MAX`
+    fn parse(output: &str) -> Self {
+        let mut usage = Self::default();
+        for line in output.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().parse::<u64>().ok().filter(|&v| v != u64::MAX);
+            match key {
+                "MemoryCurrent" => usage.memory_bytes = value,
+                "CPUUsageNSec" => usage.cpu_seconds = value.map(|ns| ns as f64 / 1e9),
+                "TasksCurrent" => usage.tasks = value,
+                _ => {}
+            }
+        }
+        usage
+    }
+}
+
+/// Quote a value for a POSIX shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// What to put into a new runner container
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpawnOptions<'a> {
+    pub labels: &'a [String],
+    /// Extra (path, contents, mode) files written into the container root before start
+    pub files: &'a [(&'a str, &'a str, FileMode)],
+    /// Isolated containers get no host secret mounts
+    pub isolated: bool,
+    /// Container configuration (config path or flake reference) or nspawn
+    /// image, instead of the backend default
+    pub template: Option<&'a str>,
+    /// Host device nodes bound into the container and allowed by its unit
+    pub devices: &'a [String],
+    /// Extra host paths bound into the container
+    pub binds: &'a [BindMount],
+    pub egress: EgressMode,
+}
+
+/// The runner container operations the pool controller and the HTTP API
+/// depend on. `ContainerManager` implements it on the host with
+/// nixos-container or machinectl; `Fleet` spreads containers over remote
+/// hosts as well; `FakeBackend` keeps containers in memory for tests.
+#[async_trait]
+pub trait Backend: Send + Sync {
+    /// Backend name as reported by the API, e.g. `nixos`
+    fn name(&self) -> &'static str;
+    /// How runner names on GitHub relate to container names
+    fn naming(&self) -> &RunnerNaming;
+
+    /// GitHub runner name registered by a container
+    fn runner_name(&self, container: &str) -> String {
+        self.naming().runner_name(container)
+    }
+
+    /// Pool containers (`r` followed by digits)
+    async fn list(&self) -> Result<Vec<String>, BackendError>;
+    /// Containers spawned via the external scheduler API (`x` followed by digits)
+    async fn list_external(&self) -> Result<Vec<String>, BackendError>;
+    /// All runner containers, including old `j` style ones
+    async fn list_all(&self) -> Result<Vec<String>, BackendError>;
+
+    /// Create and start a runner container with the given name and options
+    async fn spawn_container(
+        &self,
+        name: &str,
+        token: &SecretString,
+        options: &SpawnOptions<'_>,
+    ) -> Result<(), BackendError>;
+
+    /// Create and start a container for a pool slot
+    async fn spawn_pool_container(
+        &self,
+        slot: usize,
+        token: &SecretString,
+        options: &SpawnOptions<'_>,
+    ) -> Result<String, BackendError> {
+        let name = ContainerManager::slot_to_container_name(slot);
+        self.spawn_container(&name, token, options).await?;
+        Ok(name)
+    }
+
+    /// Whether the container's runner has finished (or can't be reached)
+    async fn is_runner_completed(&self, name: &str) -> Result<bool, BackendError>;
+    /// Register a finished container's runner again with a fresh token
+    async fn reregister(&self, name: &str, token: &SecretString) -> Result<(), BackendError>;
+    /// Capture logs, stop and destroy a container and remove its artifacts
+    async fn cleanup_container(&self, name: &str) -> Result<(), BackendError>;
+    /// Remove a container but preserve its root filesystem, returning where
+    async fn quarantine(&self, name: &str) -> Result<PathBuf, BackendError>;
+
+    /// Save the runner journal so it survives container destruction
+    async fn capture_logs(&self, name: &str) -> Result<(), BackendError>;
+    async fn resource_usage(&self, name: &str) -> Result<ResourceUsage, BackendError>;
+    /// Last `tail` lines of the runner journal of a live container
+    async fn runner_logs(&self, name: &str, tail: usize) -> Result<String, BackendError>;
+    /// Follow the runner journal of a live container; killed when dropped
+    fn follow_runner_logs(&self, name: &str, tail: usize) -> Result<Child, BackendError>;
+
+    /// Write a file into a container's root filesystem (path relative to the root)
+    fn write_container_file(
+        &self,
+        name: &str,
+        path: &str,
+        contents: &str,
+        mode: FileMode,
+    ) -> Result<(), BackendError>;
+    fn read_container_file(&self, name: &str, path: &str) -> Option<String>;
+    /// Newest runner worker diagnostic log, if the runner ran a job
+    fn read_worker_log(&self, name: &str) -> Option<String>;
+    /// Newest (zstd-compressed) captured runner log of a container
+    fn captured_logs_path(&self, name: &str) -> Option<PathBuf>;
+    /// Decompressed newest captured runner log
+    fn read_captured_logs(&self, name: &str) -> Option<String>;
+}
+
+pub struct ContainerManager {
+    nixos_container_bin: PathBuf,
+    machinectl_bin: PathBuf,
+    container_template: PathBuf,
+    state_dir: PathBuf,
+    backend: ContainerBackend,
+    naming: RunnerNaming,
+    /// Size of the throwaway tmpfs each container's writable root lives on;
+    /// `None` keeps roots on disk
+    root_tmpfs: Option<String>,
+    /// Hosts and networks containers in `EgressMode::Allowlist` may reach
+    egress_allowlist: Vec<String>,
+    /// Held while a nixos container picks its subnet and is created, so
+    /// concurrent spawns don't pick the same one
+    create_lock: tokio::sync::Mutex<()>,
+}
+
+impl ContainerManager {
+    pub fn new(state_dir: PathBuf, backend: ContainerBackend, naming: RunnerNaming) -> Self {
+        Self {
+            nixos_container_bin: PathBuf::from(NIXOS_CONTAINER_BIN),
+            machinectl_bin: PathBuf::from("machinectl"),
+            container_template: PathBuf::from(DEFAULT_CONTAINER_TEMPLATE),
+            state_dir,
+            backend,
+            naming,
+            root_tmpfs: None,
+            egress_allowlist: Vec::new(),
+            create_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn with_egress_allowlist(mut self, allowlist: Vec<String>) -> Self {
+        self.egress_allowlist = allowlist;
+        self
+    }
+
+    /// Keep each container's writable root on a tmpfs of `size` (e.g. `8G`)
+    pub fn with_root_tmpfs(mut self, size: Option<String>) -> Self {
+        self.root_tmpfs = size;
+        self
+    }
+
+    pub fn backend(&self) -> &ContainerBackend {
+        &self.backend
+    }
+
+    /// How runner names on GitHub relate to container names
+    pub fn naming(&self) -> &RunnerNaming {
+        &self.naming
+    }
+
+    /// GitHub runner name registered by a container
+    pub fn runner_name(&self, container: &str) -> String {
+        self.naming.runner_name(container)
+    }
+
+    /// Run a management command (nixos-container or machinectl) and return output
+    async fn run_tool(&self, program: &Path, args: &[&str]) -> Result<String, BackendError> {
+        let output = Command::new(program)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|source| BackendError::Exec {
+                program: program.display().to_string(),
+                source,
+            })?;
+
+        if !output.status.success() {
+            return Err(BackendError::Command {
+                command: format!("{} {}", program.display(), args.join(" ")),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Run nixos-container command and return output
+    async fn run_container_cmd(&self, args: &[&str]) -> Result<String, BackendError> {
+        self.run_tool(&self.nixos_container_bin, args).await
+    }
+
+    /// Run machinectl command and return output
+    async fn run_machinectl(&self, args: &[&str]) -> Result<String, BackendError> {
+        self.run_tool(&self.machinectl_bin, args).await
+    }
+
+    /// Build a command that runs `cmd` inside a container
+    fn exec_command(&self, name: &str, cmd: &[&str]) -> Command {
+        let mut command = match self.backend {
+            ContainerBackend::Nixos => {
+                let mut c = Command::new(&self.nixos_container_bin);
+                c.args(["run", name, "--"]);
+                c
+            }
+            ContainerBackend::Nspawn { .. } => {
+                let mut c = Command::new("systemd-run");
+                c.arg(format!("--machine={}", name))
+                    .args(["--quiet", "--wait", "--pipe", "--"]);
+                c
+            }
+        };
+        command.args(cmd);
+        command
+    }
+
+    /// Host directory holding container root filesystems
+    fn containers_dir(&self) -> &'static Path {
+        self.backend.containers_dir()
+    }
+
+    /// Root filesystem of a container on the host
+    fn container_root(&self, name: &str) -> PathBuf {
+        self.containers_dir().join(name)
+    }
+
+    /// Run a command inside a container
+    async fn run_in_container(&self, name: &str, cmd: &[&str]) -> Result<String, BackendError> {
+        let output = self
+            .exec_command(name, cmd)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|source| BackendError::Exec {
+                program: format!("{:?} in {}", cmd.join(" "), name),
+                source,
+            })?;
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Check if container can be reached
+    async fn container_is_reachable(&self, name: &str) -> bool {
+        let result = self
+            .exec_command(name, &["true"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+
+        matches!(result, Ok(status) if status.success())
+    }
+
+    /// List containers whose name is one of `prefixes` followed by digits
+    async fn list_with_prefixes(&self, prefixes: &[char]) -> Result<Vec<String>, BackendError> {
+        let output = match self.backend {
+            ContainerBackend::Nixos => self.run_container_cmd(&["list"]).await?,
+            // Images rather than running machines, so stopped containers are still found
+            ContainerBackend::Nspawn { .. } => {
+                self.run_machinectl(&["list-images", "--no-legend"]).await?
+            }
+        };
+
+        let containers: Vec<String> = output
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(|s| s.to_string())
+            .filter(|name| name_has_prefix(name, prefixes))
+            .collect();
+
+        Ok(containers)
+    }
+
+    /// Whether `name` looks like a container managed by this controller
+    pub fn is_runner_name(name: &str) -> bool {
+        name_has_prefix(name, &['r', 'x', 'j'])
+    }
+
+    /// List all pool containers (names starting with 'r' followed by digits)
+    pub async fn list(&self) -> Result<Vec<String>, BackendError> {
+        self.list_with_prefixes(&['r']).await
+    }
+
+    /// List containers spawned via the external scheduler API (x* style)
+    pub async fn list_external(&self) -> Result<Vec<String>, BackendError> {
+        self.list_with_prefixes(&['x']).await
+    }
+
+    /// List all runner containers (pool r*, external x*, and old j* style for migration)
+    pub async fn list_all(&self) -> Result<Vec<String>, BackendError> {
+        self.list_with_prefixes(&['r', 'x', 'j']).await
+    }
+
+    /// Get a free subnet octet in the 100-199 range
+    pub async fn get_free_subnet(&self) -> Result<u8, BackendError> {
+        let containers = self.list_all().await?;
+        let mut used_subnets = HashSet::new();
+
+        for container in containers {
+            if let Ok(ip) = self.run_container_cmd(&["show-ip", &container]).await {
+                used_subnets.extend(subnet_octet(&ip));
+            }
+        }
+
+        Ok(free_subnet(&used_subnets))
+    }
+
+    /// Convert pool slot index to container name (r + slot number)
+    pub fn slot_to_container_name(slot: usize) -> String {
+        format!("r{}", slot)
+    }
+
+    /// Preferred external container name for a job (x + last 7 digits); the
+    /// controller picks the next free one if another job's container has it.
+    /// nixos-container limits names to 11 characters.
+    pub fn job_to_container_name(job_id: u64) -> String {
+        format!("x{:07}", job_id % 10_000_000)
+    }
+
+    /// Write nspawn configuration for Docker support, plus any device and
+    /// extra binds
+    fn write_nspawn_config(
+        &self,
+        name: &str,
+        options: &SpawnOptions<'_>,
+    ) -> Result<(), BackendError> {
+        let nspawn_dir = Path::new("/etc/systemd/nspawn");
+        std::fs::create_dir_all(nspawn_dir).map_err(BackendError::io("create", nspawn_dir))?;
+
+        let template = match self.backend {
+            ContainerBackend::Nixos if options.isolated => NSPAWN_ISOLATED_CONFIG_TEMPLATE,
+            ContainerBackend::Nixos => NSPAWN_CONFIG_TEMPLATE,
+            ContainerBackend::Nspawn { .. } => NSPAWN_MACHINE_TEMPLATE,
+        };
+
+        // nspawn images are cloned by machinectl, so their root can't be
+        // mounted beforehand; a tmpfs overlay on top of the clone takes the writes
+        let volatile =
+            self.root_tmpfs.is_some() && matches!(self.backend, ContainerBackend::Nspawn { .. });
+        let config = nspawn_config(template, volatile, options.devices, options.binds);
+        let config_path = nspawn_dir.join(format!("{}.nspawn", name));
+        std::fs::write(&config_path, config).map_err(BackendError::io("write", &config_path))?;
+
+        Ok(())
+    }
+
+    /// Mount a fresh tmpfs as the container's root before it is created
+    /// (nixos backend), so nothing a job writes ever reaches the disk
+    async fn mount_root_tmpfs(&self, name: &str) -> Result<(), BackendError> {
+        let Some(args) = self.root_tmpfs_mount_args(name) else {
+            return Ok(());
+        };
+        let root = self.container_root(name);
+        std::fs::create_dir_all(&root).map_err(BackendError::io("create", &root))?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.run_tool(Path::new("mount"), &args).await?;
+        Ok(())
+    }
+
+    /// `mount` arguments for a container's tmpfs root; `None` without one or
+    /// with the nspawn backend, whose roots get an overlay instead
+    fn root_tmpfs_mount_args(&self, name: &str) -> Option<Vec<String>> {
+        let (Some(size), ContainerBackend::Nixos) = (&self.root_tmpfs, &self.backend) else {
+            return None;
+        };
+        let root = self.container_root(name).to_string_lossy().to_string();
+        let options = format!("size={},mode=0700", size);
+        Some(["-t", "tmpfs", "-o", &options, "tmpfs", &root].map(str::to_string).to_vec())
+    }
+
+    /// Drop a container's tmpfs root; removing the root directory afterwards
+    /// is instant. Not being mounted is fine.
+    async fn unmount_root_tmpfs(&self, name: &str) {
+        if self.root_tmpfs.is_none() || !matches!(self.backend, ContainerBackend::Nixos) {
+            return;
+        }
+        let _ = Command::new("umount")
+            .arg(self.container_root(name))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+    }
+
+    /// Drop-in directory for the systemd unit running a container
+    fn unit_dropin_dir(&self, name: &str) -> PathBuf {
+        PathBuf::from(format!("/run/systemd/system/{}.d", self.unit_name(name)))
+    }
+
+    /// Host systemd unit running a container
+    fn unit_name(&self, name: &str) -> String {
+        match self.backend {
+            ContainerBackend::Nixos => format!("container@{}.service", name),
+            ContainerBackend::Nspawn { .. } => format!("systemd-nspawn@{}.service", name),
+        }
+    }
+
+    /// Current memory, CPU and task usage of a container
+    pub async fn resource_usage(&self, name: &str) -> Result<ResourceUsage, BackendError> {
+        let unit = self.unit_name(name);
+        let output = self
+            .run_tool(
+                Path::new("systemctl"),
+                &["show", &unit, "-p", "MemoryCurrent", "-p", "CPUUsageNSec", "-p", "TasksCurrent"],
+            )
+            .await?;
+        Ok(ResourceUsage::parse(&output))
+    }
+
+    /// Allow the container's unit to access `devices` (the unit's device
+    /// policy blocks bound device nodes otherwise). Replaces the drop-in of a
+    /// previous container with this name and reloads systemd if anything changed.
+    async fn write_device_policy(
+        &self,
+        name: &str,
+        devices: &[String],
+    ) -> Result<(), BackendError> {
+        let dir = self.unit_dropin_dir(name);
+        let existed = dir.exists();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        if !devices.is_empty() {
+            std::fs::create_dir_all(&dir).map_err(BackendError::io("create", &dir))?;
+            let allow: String = devices
+                .iter()
+                .map(|device| format!("DeviceAllow={} rwm\n", device))
+                .collect();
+            let path = dir.join("50-devices.conf");
+            std::fs::write(&path, format!("[Service]\n{}", allow))
+                .map_err(BackendError::io("write", &path))?;
+        }
+        if existed || !devices.is_empty() {
+            self.run_tool(Path::new("systemctl"), &["daemon-reload"]).await?;
+        }
+        Ok(())
+    }
+
+    /// Create and start a runner container with the given name and options
+    pub async fn spawn_container(
+        &self,
+        name: &str,
+        token: &SecretString,
+        options: &SpawnOptions<'_>,
+    ) -> Result<(), BackendError> {
+        let name = name.to_string();
+        let SpawnOptions {
+            labels,
+            files,
+            isolated,
+            template,
+            devices,
+            egress,
+            ..
+        } = *options;
+
+        info!(
+            name = %name,
+            backend = ?self.backend,
+            labels = ?labels,
+            isolated,
+            template = ?template,
+            devices = ?devices,
+            egress = ?egress,
+            "Spawning container"
+        );
+
+        // Clean up any existing container with same name
+        if self.list_all().await?.contains(&name) {
+            warn!(name = %name, "Cleaning up existing container first");
+            self.cleanup_container(&name).await?;
+        }
+
+        // Clean up leftover artifacts
+        self.cleanup_artifacts(&name).await;
+
+        // Write nspawn config for Docker support
+        self.write_nspawn_config(&name, options)?;
+        self.write_device_policy(&name, devices).await?;
+
+        if let Err(e) = self.mount_root_tmpfs(&name).await {
+            self.cleanup_artifacts(&name).await;
+            return Err(e);
+        }
+
+        if let Err(e) = self.create(&name, template).await {
+            // Cleanup on failure
+            self.cleanup_artifacts(&name).await;
+            return Err(e);
+        }
+
+        // Write token into container filesystem before starting
+        let container_root = self.container_root(&name);
+        self.write_runner_token(&name, token)?;
+
+        // Write runner labels next to the token (read by the runner service on configure)
+        let mut labels = labels.to_vec();
+        labels.extend(self.naming.instance_label().map(str::to_string));
+        let labels_path = container_root.join(RUNNER_LABELS_PATH);
+        std::fs::write(&labels_path, labels.join(","))
+            .map_err(BackendError::io("write", &labels_path))?;
+
+        let name_path = container_root.join(RUNNER_NAME_PATH);
+        std::fs::write(&name_path, self.runner_name(&name))
+            .map_err(BackendError::io("write", &name_path))?;
+
+        for &(path, contents, mode) in files {
+            self.write_container_file(&name, path, contents, mode)?;
+        }
+
+        if let Err(e) = egress::apply(&name, egress, &self.egress_allowlist).await {
+            warn!(name = %name, error = %e, "Failed to apply egress policy, cleaning up");
+            self.cleanup_container(&name).await?;
+            return Err(e);
+        }
+
+        // Start container
+        let start_result = match self.backend {
+            ContainerBackend::Nixos => self.run_container_cmd(&["start", &name]).await,
+            ContainerBackend::Nspawn { .. } => self.run_machinectl(&["start", &name]).await,
+        };
+        if let Err(e) = start_result {
+            warn!(name = %name, error = %e, "Failed to start container, cleaning up");
+            self.cleanup_container(&name).await?;
+            return Err(e);
+        }
+
+        info!(name = %name, "Container started");
+        Ok(())
+    }
+
+    /// Create the container filesystem (not yet started). `template` overrides
+    /// the default container configuration (nixos) or image (nspawn).
+    async fn create(&self, name: &str, template: Option<&str>) -> Result<(), BackendError> {
+        match &self.backend {
+            ContainerBackend::Nixos => {
+                let _guard = self.create_lock.lock().await;
+                let subnet = self.get_free_subnet().await?;
+                let local_addr = format!("192.168.{}.11", subnet);
+                let host_addr = format!("192.168.{}.10", subnet);
+
+                // `path#output` is a flake reference, anything else a config file
+                let template = template.unwrap_or(self.container_template.to_str().unwrap());
+                let source = if template.contains('#') {
+                    "--flake"
+                } else {
+                    "--config-file"
+                };
+
+                self.run_container_cmd(&[
+                    "create",
+                    name,
+                    source,
+                    template,
+                    "--local-address",
+                    &local_addr,
+                    "--host-address",
+                    &host_addr,
+                ])
+                .await?;
+            }
+            ContainerBackend::Nspawn { image } => {
+                let image = template.unwrap_or(image);
+                self.run_machinectl(&nspawn_create_args(image, name)).await?;
+
+                // Only now is a tarball's content known
+                let root = self.container_root(name);
+                if root.is_dir() && !has_runner_unit(&root) {
+                    let _ = self.run_machinectl(&["remove", name]).await;
+                    return Err(BackendError::MissingRunnerUnit {
+                        image: image.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if the github-runner service inside container has completed
+    pub async fn is_runner_completed(&self, name: &str) -> Result<bool, BackendError> {
+        // First check if container is reachable
+        if !self.container_is_reachable(name).await {
+            debug!(name = %name, "Container not reachable, considering completed");
+            return Ok(true);
+        }
+
+        // Check github-runner service status
+        let status = self
+            .run_in_container(name, &["systemctl", "is-active", "github-runner.service"])
+            .await
+            .unwrap_or_else(|_| "unknown".to_string());
+
+        let status = status.trim();
+
+        // Whether an inactive service ever ran
+        let result = match status {
+            "inactive" => self
+                .run_in_container(
+                    name,
+                    &["systemctl", "show", "github-runner.service", "--property=Result"],
+                )
+                .await
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        let completed = runner_service_completed(status, &result);
+        debug!(name = %name, status, completed, "Runner service status");
+        Ok(completed)
+    }
+
+    /// Register a finished container's runner again with a fresh token, so
+    /// the container serves another job. The runner service wipes its state
+    /// and work directories on start; caches elsewhere survive.
+    pub async fn reregister(&self, name: &str, token: &SecretString) -> Result<(), BackendError> {
+        self.write_runner_token(name, token)?;
+        self.run_in_container(name, &["systemctl", "restart", "github-runner.service"])
+            .await?;
+        Ok(())
+    }
+
+    /// Stop a container
+    pub async fn stop(&self, name: &str) -> Result<(), BackendError> {
+        debug!(name = %name, "Stopping container");
+
+        match self.backend {
+            ContainerBackend::Nixos => {
+                // Stop systemd service first
+                let _ = Command::new("systemctl")
+                    .args(["stop", &format!("container@{}.service", name)])
+                    .status()
+                    .await;
+
+                // Then nixos-container stop
+                let _ = self.run_container_cmd(&["stop", name]).await;
+            }
+            ContainerBackend::Nspawn { .. } => {
+                let _ = self.run_machinectl(&["terminate", name]).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Destroy a container
+    pub async fn destroy(&self, name: &str) -> Result<(), BackendError> {
+        debug!(name = %name, "Destroying container");
+        self.unmount_root_tmpfs(name).await;
+        let _ = match self.backend {
+            ContainerBackend::Nixos => self.run_container_cmd(&["destroy", name]).await,
+            ContainerBackend::Nspawn { .. } => self.run_machinectl(&["remove", name]).await,
+        };
+        Ok(())
+    }
+
+    /// Clean up leftover artifacts (network interface, profiles, etc.)
+    async fn cleanup_artifacts(&self, name: &str) {
+        // Remove nspawn config
+        let nspawn_config = PathBuf::from(format!("/etc/systemd/nspawn/{}.nspawn", name));
+        let _ = std::fs::remove_file(&nspawn_config);
+
+        // Remove container profiles
+        let profile_dir = PathBuf::from(format!("/nix/var/nix/profiles/per-container/{}", name));
+        let _ = std::fs::remove_dir_all(&profile_dir);
+
+        // Remove container root
+        self.unmount_root_tmpfs(name).await;
+        let _ = std::fs::remove_dir_all(self.container_root(name));
+
+        // Remove network interface (shell out to ip)
+        let _ = Command::new("ip")
+            .args(["link", "delete", &format!("ve-{}", name)])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+
+        egress::remove(name).await;
+
+        // Registration tokens used to be staged in the state directory
+        let _ = std::fs::remove_file(self.state_dir.join(format!("{}.token", name)));
+    }
+
+    /// Write the registration token into a container, readable by root only:
+    /// the runner service loads it as a systemd credential, so it never
+    /// shows up in a process listing or in the runner user's reach
+    fn write_runner_token(&self, name: &str, token: &SecretString) -> Result<(), BackendError> {
+        let path = self.container_root(name).join(RUNNER_TOKEN_PATH);
+        write_file(&path, token.expose(), FileMode::Root)
+    }
+
+    /// Write a file into a container's root filesystem (path relative to the root)
+    pub fn write_container_file(
+        &self,
+        name: &str,
+        path: &str,
+        contents: &str,
+        mode: FileMode,
+    ) -> Result<(), BackendError> {
+        write_file(&self.container_root(name).join(path), contents, mode)
+    }
+
+    /// Read a file from a container's root filesystem, if present
+    pub fn read_container_file(&self, name: &str, path: &str) -> Option<String> {
+        let full_path = self.container_root(name).join(path);
+        std::fs::read_to_string(full_path).ok()
+    }
+
+    /// Contents of the newest runner worker diagnostic log, if the runner ran a job
+    pub fn read_worker_log(&self, name: &str) -> Option<String> {
+        let dir = self.container_root(name).join(RUNNER_DIAG_DIR);
+        // File names embed a UTC timestamp, so the newest sorts last
+        let newest = std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("Worker_") && n.ends_with(".log"))
+            })
+            .max()?;
+
+        std::fs::read_to_string(newest).ok()
+    }
+
+    /// Directory runner logs are captured to when containers are cleaned up
+    pub fn captured_logs_dir(&self) -> PathBuf {
+        captured_logs_dir(&self.state_dir)
+    }
+
+    /// Path of the newest (zstd-compressed) runner log captured from a container
+    pub fn captured_logs_path(&self, name: &str) -> Option<PathBuf> {
+        newest_captured_log(&self.captured_logs_dir(), name)
+    }
+
+    /// Decompressed captured runner log, including uncompressed logs from older versions
+    pub fn read_captured_logs(&self, name: &str) -> Option<String> {
+        match self.captured_logs_path(name).map(std::fs::read) {
+            Some(Ok(compressed)) => zstd::decode_all(compressed.as_slice())
+                .ok()
+                .map(|b| String::from_utf8_lossy(&b).to_string()),
+            _ => {
+                let legacy = self.captured_logs_dir().join(format!("{}.log", name));
+                std::fs::read_to_string(legacy).ok()
+            }
+        }
+    }
+
+    /// Fetch the last `tail` lines of the runner journal from a live container
+    pub async fn runner_logs(&self, name: &str, tail: usize) -> Result<String, BackendError> {
+        let tail = tail.to_string();
+        let output = self
+            .exec_command(
+                name,
+                &[
+                    "journalctl",
+                    "-u",
+                    "github-runner.service",
+                    "--no-pager",
+                    "-o",
+                    "short-iso",
+                    "-n",
+                    &tail,
+                ],
+            )
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|source| BackendError::Exec {
+                program: format!("journalctl in {}", name),
+                source,
+            })?;
+
+        if !output.status.success() {
+            return Err(BackendError::Command {
+                command: format!("journalctl in {}", name),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Start following the runner journal of a live container.
+    /// The returned child is killed when dropped.
+    pub fn follow_runner_logs(&self, name: &str, tail: usize) -> Result<Child, BackendError> {
+        self.exec_command(name, &["journalctl", "-u", "github-runner.service"])
+            .args(["--no-pager", "-o", "short-iso", "-f", "-n", &tail.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|source| BackendError::Exec {
+                program: format!("journalctl -f in {}", name),
+                source,
+            })
+    }
+
+    /// Save the runner journal to the state dir so it survives container destruction
+    pub async fn capture_logs(&self, name: &str) -> Result<(), BackendError> {
+        if !self.container_is_reachable(name).await {
+            return Ok(());
+        }
+
+        let logs = self
+            .run_in_container(
+                name,
+                &["journalctl", "-u", "github-runner.service", "--no-pager", "-o", "short-iso"],
+            )
+            .await?;
+
+        store_captured_logs(&self.captured_logs_dir(), name, unix_now(), &logs)?;
+        debug!(name = %name, "Captured runner logs");
+        Ok(())
+    }
+
+    /// Directory holding preserved root filesystems of quarantined containers.
+    /// Lives next to the container roots so moving a root is a cheap rename.
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.containers_dir().join(".quarantine")
+    }
+
+    /// Quarantine a container for incident response: cut its network, stop the
+    /// runner, and preserve its root filesystem (plus runner logs) instead of
+    /// deleting it. The container itself is removed so its name can be reused.
+    /// Returns where the root filesystem was moved to.
+    pub async fn quarantine(&self, name: &str) -> Result<PathBuf, BackendError> {
+        warn!(name = %name, "Quarantining container");
+
+        // Cut networking first so nothing else leaves the container
+        let link = format!("ve-{}", name);
+        match Command::new("ip")
+            .args(["link", "set", &link, "down"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+        {
+            Ok(status) if status.success() => debug!(name = %name, "Network cut"),
+            _ => warn!(name = %name, link = %link, "Failed to bring down container network"),
+        }
+
+        let _ = self
+            .run_in_container(name, &["systemctl", "stop", "github-runner.service"])
+            .await;
+
+        if let Err(e) = self.capture_logs(name).await {
+            warn!(name = %name, error = %e, "Failed to capture runner logs");
+        }
+
+        self.stop(name).await?;
+
+        let target = self
+            .quarantine_dir()
+            .join(format!("{}-{}", name, unix_now()));
+        let dir = self.quarantine_dir();
+        std::fs::create_dir_all(&dir).map_err(BackendError::io("create", &dir))?;
+        std::fs::rename(self.container_root(name), &target)
+            .map_err(BackendError::io("preserve container root at", &target))?;
+
+        if let Some(logs) = self.captured_logs_path(name) {
+            let _ = std::fs::copy(&logs, target.with_extension("log.zst"));
+        }
+
+        self.destroy(name).await?;
+        self.cleanup_artifacts(name).await;
+
+        warn!(name = %name, path = ?target, "Container quarantined");
+        Ok(target)
+    }
+
+    /// Full cleanup of a container
+    pub async fn cleanup_container(&self, name: &str) -> Result<(), BackendError> {
+        info!(name = %name, "Cleaning up container");
+
+        if let Err(e) = self.capture_logs(name).await {
+            warn!(name = %name, error = %e, "Failed to capture runner logs");
+        }
+
+        self.stop(name).await?;
+        self.destroy(name).await?;
+        self.cleanup_artifacts(name).await;
+        if let Err(e) = self.write_device_policy(name, &[]).await {
+            warn!(name = %name, error = %e, "Failed to remove device policy");
+        }
+
+        info!(name = %name, "Container cleaned up");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for ContainerManager {
+    fn name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    fn naming(&self) -> &RunnerNaming {
+        ContainerManager::naming(self)
+    }
+
+    async fn list(&self) -> Result<Vec<String>, BackendError> {
+        ContainerManager::list(self).await
+    }
+
+    async fn list_external(&self) -> Result<Vec<String>, BackendError> {
+        ContainerManager::list_external(self).await
+    }
+
+    async fn list_all(&self) -> Result<Vec<String>, BackendError> {
+        ContainerManager::list_all(self).await
+    }
+
+    async fn spawn_container(
+        &self,
+        name: &str,
+        token: &SecretString,
+        options: &SpawnOptions<'_>,
+    ) -> Result<(), BackendError> {
+        ContainerManager::spawn_container(self, name, token, options).await
+    }
+
+    async fn is_runner_completed(&self, name: &str) -> Result<bool, BackendError> {
+        ContainerManager::is_runner_completed(self, name).await
+    }
+
+    async fn reregister(&self, name: &str, token: &SecretString) -> Result<(), BackendError> {
+        ContainerManager::reregister(self, name, token).await
+    }
+
+    async fn cleanup_container(&self, name: &str) -> Result<(), BackendError> {
+        ContainerManager::cleanup_container(self, name).await
+    }
+
+    async fn quarantine(&self, name: &str) -> Result<PathBuf, BackendError> {
+        ContainerManager::quarantine(self, name).await
+    }
+
+    async fn capture_logs(&self, name: &str) -> Result<(), BackendError> {
+        ContainerManager::capture_logs(self, name).await
+    }
+
+    async fn resource_usage(&self, name: &str) -> Result<ResourceUsage, BackendError> {
+        ContainerManager::resource_usage(self, name).await
+    }
+
+    async fn runner_logs(&self, name: &str, tail: usize) -> Result<String, BackendError> {
+        ContainerManager::runner_logs(self, name, tail).await
+    }
+
+    fn follow_runner_logs(&self, name: &str, tail: usize) -> Result<Child, BackendError> {
+        ContainerManager::follow_runner_logs(self, name, tail)
+    }
+
+    fn write_container_file(
+        &self,
+        name: &str,
+        path: &str,
+        contents: &str,
+        mode: FileMode,
+    ) -> Result<(), BackendError> {
+        ContainerManager::write_container_file(self, name, path, contents, mode)
+    }
+
+    fn read_container_file(&self, name: &str, path: &str) -> Option<String> {
+        ContainerManager::read_container_file(self, name, path)
+    }
+
+    fn read_worker_log(&self, name: &str) -> Option<String> {
+        ContainerManager::read_worker_log(self, name)
+    }
+
+    fn captured_logs_path(&self, name: &str) -> Option<PathBuf> {
+        ContainerManager::captured_logs_path(self, name)
+    }
+
+    fn read_captured_logs(&self, name: &str) -> Option<String> {
+        ContainerManager::read_captured_logs(self, name)
+    }
+}
+
+/// machinectl arguments creating machine `name` from `image`: tarballs and OS
+/// trees are imported; anything else names an existing image, which machinectl
+/// clones (cheap on btrfs)
+fn nspawn_create_args<'a>(image: &'a str, name: &'a str) -> [&'a str; 3] {
+    let path = Path::new(image);
+    if path.is_file() {
+        ["import-tar", image, name]
+    } else if path.is_dir() {
+        ["import-fs", image, name]
+    } else {
+        ["clone", image, name]
+    }
+}
+
+/// Whether the OS tree at `root` installs `github-runner.service`
+fn has_runner_unit(root: &Path) -> bool {
+    RUNNER_UNIT_DIRS
+        .iter()
+        .any(|dir| root.join(dir).join("github-runner.service").exists())
+}
+
+/// Refuse an nspawn image that can't run a runner, before any container is
+/// created from it. OS trees and images in `machines_dir` are checked; a
+/// tarball's content is only known once imported, and checked then.
+pub fn check_nspawn_image(image: &str, machines_dir: &Path) -> Result<(), BackendError> {
+    let path = Path::new(image);
+    let root = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        machines_dir.join(image)
+    };
+    if root.is_dir() && !has_runner_unit(&root) {
+        return Err(BackendError::MissingRunnerUnit {
+            image: image.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// nspawn settings from a template, with a tmpfs overlay root if `volatile`,
+/// and `Bind=` lines for device nodes and extra binds appended to its `[Files]`
+/// section
+fn nspawn_config(
+    template: &str,
+    volatile: bool,
+    devices: &[String],
+    binds: &[BindMount],
+) -> String {
+    let (files, rest) = match template.find("\n[Network]") {
+        Some(at) => template.split_at(at),
+        None => (template, ""),
+    };
+    let mut config = if volatile {
+        files.replacen("[Exec]\n", "[Exec]\nVolatile=overlay\n", 1)
+    } else {
+        files.to_string()
+    };
+    for path in devices {
+        config.push_str(&format!("Bind={}\n", path));
+    }
+    for bind in binds {
+        config.push_str(&bind.nspawn_line());
+    }
+    config.push_str(rest);
+    config
+}
+
+/// Subnet octet of a container address like "192.168.150.11" (150)
+fn subnet_octet(ip: &str) -> Option<u8> {
+    ip.trim().split('.').nth(2)?.parse().ok()
+}
+
+/// First octet in the 100-199 range no container uses
+fn free_subnet(used: &HashSet<u8>) -> u8 {
+    // Fallback - shouldn't happen with max 7 concurrent
+    (100..=199).find(|octet| !used.contains(octet)).unwrap_or(100)
+}
+
+/// Whether the runner service finished, from `systemctl is-active` and, for
+/// an inactive service, the `Result=` property telling whether it ever ran
+fn runner_service_completed(status: &str, result: &str) -> bool {
+    match status {
+        "failed" => true,
+        "inactive" => result.contains("success") || result.contains("exit-code"),
+        _ => false,
+    }
+}
+
+/// Directory of captured runner logs in a state directory
+pub fn captured_logs_dir(state_dir: &Path) -> PathBuf {
+    state_dir.join("logs")
+}
+
+/// Newest log captured from container `name` in `dir`. Each capture is kept as
+/// `<name>.<captured at>.log.zst`, so a pool slot's next job doesn't overwrite
+/// the previous one's; older versions wrote `<name>.log.zst`.
+fn newest_captured_log(dir: &Path, name: &str) -> Option<PathBuf> {
+    let prefix = format!("{}.", name);
+    let newest = std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name().into_string().ok()?;
+            let at: u64 = file.strip_prefix(&prefix)?.strip_suffix(".log.zst")?.parse().ok()?;
+            Some((at, entry.path()))
+        })
+        .max_by_key(|(at, _)| *at)
+        .map(|(_, path)| path);
+    newest.or_else(|| Some(dir.join(format!("{}.log.zst", name))).filter(|p| p.exists()))
+}
+
+/// Delete captured logs written more than `retention` ago, returning how many
+pub fn prune_captured_logs(dir: &Path, retention: Duration) -> std::io::Result<usize> {
+    let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+        return Ok(0);
+    };
+    let mut pruned = 0;
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| modified < cutoff);
+        if expired && std::fs::remove_file(entry.path()).is_ok() {
+            pruned += 1;
+        }
+    }
+    Ok(pruned)
+}
+
+/// Compress a runner journal into `dir` as the newest capture of `name`
+fn store_captured_logs(
+    dir: &Path,
+    name: &str,
+    captured_at: u64,
+    logs: &str,
+) -> Result<(), BackendError> {
+    std::fs::create_dir_all(dir).map_err(BackendError::io("create", dir))?;
+    let path = dir.join(format!("{}.{}.log.zst", name, captured_at));
+    let compressed = zstd::encode_all(logs.as_bytes(), LOG_ZSTD_LEVEL)
+        .map_err(BackendError::io("compress logs into", &path))?;
+    std::fs::write(&path, compressed).map_err(BackendError::io("write", &path))
+}
+
+fn name_has_prefix(name: &str, prefixes: &[char]) -> bool {
+    name.starts_with(prefixes) && name.len() > 1 && name[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_push_files() {
+        let push = CachePush {
+            target: "s3://ci-cache?region=eu-central-1".to_string(),
+            command: None,
+            signing_key: Some("ci-cache-1:c2VjcmV0".into()),
+            credentials: None,
+        };
+        let files = push.files();
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            files[0].1,
+            "CACHE_PUSH_TARGET='s3://ci-cache?region=eu-central-1'\nCACHE_PUSH_COMMAND=''\n"
+        );
+        assert_eq!(files[1].0, CACHE_SIGNING_KEY_PATH);
+        assert_eq!(files[1].2, FileMode::Root);
+
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn test_resource_usage_parse() {
+        let usage = ResourceUsage::parse(
+            "MemoryCurrent=104857600\nCPUUsageNSec=2500000000\nTasksCurrent=18446744073709551615\n",
+        );
+        assert_eq!(usage.memory_bytes, Some(104_857_600));
+        assert_eq!(usage.cpu_seconds, Some(2.5));
+        assert_eq!(usage.tasks, None);
+        assert_eq!(ResourceUsage::parse("MemoryCurrent=[not set]\n"), ResourceUsage::default());
+    }
+
+    #[test]
+    fn test_bind_mount_parse_and_allowlist() {
+        let bind: BindMount = "/var/cache/cargo".parse().unwrap();
+        assert_eq!(bind, BindMount::read_only("/var/cache/cargo"));
+        let bind: BindMount = "/run/docker.sock:/var/run/docker.sock:rw".parse().unwrap();
+        assert_eq!(bind.target, "/var/run/docker.sock");
+        assert!(!bind.read_only);
+        assert!("/a:/b:/c".parse::<BindMount>().is_err());
+
+        let dir = std::env::temp_dir().join(format!("bind-allowlist-{}", std::process::id()));
+        let cache = dir.join("cache");
+        std::fs::create_dir_all(cache.join("sccache")).unwrap();
+        std::fs::create_dir_all(dir.join("cache-other")).unwrap();
+        std::os::unix::fs::symlink(dir.join("cache-other"), cache.join("link")).unwrap();
+        let bind = |path: &Path| BindMount::read_only(path.to_str().unwrap());
+
+        let allowed = vec![cache.clone()];
+        assert!(bind(&cache.join("sccache")).check_allowed(&allowed).is_ok());
+        assert!(bind(&dir.join("cache-other")).check_allowed(&allowed).is_err());
+        // A symlink out of the allowed directory, and a missing source
+        assert!(bind(&cache.join("link")).check_allowed(&allowed).is_err());
+        assert!(bind(&cache.join("missing")).check_allowed(&allowed).is_err());
+        assert!(BindMount::read_only("/var/cache/../../etc").check_allowed(&allowed).is_err());
+        assert!(BindMount::read_only("/etc").check_allowed(&[]).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_runner_env_files() {
+        let var: EnvVar = "SCCACHE_ENDPOINT=http://cache:9000/?a=b".parse().unwrap();
+        assert_eq!(var.value, "http://cache:9000/?a=b");
+        assert!("1FOO=bar".parse::<EnvVar>().is_err());
+        assert!("NO_VALUE".parse::<EnvVar>().is_err());
+        assert!("WORK_DIR=/".parse::<EnvVar>().is_err());
+        assert!(check_secret_name("../etc/shadow").is_err());
+
+        let dir = std::env::temp_dir().join(format!("runner-env-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("registry-auth"), "secret").unwrap();
+        let secrets = vec!["registry-auth".to_string()];
+        let files = runner_env_files(&[var], &secrets, Some(&dir)).unwrap();
+        let secret = format!("{}/registry-auth", RUNNER_SECRETS_DIR);
+        assert_eq!(files[0], (secret, "secret".into(), FileMode::Runner));
+        assert_eq!(
+            files[1].1,
+            "SCCACHE_ENDPOINT='http://cache:9000/?a=b'\n\
+             RUNNER_SECRETS_DIR=/var/lib/github-runner-secrets\n"
+        );
+        assert!(runner_env_files(&[], &["missing".to_string()], Some(&dir)).is_err());
+        assert!(runner_env_files(&[], &secrets, None).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_captured_logs_kept_per_job() {
+        let dir = std::env::temp_dir().join(format!("captured-logs-{}", std::process::id()));
+        store_captured_logs(&dir, "r1", 100, "first job").unwrap();
+        store_captured_logs(&dir, "r1", 200, "second job").unwrap();
+        store_captured_logs(&dir, "r10", 300, "other slot").unwrap();
+        let newest = newest_captured_log(&dir, "r1").unwrap();
+        assert_eq!(newest, dir.join("r1.200.log.zst"));
+        let logs = zstd::decode_all(std::fs::read(newest).unwrap().as_slice()).unwrap();
+        assert_eq!(logs, b"second job");
+        assert!(newest_captured_log(&dir, "r2").is_none());
+
+        let week = Duration::from_secs(7 * 86400);
+        let old = std::fs::File::options().write(true).open(dir.join("r1.100.log.zst")).unwrap();
+        old.set_modified(SystemTime::now() - week * 2).unwrap();
+        assert_eq!(prune_captured_logs(&dir, week).unwrap(), 1);
+        assert!(!dir.join("r1.100.log.zst").exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_container_file_modes() {
+        let dir = std::env::temp_dir().join(format!("container-files-{}", std::process::id()));
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let labels = dir.join("var/lib/github-runner-labels");
+        write_file(&labels, "gpu", FileMode::Public).unwrap();
+        assert_eq!(mode(&labels), 0o644);
+
+        // A token rewritten over a file an older version left world-readable
+        let token = dir.join("var/lib/github-runner-token");
+        std::fs::write(&token, "old").unwrap();
+        std::fs::set_permissions(&token, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_file(&token, "new", FileMode::Root).unwrap();
+        assert_eq!(mode(&token), 0o600);
+        assert_eq!(std::fs::read_to_string(&token).unwrap(), "new");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_gpu_binds_in_nspawn_config() {
+        let gpus = GpuConfig {
+            label: "gpu".to_string(),
+            devices: vec![
+                vec!["/dev/nvidia0".to_string()],
+                vec!["/dev/nvidia1".to_string()],
+            ],
+            shared_devices: vec!["/dev/nonexistent-nvidiactl".to_string()],
+            driver_paths: vec!["/nonexistent/opengl-driver".to_string()],
+        };
+        assert!(gpus.requested(&["self-hosted".to_string(), "GPU".to_string()]));
+        assert_eq!(gpus.device_nodes(1), vec!["/dev/nvidia1"]);
+        assert!(gpus.driver_binds().is_empty());
+
+        let devices = gpus.device_nodes(0);
+        let binds = vec![
+            BindMount::read_only("/run/opengl-driver"),
+            "/var/cache/sccache:/cache:rw".parse().unwrap(),
+        ];
+        let config = nspawn_config(NSPAWN_MACHINE_TEMPLATE, false, &devices, &binds);
+        assert!(config.contains(
+            "BindReadOnly=/lib/modules\nBind=/dev/nvidia0\nBindReadOnly=/run/opengl-driver\n\
+             Bind=/var/cache/sccache:/cache\n\n[Network]"
+        ));
+        assert_eq!(nspawn_config(NSPAWN_CONFIG_TEMPLATE, false, &[], &[]), NSPAWN_CONFIG_TEMPLATE);
+
+        let config = nspawn_config(NSPAWN_MACHINE_TEMPLATE, true, &[], &[]);
+        assert!(config.starts_with("[Exec]\nVolatile=overlay\nSystemCallFilter="));
+        assert!(config.ends_with("[Network]\nVirtualEthernet=yes\n"));
+    }
+
+    #[test]
+    fn test_nspawn_image_contract() {
+        let dir = std::env::temp_dir().join(format!("nspawn-images-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let tree = dir.join("runner-base");
+        std::fs::create_dir_all(tree.join("etc/systemd/system")).unwrap();
+        let tarball = dir.join("runner.tar.xz");
+        std::fs::write(&tarball, "").unwrap();
+
+        // By name from the machines directory, or as an OS tree path
+        let tree_path = tree.display().to_string();
+        let err = check_nspawn_image("runner-base", &dir).unwrap_err();
+        assert_eq!(err.to_string(), "nspawn image runner-base has no github-runner.service unit");
+        assert!(check_nspawn_image(&tree_path, Path::new("/nonexistent")).is_err());
+        let units = tree.join("usr/lib/systemd/system");
+        std::fs::create_dir_all(&units).unwrap();
+        std::fs::write(units.join("github-runner.service"), "[Service]\n").unwrap();
+        assert!(check_nspawn_image("runner-base", &dir).is_ok());
+
+        // Tarballs are checked once imported; unknown names are machinectl's to refuse
+        let tarball_path = tarball.display().to_string();
+        assert!(check_nspawn_image(&tarball_path, &dir).is_ok());
+        assert!(check_nspawn_image("missing", &dir).is_ok());
+
+        assert_eq!(nspawn_create_args(&tarball_path, "r0")[0], "import-tar");
+        assert_eq!(nspawn_create_args(&tree_path, "r0")[0], "import-fs");
+        assert_eq!(nspawn_create_args("runner-base", "r0"), ["clone", "runner-base", "r0"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_nspawn_lifecycle() {
+        let dir = std::env::temp_dir().join(format!("nspawn-lifecycle-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("calls");
+        let machinectl = dir.join("machinectl");
+        std::fs::write(
+            &machinectl,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> {}\n\
+                 [ \"$1\" = list-images ] && printf 'r0 subvolume no\\nrunner-base subvolume \
+                 no\\nx0000001 subvolume no\\n'\nexit 0\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&machinectl, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let backend = ContainerBackend::Nspawn {
+            image: "runner-base".to_string(),
+        };
+        let mut manager = ContainerManager::new(dir.clone(), backend, RunnerNaming::default());
+        manager.machinectl_bin = machinectl;
+
+        tokio_test::block_on(async {
+            manager.create("r1", None).await.unwrap();
+            manager.create("r2", Some("runner-gpu")).await.unwrap();
+            assert_eq!(manager.list().await.unwrap(), ["r0"]);
+            assert_eq!(manager.list_external().await.unwrap(), ["x0000001"]);
+            manager.stop("r0").await.unwrap();
+            manager.destroy("r0").await.unwrap();
+        });
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            "clone runner-base r1\nclone runner-gpu r2\nlist-images --no-legend\n\
+             list-images --no-legend\nterminate r0\nremove r0\n"
+        );
+
+        // Commands inside the machine go through systemd-run
+        let exec = manager.exec_command("r0", &["systemctl", "is-active", "github-runner.service"]);
+        let args: Vec<_> = exec.as_std().get_args().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(exec.as_std().get_program(), "systemd-run");
+        assert_eq!(
+            args,
+            [
+                "--machine=r0",
+                "--quiet",
+                "--wait",
+                "--pipe",
+                "--",
+                "systemctl",
+                "is-active",
+                "github-runner.service"
+            ]
+        );
+        assert_eq!(manager.unit_name("r0"), "systemd-nspawn@r0.service");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_root_tmpfs() {
+        let manager = |backend| {
+            ContainerManager::new(PathBuf::from("/tmp"), backend, RunnerNaming::default())
+        };
+        let size = Some("8G".to_string());
+
+        // nixos roots are mounted before the container is created
+        let nixos = manager(ContainerBackend::Nixos).with_root_tmpfs(size.clone());
+        let root = nixos.container_root("r0").display().to_string();
+        assert_eq!(
+            nixos.root_tmpfs_mount_args("r0").unwrap(),
+            ["-t", "tmpfs", "-o", "size=8G,mode=0700", "tmpfs", root.as_str()]
+        );
+        assert!(manager(ContainerBackend::Nixos).root_tmpfs_mount_args("r0").is_none());
+
+        // nspawn machines are cloned by machinectl and get an overlay instead
+        let image = "/var/lib/machines/runner.tar".to_string();
+        let nspawn = manager(ContainerBackend::Nspawn { image }).with_root_tmpfs(size);
+        assert!(nspawn.root_tmpfs_mount_args("r0").is_none());
+    }
+}
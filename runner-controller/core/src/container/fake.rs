@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::process::Child;
+
+use crate::secrets::SecretString;
+use super::{
+    name_has_prefix, Backend, BackendError, FileMode, ResourceUsage, RunnerNaming, SpawnOptions,
+};
+
+/// A backend operation, as recorded by `FakeBackend`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendCall {
+    Spawn(String),
+    Reregister(String),
+    Cleanup(String),
+    Quarantine(String),
+}
+
+/// In-memory container backend for tests of the controller logic.
+///
+/// Spawned containers exist until cleaned up; their runners keep running
+/// until `complete` is called. Every spawn, re-registration, cleanup and
+/// quarantine is recorded. Failures queued with `fail_next_spawn` and
+/// `fail_next_cleanup` are returned by the next such calls, in order, and
+/// `set_delay` makes spawns and cleanups take that long.
+#[derive(Default)]
+pub struct FakeBackend {
+    naming: RunnerNaming,
+    state: Mutex<FakeState>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    containers: BTreeMap<String, FakeContainer>,
+    calls: Vec<BackendCall>,
+    spawn_failures: VecDeque<BackendError>,
+    cleanup_failures: VecDeque<BackendError>,
+    delay: Duration,
+    /// Logs of containers cleaned up, as a real backend would capture them
+    captured_logs: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct FakeContainer {
+    token: String,
+    labels: Vec<String>,
+    files: HashMap<String, (String, FileMode)>,
+    completed: bool,
+    usage: ResourceUsage,
+}
+
+impl FakeBackend {
+    pub fn new(naming: RunnerNaming) -> Self {
+        Self {
+            naming,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Add a container with a running runner, as if left by a previous run
+    pub fn insert(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.containers.insert(name.to_string(), FakeContainer::default());
+    }
+
+    /// Let the runner in `name` finish its job
+    pub fn complete(&self, name: &str) {
+        if let Some(container) = self.state.lock().unwrap().containers.get_mut(name) {
+            container.completed = true;
+        }
+    }
+
+    /// Report `usage` as the container's resource usage
+    pub fn set_resource_usage(&self, name: &str, usage: ResourceUsage) {
+        if let Some(container) = self.state.lock().unwrap().containers.get_mut(name) {
+            container.usage = usage;
+        }
+    }
+
+    /// Fail the next spawn with `error`
+    pub fn fail_next_spawn(&self, error: BackendError) {
+        self.state.lock().unwrap().spawn_failures.push_back(error);
+    }
+
+    /// Fail the next cleanup with `error`; the container stays
+    pub fn fail_next_cleanup(&self, error: BackendError) {
+        self.state.lock().unwrap().cleanup_failures.push_back(error);
+    }
+
+    /// Make every spawn and cleanup take `delay`
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().delay = delay;
+    }
+
+    /// Names of the existing containers, sorted
+    pub fn containers(&self) -> Vec<String> {
+        self.state.lock().unwrap().containers.keys().cloned().collect()
+    }
+
+    /// Labels a container's runner registers with
+    pub fn labels(&self, name: &str) -> Option<Vec<String>> {
+        let state = self.state.lock().unwrap();
+        state.containers.get(name).map(|c| c.labels.clone())
+    }
+
+    /// Registration token a container's runner last got
+    pub fn token(&self, name: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.containers.get(name).map(|c| c.token.clone())
+    }
+
+    /// Mode a file in a container was last written with
+    pub fn file_mode(&self, name: &str, path: &str) -> Option<FileMode> {
+        let state = self.state.lock().unwrap();
+        state.containers.get(name)?.files.get(path).map(|(_, mode)| *mode)
+    }
+
+    /// Every recorded operation so far, in order
+    pub fn calls(&self) -> Vec<BackendCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    fn list_with_prefixes(&self, prefixes: &[char]) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .containers
+            .keys()
+            .filter(|name| name_has_prefix(name, prefixes))
+            .cloned()
+            .collect()
+    }
+
+    async fn delay(&self) {
+        let delay = self.state.lock().unwrap().delay;
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn not_found(name: &str) -> BackendError {
+        BackendError::Command {
+            command: format!("fake backend {}", name),
+            stderr: "no such container".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for FakeBackend {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn naming(&self) -> &RunnerNaming {
+        &self.naming
+    }
+
+    async fn list(&self) -> Result<Vec<String>, BackendError> {
+        Ok(self.list_with_prefixes(&['r']))
+    }
+
+    async fn list_external(&self) -> Result<Vec<String>, BackendError> {
+        Ok(self.list_with_prefixes(&['x']))
+    }
+
+    async fn list_all(&self) -> Result<Vec<String>, BackendError> {
+        Ok(self.list_with_prefixes(&['r', 'x', 'j']))
+    }
+
+    async fn spawn_container(
+        &self,
+        name: &str,
+        token: &SecretString,
+        options: &SpawnOptions<'_>,
+    ) -> Result<(), BackendError> {
+        self.delay().await;
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(BackendCall::Spawn(name.to_string()));
+        if let Some(error) = state.spawn_failures.pop_front() {
+            return Err(error);
+        }
+
+        let mut labels = options.labels.to_vec();
+        labels.extend(self.naming.instance_label().map(str::to_string));
+        let files = options
+            .files
+            .iter()
+            .map(|&(path, contents, mode)| (path.to_string(), (contents.to_string(), mode)))
+            .collect();
+        let container = FakeContainer {
+            token: token.expose().to_string(),
+            labels,
+            files,
+            ..Default::default()
+        };
+        state.containers.insert(name.to_string(), container);
+        Ok(())
+    }
+
+    async fn is_runner_completed(&self, name: &str) -> Result<bool, BackendError> {
+        let state = self.state.lock().unwrap();
+        // Like the real backends, an unreachable container counts as completed
+        Ok(state.containers.get(name).is_none_or(|c| c.completed))
+    }
+
+    async fn reregister(&self, name: &str, token: &SecretString) -> Result<(), BackendError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(BackendCall::Reregister(name.to_string()));
+        let container = state.containers.get_mut(name).ok_or_else(|| Self::not_found(name))?;
+        container.token = token.expose().to_string();
+        container.completed = false;
+        Ok(())
+    }
+
+    async fn cleanup_container(&self, name: &str) -> Result<(), BackendError> {
+        self.delay().await;
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(BackendCall::Cleanup(name.to_string()));
+        if let Some(error) = state.cleanup_failures.pop_front() {
+            return Err(error);
+        }
+        if state.containers.remove(name).is_some() {
+            let logs = format!("runner log of {}", name);
+            state.captured_logs.insert(name.to_string(), logs);
+        }
+        Ok(())
+    }
+
+    async fn quarantine(&self, name: &str) -> Result<PathBuf, BackendError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(BackendCall::Quarantine(name.to_string()));
+        state.containers.remove(name).ok_or_else(|| Self::not_found(name))?;
+        Ok(PathBuf::from("/quarantine").join(name))
+    }
+
+    async fn capture_logs(&self, _name: &str) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    async fn resource_usage(&self, name: &str) -> Result<ResourceUsage, BackendError> {
+        let state = self.state.lock().unwrap();
+        let container = state.containers.get(name).ok_or_else(|| Self::not_found(name))?;
+        Ok(container.usage.clone())
+    }
+
+    async fn runner_logs(&self, name: &str, _tail: usize) -> Result<String, BackendError> {
+        let state = self.state.lock().unwrap();
+        match state.containers.contains_key(name) {
+            true => Ok(format!("runner log of {}", name)),
+            false => Err(Self::not_found(name)),
+        }
+    }
+
+    fn follow_runner_logs(&self, name: &str, _tail: usize) -> Result<Child, BackendError> {
+        Err(BackendError::Command {
+            command: format!("journalctl -f in {}", name),
+            stderr: "not supported by the fake backend".to_string(),
+        })
+    }
+
+    fn write_container_file(
+        &self,
+        name: &str,
+        path: &str,
+        contents: &str,
+        mode: FileMode,
+    ) -> Result<(), BackendError> {
+        let mut state = self.state.lock().unwrap();
+        let container = state.containers.get_mut(name).ok_or_else(|| Self::not_found(name))?;
+        container.files.insert(path.to_string(), (contents.to_string(), mode));
+        Ok(())
+    }
+
+    fn read_container_file(&self, name: &str, path: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.containers.get(name)?.files.get(path).map(|(contents, _)| contents.clone())
+    }
+
+    fn read_worker_log(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    fn captured_logs_path(&self, _name: &str) -> Option<PathBuf> {
+        None
+    }
+
+    fn read_captured_logs(&self, name: &str) -> Option<String> {
+        self.state.lock().unwrap().captured_logs.get(name).cloned()
+    }
+}
@@ -0,0 +1,1034 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Output, Stdio};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+use tokio::runtime::RuntimeFlavor;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+
+use super::{
+    free_subnet, name_has_prefix, runner_service_completed, shell_quote, store_captured_logs,
+    subnet_octet, Backend, BackendError, FileMode, ResourceUsage, RunnerNaming, SpawnOptions,
+    RUNNER_DIAG_DIR, RUNNER_LABELS_PATH, RUNNER_NAME_PATH, RUNNER_TOKEN_PATH,
+};
+use crate::egress::EgressMode;
+use crate::secrets::SecretString;
+use crate::state::unix_now;
+
+/// Seconds ssh waits for a remote host before the operation fails
+const SSH_CONNECT_TIMEOUT: u64 = 10;
+/// ssh's exit status when it couldn't reach the host, as opposed to the
+/// remote command failing
+const SSH_FAILED: i32 = 255;
+
+/// Container roots of nixos-container on a remote host
+const NIXOS_CONTAINERS_DIR: &str = "/var/lib/nixos-containers";
+const DEFAULT_REMOTE_TEMPLATE: &str = "/etc/nixos/ci-container-template.nix";
+
+/// Print the newest runner worker log in the directory `$1`
+const WORKER_LOG_SCRIPT: &str =
+    r#"f=$(ls -1 "$1"/Worker_*.log 2>/dev/null | tail -n 1) && [ -n "$f" ] && cat "$f""#;
+/// Runner service state and result, or `unreachable` if the container can't
+/// be entered
+const NIXOS_STATUS_SCRIPT: &str = concat!(
+    r#"nixos-container run "$1" -- sh -c 'systemctl is-active github-runner.service; "#,
+    r#"systemctl show github-runner.service --property=Result' || echo unreachable"#,
+);
+
+/// How runner containers run on remote hosts (`REMOTE_RUNTIME`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "runtime", rename_all = "lowercase")]
+pub enum RemoteRuntime {
+    /// nixos-container, from a container configuration on the remote host
+    Nixos { template: String },
+    /// Docker, from an image whose entrypoint configures and runs the runner
+    /// from the same files as the container template
+    Docker { image: String },
+}
+
+impl RemoteRuntime {
+    /// `name` (nixos or docker) with its template or image
+    pub fn new(name: &str, template: Option<String>) -> Result<Self> {
+        match name {
+            "nixos" => Ok(Self::Nixos {
+                template: template.unwrap_or_else(|| DEFAULT_REMOTE_TEMPLATE.to_string()),
+            }),
+            "docker" => Ok(Self::Docker {
+                image: template.context("the docker runtime needs an image")?,
+            }),
+            other => anyhow::bail!("unknown remote runtime '{}', expected nixos or docker", other),
+        }
+    }
+}
+
+/// A remote host runner containers are placed on, as `name=destination:capacity`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RemoteHostConfig {
+    pub name: String,
+    /// ssh destination, e.g. `root@builder-1`; ports and jump hosts come
+    /// from the ssh configuration
+    pub destination: String,
+    /// Containers the host runs at once
+    pub capacity: usize,
+}
+
+impl FromStr for RemoteHostConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, rest) = s
+            .split_once('=')
+            .with_context(|| format!("'{}' is not name=destination:capacity", s))?;
+        let (destination, capacity) = rest
+            .rsplit_once(':')
+            .with_context(|| format!("'{}' is missing the capacity", s))?;
+        let name = name.trim();
+        if name.is_empty() || name == "local" {
+            anyhow::bail!("'{}' needs a host name other than 'local'", s);
+        }
+        let capacity = capacity
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&c| c > 0)
+            .with_context(|| format!("capacity of {} must be a positive number", name))?;
+        Ok(Self {
+            name: name.to_string(),
+            destination: destination.trim().to_string(),
+            capacity,
+        })
+    }
+}
+
+/// Remote hosts jobs are placed on besides the controller host (`REMOTE_HOSTS`)
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteConfig {
+    pub hosts: Vec<RemoteHostConfig>,
+    pub runtime: RemoteRuntime,
+    /// Identity file for ssh; `None` uses the ssh configuration
+    pub ssh_key: Option<PathBuf>,
+    /// External runners kept on the controller host before remote hosts
+    /// are used
+    pub local_capacity: usize,
+}
+
+/// External runners on one host of the fleet against its capacity
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HostCapacity {
+    /// `local` for the controller host
+    pub host: String,
+    pub capacity: usize,
+    pub running: usize,
+    /// Whether the last listing reached the host
+    pub reachable: bool,
+}
+
+/// Run blocking ssh work from the synchronous `Backend` methods without
+/// stalling the other tasks of a multi-threaded runtime
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// Script writing stdin to the file `$1` with `mode`, creating its directory;
+/// the mode is set before anything is written
+fn write_script(mode: FileMode) -> String {
+    let mut script = format!(
+        r#"mkdir -p "$(dirname "$1")" && touch "$1" && chmod {:o} "$1""#,
+        mode.permissions()
+    );
+    if let Some(uid) = mode.owner() {
+        script.push_str(&format!(r#" && chown {} "$1""#, uid));
+    }
+    script + r#" && cat > "$1""#
+}
+
+/// One remote host, driven with nixos-container or docker over ssh
+struct RemoteHost {
+    config: RemoteHostConfig,
+    runtime: RemoteRuntime,
+    ssh_key: Option<PathBuf>,
+    /// Held while a nixos container picks its subnet and is created
+    create_lock: tokio::sync::Mutex<()>,
+}
+
+impl RemoteHost {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// ssh arguments running `args` on the host, quoted for its shell
+    fn ssh_args<S: AsRef<str>>(&self, args: &[S]) -> Vec<String> {
+        let mut ssh = vec![
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+            "-o".to_string(),
+            format!("ConnectTimeout={}", SSH_CONNECT_TIMEOUT),
+        ];
+        if let Some(key) = &self.ssh_key {
+            ssh.extend(["-i".to_string(), key.display().to_string()]);
+        }
+        let command: Vec<String> = args.iter().map(|a| shell_quote(a.as_ref())).collect();
+        ssh.extend([self.config.destination.clone(), "--".to_string(), command.join(" ")]);
+        ssh
+    }
+
+    /// Map a finished ssh to its output: an unreachable host is an error,
+    /// a failed remote command is left to the caller
+    fn check_ssh<S: AsRef<str>>(
+        &self,
+        args: &[S],
+        output: Output,
+    ) -> Result<Output, BackendError> {
+        if output.status.code() == Some(SSH_FAILED) {
+            return Err(BackendError::Command {
+                command: format!("ssh {} {}", self.config.destination, args[0].as_ref()),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+        Ok(output)
+    }
+
+    fn exec_error(&self, source: std::io::Error) -> BackendError {
+        BackendError::Exec {
+            program: format!("ssh {}", self.config.destination),
+            source,
+        }
+    }
+
+    /// Run `args` on the host, feeding it `input`
+    async fn output<S: AsRef<str>>(
+        &self,
+        args: &[S],
+        input: Option<&str>,
+    ) -> Result<Output, BackendError> {
+        let mut child = Command::new("ssh")
+            .args(self.ssh_args(args))
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| self.exec_error(e))?;
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin
+                .write_all(input.as_bytes())
+                .await
+                .map_err(|e| self.exec_error(e))?;
+        }
+        let output = child.wait_with_output().await.map_err(|e| self.exec_error(e))?;
+        self.check_ssh(args, output)
+    }
+
+    /// `output` for the synchronous `Backend` methods
+    fn output_blocking<S: AsRef<str>>(
+        &self,
+        args: &[S],
+        input: Option<&str>,
+    ) -> Result<Output, BackendError> {
+        blocking(|| {
+            let mut child = std::process::Command::new("ssh")
+                .args(self.ssh_args(args))
+                .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| self.exec_error(e))?;
+            if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+                stdin
+                    .write_all(input.as_bytes())
+                    .map_err(|e| self.exec_error(e))?;
+            }
+            let output = child.wait_with_output().map_err(|e| self.exec_error(e))?;
+            self.check_ssh(args, output)
+        })
+    }
+
+    /// Stdout of a remote command that must succeed
+    fn stdout<S: AsRef<str>>(&self, args: &[S], output: Output) -> Result<String, BackendError> {
+        if !output.status.success() {
+            let command: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+            return Err(BackendError::Command {
+                command: format!("{} on {}", command.join(" "), self.name()),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    async fn run<S: AsRef<str>>(
+        &self,
+        args: &[S],
+        input: Option<&str>,
+    ) -> Result<String, BackendError> {
+        let output = self.output(args, input).await?;
+        self.stdout(args, output)
+    }
+
+    /// Run a command whose failure doesn't matter, as long as the host was reached
+    async fn run_best_effort<S: AsRef<str>>(&self, args: &[S]) -> Result<(), BackendError> {
+        self.output(args, None).await.map(|_| ())
+    }
+
+    /// Root filesystem of a nixos container on the host
+    fn container_root(name: &str) -> String {
+        format!("{}/{}", NIXOS_CONTAINERS_DIR, name)
+    }
+
+    /// Command running `cmd` inside a container
+    fn exec_args(&self, name: &str, cmd: &[&str]) -> Vec<String> {
+        let prefix: &[&str] = match self.runtime {
+            RemoteRuntime::Nixos { .. } => &["nixos-container", "run", name, "--"],
+            RemoteRuntime::Docker { .. } => &["docker", "exec", name],
+        };
+        prefix.iter().chain(cmd).map(|s| s.to_string()).collect()
+    }
+
+    /// Command writing stdin to `path` (relative to the container root) of a
+    /// live container
+    fn write_args(&self, name: &str, path: &str, mode: FileMode) -> Vec<String> {
+        let path = path.trim_start_matches('/');
+        let script = write_script(mode);
+        match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                let full = format!("{}/{}", Self::container_root(name), path);
+                ["sh", "-c", &script, "sh", &full].map(String::from).to_vec()
+            }
+            RemoteRuntime::Docker { .. } => {
+                let full = format!("/{}", path);
+                ["docker", "exec", "-i", name, "sh", "-c", &script, "sh", &full]
+                    .map(String::from)
+                    .to_vec()
+            }
+        }
+    }
+
+    /// Command printing `path` (relative to the container root) of a container
+    fn read_args(&self, name: &str, path: &str) -> Vec<String> {
+        let path = path.trim_start_matches('/');
+        match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                vec!["cat".to_string(), format!("{}/{}", Self::container_root(name), path)]
+            }
+            RemoteRuntime::Docker { .. } => self.exec_args(name, &["cat", &format!("/{}", path)]),
+        }
+    }
+
+    /// Runner containers on the host
+    async fn list(&self) -> Result<Vec<String>, BackendError> {
+        let output = match self.runtime {
+            RemoteRuntime::Nixos { .. } => self.run(&["nixos-container", "list"], None).await?,
+            RemoteRuntime::Docker { .. } => {
+                self.run(&["docker", "ps", "-a", "--format", "{{.Names}}"], None)
+                    .await?
+            }
+        };
+        Ok(output
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .filter(|name| name_has_prefix(name, &['r', 'x', 'j']))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Create a container, put `files` into it and start it; a container
+    /// left half-created is removed again
+    async fn spawn(
+        &self,
+        name: &str,
+        files: &[(&str, &str, FileMode)],
+    ) -> Result<(), BackendError> {
+        info!(host = %self.name(), name = %name, "Spawning container on remote host");
+        if self.list().await?.iter().any(|n| n == name) {
+            warn!(host = %self.name(), name = %name, "Cleaning up existing container first");
+            self.cleanup(name).await?;
+        }
+        let result = match &self.runtime {
+            RemoteRuntime::Nixos { template } => self.spawn_nixos(name, template, files).await,
+            RemoteRuntime::Docker { image } => self.spawn_docker(name, image, files).await,
+        };
+        if let Err(e) = &result {
+            warn!(host = %self.name(), name = %name, error = %e, "Failed to spawn, cleaning up");
+            let _ = self.cleanup(name).await;
+        }
+        result
+    }
+
+    async fn spawn_nixos(
+        &self,
+        name: &str,
+        template: &str,
+        files: &[(&str, &str, FileMode)],
+    ) -> Result<(), BackendError> {
+        {
+            let _guard = self.create_lock.lock().await;
+            let mut used = HashSet::new();
+            for container in self.list().await? {
+                if let Ok(ip) = self.run(&["nixos-container", "show-ip", &container], None).await {
+                    used.extend(subnet_octet(&ip));
+                }
+            }
+            let subnet = free_subnet(&used);
+            let source = if template.contains('#') {
+                "--flake"
+            } else {
+                "--config-file"
+            };
+            self.run(
+                &[
+                    "nixos-container",
+                    "create",
+                    name,
+                    source,
+                    template,
+                    "--local-address",
+                    &format!("192.168.{}.11", subnet),
+                    "--host-address",
+                    &format!("192.168.{}.10", subnet),
+                ],
+                None,
+            )
+            .await?;
+        }
+        for &(path, contents, mode) in files {
+            self.run(&self.write_args(name, path, mode), Some(contents)).await?;
+        }
+        self.run(&["nixos-container", "start", name], None).await?;
+        Ok(())
+    }
+
+    /// Docker containers get their files copied in from a staging directory
+    /// before they start
+    async fn spawn_docker(
+        &self,
+        name: &str,
+        image: &str,
+        files: &[(&str, &str, FileMode)],
+    ) -> Result<(), BackendError> {
+        self.run(&["docker", "create", "--name", name, image], None).await?;
+        let stage = self.run(&["mktemp", "-d"], None).await?.trim().to_string();
+        let copied = async {
+            for &(path, contents, mode) in files {
+                let full = format!("{}/{}", stage, path.trim_start_matches('/'));
+                self.run(&["sh", "-c", &write_script(mode), "sh", &full], Some(contents))
+                    .await?;
+            }
+            // `-a` keeps the owners of files the runner user reads
+            let (source, target) = (format!("{}/.", stage), format!("{}:/", name));
+            self.run(&["docker", "cp", "-a", &source, &target], None).await
+        }
+        .await;
+        let _ = self.run_best_effort(&["rm", "-rf", &stage]).await;
+        copied?;
+        self.run(&["docker", "start", name], None).await?;
+        Ok(())
+    }
+
+    /// Whether the runner has finished; a container that is gone or can't
+    /// be entered counts as finished, a host that can't be reached doesn't
+    async fn is_runner_completed(&self, name: &str) -> Result<bool, BackendError> {
+        match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                let output = self.run(&["sh", "-c", NIXOS_STATUS_SCRIPT, "sh", name], None).await?;
+                let mut lines = output.lines().map(str::trim);
+                let status = lines.next().unwrap_or_default();
+                if status == "unreachable" {
+                    debug!(host = %self.name(), name = %name, "Container not reachable");
+                    return Ok(true);
+                }
+                Ok(runner_service_completed(status, lines.next().unwrap_or_default()))
+            }
+            RemoteRuntime::Docker { .. } => {
+                let output = self
+                    .output(&["docker", "inspect", "-f", "{{.State.Status}}", name], None)
+                    .await?;
+                let status = String::from_utf8_lossy(&output.stdout);
+                Ok(!matches!(status.trim(), "created" | "running" | "restarting" | "paused"))
+            }
+        }
+    }
+
+    async fn reregister(&self, name: &str, token: &SecretString) -> Result<(), BackendError> {
+        let args = self.write_args(name, RUNNER_TOKEN_PATH, FileMode::Root);
+        self.run(&args, Some(token.expose())).await?;
+        match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                let restart = ["systemctl", "restart", "github-runner.service"];
+                self.run(&self.exec_args(name, &restart), None).await?;
+            }
+            RemoteRuntime::Docker { .. } => {
+                self.run(&["docker", "restart", name], None).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop and destroy a container
+    async fn cleanup(&self, name: &str) -> Result<(), BackendError> {
+        match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                self.run_best_effort(&["nixos-container", "stop", name]).await?;
+                self.run_best_effort(&["nixos-container", "destroy", name]).await?;
+                let link = format!("ve-{}", name);
+                self.run_best_effort(&["ip", "link", "delete", &link]).await?;
+            }
+            RemoteRuntime::Docker { .. } => {
+                self.run_best_effort(&["docker", "rm", "-f", name]).await?;
+            }
+        }
+        info!(host = %self.name(), name = %name, "Container cleaned up");
+        Ok(())
+    }
+
+    /// Cut the container's network, stop it and keep its root filesystem
+    /// (nixos) or the stopped container under another name (docker).
+    /// Returns where, as `host:path`.
+    async fn quarantine(&self, name: &str) -> Result<PathBuf, BackendError> {
+        warn!(host = %self.name(), name = %name, "Quarantining container");
+        let kept = match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                let link = format!("ve-{}", name);
+                self.run_best_effort(&["ip", "link", "set", &link, "down"]).await?;
+                let stop = ["systemctl", "stop", "github-runner.service"];
+                self.run_best_effort(&self.exec_args(name, &stop)).await?;
+                self.run_best_effort(&["nixos-container", "stop", name]).await?;
+                let target =
+                    format!("{}/.quarantine/{}-{}", NIXOS_CONTAINERS_DIR, name, unix_now());
+                let root = Self::container_root(name);
+                let preserve = r#"mkdir -p "$(dirname "$2")" && mv "$1" "$2""#;
+                self.run(&["sh", "-c", preserve, "sh", &root, &target], None).await?;
+                self.run_best_effort(&["nixos-container", "destroy", name]).await?;
+                target
+            }
+            RemoteRuntime::Docker { .. } => {
+                let target = format!("quarantined-{}-{}", name, unix_now());
+                self.run_best_effort(&["docker", "network", "disconnect", "bridge", name])
+                    .await?;
+                self.run(&["docker", "stop", name], None).await?;
+                self.run(&["docker", "rename", name, &target], None).await?;
+                target
+            }
+        };
+        let target = PathBuf::from(format!("{}:{}", self.name(), kept));
+        warn!(name = %name, path = ?target, "Container quarantined");
+        Ok(target)
+    }
+
+    /// Runner log of a live container, the last `tail` lines or all of it
+    fn journal_args(&self, name: &str, tail: Option<usize>, follow: bool) -> Vec<String> {
+        let tail = tail.map(|n| n.to_string());
+        match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                let mut cmd = vec!["journalctl", "-u", "github-runner.service"];
+                cmd.extend(["--no-pager", "-o", "short-iso"]);
+                cmd.extend(tail.as_deref().map(|n| ["-n", n]).into_iter().flatten());
+                if follow {
+                    cmd.push("-f");
+                }
+                self.exec_args(name, &cmd)
+            }
+            RemoteRuntime::Docker { .. } => {
+                let mut cmd = vec!["sh", "-c", r#"docker logs "$@" 2>&1"#, "sh", "--timestamps"];
+                cmd.extend(["--tail", tail.as_deref().unwrap_or("all")]);
+                if follow {
+                    cmd.push("-f");
+                }
+                cmd.push(name);
+                cmd.into_iter().map(str::to_string).collect()
+            }
+        }
+    }
+
+    async fn journal(&self, name: &str, tail: Option<usize>) -> Result<String, BackendError> {
+        self.run(&self.journal_args(name, tail, false), None).await
+    }
+
+    /// Docker hosts report no usage; nothing accounts it like systemd does
+    async fn resource_usage(&self, name: &str) -> Result<ResourceUsage, BackendError> {
+        match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                let unit = format!("container@{}.service", name);
+                let output = self
+                    .run(
+                        &[
+                            "systemctl",
+                            "show",
+                            &unit,
+                            "-p",
+                            "MemoryCurrent",
+                            "-p",
+                            "CPUUsageNSec",
+                            "-p",
+                            "TasksCurrent",
+                        ],
+                        None,
+                    )
+                    .await?;
+                Ok(ResourceUsage::parse(&output))
+            }
+            RemoteRuntime::Docker { .. } => Ok(ResourceUsage::default()),
+        }
+    }
+
+    fn worker_log(&self, name: &str) -> Option<String> {
+        let args = match self.runtime {
+            RemoteRuntime::Nixos { .. } => {
+                let dir = format!("{}/{}", Self::container_root(name), RUNNER_DIAG_DIR);
+                ["sh", "-c", WORKER_LOG_SCRIPT, "sh", &dir].map(String::from).to_vec()
+            }
+            RemoteRuntime::Docker { .. } => {
+                let dir = format!("/{}", RUNNER_DIAG_DIR);
+                self.exec_args(name, &["sh", "-c", WORKER_LOG_SCRIPT, "sh", &dir])
+            }
+        };
+        let output = self.output_blocking(&args, None).ok()?;
+        self.stdout(&args, output).ok()
+    }
+}
+
+/// Where the fleet put each external container
+#[derive(Debug, Default)]
+struct Placements {
+    /// Host index of each external container; `None` is the controller host
+    hosts: HashMap<String, Option<usize>>,
+    /// Containers being spawned, kept while listings don't show them yet
+    spawning: HashSet<String>,
+    /// Hosts whose last listing failed
+    unreachable: HashSet<usize>,
+}
+
+/// Container backend spreading external runners over the controller host
+/// and remote hosts reached over ssh.
+///
+/// The warm pool and jobs needing the controller host (per-label
+/// templates, devices, bind mounts, egress policies) stay local. Other
+/// external runners fill the controller host up to its capacity, then go to
+/// the reachable remote host with the most room. Runner logs are captured
+/// into the controller's state directory wherever the container ran.
+pub struct Fleet {
+    local: Arc<dyn Backend>,
+    local_capacity: usize,
+    /// Where runner logs of remote containers are captured to
+    logs_dir: PathBuf,
+    hosts: Vec<RemoteHost>,
+    placements: Mutex<Placements>,
+}
+
+impl Fleet {
+    pub fn new(local: Arc<dyn Backend>, config: &RemoteConfig, logs_dir: PathBuf) -> Self {
+        let hosts = config
+            .hosts
+            .iter()
+            .map(|host| RemoteHost {
+                config: host.clone(),
+                runtime: config.runtime.clone(),
+                ssh_key: config.ssh_key.clone(),
+                create_lock: tokio::sync::Mutex::new(()),
+            })
+            .collect();
+        Self {
+            local,
+            local_capacity: config.local_capacity,
+            logs_dir,
+            hosts,
+            placements: Mutex::default(),
+        }
+    }
+
+    /// Remote host a container runs on; `None` for the controller host
+    fn remote(&self, name: &str) -> Option<&RemoteHost> {
+        let host = (*self.placements.lock().unwrap().hosts.get(name)?)?;
+        self.hosts.get(host)
+    }
+
+    /// External runners per host against their capacity, controller host first
+    pub fn capacity(&self) -> Vec<HostCapacity> {
+        let placements = self.placements.lock().unwrap();
+        let running = |host| placements.hosts.values().filter(|&&h| h == host).count();
+        let local = HostCapacity {
+            host: "local".to_string(),
+            capacity: self.local_capacity,
+            running: running(None),
+            reachable: true,
+        };
+        let remote = self.hosts.iter().enumerate().map(|(i, host)| HostCapacity {
+            host: host.name().to_string(),
+            capacity: host.config.capacity,
+            running: running(Some(i)),
+            reachable: !placements.unreachable.contains(&i),
+        });
+        [local].into_iter().chain(remote).collect()
+    }
+
+    /// List the containers of every host and update where each one runs.
+    /// Containers of a host that can't be reached are still reported, so
+    /// they aren't taken for gone.
+    async fn refresh(&self) -> Result<Vec<String>, BackendError> {
+        let local = self.local.list_all().await?;
+        let listings = join_all(self.hosts.iter().map(RemoteHost::list)).await;
+
+        let mut placements = self.placements.lock().unwrap();
+        let previous = std::mem::take(&mut placements.hosts);
+        placements.hosts = previous
+            .iter()
+            .filter(|(name, _)| placements.spawning.contains(*name))
+            .map(|(name, host)| (name.clone(), *host))
+            .collect();
+        for name in local.iter().filter(|name| name_has_prefix(name, &['x'])) {
+            placements.hosts.insert(name.clone(), None);
+        }
+
+        let mut names = local;
+        for (i, listing) in listings.into_iter().enumerate() {
+            let found = match listing {
+                Ok(found) => {
+                    placements.unreachable.remove(&i);
+                    found
+                }
+                Err(e) => {
+                    if placements.unreachable.insert(i) {
+                        let host = self.hosts[i].name();
+                        warn!(host = %host, error = %e, "Remote host unreachable");
+                    }
+                    previous
+                        .iter()
+                        .filter(|(_, host)| **host == Some(i))
+                        .map(|(name, _)| name.clone())
+                        .collect()
+                }
+            };
+            for name in found {
+                placements.hosts.insert(name.clone(), Some(i));
+                names.push(name);
+            }
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Pick a host for a new container and reserve its place there
+    fn place(&self, name: &str, options: &SpawnOptions<'_>) -> Result<Option<usize>, BackendError> {
+        if !name_has_prefix(name, &['x']) {
+            return Ok(None);
+        }
+        let needs_local = options.template.is_some()
+            || !options.devices.is_empty()
+            || !options.binds.is_empty()
+            || options.egress != EgressMode::Full;
+
+        let mut placements = self.placements.lock().unwrap();
+        let mut local_used = 0;
+        let mut used = vec![0; self.hosts.len()];
+        for (_, host) in placements.hosts.iter().filter(|(n, _)| *n != name) {
+            match host {
+                None => local_used += 1,
+                Some(i) => used[*i] += 1,
+            }
+        }
+        let host = if needs_local || local_used < self.local_capacity {
+            None
+        } else {
+            let free = |i: usize| self.hosts[i].config.capacity.saturating_sub(used[i]);
+            let host = (0..self.hosts.len())
+                .filter(|i| !placements.unreachable.contains(i) && free(*i) > 0)
+                .max_by_key(|&i| (free(i), std::cmp::Reverse(i)))
+                .ok_or_else(|| BackendError::NoCapacity {
+                    name: name.to_string(),
+                })?;
+            Some(host)
+        };
+        placements.hosts.insert(name.to_string(), host);
+        placements.spawning.insert(name.to_string());
+        Ok(host)
+    }
+
+    async fn spawn_remote(
+        &self,
+        host: &RemoteHost,
+        name: &str,
+        token: &SecretString,
+        options: &SpawnOptions<'_>,
+    ) -> Result<(), BackendError> {
+        let mut labels = options.labels.to_vec();
+        labels.extend(self.naming().instance_label().map(str::to_string));
+        let labels = labels.join(",");
+        let runner_name = self.runner_name(name);
+        let mut files = vec![
+            (RUNNER_TOKEN_PATH, token.expose(), FileMode::Root),
+            (RUNNER_LABELS_PATH, labels.as_str(), FileMode::Public),
+            (RUNNER_NAME_PATH, runner_name.as_str(), FileMode::Public),
+        ];
+        files.extend(options.files.iter().copied());
+        host.spawn(name, &files).await
+    }
+}
+
+#[async_trait]
+impl Backend for Fleet {
+    fn name(&self) -> &'static str {
+        self.local.name()
+    }
+
+    fn naming(&self) -> &RunnerNaming {
+        self.local.naming()
+    }
+
+    async fn list(&self) -> Result<Vec<String>, BackendError> {
+        let names = self.refresh().await?;
+        Ok(names.into_iter().filter(|n| name_has_prefix(n, &['r'])).collect())
+    }
+
+    async fn list_external(&self) -> Result<Vec<String>, BackendError> {
+        let names = self.refresh().await?;
+        Ok(names.into_iter().filter(|n| name_has_prefix(n, &['x'])).collect())
+    }
+
+    async fn list_all(&self) -> Result<Vec<String>, BackendError> {
+        self.refresh().await
+    }
+
+    async fn spawn_container(
+        &self,
+        name: &str,
+        token: &SecretString,
+        options: &SpawnOptions<'_>,
+    ) -> Result<(), BackendError> {
+        // A container of the same name elsewhere is replaced, like the local one
+        if let Some(host) = self.remote(name) {
+            host.cleanup(name).await?;
+            self.placements.lock().unwrap().hosts.remove(name);
+        }
+
+        let host = self.place(name, options)?;
+        let result = match host {
+            None => self.local.spawn_container(name, token, options).await,
+            Some(i) => self.spawn_remote(&self.hosts[i], name, token, options).await,
+        };
+
+        let mut placements = self.placements.lock().unwrap();
+        placements.spawning.remove(name);
+        if result.is_err() {
+            placements.hosts.remove(name);
+        }
+        result
+    }
+
+    async fn is_runner_completed(&self, name: &str) -> Result<bool, BackendError> {
+        match self.remote(name) {
+            Some(host) => host.is_runner_completed(name).await,
+            None => self.local.is_runner_completed(name).await,
+        }
+    }
+
+    async fn reregister(&self, name: &str, token: &SecretString) -> Result<(), BackendError> {
+        match self.remote(name) {
+            Some(host) => host.reregister(name, token).await,
+            None => self.local.reregister(name, token).await,
+        }
+    }
+
+    async fn cleanup_container(&self, name: &str) -> Result<(), BackendError> {
+        let Some(host) = self.remote(name) else {
+            return self.local.cleanup_container(name).await;
+        };
+        if let Err(e) = self.capture_logs(name).await {
+            warn!(name = %name, error = %e, "Failed to capture runner logs");
+        }
+        host.cleanup(name).await?;
+        self.placements.lock().unwrap().hosts.remove(name);
+        Ok(())
+    }
+
+    async fn quarantine(&self, name: &str) -> Result<PathBuf, BackendError> {
+        let Some(host) = self.remote(name) else {
+            return self.local.quarantine(name).await;
+        };
+        if let Err(e) = self.capture_logs(name).await {
+            warn!(name = %name, error = %e, "Failed to capture runner logs");
+        }
+        let target = host.quarantine(name).await?;
+        self.placements.lock().unwrap().hosts.remove(name);
+        Ok(target)
+    }
+
+    async fn capture_logs(&self, name: &str) -> Result<(), BackendError> {
+        let Some(host) = self.remote(name) else {
+            return self.local.capture_logs(name).await;
+        };
+        let logs = host.journal(name, None).await?;
+        store_captured_logs(&self.logs_dir, name, unix_now(), &logs)?;
+        debug!(host = %host.name(), name = %name, "Captured runner logs");
+        Ok(())
+    }
+
+    async fn resource_usage(&self, name: &str) -> Result<ResourceUsage, BackendError> {
+        match self.remote(name) {
+            Some(host) => host.resource_usage(name).await,
+            None => self.local.resource_usage(name).await,
+        }
+    }
+
+    async fn runner_logs(&self, name: &str, tail: usize) -> Result<String, BackendError> {
+        match self.remote(name) {
+            Some(host) => host.journal(name, Some(tail)).await,
+            None => self.local.runner_logs(name, tail).await,
+        }
+    }
+
+    fn follow_runner_logs(&self, name: &str, tail: usize) -> Result<Child, BackendError> {
+        let Some(host) = self.remote(name) else {
+            return self.local.follow_runner_logs(name, tail);
+        };
+        Command::new("ssh")
+            .args(host.ssh_args(&host.journal_args(name, Some(tail), true)))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| host.exec_error(e))
+    }
+
+    fn write_container_file(
+        &self,
+        name: &str,
+        path: &str,
+        contents: &str,
+        mode: FileMode,
+    ) -> Result<(), BackendError> {
+        let Some(host) = self.remote(name) else {
+            return self.local.write_container_file(name, path, contents, mode);
+        };
+        let args = host.write_args(name, path, mode);
+        let output = host.output_blocking(&args, Some(contents))?;
+        host.stdout(&args, output).map(|_| ())
+    }
+
+    fn read_container_file(&self, name: &str, path: &str) -> Option<String> {
+        let Some(host) = self.remote(name) else {
+            return self.local.read_container_file(name, path);
+        };
+        let args = host.read_args(name, path);
+        let output = host.output_blocking(&args, None).ok()?;
+        host.stdout(&args, output).ok()
+    }
+
+    fn read_worker_log(&self, name: &str) -> Option<String> {
+        match self.remote(name) {
+            Some(host) => host.worker_log(name),
+            None => self.local.read_worker_log(name),
+        }
+    }
+
+    fn captured_logs_path(&self, name: &str) -> Option<PathBuf> {
+        self.local.captured_logs_path(name)
+    }
+
+    fn read_captured_logs(&self, name: &str) -> Option<String> {
+        self.local.read_captured_logs(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::FakeBackend;
+
+    #[test]
+    fn test_fleet_placement() {
+        let host: RemoteHostConfig = "builder-1=root@10.0.0.5:2".parse().unwrap();
+        assert_eq!((host.destination.as_str(), host.capacity), ("root@10.0.0.5", 2));
+        assert!("local=root@10.0.0.6:2".parse::<RemoteHostConfig>().is_err());
+        assert!("builder-2=root@10.0.0.6:0".parse::<RemoteHostConfig>().is_err());
+
+        let config = RemoteConfig {
+            hosts: vec![host, "builder-2=root@10.0.0.6:1".parse().unwrap()],
+            runtime: RemoteRuntime::new("nixos", None).unwrap(),
+            ssh_key: None,
+            local_capacity: 1,
+        };
+        let logs_dir = PathBuf::from("/nonexistent");
+        let fleet = Fleet::new(Arc::new(FakeBackend::default()), &config, logs_dir);
+        let options = SpawnOptions::default();
+
+        // The pool stays local; external runners fill the controller host first,
+        // then the remote host with the most room
+        assert_eq!(fleet.place("r0", &options).unwrap(), None);
+        assert_eq!(fleet.place("x0000001", &options).unwrap(), None);
+        assert_eq!(fleet.place("x0000002", &options).unwrap(), Some(0));
+        assert_eq!(fleet.place("x0000003", &options).unwrap(), Some(0));
+        assert_eq!(fleet.place("x0000004", &options).unwrap(), Some(1));
+        assert!(matches!(
+            fleet.place("x0000005", &options),
+            Err(BackendError::NoCapacity { .. })
+        ));
+
+        // Jobs needing the controller host go there regardless
+        let isolated = SpawnOptions {
+            egress: EgressMode::Isolated,
+            ..SpawnOptions::default()
+        };
+        assert_eq!(fleet.place("x0000005", &isolated).unwrap(), None);
+        let capacity = fleet.capacity();
+        assert_eq!((capacity[0].running, capacity[1].running, capacity[2].running), (2, 2, 1));
+    }
+
+    #[test]
+    fn test_write_script_modes() {
+        let token = write_script(FileMode::Root);
+        assert!(token.contains(r#"chmod 600 "$1" && cat"#) && !token.contains("chown"));
+        assert!(write_script(FileMode::Runner).contains(r#"chown 1000 "$1" && cat"#));
+        assert!(write_script(FileMode::Public).contains("chmod 644"));
+    }
+
+    #[test]
+    fn test_token_delivery() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let config = RemoteConfig {
+            hosts: vec!["builder-1=root@10.0.0.5:2".parse().unwrap()],
+            runtime: RemoteRuntime::new("nixos", None).unwrap(),
+            ssh_key: None,
+            local_capacity: 1,
+        };
+        let logs_dir = PathBuf::from("/nonexistent");
+        let fleet = Fleet::new(Arc::new(FakeBackend::default()), &config, logs_dir);
+        let host = &fleet.hosts[0];
+
+        // The token travels on stdin, never on the ssh command line
+        let token = "fake-registration-token";
+        let args = host.write_args("x0000001", RUNNER_TOKEN_PATH, FileMode::Root);
+        assert!(!host.ssh_args(&args).iter().any(|arg| arg.contains(token)));
+        let target = format!("{}/x0000001/{}", NIXOS_CONTAINERS_DIR, RUNNER_TOKEN_PATH);
+        assert_eq!(args.last(), Some(&target));
+
+        // Run the same script locally against a scratch file
+        let dir = std::env::temp_dir().join(format!("remote-token-{}", std::process::id()));
+        let path = dir.join(RUNNER_TOKEN_PATH);
+        let mut child = std::process::Command::new(&args[0])
+            .args(&args[1..args.len() - 1])
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(token.as_bytes()).unwrap();
+        assert!(child.wait().unwrap().success());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), token);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
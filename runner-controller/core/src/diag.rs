@@ -0,0 +1,129 @@
+use serde::Serialize;
+
+use crate::state::parse_utc_timestamp;
+
+/// Compact outcome of a job, extracted from the runner's worker diagnostic log
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct JobSummary {
+    /// Overall job result as reported by the runner (e.g. `Succeeded`, `Failed`)
+    pub result: Option<String>,
+    /// Coarse reason for a failed job, for fleet-wide grouping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_category: Option<&'static str>,
+    pub steps: Vec<StepSummary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StepSummary {
+    pub name: String,
+    pub result: Option<String>,
+    pub duration_secs: Option<u64>,
+}
+
+/// Parse a runner `_diag/Worker_*.log`. Returns `None` if no job steps were found.
+pub fn parse_worker_log(log: &str) -> Option<JobSummary> {
+    let mut summary = JobSummary::default();
+    // Step being processed and when it started
+    let mut current: Option<(StepSummary, Option<u64>)> = None;
+
+    for line in log.lines() {
+        let at = line_timestamp(line);
+
+        if let Some(rest) = line.split("Processing step: DisplayName='").nth(1) {
+            close_step(&mut summary.steps, &mut current, at);
+            let name = rest.split('\'').next().unwrap_or_default().to_string();
+            let step = StepSummary {
+                name,
+                result: None,
+                duration_secs: None,
+            };
+            current = Some((step, at));
+        } else if let Some(result) = line.split("Step result: ").nth(1) {
+            if let Some((step, _)) = current.as_mut() {
+                step.result = Some(result.trim().to_string());
+            }
+        } else if let Some(result) = line.split("Job result after all job steps finish: ").nth(1) {
+            close_step(&mut summary.steps, &mut current, at);
+            summary.result = Some(result.trim().to_string());
+        }
+    }
+    close_step(&mut summary.steps, &mut current, None);
+
+    if summary.steps.is_empty() {
+        return None;
+    }
+
+    summary.failure_category = failure_category(log, &summary);
+    Some(summary)
+}
+
+/// Finish the step in progress, timing it up to `at`
+fn close_step(
+    steps: &mut Vec<StepSummary>,
+    current: &mut Option<(StepSummary, Option<u64>)>,
+    at: Option<u64>,
+) {
+    if let Some((mut step, started)) = current.take() {
+        if let (Some(start), Some(end)) = (started, at) {
+            step.duration_secs = Some(end.saturating_sub(start));
+        }
+        steps.push(step);
+    }
+}
+
+/// Classify why a job did not succeed
+fn failure_category(log: &str, summary: &JobSummary) -> Option<&'static str> {
+    match summary.result.as_deref() {
+        Some("Succeeded") => return None,
+        Some("Canceled") | Some("Cancelled") => return Some("cancelled"),
+        _ => {}
+    }
+
+    if log.contains("No space left on device") {
+        Some("disk_full")
+    } else if log.contains("exceeded the maximum execution time") {
+        Some("timeout")
+    } else if summary
+        .steps
+        .iter()
+        .any(|s| s.result.as_deref() == Some("Failed"))
+    {
+        Some("step_failed")
+    } else {
+        Some("unknown")
+    }
+}
+
+/// Unix time of a `[2024-01-15 10:23:45Z INFO ...]` log line prefix
+fn line_timestamp(line: &str) -> Option<u64> {
+    parse_utc_timestamp(line.strip_prefix('[')?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_worker_log() {
+        let log = "\
+[2024-01-15 10:00:00Z INFO JobRunner] Starting the job execution context.
+[2024-01-15 10:00:01Z INFO StepsRunner] Processing step: DisplayName='Set up job'
+[2024-01-15 10:00:03Z INFO StepsRunner] Step result: Succeeded
+[2024-01-15 10:00:03Z INFO StepsRunner] Processing step: DisplayName='Run nix build'
+[2024-01-15 10:02:03Z INFO StepsRunner] Step result: Failed
+[2024-01-15 10:02:04Z INFO JobRunner] Job result after all job steps finish: Failed
+";
+
+        assert_eq!(line_timestamp("[1970-01-02 00:00:01Z INFO x]"), Some(86401));
+
+        let summary = parse_worker_log(log).unwrap();
+        assert_eq!(summary.result.as_deref(), Some("Failed"));
+        assert_eq!(summary.failure_category, Some("step_failed"));
+        assert_eq!(summary.steps.len(), 2);
+        assert_eq!(summary.steps[0].duration_secs, Some(2));
+        assert_eq!(summary.steps[1].name, "Run nix build");
+        assert_eq!(summary.steps[1].duration_secs, Some(121));
+
+        assert_eq!(parse_worker_log("no steps here"), None);
+    }
+}
@@ -0,0 +1,89 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpStream;
+
+use crate::config::{Config, IpFamily};
+use crate::github::{GitHubClient, GITHUB_API_HOST};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run connectivity diagnostics against the GitHub API and print a report.
+/// Returns true if the API is reachable with the configured client.
+pub async fn run(config: &Config, github: &GitHubClient) -> bool {
+    let network = &config.network;
+    println!("runner-controller doctor");
+    println!("  ip family: {:?}", network.ip_family);
+    println!();
+
+    // DNS
+    let addrs: Vec<SocketAddr> = if network.resolve.is_empty() {
+        match tokio::net::lookup_host((GITHUB_API_HOST, 443)).await {
+            Ok(addrs) => {
+                let addrs: Vec<SocketAddr> = addrs.collect();
+                println!("[ok]   DNS {} -> {} address(es)", GITHUB_API_HOST, addrs.len());
+                addrs
+            }
+            Err(e) => {
+                println!("[fail] DNS {}: {}", GITHUB_API_HOST, e);
+                Vec::new()
+            }
+        }
+    } else {
+        println!("[ok]   DNS bypassed (GITHUB_API_RESOLVE)");
+        network
+            .resolve
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, 443))
+            .collect()
+    };
+
+    let v4 = addrs.iter().filter(|a| a.is_ipv4()).count();
+    let v6 = addrs.iter().filter(|a| a.is_ipv6()).count();
+    println!("       {} IPv4, {} IPv6", v4, v6);
+
+    match network.ip_family {
+        IpFamily::Ipv6 if v6 == 0 => println!("[warn] IPv6 forced but no AAAA records resolved"),
+        IpFamily::Ipv4 if v4 == 0 => println!("[warn] IPv4 forced but no A records resolved"),
+        _ => {}
+    }
+
+    // TCP reachability per address; through a proxy only the proxy is dialed
+    if network.uses_proxy() {
+        println!("[info] GitHub API requests go through a proxy, skipping direct TCP checks");
+    }
+    for addr in addrs.iter().filter(|_| !network.uses_proxy()) {
+        let skipped = match network.ip_family {
+            IpFamily::Auto => false,
+            IpFamily::Ipv4 => addr.is_ipv6(),
+            IpFamily::Ipv6 => addr.is_ipv4(),
+        };
+        if skipped {
+            continue;
+        }
+
+        let started = Instant::now();
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => println!("[ok]   TCP {} ({} ms)", addr, started.elapsed().as_millis()),
+            Ok(Err(e)) => println!("[fail] TCP {}: {}", addr, e),
+            Err(_) => println!("[fail] TCP {}: timed out", addr),
+        }
+    }
+
+    // Full HTTPS request through the configured client
+    let started = Instant::now();
+    match github.list_runners().await {
+        Ok(runners) => {
+            println!(
+                "[ok]   GitHub API: {} runner(s) listed ({} ms)",
+                runners.len(),
+                started.elapsed().as_millis()
+            );
+            true
+        }
+        Err(e) => {
+            println!("[fail] GitHub API: {:#}", e);
+            false
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::str::FromStr;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, warn};
+use utoipa::ToSchema;
+
+use crate::container::BackendError;
+
+/// Where a runner container may connect to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EgressMode {
+    /// Unrestricted (NAT through the host)
+    #[default]
+    Full,
+    /// Only the hosts and networks of the egress allowlist, plus DNS
+    Allowlist,
+    /// Nothing beyond the host itself, e.g. a proxy or cache running there
+    Isolated,
+}
+
+impl FromStr for EgressMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "full" => Ok(Self::Full),
+            "allowlist" => Ok(Self::Allowlist),
+            "isolated" => Ok(Self::Isolated),
+            other => anyhow::bail!("unknown egress mode '{}'", other),
+        }
+    }
+}
+
+/// nftables table holding a container's egress rules
+fn table_name(container: &str) -> String {
+    format!("rc-egress-{}", container)
+}
+
+/// Resolve allowlist entries (host names, addresses or CIDR networks) to
+/// nftables address expressions. Names that don't resolve are skipped.
+pub async fn resolve(allowlist: &[String]) -> Vec<String> {
+    let mut addrs = Vec::new();
+    for entry in allowlist {
+        if is_literal(entry) {
+            addrs.push(entry.clone());
+            continue;
+        }
+        match tokio::net::lookup_host((entry.as_str(), 443)).await {
+            Ok(resolved) => addrs.extend(resolved.map(|a| a.ip().to_string())),
+            Err(e) => warn!(host = %entry, error = %e, "Failed to resolve egress allowlist entry"),
+        }
+    }
+    addrs.sort();
+    addrs.dedup();
+    addrs
+}
+
+/// Whether an allowlist entry is an address or CIDR network rather than a name
+fn is_literal(entry: &str) -> bool {
+    let (ip, prefix) = match entry.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix)),
+        None => (entry, None),
+    };
+    ip.parse::<IpAddr>().is_ok() && prefix.is_none_or(|p| p.parse::<u8>().is_ok())
+}
+
+/// nftables script confining traffic the host forwards from a container's
+/// `ve-<name>` interface. Each container gets its own table, so removing the
+/// table removes exactly its rules.
+pub fn ruleset(container: &str, mode: EgressMode, addrs: &[String]) -> String {
+    let table = table_name(container);
+    let iface = format!("ve-{}", container);
+    let mut rules = vec![format!("iifname \"{}\" ct state established,related accept", iface)];
+    if mode == EgressMode::Allowlist {
+        // Containers resolve names themselves; DNS could tunnel data out, the
+        // allowlist guards against accidents rather than determined jobs
+        rules.push(format!(
+            "iifname \"{}\" meta l4proto {{ tcp, udp }} th dport 53 accept",
+            iface
+        ));
+        let (v6, v4): (Vec<&String>, Vec<&String>) = addrs.iter().partition(|a| a.contains(':'));
+        for (family, addrs) in [("ip", v4), ("ip6", v6)] {
+            if !addrs.is_empty() {
+                let set: Vec<&str> = addrs.iter().map(|a| a.as_str()).collect();
+                rules.push(format!(
+                    "iifname \"{}\" {} daddr {{ {} }} accept",
+                    iface,
+                    family,
+                    set.join(", ")
+                ));
+            }
+        }
+    }
+    rules.push(format!("iifname \"{}\" drop", iface));
+
+    // Declaring the table before deleting it makes the replacement atomic
+    // whether or not it existed
+    let mut script = format!("table inet {0}\ndelete table inet {0}\ntable inet {0} {{\n", table);
+    script.push_str("  chain forward {\n    type filter hook forward priority -10; policy accept;\n");
+    for rule in rules {
+        script.push_str(&format!("    {}\n", rule));
+    }
+    script.push_str("  }\n}\n");
+    script
+}
+
+/// Install a container's egress rules; `Full` removes any left over
+pub async fn apply(
+    container: &str,
+    mode: EgressMode,
+    allowlist: &[String],
+) -> Result<(), BackendError> {
+    if mode == EgressMode::Full {
+        remove(container).await;
+        return Ok(());
+    }
+    let addrs = match mode {
+        EgressMode::Allowlist => resolve(allowlist).await,
+        _ => Vec::new(),
+    };
+    debug!(container, mode = ?mode, addrs = addrs.len(), "Applying egress policy");
+
+    let exec_failed = |source| BackendError::Exec {
+        program: "nft".to_string(),
+        source,
+    };
+    let mut child = Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(exec_failed)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(ruleset(container, mode, &addrs).as_bytes())
+            .await
+            .map_err(exec_failed)?;
+    }
+    let output = child.wait_with_output().await.map_err(exec_failed)?;
+    if !output.status.success() {
+        return Err(BackendError::Command {
+            command: "nft (egress policy)".to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Remove a container's egress rules, if any
+pub async fn remove(container: &str) {
+    let _ = Command::new("nft")
+        .args(["delete", "table", "inet", &table_name(container)])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist_ruleset() {
+        assert!(is_literal("140.82.112.0/20"));
+        assert!(is_literal("2606:50c0::1"));
+        assert!(!is_literal("github.com"));
+        assert!(!is_literal("10.0.0.0/x"));
+
+        let addrs = vec!["140.82.112.0/20".to_string(), "2606:50c0::1".to_string()];
+        let rules = ruleset("r0", EgressMode::Allowlist, &addrs);
+        assert!(rules.starts_with("table inet rc-egress-r0\ndelete table inet rc-egress-r0\n"));
+        assert!(rules.contains("iifname \"ve-r0\" ip daddr { 140.82.112.0/20 } accept"));
+        assert!(rules.contains("iifname \"ve-r0\" ip6 daddr { 2606:50c0::1 } accept"));
+        assert!(rules.ends_with("    iifname \"ve-r0\" drop\n  }\n}\n"));
+
+        let isolated = ruleset("x0000001", EgressMode::Isolated, &[]);
+        assert!(!isolated.contains("daddr"));
+        assert!(!isolated.contains("dport 53"));
+        assert_eq!("allowlist".parse::<EgressMode>().unwrap(), EgressMode::Allowlist);
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+/// A spawn refused because the repository already holds its share of slots
+/// while other repositories are waiting
+#[derive(Debug, Clone, Error)]
+#[error("fair share exceeded: {repository} holds {running} of its {share} slots while others wait")]
+pub struct FairShareExceeded {
+    pub repository: String,
+    pub running: usize,
+    pub share: usize,
+}
+
+/// Splits external runner slots between repositories by weight, so one repo
+/// with a huge matrix can't hold every slot while others queue.
+///
+/// A repository counts as competing while it holds runners or asked for one
+/// within the demand window; a repository alone may use every slot. Once
+/// another one competes, a repository at or over its share gets no new
+/// runners until it drops below, which frees slots for the others as its
+/// jobs finish.
+#[derive(Debug)]
+pub struct FairShare {
+    enabled: bool,
+    /// Lowercased repository to weight; unlisted repositories weigh 1
+    weights: HashMap<String, u32>,
+    window: Duration,
+    /// Last spawn request per lowercased repository
+    demand: Mutex<HashMap<String, Instant>>,
+}
+
+impl FairShare {
+    pub fn new(enabled: bool, weights: &[(String, u32)], window: Duration) -> Self {
+        Self {
+            enabled,
+            weights: weights
+                .iter()
+                .map(|(repo, weight)| (repo.to_ascii_lowercase(), *weight))
+                .collect(),
+            window,
+            demand: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn weight(&self, repo: &str) -> u32 {
+        self.weights.get(repo).copied().unwrap_or(1)
+    }
+
+    /// Record a spawn request and check it against the repository's share.
+    /// `running` counts runners per repository; `capacity` is the slot total.
+    pub fn check(
+        &self,
+        repository: &str,
+        running: &HashMap<String, usize>,
+        capacity: usize,
+    ) -> Result<(), FairShareExceeded> {
+        self.check_at(repository, running, capacity, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        repository: &str,
+        running: &HashMap<String, usize>,
+        capacity: usize,
+        now: Instant,
+    ) -> Result<(), FairShareExceeded> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let repo = repository.to_ascii_lowercase();
+        let running: HashMap<String, usize> = running
+            .iter()
+            .map(|(r, n)| (r.to_ascii_lowercase(), *n))
+            .collect();
+
+        let mut demand = self.demand.lock().unwrap();
+        demand.insert(repo.clone(), now);
+        demand.retain(|_, at| now.saturating_duration_since(*at) < self.window);
+
+        let mut competing: Vec<&String> = demand.keys().collect();
+        competing.extend(running.iter().filter(|(_, n)| **n > 0).map(|(r, _)| r));
+        competing.sort();
+        competing.dedup();
+
+        let total: u32 = competing.iter().map(|r| self.weight(r)).sum();
+        let share = (capacity as u64 * u64::from(self.weight(&repo)) / u64::from(total.max(1)))
+            .max(1) as usize;
+        let held = running.get(&repo).copied().unwrap_or(0);
+        if competing.len() > 1 && held >= share {
+            return Err(FairShareExceeded {
+                repository: repository.to_string(),
+                running: held,
+                share,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fair_share() {
+        let weights = vec![("org/big".to_string(), 2)];
+        let fair = FairShare::new(true, &weights, Duration::from_secs(600));
+        let now = Instant::now();
+        let running = |pairs: &[(&str, usize)]| {
+            pairs.iter().map(|(r, n)| (r.to_string(), *n)).collect::<HashMap<_, _>>()
+        };
+
+        // Alone, a repository may use every slot
+        assert!(fair.check_at("org/a", &running(&[("org/a", 9)]), 10, now).is_ok());
+
+        // org/b asked too: shares are 5/5 and org/a is over its share
+        assert!(fair.check_at("org/b", &running(&[("org/a", 10)]), 10, now).is_ok());
+        let err = fair
+            .check_at("org/a", &running(&[("org/a", 9)]), 10, now)
+            .unwrap_err();
+        assert_eq!((err.running, err.share), (9, 5));
+        assert!(fair.check_at("org/a", &running(&[("org/a", 4)]), 10, now).is_ok());
+
+        // Weighted: org/big gets 2 of every 4 slots next to org/a and org/b
+        let held = running(&[("org/a", 3), ("org/b", 3), ("Org/Big", 6)]);
+        assert_eq!(fair.check_at("org/big", &held, 12, now).unwrap_err().share, 6);
+
+        // Demand expires after the window
+        let later = now + Duration::from_secs(601);
+        assert!(fair.check_at("org/a", &running(&[("org/a", 9)]), 10, later).is_ok());
+
+        let disabled = FairShare::new(false, &[], Duration::from_secs(600));
+        assert!(disabled.check("org/a", &running(&[("org/a", 9)]), 10).is_ok());
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::breaker::BreakerState;
+use super::budget::BudgetStatus;
+use super::client::GitHubClient;
+use super::error::GitHubError;
+use super::types::{
+    CheckRunOutput, RepoMetadata, Runner, RunnerRelease, WorkflowJob, WorkflowRun,
+};
+use crate::secrets::SecretString;
+
+/// The GitHub operations the pool controller and the HTTP API depend on.
+/// `GitHubClient` implements it against the REST API; `FakeGitHub` keeps
+/// runners and jobs in memory for tests.
+#[async_trait]
+pub trait GitHubApi: Send + Sync {
+    /// Cheap request to test whether the API is reachable
+    async fn probe(&self) -> Result<(), GitHubError>;
+    fn circuit_state(&self) -> BreakerState;
+    /// Seconds until an open circuit admits a probe
+    fn circuit_retry_in(&self) -> Option<u64>;
+    /// Number of consecutive requests rejected as unauthorized
+    fn consecutive_auth_failures(&self) -> u32;
+    /// Rate limit budget as of the last response, if GitHub reported one
+    fn rate_budget(&self) -> Option<BudgetStatus>;
+
+    /// Token for registering a new runner
+    async fn get_registration_token(&self) -> Result<SecretString, GitHubError>;
+    async fn list_runners(&self) -> Result<Vec<Runner>, GitHubError>;
+    async fn delete_runner(&self, runner_id: u64) -> Result<(), GitHubError>;
+    /// Delete the runner registered as `name`; none being registered is fine
+    async fn delete_runner_by_name(&self, name: &str) -> Result<(), GitHubError>;
+
+    async fn get_job(&self, job_id: u64) -> Result<WorkflowJob, GitHubError>;
+    async fn get_run(&self, run_id: u64) -> Result<WorkflowRun, GitHubError>;
+    /// Jobs waiting for a runner, longest waiting first
+    async fn list_queued_jobs(&self) -> Result<Vec<WorkflowJob>, GitHubError>;
+    async fn list_in_progress_jobs(&self) -> Result<Vec<WorkflowJob>, GitHubError>;
+
+    async fn repo_metadata(&self, repo: &str) -> Result<RepoMetadata, GitHubError>;
+    /// Recent actions runner releases, newest first
+    async fn list_runner_releases(&self) -> Result<Vec<RunnerRelease>, GitHubError>;
+    /// Metadata of `repo` if already known, without any request
+    fn cached_repo_metadata(&self, repo: &str) -> Option<RepoMetadata>;
+
+    async fn create_check_run(&self, head_sha: &str, output: CheckRunOutput) -> Result<u64>;
+    async fn update_check_run(
+        &self,
+        id: u64,
+        output: CheckRunOutput,
+        conclusion: Option<&str>,
+    ) -> Result<()>;
+    /// Set the `self-hosted runner` commit status of `sha`
+    async fn create_commit_status(&self, sha: &str, state: &str, description: &str) -> Result<()>;
+
+    /// Job-scoped token with `permissions`; `None` without a GitHub App
+    async fn mint_job_token(&self, permissions: &BTreeMap<String, String>)
+        -> Result<Option<SecretString>>;
+    async fn revoke_job_token(&self, token: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl GitHubApi for GitHubClient {
+    async fn probe(&self) -> Result<(), GitHubError> {
+        GitHubClient::probe(self).await
+    }
+
+    fn circuit_state(&self) -> BreakerState {
+        GitHubClient::circuit_state(self)
+    }
+
+    fn circuit_retry_in(&self) -> Option<u64> {
+        GitHubClient::circuit_retry_in(self)
+    }
+
+    fn consecutive_auth_failures(&self) -> u32 {
+        GitHubClient::consecutive_auth_failures(self)
+    }
+
+    fn rate_budget(&self) -> Option<BudgetStatus> {
+        GitHubClient::rate_budget(self)
+    }
+
+    async fn get_registration_token(&self) -> Result<SecretString, GitHubError> {
+        GitHubClient::get_registration_token(self).await
+    }
+
+    async fn list_runners(&self) -> Result<Vec<Runner>, GitHubError> {
+        GitHubClient::list_runners(self).await
+    }
+
+    async fn delete_runner(&self, runner_id: u64) -> Result<(), GitHubError> {
+        GitHubClient::delete_runner(self, runner_id).await
+    }
+
+    async fn delete_runner_by_name(&self, name: &str) -> Result<(), GitHubError> {
+        GitHubClient::delete_runner_by_name(self, name).await
+    }
+
+    async fn get_job(&self, job_id: u64) -> Result<WorkflowJob, GitHubError> {
+        GitHubClient::get_job(self, job_id).await
+    }
+
+    async fn get_run(&self, run_id: u64) -> Result<WorkflowRun, GitHubError> {
+        GitHubClient::get_run(self, run_id).await
+    }
+
+    async fn list_queued_jobs(&self) -> Result<Vec<WorkflowJob>, GitHubError> {
+        GitHubClient::list_queued_jobs(self).await
+    }
+
+    async fn list_in_progress_jobs(&self) -> Result<Vec<WorkflowJob>, GitHubError> {
+        GitHubClient::list_in_progress_jobs(self).await
+    }
+
+    async fn repo_metadata(&self, repo: &str) -> Result<RepoMetadata, GitHubError> {
+        GitHubClient::repo_metadata(self, repo).await
+    }
+
+    fn cached_repo_metadata(&self, repo: &str) -> Option<RepoMetadata> {
+        GitHubClient::cached_repo_metadata(self, repo)
+    }
+
+    async fn list_runner_releases(&self) -> Result<Vec<RunnerRelease>, GitHubError> {
+        GitHubClient::list_runner_releases(self).await
+    }
+
+    async fn create_check_run(&self, head_sha: &str, output: CheckRunOutput) -> Result<u64> {
+        GitHubClient::create_check_run(self, head_sha, output).await
+    }
+
+    async fn update_check_run(
+        &self,
+        id: u64,
+        output: CheckRunOutput,
+        conclusion: Option<&str>,
+    ) -> Result<()> {
+        GitHubClient::update_check_run(self, id, output, conclusion).await
+    }
+
+    async fn create_commit_status(&self, sha: &str, state: &str, description: &str) -> Result<()> {
+        GitHubClient::create_commit_status(self, sha, state, description).await
+    }
+
+    async fn mint_job_token(
+        &self,
+        permissions: &BTreeMap<String, String>,
+    ) -> Result<Option<SecretString>> {
+        GitHubClient::mint_job_token(self, permissions).await
+    }
+
+    async fn revoke_job_token(&self, token: &str) -> Result<()> {
+        GitHubClient::revoke_job_token(self, token).await
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use tracing::debug;
+
+use super::http_client;
+use super::types::*;
+use crate::config::NetworkConfig;
+
+const GITHUB_API_BASE: &str = "https://api.github.com";
+
+#[derive(Serialize)]
+struct JwtClaims {
+    iat: u64,
+    exp: u64,
+    iss: String,
+}
+
+#[derive(Serialize)]
+struct AccessTokenRequest<'a> {
+    repositories: Vec<&'a str>,
+    permissions: &'a BTreeMap<String, String>,
+}
+
+/// GitHub App credentials used to mint short-lived installation tokens for jobs
+pub struct GitHubApp {
+    client: Client,
+    app_id: String,
+    installation_id: u64,
+    /// Swapped in place when the key file is rotated
+    key: RwLock<EncodingKey>,
+}
+
+impl GitHubApp {
+    pub fn new(
+        app_id: String,
+        installation_id: u64,
+        private_key_pem: &[u8],
+        network: &NetworkConfig,
+    ) -> Result<Self> {
+        let key = EncodingKey::from_rsa_pem(private_key_pem)
+            .context("Failed to parse GitHub App private key")?;
+
+        let client = http_client(network)?;
+
+        Ok(Self {
+            client,
+            app_id,
+            installation_id,
+            key: RwLock::new(key),
+        })
+    }
+
+    /// Replace the private key, e.g. after the key file was rotated
+    pub fn set_key(&self, private_key_pem: &[u8]) -> Result<()> {
+        let key = EncodingKey::from_rsa_pem(private_key_pem)
+            .context("Failed to parse GitHub App private key")?;
+        *self.key.write().unwrap() = key;
+        Ok(())
+    }
+
+    /// Short-lived JWT identifying the app itself
+    fn app_jwt(&self) -> Result<String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+
+        // Backdate to tolerate clock drift; GitHub caps lifetime at 10 minutes
+        let claims = JwtClaims {
+            iat: now.saturating_sub(60),
+            exp: now + 540,
+            iss: self.app_id.clone(),
+        };
+
+        let key = self.key.read().unwrap();
+        jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &key)
+            .context("Failed to sign GitHub App JWT")
+    }
+
+    /// Mint an installation token limited to `repo` and `permissions`
+    pub async fn mint_installation_token(
+        &self,
+        repo: &str,
+        permissions: &BTreeMap<String, String>,
+    ) -> Result<InstallationToken> {
+        let url = format!(
+            "{}/app/installations/{}/access_tokens",
+            GITHUB_API_BASE, self.installation_id
+        );
+
+        // The API takes repository names without the owner
+        let repo_name = repo.rsplit('/').next().unwrap_or(repo);
+        let body = AccessTokenRequest {
+            repositories: vec![repo_name],
+            permissions,
+        };
+
+        let resp = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.app_jwt()?))
+            .header("Accept", "application/vnd.github.v3+json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to request installation token")?;
+
+        let status = resp.status();
+        if status != StatusCode::CREATED {
+            let body = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Installation token request failed ({}): {}", status, body);
+        }
+
+        resp.json::<InstallationToken>()
+            .await
+            .context("Failed to parse installation token response")
+    }
+
+    /// Revoke an installation token before it expires
+    pub async fn revoke_installation_token(&self, token: &str) -> Result<()> {
+        let url = format!("{}/installation/token", GITHUB_API_BASE);
+
+        let resp = self
+            .client
+            .delete(&url)
+            .header("Authorization", format!("token {}", token))
+            .header("Accept", "application/vnd.github.v3+json")
+            .send()
+            .await
+            .context("Failed to revoke installation token")?;
+
+        match resp.status() {
+            // 401: already expired or revoked
+            StatusCode::NO_CONTENT | StatusCode::UNAUTHORIZED => {
+                debug!("Installation token revoked");
+                Ok(())
+            }
+            status => anyhow::bail!("Installation token revocation failed: {}", status),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Health of the GitHub API as seen by the circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    /// Requests fail fast until the cooldown has passed
+    Open,
+    /// Cooldown passed; the next request decides whether to close or reopen
+    HalfOpen,
+}
+
+/// Stops calling GitHub after repeated failures so an outage doesn't turn into
+/// a retry storm, log spam and container churn
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Consecutive failed requests that open the circuit; 0 disables it
+    threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<BreakerInner>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerInner {
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            inner: Mutex::new(BreakerInner::default()),
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        let inner = self.inner.lock().unwrap();
+        match inner.opened_at {
+            None => BreakerState::Closed,
+            Some(at) if at.elapsed() < self.cooldown => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    /// Seconds until an open circuit lets a probe through
+    pub fn retry_in(&self) -> Option<u64> {
+        let inner = self.inner.lock().unwrap();
+        let at = inner.opened_at?;
+        Some(self.cooldown.saturating_sub(at.elapsed()).as_secs())
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.opened_at.take().is_some() {
+            info!("GitHub API reachable again, closing circuit breaker");
+        }
+        inner.failures = 0;
+    }
+
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.failures += 1;
+
+        let half_open = inner
+            .opened_at
+            .is_some_and(|at| at.elapsed() >= self.cooldown);
+        if half_open || (inner.opened_at.is_none() && inner.failures >= self.threshold) {
+            warn!(
+                failures = inner.failures,
+                cooldown = ?self.cooldown,
+                "GitHub API failing, opening circuit breaker"
+            );
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_transitions() {
+        let breaker = CircuitBreaker::new(2, Duration::ZERO);
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        breaker.record_failure();
+        // Zero cooldown: immediately ready for a probe
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(breaker.retry_in().unwrap() > 0);
+
+        let disabled = CircuitBreaker::new(0, Duration::ZERO);
+        disabled.record_failure();
+        assert_eq!(disabled.state(), BreakerState::Closed);
+    }
+}
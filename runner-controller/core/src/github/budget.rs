@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Length of GitHub's primary rate limit window
+const WINDOW_SECS: u64 = 3600;
+/// Shortest part of the window the spending pace is measured over, so a
+/// burst right after a reset doesn't stretch polling for the whole hour
+const MIN_PACE_SECS: u64 = 300;
+/// Longest stretch of the poll intervals while the budget is tight
+pub const MAX_POLL_STRETCH: f64 = 8.0;
+
+/// How much a request matters when the rate limit runs low
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Registering runners and cleaning up after them; may spend the reserve
+    Essential,
+    /// Listings and lookups that a later poll repeats anyway
+    Deferrable,
+}
+
+impl Priority {
+    /// Requests that change something are essential, reads deferrable.
+    /// `GET /rate_limit` is free and always goes through.
+    pub fn of(method: &Method, endpoint: &str) -> Self {
+        if *method != Method::GET || endpoint == "/rate_limit" {
+            Priority::Essential
+        } else {
+            Priority::Deferrable
+        }
+    }
+}
+
+/// Rate limit budget as last reported by GitHub
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BudgetStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Unix time the window resets
+    pub reset_at: u64,
+    /// Factor the controller stretches its poll intervals by so the
+    /// remaining requests last until the reset; 1 while the budget is ample
+    pub poll_stretch: f64,
+    /// Requests charged to each repository in the current window
+    pub spent: BTreeMap<String, u64>,
+}
+
+/// Schedules GitHub requests against the remaining rate limit: once only the
+/// reserve is left, or a repository used up its share, deferrable requests
+/// wait for the reset while registration and cleanup go on
+#[derive(Debug)]
+pub struct RateBudget {
+    /// Requests held back for essential calls
+    reserve: u64,
+    /// Requests per repository and window; `None` is unlimited
+    repo_limit: Option<u64>,
+    inner: Mutex<BudgetInner>,
+}
+
+#[derive(Debug, Default)]
+struct BudgetInner {
+    /// `None` until a response carried rate limit headers
+    window: Option<Window>,
+    spent: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    limit: u64,
+    remaining: u64,
+    reset_at: u64,
+}
+
+impl Window {
+    /// Factor to stretch polling by for the current pace to last until the reset
+    fn stretch(&self, reserve: u64, now: u64) -> f64 {
+        let left = self.reset_at.saturating_sub(now);
+        if left == 0 {
+            return 1.0;
+        }
+        let available = self.remaining.saturating_sub(reserve);
+        if available == 0 {
+            return MAX_POLL_STRETCH;
+        }
+        let elapsed = WINDOW_SECS.saturating_sub(left).max(MIN_PACE_SECS);
+        let used = self.limit.saturating_sub(self.remaining);
+        let projected = used as f64 * left as f64 / elapsed as f64;
+        (projected / available as f64).clamp(1.0, MAX_POLL_STRETCH)
+    }
+}
+
+impl RateBudget {
+    pub fn new(reserve: u64, repo_limit: Option<u64>) -> Self {
+        Self {
+            reserve,
+            repo_limit,
+            inner: Mutex::new(BudgetInner::default()),
+        }
+    }
+
+    /// Whether a request of `priority` against `repo` may be sent now;
+    /// otherwise the seconds until the window resets
+    pub fn admit(&self, priority: Priority, repo: Option<&str>, now: u64) -> Result<(), u64> {
+        if priority == Priority::Essential {
+            return Ok(());
+        }
+        let inner = self.inner.lock().unwrap();
+        let Some(window) = inner.window.filter(|w| w.reset_at > now) else {
+            return Ok(());
+        };
+        let wait = window.reset_at - now;
+        if window.remaining <= self.reserve {
+            return Err(wait);
+        }
+        let spent = repo.and_then(|r| inner.spent.get(r)).copied().unwrap_or(0);
+        if self.repo_limit.is_some_and(|limit| spent >= limit) {
+            return Err(wait);
+        }
+        Ok(())
+    }
+
+    /// Take the budget from a response's `x-ratelimit-*` headers, charging
+    /// the request to `repo` unless it was free (a 304)
+    pub fn record(&self, headers: &HeaderMap, repo: Option<&str>, charged: bool, now: u64) {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        if header("x-ratelimit-resource").is_some_and(|r| r != "core") {
+            return;
+        }
+        let number = |name: &str| header(name).and_then(|v| v.parse::<u64>().ok());
+        let mut inner = self.inner.lock().unwrap();
+        if let (Some(limit), Some(remaining), Some(reset_at)) = (
+            number("x-ratelimit-limit"),
+            number("x-ratelimit-remaining"),
+            number("x-ratelimit-reset"),
+        ) {
+            if inner.window.is_some_and(|w| w.reset_at != reset_at) {
+                inner.spent.clear();
+            }
+            inner.window = Some(Window {
+                limit,
+                remaining,
+                reset_at,
+            });
+        } else if inner.window.is_some_and(|w| w.reset_at <= now) {
+            inner.window = None;
+            inner.spent.clear();
+        }
+        if let Some(repo) = repo.filter(|_| charged) {
+            *inner.spent.entry(repo.to_ascii_lowercase()).or_default() += 1;
+        }
+    }
+
+    pub fn status(&self, now: u64) -> Option<BudgetStatus> {
+        let inner = self.inner.lock().unwrap();
+        let window = inner.window.filter(|w| w.reset_at > now)?;
+        Some(BudgetStatus {
+            limit: window.limit,
+            remaining: window.remaining,
+            reset_at: window.reset_at,
+            poll_stretch: window.stretch(self.reserve, now),
+            spent: inner.spent.clone(),
+        })
+    }
+}
+
+/// `owner/repo` of a `/repos/{owner}/{repo}/...` endpoint, lowercased
+pub fn endpoint_repo(endpoint: &str) -> Option<String> {
+    let mut parts = endpoint.strip_prefix("/repos/")?.splitn(3, '/');
+    let owner = parts.next().filter(|p| !p.is_empty())?;
+    let repo = parts.next().filter(|p| !p.is_empty())?;
+    let repo = repo.split(['?', '#']).next().unwrap_or(repo);
+    Some(format!("{}/{}", owner, repo).to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_budget() {
+        let headers = |remaining: u64, reset: u64| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-ratelimit-limit", "5000".parse().unwrap());
+            headers.insert("x-ratelimit-remaining", remaining.to_string().parse().unwrap());
+            headers.insert("x-ratelimit-reset", reset.to_string().parse().unwrap());
+            headers
+        };
+        let repo_of = |endpoint| endpoint_repo(endpoint).unwrap_or_default();
+        assert_eq!(repo_of("/repos/Owner/Repo/actions/runners"), "owner/repo");
+        assert_eq!(repo_of("/repos/actions/runner/releases?per_page=30"), "actions/runner");
+        assert_eq!(endpoint_repo("/rate_limit"), None);
+
+        let budget = RateBudget::new(500, Some(2));
+        let repo = Some("owner/repo");
+        assert!(budget.status(0).is_none());
+        assert!(budget.admit(Priority::Deferrable, repo, 0).is_ok());
+
+        // Half an hour in, 1000 requests spent: the pace lasts until the reset
+        budget.record(&headers(4000, 3600), repo, true, 1800);
+        let status = budget.status(1800).unwrap();
+        assert_eq!(status.poll_stretch, 1.0);
+        assert_eq!(status.spent["owner/repo"], 1);
+        assert!(budget.admit(Priority::Deferrable, repo, 1800).is_ok());
+
+        // The repository's share is used up; other repositories go on
+        budget.record(&headers(3999, 3600), repo, true, 1800);
+        assert_eq!(budget.admit(Priority::Deferrable, repo, 1800), Err(1800));
+        assert!(budget.admit(Priority::Deferrable, Some("other/repo"), 1800).is_ok());
+        assert!(budget.admit(Priority::Essential, repo, 1800).is_ok());
+
+        // 3500 spent in half an hour with 1000 left over the reserve: stretch
+        budget.record(&headers(1500, 3600), None, true, 1800);
+        assert_eq!(budget.status(1800).unwrap().poll_stretch, 3.5);
+        budget.record(&headers(400, 3600), None, true, 1800);
+        assert_eq!(budget.status(1800).unwrap().poll_stretch, MAX_POLL_STRETCH);
+        assert!(budget.admit(Priority::Deferrable, None, 1800).is_err());
+
+        // A new window starts over
+        budget.record(&headers(4999, 7200), repo, true, 3700);
+        assert!(budget.admit(Priority::Deferrable, repo, 3700).is_ok());
+        assert_eq!(budget.status(3700).unwrap().spent["owner/repo"], 1);
+        let delete = Priority::of(&Method::DELETE, "/repos/o/r/actions/runners/1");
+        assert_eq!(delete, Priority::Essential);
+    }
+}
@@ -0,0 +1,786 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Client, Method, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use tracing::{debug, warn};
+
+use super::app::GitHubApp;
+use super::breaker::{BreakerState, CircuitBreaker};
+use super::budget::{endpoint_repo, BudgetStatus, Priority, RateBudget};
+use super::error::GitHubError;
+use super::http_client;
+use super::retry::{Backoff, RetrySettings};
+use super::types::*;
+use crate::config::NetworkConfig;
+use crate::secrets::SecretString;
+use crate::state::unix_now;
+
+const GITHUB_API_BASE: &str = "https://api.github.com";
+/// Longest rate-limit wait slept through inside a single request; longer
+/// waits fail the request and block further calls until the reset
+const MAX_RATE_LIMIT_WAIT_SECS: u64 = 120;
+/// GitHub asks for at least a minute when a secondary limit omits Retry-After
+const SECONDARY_RATE_LIMIT_WAIT_SECS: u64 = 60;
+/// Cached GET responses; beyond this the least recently used are dropped
+const MAX_CACHED_RESPONSES: usize = 256;
+/// Repository metadata changes rarely; refreshes are conditional requests
+const REPO_METADATA_TTL: Duration = Duration::from_secs(15 * 60);
+/// Installation tokens expire after an hour; re-mint well before that
+const CHECKS_TOKEN_TTL: Duration = Duration::from_secs(45 * 60);
+
+pub struct GitHubClient {
+    client: Client,
+    /// REST API root, `https://api.github.com` unless pointed elsewhere
+    api_base: String,
+    repo: String,
+    /// Swapped in place when the token file is rotated
+    token: RwLock<SecretString>,
+    /// Consecutive 401 responses, reset on any successful request
+    auth_failures: AtomicU32,
+    /// Optional GitHub App used to mint job-scoped tokens
+    app: Option<GitHubApp>,
+    /// Last ETag and body per GET URL, for conditional requests
+    etags: Mutex<ResponseCache>,
+    /// Unix time before which requests are refused locally after a rate limit
+    rate_limited_until: AtomicU64,
+    breaker: CircuitBreaker,
+    budget: RateBudget,
+    retry: RetrySettings,
+    /// Repository metadata per `owner/repo`, with when it was fetched
+    repos: Mutex<HashMap<String, (Instant, RepoMetadata)>>,
+    /// App installation token for the check runs API, with when it was minted
+    checks_token: Mutex<Option<(Instant, SecretString)>>,
+}
+
+/// Last ETag and body per GET URL. Job and run URLs come and go with the
+/// jobs, so only the most recently used `MAX_CACHED_RESPONSES` are kept.
+#[derive(Default)]
+struct ResponseCache {
+    entries: HashMap<String, CachedResponse>,
+    /// Counts lookups and inserts, ordering entries by last use
+    clock: u64,
+}
+
+struct CachedResponse {
+    etag: String,
+    body: Vec<u8>,
+    used: u64,
+}
+
+impl ResponseCache {
+    /// ETag to send for `url`, marking its entry as used
+    fn etag(&mut self, url: &str) -> Option<String> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(url)?;
+        entry.used = self.clock;
+        Some(entry.etag.clone())
+    }
+
+    fn body(&self, url: &str) -> Option<Vec<u8>> {
+        self.entries.get(url).map(|entry| entry.body.clone())
+    }
+
+    fn insert(&mut self, url: String, etag: String, body: Vec<u8>) {
+        self.clock += 1;
+        if self.entries.len() >= MAX_CACHED_RESPONSES && !self.entries.contains_key(&url) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(url, _)| url.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        let used = self.clock;
+        self.entries.insert(url, CachedResponse { etag, body, used });
+    }
+}
+
+impl GitHubClient {
+    pub fn new(
+        repo: String,
+        token: impl Into<SecretString>,
+        network: &NetworkConfig,
+    ) -> Result<Self> {
+        let client = http_client(network)?;
+
+        Ok(Self {
+            client,
+            api_base: GITHUB_API_BASE.to_string(),
+            repo,
+            token: RwLock::new(token.into()),
+            auth_failures: AtomicU32::new(0),
+            app: None,
+            etags: Mutex::new(ResponseCache::default()),
+            rate_limited_until: AtomicU64::new(0),
+            breaker: CircuitBreaker::new(0, Duration::ZERO),
+            budget: RateBudget::new(0, None),
+            retry: RetrySettings::default(),
+            repos: Mutex::new(HashMap::new()),
+            checks_token: Mutex::new(None),
+        })
+    }
+
+    /// Attach GitHub App credentials for minting job-scoped tokens
+    pub fn with_app(mut self, app: GitHubApp) -> Self {
+        self.app = Some(app);
+        self
+    }
+
+    /// Send requests to `url` instead of the public API, e.g. a mock server
+    pub fn with_api_base(mut self, url: impl Into<String>) -> Self {
+        self.api_base = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Use a new token for all further requests
+    pub fn set_token(&self, token: impl Into<SecretString>) {
+        *self.token.write().unwrap() = token.into();
+    }
+
+    /// GitHub App credentials, if job-scoped tokens are enabled
+    pub fn app(&self) -> Option<&GitHubApp> {
+        self.app.as_ref()
+    }
+
+    /// Fail fast after `threshold` consecutive failed requests until `cooldown` passes
+    pub fn with_circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.breaker = CircuitBreaker::new(threshold, cooldown);
+        self
+    }
+
+    /// Keep `reserve` requests for registration and cleanup, and spend at
+    /// most `repo_limit` requests per repository and rate limit window
+    pub fn with_rate_budget(mut self, reserve: u64, repo_limit: Option<u64>) -> Self {
+        self.budget = RateBudget::new(reserve, repo_limit);
+        self
+    }
+
+    /// Rate limit budget as of the last response
+    pub fn rate_budget(&self) -> Option<BudgetStatus> {
+        self.budget.status(unix_now())
+    }
+
+    /// Retry failed requests according to `retry` instead of the default policy
+    pub fn with_retry(mut self, retry: RetrySettings) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn circuit_state(&self) -> BreakerState {
+        self.breaker.state()
+    }
+
+    /// Seconds until an open circuit admits a probe
+    pub fn circuit_retry_in(&self) -> Option<u64> {
+        self.breaker.retry_in()
+    }
+
+    /// Cheap request to test whether the API is back; doesn't count against
+    /// the rate limit
+    pub async fn probe(&self) -> Result<(), GitHubError> {
+        self.get::<serde_json::Value>("/rate_limit").await.map(|_| ())
+    }
+
+    /// Scopes of a classic token, from the `X-OAuth-Scopes` header of a
+    /// single unretried request. `None` for fine-grained and installation
+    /// tokens, which have permissions instead of scopes.
+    pub async fn token_scopes(&self) -> Result<Option<Vec<String>>, GitHubError> {
+        let authorization = authorization(self.token.read().unwrap().expose())?;
+        let resp = self
+            .client
+            .get(format!("{}/rate_limit", self.api_base))
+            .header("Authorization", authorization)
+            .header("Accept", "application/vnd.github.v3+json")
+            .send()
+            .await
+            .map_err(|e| GitHubError::Transient(e.to_string()))?;
+        match resp.status() {
+            StatusCode::UNAUTHORIZED => return Err(GitHubError::Unauthorized),
+            status if !status.is_success() => {
+                return Err(GitHubError::Transient(format!("/rate_limit returned {}", status)))
+            }
+            _ => {}
+        }
+        let scopes = resp
+            .headers()
+            .get("x-oauth-scopes")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            });
+        Ok(scopes)
+    }
+
+    fn record_success(&self) {
+        self.auth_failures.store(0, Ordering::Relaxed);
+        self.breaker.record_success();
+    }
+
+    /// Number of consecutive requests rejected as unauthorized
+    pub fn consecutive_auth_failures(&self) -> u32 {
+        self.auth_failures.load(Ordering::Relaxed)
+    }
+
+    /// Fail fast while the circuit is open or a known rate limit window is
+    /// still open, rather than spending a request that is certain to fail.
+    /// Deferrable requests also wait while the budget is down to the reserve.
+    fn check_available(&self, priority: Priority, repo: Option<&str>) -> Result<(), GitHubError> {
+        if self.breaker.state() == BreakerState::Open {
+            return Err(GitHubError::Transient("GitHub API circuit breaker open".to_string()));
+        }
+
+        let until = self.rate_limited_until.load(Ordering::Relaxed);
+        let now = unix_now();
+        if until > now {
+            return Err(GitHubError::RateLimited(until - now));
+        }
+        self.budget.admit(priority, repo, now).map_err(|wait| {
+            debug!(wait_secs = wait, repo, "Rate limit budget tight, deferring request");
+            GitHubError::RateLimited(wait)
+        })
+    }
+
+    /// Wait out a 403/429 response. Honors `Retry-After` and
+    /// `x-ratelimit-reset` when present and falls back to the retry backoff
+    /// for anything else (e.g. a plain permission error).
+    async fn wait_for_rate_limit(
+        &self,
+        resp: Response,
+        backoff: &mut Backoff,
+    ) -> Result<(), GitHubError> {
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = resp.text().await.unwrap_or_default();
+        let attempt = backoff.attempts();
+
+        let Some(wait) = rate_limit_wait(&headers, &body, unix_now()) else {
+            warn!(status = %status, attempt, "Forbidden, backing off");
+            backoff.sleep().await;
+            return Ok(());
+        };
+
+        if wait > MAX_RATE_LIMIT_WAIT_SECS {
+            self.rate_limited_until
+                .store(unix_now() + wait, Ordering::Relaxed);
+            return Err(GitHubError::RateLimited(wait));
+        }
+        if !backoff.fits(Duration::from_secs(wait)) {
+            return Err(GitHubError::RateLimited(wait));
+        }
+        if !backoff.can_retry() {
+            return Ok(());
+        }
+
+        warn!(status = %status, attempt, wait_secs = wait, "Rate limited, waiting for reset");
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+        Ok(())
+    }
+
+    /// Make a GET request with retries (see `RetryPolicy`).
+    ///
+    /// Sends `If-None-Match` with the last seen ETag; a 304 reuses the cached
+    /// body and does not count against the rate limit.
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, GitHubError> {
+        self.request(Method::GET, endpoint, None, None).await
+    }
+
+    /// Make a POST request with retries
+    async fn post<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, GitHubError> {
+        self.request(Method::POST, endpoint, None, None).await
+    }
+
+    /// Make a DELETE request; a resource that is already gone counts as deleted
+    async fn delete(&self, endpoint: &str) -> Result<(), GitHubError> {
+        match self.request::<()>(Method::DELETE, endpoint, None, None).await {
+            Err(GitHubError::NotFound(_)) => {
+                debug!("Resource already deleted: {}", endpoint);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Make a request with an optional JSON body and token (instead of the
+    /// main token), retrying per the endpoint's `RetryPolicy`. Failures are
+    /// `GitHubError`s; empty responses parse as JSON `null`.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        body: Option<&serde_json::Value>,
+        token: Option<&str>,
+    ) -> Result<T, GitHubError> {
+        let bytes = self.send(&method, endpoint, body, token).await?;
+        let bytes: &[u8] = if bytes.is_empty() { b"null" } else { &bytes };
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    /// Send a request until it succeeds or fails for good, returning the body
+    async fn send(
+        &self,
+        method: &Method,
+        endpoint: &str,
+        body: Option<&serde_json::Value>,
+        token: Option<&str>,
+    ) -> Result<Vec<u8>, GitHubError> {
+        let url = format!("{}{}", self.api_base, endpoint);
+        let conditional = *method == Method::GET;
+        let priority = Priority::of(method, endpoint);
+        let repo = endpoint_repo(endpoint);
+        let mut backoff = self.retry.for_endpoint(endpoint).backoff();
+        let mut last_error = String::new();
+
+        while let Some(attempt) = backoff.next_attempt() {
+            self.check_available(priority, repo.as_deref())?;
+            debug!(url = %url, method = %method, attempt, "GitHub API request");
+
+            let authorization = {
+                let main = self.token.read().unwrap();
+                authorization(token.unwrap_or(main.expose()))?
+            };
+            let mut request = self
+                .client
+                .request(method.clone(), &url)
+                .header("Authorization", authorization)
+                .header("Accept", "application/vnd.github.v3+json");
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            if conditional {
+                if let Some(etag) = self.etags.lock().unwrap().etag(&url) {
+                    request = request.header("If-None-Match", etag);
+                }
+            }
+
+            let resp = match request.send().await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    warn!(error = %e, attempt, "GitHub API request failed, retrying");
+                    last_error = e.to_string();
+                    backoff.sleep().await;
+                    continue;
+                }
+            };
+
+            let status = resp.status();
+            let charged = status != StatusCode::NOT_MODIFIED;
+            self.budget.record(resp.headers(), repo.as_deref(), charged, unix_now());
+            let remaining = resp
+                .headers()
+                .get("x-ratelimit-remaining")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u32>().ok());
+            if let Some(remaining) = remaining.filter(|&r| r < 100) {
+                warn!(remaining, "GitHub API rate limit low");
+            }
+
+            match status {
+                StatusCode::OK | StatusCode::CREATED | StatusCode::NO_CONTENT => {
+                    self.record_success();
+                    let etag = resp
+                        .headers()
+                        .get("etag")
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.to_string());
+                    let bytes = resp.bytes().await.map_err(|e| {
+                        GitHubError::Transient(format!("Failed to read response: {}", e))
+                    })?;
+                    let bytes = bytes.to_vec();
+                    if let Some(etag) = etag.filter(|_| conditional) {
+                        self.etags.lock().unwrap().insert(url.clone(), etag, bytes.clone());
+                    }
+                    return Ok(bytes);
+                }
+                StatusCode::NOT_MODIFIED => {
+                    self.record_success();
+                    debug!(url = %url, "GitHub API not modified, using cached response");
+                    if let Some(body) = self.etags.lock().unwrap().body(&url) {
+                        return Ok(body);
+                    }
+                    // Cache entry vanished; retry unconditionally
+                    continue;
+                }
+                StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => {
+                    last_error = format!("{}", status);
+                    self.wait_for_rate_limit(resp, &mut backoff).await?;
+                }
+                StatusCode::UNAUTHORIZED => {
+                    self.auth_failures.fetch_add(1, Ordering::Relaxed);
+                    return Err(GitHubError::Unauthorized);
+                }
+                StatusCode::NOT_FOUND => {
+                    return Err(GitHubError::NotFound(endpoint.to_string()));
+                }
+                _ if !status.is_server_error() => {
+                    // GitHub answered, so this says nothing about its availability
+                    let body = resp.text().await.unwrap_or_default();
+                    return Err(GitHubError::Rejected {
+                        status: status.as_u16(),
+                        message: body.trim().to_string(),
+                    });
+                }
+                _ => {
+                    let body = resp.text().await.unwrap_or_default();
+                    warn!(status = %status, body = %body, attempt, "GitHub API error, retrying");
+                    last_error = format!("{}: {}", status, body.trim());
+                    backoff.sleep().await;
+                }
+            }
+        }
+
+        self.breaker.record_failure();
+        Err(GitHubError::Transient(format!(
+            "GitHub API {} failed after {} attempts: {} ({})",
+            method,
+            backoff.attempts(),
+            endpoint,
+            last_error
+        )))
+    }
+
+    /// Get a registration token for new runners
+    pub async fn get_registration_token(&self) -> Result<SecretString, GitHubError> {
+        let endpoint = format!("/repos/{}/actions/runners/registration-token", self.repo);
+        let response: RegistrationTokenResponse = self.post(&endpoint).await?;
+        Ok(response.token)
+    }
+
+    /// List all runners for the repository
+    pub async fn list_runners(&self) -> Result<Vec<Runner>, GitHubError> {
+        let endpoint = format!("/repos/{}/actions/runners?per_page=100", self.repo);
+        let response: RunnersResponse = self.get(&endpoint).await?;
+        Ok(response.runners)
+    }
+
+    /// Get a workflow job, including which runner it was assigned to
+    pub async fn get_job(&self, job_id: u64) -> Result<WorkflowJob, GitHubError> {
+        let endpoint = format!("/repos/{}/actions/jobs/{}", self.repo, job_id);
+        self.get(&endpoint).await
+    }
+
+    /// Get a workflow run: its workflow file, branch and triggering user
+    pub async fn get_run(&self, run_id: u64) -> Result<WorkflowRun, GitHubError> {
+        let endpoint = format!("/repos/{}/actions/runs/{}", self.repo, run_id);
+        self.get(&endpoint).await
+    }
+
+    /// List jobs still waiting for a runner across queued workflow runs,
+    /// longest waiting first
+    pub async fn list_queued_jobs(&self) -> Result<Vec<WorkflowJob>, GitHubError> {
+        let mut jobs = self.list_jobs("queued").await?;
+        sort_oldest_first(&mut jobs);
+        Ok(jobs)
+    }
+
+    /// Jobs currently running, from the most recent in-progress workflow runs
+    pub async fn list_in_progress_jobs(&self) -> Result<Vec<WorkflowJob>, GitHubError> {
+        self.list_jobs("in_progress").await
+    }
+
+    /// Jobs with `status` from the most recent workflow runs with that status
+    async fn list_jobs(&self, status: &str) -> Result<Vec<WorkflowJob>, GitHubError> {
+        let endpoint = format!(
+            "/repos/{}/actions/runs?status={}&per_page=20",
+            self.repo, status
+        );
+        let runs: WorkflowRunsResponse = self.get(&endpoint).await?;
+
+        let mut jobs = Vec::new();
+        for run in runs.workflow_runs {
+            let endpoint = format!(
+                "/repos/{}/actions/runs/{}/jobs?filter=latest&per_page=100",
+                self.repo, run.id
+            );
+            let response: WorkflowJobsResponse = self.get(&endpoint).await?;
+            jobs.extend(response.jobs.into_iter().filter(|j| j.status == status));
+        }
+
+        Ok(jobs)
+    }
+
+    /// Recent actions runner releases, newest first
+    pub async fn list_runner_releases(&self) -> Result<Vec<RunnerRelease>, GitHubError> {
+        self.get("/repos/actions/runner/releases?per_page=30").await
+    }
+
+    /// Metadata of `repo`, served from cache and refreshed after
+    /// `REPO_METADATA_TTL`. If a refresh fails the stale entry is returned.
+    pub async fn repo_metadata(&self, repo: &str) -> Result<RepoMetadata, GitHubError> {
+        let key = repo.to_ascii_lowercase();
+        let cached = self.repos.lock().unwrap().get(&key).cloned();
+        if let Some((fetched, metadata)) = &cached {
+            if fetched.elapsed() < REPO_METADATA_TTL {
+                return Ok(metadata.clone());
+            }
+        }
+
+        match self.fetch_repo_metadata(repo).await {
+            Ok(metadata) => {
+                self.repos
+                    .lock()
+                    .unwrap()
+                    .insert(key, (Instant::now(), metadata.clone()));
+                Ok(metadata)
+            }
+            Err(e) => match cached {
+                Some((_, metadata)) => {
+                    warn!(repo = %repo, error = %e, "Failed to refresh repository metadata, using cached");
+                    Ok(metadata)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Metadata of `repo` if it is cached, without any request
+    pub fn cached_repo_metadata(&self, repo: &str) -> Option<RepoMetadata> {
+        let repos = self.repos.lock().unwrap();
+        repos.get(&repo.to_ascii_lowercase()).map(|(_, m)| m.clone())
+    }
+
+    async fn fetch_repo_metadata(&self, repo: &str) -> Result<RepoMetadata, GitHubError> {
+        let response: RepositoryResponse = self.get(&format!("/repos/{}", repo)).await?;
+        // Needs admin read access; not every token has it
+        let actions_enabled = self
+            .get::<ActionsPermissionsResponse>(&format!("/repos/{}/actions/permissions", repo))
+            .await
+            .map(|p| p.enabled)
+            .map_err(|e| debug!(repo = %repo, error = %e, "Actions permissions unavailable"))
+            .ok();
+
+        let visibility = response.visibility.unwrap_or_else(|| {
+            if response.private { "private" } else { "public" }.to_string()
+        });
+        Ok(RepoMetadata {
+            default_branch: response.default_branch,
+            visibility,
+            private: response.private,
+            actions_enabled,
+            fetched_at: unix_now(),
+        })
+    }
+
+    /// Delete a runner by ID
+    pub async fn delete_runner(&self, runner_id: u64) -> Result<(), GitHubError> {
+        let endpoint = format!("/repos/{}/actions/runners/{}", self.repo, runner_id);
+        self.delete(&endpoint).await
+    }
+
+    /// Find a runner by name and return its ID
+    pub async fn find_runner_by_name(&self, name: &str) -> Result<Option<u64>, GitHubError> {
+        let runners = self.list_runners().await?;
+        Ok(runners.into_iter().find(|r| r.name == name).map(|r| r.id))
+    }
+
+    /// Delete a runner by name (convenience method)
+    pub async fn delete_runner_by_name(&self, name: &str) -> Result<(), GitHubError> {
+        if let Some(runner_id) = self.find_runner_by_name(name).await? {
+            self.delete_runner(runner_id).await?;
+            debug!(name = %name, runner_id, "Deleted runner");
+        } else {
+            debug!(name = %name, "Runner not found, nothing to delete");
+        }
+        Ok(())
+    }
+
+    /// Create a check run on `head_sha`, returning its ID
+    pub async fn create_check_run(&self, head_sha: &str, output: CheckRunOutput) -> Result<u64> {
+        let endpoint = format!("/repos/{}/check-runs", self.repo);
+        let body = serde_json::json!({
+            "name": CHECK_RUN_NAME,
+            "head_sha": head_sha,
+            "status": "queued",
+            "output": output,
+        });
+        let token = self.checks_token().await?;
+        let token = token.as_ref().map(SecretString::expose);
+        let run: CheckRun = self
+            .request(Method::POST, &endpoint, Some(&body), token)
+            .await?;
+        Ok(run.id)
+    }
+
+    /// Replace a check run's output; with a conclusion, also complete it
+    pub async fn update_check_run(
+        &self,
+        id: u64,
+        output: CheckRunOutput,
+        conclusion: Option<&str>,
+    ) -> Result<()> {
+        let endpoint = format!("/repos/{}/check-runs/{}", self.repo, id);
+        let mut body = serde_json::json!({ "output": output });
+        if let Some(conclusion) = conclusion {
+            body["status"] = "completed".into();
+            body["conclusion"] = conclusion.into();
+        }
+        let token = self.checks_token().await?;
+        let token = token.as_ref().map(SecretString::expose);
+        let _: CheckRun = self
+            .request(Method::PATCH, &endpoint, Some(&body), token)
+            .await?;
+        Ok(())
+    }
+
+    /// Set the `self-hosted runner` commit status of `sha` to `state`
+    /// (`error`, `failure`, `pending` or `success`)
+    pub async fn create_commit_status(
+        &self,
+        sha: &str,
+        state: &str,
+        description: &str,
+    ) -> Result<()> {
+        let endpoint = format!("/repos/{}/statuses/{}", self.repo, sha);
+        let description: String =
+            description.chars().take(COMMIT_STATUS_DESCRIPTION_MAX).collect();
+        let body = serde_json::json!({
+            "state": state,
+            "context": COMMIT_STATUS_CONTEXT,
+            "description": description,
+        });
+        let _: serde_json::Value = self.request(Method::POST, &endpoint, Some(&body), None).await?;
+        Ok(())
+    }
+
+    /// Token for the check runs API, which only accepts GitHub App tokens.
+    /// `None` falls back to the main token when no App is configured.
+    async fn checks_token(&self) -> Result<Option<SecretString>> {
+        let Some(app) = &self.app else {
+            return Ok(None);
+        };
+        if let Some((minted, token)) = self.checks_token.lock().unwrap().as_ref() {
+            if minted.elapsed() < CHECKS_TOKEN_TTL {
+                return Ok(Some(token.clone()));
+            }
+        }
+
+        let permissions = BTreeMap::from([("checks".to_string(), "write".to_string())]);
+        let token = app.mint_installation_token(&self.repo, &permissions).await?.token;
+        *self.checks_token.lock().unwrap() = Some((Instant::now(), token.clone()));
+        Ok(Some(token))
+    }
+
+    /// Mint a job-scoped installation token for this repository.
+    /// Returns `None` when no GitHub App is configured.
+    pub async fn mint_job_token(
+        &self,
+        permissions: &BTreeMap<String, String>,
+    ) -> Result<Option<SecretString>> {
+        let Some(app) = &self.app else {
+            return Ok(None);
+        };
+
+        let token = app.mint_installation_token(&self.repo, permissions).await?;
+        Ok(Some(token.token))
+    }
+
+    /// Revoke a token previously returned by `mint_job_token`
+    pub async fn revoke_job_token(&self, token: &str) -> Result<()> {
+        match &self.app {
+            Some(app) => app.revoke_installation_token(token).await,
+            None => Ok(()),
+        }
+    }
+}
+/// Seconds to wait before retrying a rate-limited response, or `None` if the
+/// response carries no rate limit information
+fn rate_limit_wait(headers: &HeaderMap, body: &str, now: u64) -> Option<u64> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+
+    if let Some(secs) = header("retry-after") {
+        return Some(secs.max(1));
+    }
+
+    if header("x-ratelimit-remaining") == Some(0) {
+        if let Some(reset) = header("x-ratelimit-reset") {
+            // One extra second absorbs clock skew around the reset boundary
+            return Some(reset.saturating_sub(now) + 1);
+        }
+    }
+
+    if body.to_ascii_lowercase().contains("secondary rate limit") {
+        return Some(SECONDARY_RATE_LIMIT_WAIT_SECS);
+    }
+
+    None
+}
+
+/// `Authorization` header for `token`, marked sensitive so it is left out of
+/// debug output of the request
+fn authorization(token: &str) -> Result<HeaderValue, GitHubError> {
+    let mut value = HeaderValue::try_from(format!("token {}", token))
+        .map_err(|_| GitHubError::Transient("GitHub token is not a valid header value".into()))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_cache_is_bounded() {
+        let mut cache = ResponseCache::default();
+        cache.insert("/runners".to_string(), "\"r\"".to_string(), b"[]".to_vec());
+        for job_id in 0..MAX_CACHED_RESPONSES * 2 {
+            // The polled URL stays in use while per-job URLs pile up
+            assert!(cache.etag("/runners").is_some());
+            cache.insert(format!("/jobs/{}", job_id), "\"j\"".to_string(), Vec::new());
+        }
+        assert_eq!(cache.entries.len(), MAX_CACHED_RESPONSES);
+        assert_eq!(cache.body("/runners").as_deref(), Some(&b"[]"[..]));
+        assert!(cache.body("/jobs/0").is_none());
+        assert!(cache.body(&format!("/jobs/{}", MAX_CACHED_RESPONSES * 2 - 1)).is_some());
+    }
+
+    #[test]
+    fn test_rate_limit_wait() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut map = HeaderMap::new();
+            for (name, value) in pairs {
+                map.insert(*name, value.parse().unwrap());
+            }
+            map
+        };
+
+        assert_eq!(
+            rate_limit_wait(&headers(&[("retry-after", "30")]), "", 0),
+            Some(30)
+        );
+        assert_eq!(
+            rate_limit_wait(
+                &headers(&[("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1090")]),
+                "",
+                1000
+            ),
+            Some(91)
+        );
+        assert_eq!(
+            rate_limit_wait(
+                &headers(&[("x-ratelimit-remaining", "12")]),
+                "You have exceeded a secondary rate limit",
+                0
+            ),
+            Some(SECONDARY_RATE_LIMIT_WAIT_SECS)
+        );
+        assert_eq!(
+            rate_limit_wait(&headers(&[]), "Resource not accessible by integration", 0),
+            None
+        );
+    }
+}
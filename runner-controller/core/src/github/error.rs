@@ -0,0 +1,26 @@
+use thiserror::Error;
+
+/// Why a GitHub API request failed, for callers that react differently to
+/// each (wait for the reset, alert about the token, treat as gone, retry later)
+#[derive(Debug, Error)]
+pub enum GitHubError {
+    /// A rate limit applies for this many more seconds
+    #[error("GitHub API rate limited, resets in {0}s")]
+    RateLimited(u64),
+    #[error("GitHub API unauthorized - check token")]
+    Unauthorized,
+    /// The endpoint (with the resource ID in it) doesn't exist
+    #[error("GitHub API resource not found: {0}")]
+    NotFound(String),
+    /// The response body isn't what the endpoint should return
+    #[error("invalid GitHub API response: {0}")]
+    InvalidResponse(#[from] serde_json::Error),
+    /// Any other client error (e.g. 422): the request itself is wrong, so it
+    /// isn't retried
+    #[error("GitHub API rejected the request ({status}): {message}")]
+    Rejected { status: u16, message: String },
+    /// Network errors and server errors that outlasted the retries, or the
+    /// circuit breaker is open
+    #[error("{0}")]
+    Transient(String),
+}
@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::api::GitHubApi;
+use super::breaker::BreakerState;
+use super::budget::BudgetStatus;
+use super::error::GitHubError;
+use super::types::{
+    sort_oldest_first, CheckRunOutput, RepoMetadata, Runner, RunnerLabel, RunnerRelease,
+    WorkflowJob, WorkflowRun,
+};
+use crate::secrets::SecretString;
+use crate::state::{format_utc_timestamp, unix_now};
+
+/// In-memory stand-in for the GitHub API, for tests of the controller logic.
+///
+/// Runners, jobs and runs are whatever the test put in; deletions and revoked
+/// tokens are recorded for assertions. Failures queued with `fail_next` are
+/// returned by the next REST calls, one each, in order.
+#[derive(Default)]
+pub struct FakeGitHub {
+    state: Mutex<FakeState>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    runners: Vec<Runner>,
+    next_runner_id: u64,
+    jobs: Vec<WorkflowJob>,
+    runs: HashMap<u64, WorkflowRun>,
+    repos: HashMap<String, RepoMetadata>,
+    releases: Vec<RunnerRelease>,
+    failures: VecDeque<GitHubError>,
+    deleted_runners: Vec<String>,
+    registration_tokens: u64,
+    job_tokens: bool,
+    minted_tokens: u64,
+    revoked_tokens: Vec<String>,
+    check_runs: Vec<(CheckRunOutput, Option<String>)>,
+    /// Commit SHA, state and description of each posted status
+    commit_statuses: Vec<(String, String, String)>,
+    rate_budget: Option<BudgetStatus>,
+}
+
+impl FakeGitHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an online, idle runner with `labels`, returning its ID
+    pub fn add_runner(&self, name: &str, labels: &[&str]) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_runner_id += 1;
+        let id = state.next_runner_id;
+        state.runners.push(Runner {
+            id,
+            name: name.to_string(),
+            status: "online".to_string(),
+            busy: false,
+            labels: labels
+                .iter()
+                .map(|l| RunnerLabel { name: l.to_string() })
+                .collect(),
+            ephemeral: Some(true),
+        });
+        id
+    }
+
+    /// Mark the runner registered as `name` busy with a job, or idle again
+    pub fn set_runner_busy(&self, name: &str, busy: bool) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(runner) = state.runners.iter_mut().find(|r| r.name == name) {
+            runner.busy = busy;
+        }
+    }
+
+    /// Mark the runner registered as `name` offline, e.g. its host died
+    pub fn set_runner_offline(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(runner) = state.runners.iter_mut().find(|r| r.name == name) {
+            runner.status = "offline".to_string();
+        }
+    }
+
+    /// Add or replace a job (by ID)
+    pub fn add_job(&self, job: WorkflowJob) {
+        let mut state = self.state.lock().unwrap();
+        state.jobs.retain(|j| j.id != job.id);
+        state.jobs.push(job);
+    }
+
+    /// Change a job's status, and which runner it was assigned to
+    pub fn set_job_status(&self, job_id: u64, status: &str, runner_name: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(job) = state.jobs.iter_mut().find(|j| j.id == job_id) {
+            job.status = status.to_string();
+            job.runner_name = runner_name.map(str::to_string);
+        }
+    }
+
+    pub fn add_run(&self, run: WorkflowRun) {
+        self.state.lock().unwrap().runs.insert(run.id, run);
+    }
+
+    /// Report `budget` as the rate limit budget
+    pub fn set_rate_budget(&self, budget: Option<BudgetStatus>) {
+        self.state.lock().unwrap().rate_budget = budget;
+    }
+
+    pub fn set_repo_metadata(&self, repo: &str, metadata: RepoMetadata) {
+        let mut state = self.state.lock().unwrap();
+        state.repos.insert(repo.to_ascii_lowercase(), metadata);
+    }
+
+    /// Publish an actions runner release; `published_at` is a unix timestamp
+    pub fn add_runner_release(&self, version: &str, published_at: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.releases.push(RunnerRelease {
+            tag_name: format!("v{}", version),
+            published_at: Some(format_utc_timestamp(published_at)),
+            prerelease: false,
+            draft: false,
+        });
+        state.releases.sort_by_key(|r| std::cmp::Reverse(r.published_at.clone()));
+    }
+
+    /// Return `error` from the next REST call instead of its result
+    pub fn fail_next(&self, error: GitHubError) {
+        self.state.lock().unwrap().failures.push_back(error);
+    }
+
+    /// Mint job-scoped tokens as if a GitHub App were configured
+    pub fn enable_job_tokens(&self) {
+        self.state.lock().unwrap().job_tokens = true;
+    }
+
+    pub fn runners(&self) -> Vec<Runner> {
+        self.state.lock().unwrap().runners.clone()
+    }
+
+    /// Names of the runners deleted so far, in order
+    pub fn deleted_runners(&self) -> Vec<String> {
+        self.state.lock().unwrap().deleted_runners.clone()
+    }
+
+    /// Registration tokens handed out so far
+    pub fn registration_tokens(&self) -> u64 {
+        self.state.lock().unwrap().registration_tokens
+    }
+
+    pub fn revoked_tokens(&self) -> Vec<String> {
+        self.state.lock().unwrap().revoked_tokens.clone()
+    }
+
+    /// Output and conclusion of every check run, by ID - 1
+    pub fn check_runs(&self) -> Vec<(CheckRunOutput, Option<String>)> {
+        self.state.lock().unwrap().check_runs.clone()
+    }
+
+    /// Commit SHA, state and description of every posted commit status
+    pub fn commit_statuses(&self) -> Vec<(String, String, String)> {
+        self.state.lock().unwrap().commit_statuses.clone()
+    }
+
+    /// The state, or the next queued failure
+    fn state(&self) -> Result<std::sync::MutexGuard<'_, FakeState>, GitHubError> {
+        let mut state = self.state.lock().unwrap();
+        match state.failures.pop_front() {
+            Some(error) => Err(error),
+            None => Ok(state),
+        }
+    }
+}
+
+#[async_trait]
+impl GitHubApi for FakeGitHub {
+    async fn probe(&self) -> Result<(), GitHubError> {
+        self.state().map(|_| ())
+    }
+
+    fn circuit_state(&self) -> BreakerState {
+        BreakerState::Closed
+    }
+
+    fn circuit_retry_in(&self) -> Option<u64> {
+        None
+    }
+
+    fn consecutive_auth_failures(&self) -> u32 {
+        0
+    }
+
+    fn rate_budget(&self) -> Option<BudgetStatus> {
+        self.state.lock().unwrap().rate_budget.clone()
+    }
+
+    async fn get_registration_token(&self) -> Result<SecretString, GitHubError> {
+        let mut state = self.state()?;
+        state.registration_tokens += 1;
+        Ok(format!("fake-registration-token-{}", state.registration_tokens).into())
+    }
+
+    async fn list_runners(&self) -> Result<Vec<Runner>, GitHubError> {
+        Ok(self.state()?.runners.clone())
+    }
+
+    async fn delete_runner(&self, runner_id: u64) -> Result<(), GitHubError> {
+        let mut state = self.state()?;
+        let Some(index) = state.runners.iter().position(|r| r.id == runner_id) else {
+            return Err(GitHubError::NotFound(format!("runner {}", runner_id)));
+        };
+        let runner = state.runners.remove(index);
+        state.deleted_runners.push(runner.name);
+        Ok(())
+    }
+
+    async fn delete_runner_by_name(&self, name: &str) -> Result<(), GitHubError> {
+        let mut state = self.state()?;
+        if let Some(index) = state.runners.iter().position(|r| r.name == name) {
+            let runner = state.runners.remove(index);
+            state.deleted_runners.push(runner.name);
+        }
+        Ok(())
+    }
+
+    async fn get_job(&self, job_id: u64) -> Result<WorkflowJob, GitHubError> {
+        let state = self.state()?;
+        state
+            .jobs
+            .iter()
+            .find(|j| j.id == job_id)
+            .cloned()
+            .ok_or_else(|| GitHubError::NotFound(format!("job {}", job_id)))
+    }
+
+    async fn get_run(&self, run_id: u64) -> Result<WorkflowRun, GitHubError> {
+        let state = self.state()?;
+        state
+            .runs
+            .get(&run_id)
+            .cloned()
+            .ok_or_else(|| GitHubError::NotFound(format!("run {}", run_id)))
+    }
+
+    async fn list_queued_jobs(&self) -> Result<Vec<WorkflowJob>, GitHubError> {
+        let state = self.state()?;
+        let mut jobs: Vec<_> = state
+            .jobs
+            .iter()
+            .filter(|j| j.status == "queued")
+            .cloned()
+            .collect();
+        sort_oldest_first(&mut jobs);
+        Ok(jobs)
+    }
+
+    async fn list_in_progress_jobs(&self) -> Result<Vec<WorkflowJob>, GitHubError> {
+        let state = self.state()?;
+        Ok(state
+            .jobs
+            .iter()
+            .filter(|j| j.status == "in_progress")
+            .cloned()
+            .collect())
+    }
+
+    async fn repo_metadata(&self, repo: &str) -> Result<RepoMetadata, GitHubError> {
+        let state = self.state()?;
+        Ok(state
+            .repos
+            .get(&repo.to_ascii_lowercase())
+            .cloned()
+            .unwrap_or_else(|| RepoMetadata {
+                default_branch: "main".to_string(),
+                visibility: "private".to_string(),
+                private: true,
+                actions_enabled: Some(true),
+                fetched_at: unix_now(),
+            }))
+    }
+
+    fn cached_repo_metadata(&self, repo: &str) -> Option<RepoMetadata> {
+        let state = self.state.lock().unwrap();
+        state.repos.get(&repo.to_ascii_lowercase()).cloned()
+    }
+
+    async fn list_runner_releases(&self) -> Result<Vec<RunnerRelease>, GitHubError> {
+        Ok(self.state()?.releases.clone())
+    }
+
+    async fn create_check_run(&self, _head_sha: &str, output: CheckRunOutput) -> Result<u64> {
+        let mut state = self.state()?;
+        state.check_runs.push((output, None));
+        Ok(state.check_runs.len() as u64)
+    }
+
+    async fn update_check_run(
+        &self,
+        id: u64,
+        output: CheckRunOutput,
+        conclusion: Option<&str>,
+    ) -> Result<()> {
+        let mut state = self.state()?;
+        let Some(run) = state.check_runs.get_mut((id as usize).wrapping_sub(1)) else {
+            return Err(GitHubError::NotFound(format!("check run {}", id)).into());
+        };
+        *run = (output, conclusion.map(str::to_string));
+        Ok(())
+    }
+
+    async fn create_commit_status(&self, sha: &str, state: &str, description: &str) -> Result<()> {
+        let status = (sha.to_string(), state.to_string(), description.to_string());
+        self.state()?.commit_statuses.push(status);
+        Ok(())
+    }
+
+    async fn mint_job_token(
+        &self,
+        _permissions: &BTreeMap<String, String>,
+    ) -> Result<Option<SecretString>> {
+        let mut state = self.state()?;
+        if !state.job_tokens {
+            return Ok(None);
+        }
+        state.minted_tokens += 1;
+        Ok(Some(format!("fake-job-token-{}", state.minted_tokens).into()))
+    }
+
+    async fn revoke_job_token(&self, token: &str) -> Result<()> {
+        self.state()?.revoked_tokens.push(token.to_string());
+        Ok(())
+    }
+}
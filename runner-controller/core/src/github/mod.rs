@@ -0,0 +1,77 @@
+mod api;
+mod app;
+mod breaker;
+mod budget;
+mod client;
+mod error;
+mod fake;
+mod retry;
+mod types;
+
+pub use api::GitHubApi;
+pub use app::GitHubApp;
+pub use breaker::BreakerState;
+pub use budget::{BudgetStatus, RateBudget, MAX_POLL_STRETCH};
+pub use client::GitHubClient;
+pub use error::GitHubError;
+pub use fake::FakeGitHub;
+pub use retry::{RetryPolicy, RetrySettings};
+pub use types::{
+    CheckRunOutput, RepoMetadata, Runner, RunnerLabel, RunnerRelease, WorkflowJob, WorkflowRun,
+};
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::{Certificate, Client, Proxy};
+
+use crate::config::{IpFamily, NetworkConfig};
+
+pub const GITHUB_API_HOST: &str = "api.github.com";
+
+/// Build an HTTP client for the GitHub API honoring the network settings.
+///
+/// With `IpFamily::Auto` hyper races IPv6 and IPv4 connections (happy eyeballs);
+/// a fixed family binds the local address so only that family is attempted.
+/// Without an explicit proxy, reqwest honors `HTTPS_PROXY` and `NO_PROXY`.
+pub fn http_client(network: &NetworkConfig) -> Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent("runner-controller/0.1.0")
+        .timeout(Duration::from_secs(30))
+        .connect_timeout(network.connect_timeout);
+
+    builder = match network.ip_family {
+        IpFamily::Auto => builder,
+        IpFamily::Ipv4 => builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        IpFamily::Ipv6 => builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+    };
+
+    if !network.resolve.is_empty() {
+        let addrs: Vec<SocketAddr> = network
+            .resolve
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, 443))
+            .collect();
+        builder = builder.resolve_to_addrs(GITHUB_API_HOST, &addrs);
+    }
+
+    if let Some(proxy) = &network.proxy {
+        builder = builder.proxy(Proxy::all(proxy).context("Invalid GITHUB_PROXY")?);
+    }
+
+    if let Some(path) = &network.ca_file {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Failed to read CA bundle {:?}", path))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid PEM in CA bundle {:?}", path))?;
+        if certs.is_empty() {
+            anyhow::bail!("No certificates in CA bundle {:?}", path);
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    builder.build().context("Failed to create HTTP client")
+}
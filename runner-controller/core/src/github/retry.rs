@@ -0,0 +1,221 @@
+use std::hash::{BuildHasher, RandomState};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::policy::glob_match;
+
+/// How GitHub API requests are retried: exponential backoff from
+/// `initial_backoff`, capped at `max_backoff`. Part of each delay is random so
+/// controllers that failed together (e.g. during an outage) don't retry in
+/// lockstep.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RetryPolicy {
+    /// Attempts per request, including the first
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Fraction of each delay that is random, from 0.0 (none) to 1.0 (full jitter)
+    pub jitter: f64,
+    /// No retry is waited for past this long after the first attempt
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            jitter: 0.5,
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn backoff(&self) -> Backoff {
+        Backoff {
+            policy: self.clone(),
+            started: Instant::now(),
+            attempts: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Delay after `failed` attempts; `random` picks the jittered part
+    fn delay(&self, failed: u32, random: u64) -> Duration {
+        let base = self
+            .initial_backoff
+            .saturating_mul(1 << failed.saturating_sub(1).min(16))
+            .min(self.max_backoff);
+        let jitter_ms = (base.as_millis() as f64 * self.jitter.clamp(0.0, 1.0)) as u64;
+        base - Duration::from_millis(jitter_ms) + Duration::from_millis(random % (jitter_ms + 1))
+    }
+}
+
+/// Attempts and deadline of requests to endpoints matching `pattern`
+/// (`*` matches anything), written `<pattern>=<attempts>[/<deadline secs>]`,
+/// e.g. `*/registration-token=5/60`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RetryOverride {
+    pub pattern: String,
+    pub max_attempts: u32,
+    pub deadline: Option<Duration>,
+}
+
+impl FromStr for RetryOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "invalid retry override '{}', expected e.g. '*/registration-token=5/60'",
+                s
+            )
+        };
+        let (pattern, limits) = s.split_once('=').ok_or_else(invalid)?;
+        let (attempts, deadline) = match limits.split_once('/') {
+            Some((attempts, deadline)) => (attempts, Some(deadline)),
+            None => (limits, None),
+        };
+        let max_attempts: u32 = attempts.trim().parse().map_err(|_| invalid())?;
+        let deadline = match deadline {
+            Some(secs) => Some(Duration::from_secs(secs.trim().parse().map_err(|_| invalid())?)),
+            None => None,
+        };
+        if pattern.trim().is_empty() || max_attempts == 0 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            pattern: pattern.trim().to_string(),
+            max_attempts,
+            deadline,
+        })
+    }
+}
+
+/// The retry policy and its per-endpoint overrides
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RetrySettings {
+    pub policy: RetryPolicy,
+    /// First match wins
+    pub overrides: Vec<RetryOverride>,
+}
+
+impl RetrySettings {
+    /// Policy for a request to `endpoint` (the API path, e.g. `/repos/o/r/actions/runners`)
+    pub fn for_endpoint(&self, endpoint: &str) -> RetryPolicy {
+        let path = endpoint.split('?').next().unwrap_or(endpoint);
+        match self.overrides.iter().find(|o| glob_match(&o.pattern, path)) {
+            Some(o) => RetryPolicy {
+                max_attempts: o.max_attempts,
+                deadline: o.deadline.or(self.policy.deadline),
+                ..self.policy.clone()
+            },
+            None => self.policy.clone(),
+        }
+    }
+}
+
+/// Retry state of one request
+#[derive(Debug)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    started: Instant,
+    attempts: u32,
+    /// The deadline leaves no room for another wait
+    exhausted: bool,
+}
+
+impl Backoff {
+    /// Start the next attempt, returning its number, or `None` when the
+    /// attempts or the deadline are used up
+    pub fn next_attempt(&mut self) -> Option<u32> {
+        if self.exhausted || self.attempts >= self.policy.max_attempts {
+            return None;
+        }
+        self.attempts += 1;
+        Some(self.attempts)
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Whether another attempt may follow the current one
+    pub fn can_retry(&self) -> bool {
+        !self.exhausted && self.attempts < self.policy.max_attempts
+    }
+
+    /// Whether waiting `wait` before the next attempt stays within the deadline
+    pub fn fits(&self, wait: Duration) -> bool {
+        self.policy
+            .deadline
+            .is_none_or(|deadline| self.started.elapsed() + wait <= deadline)
+    }
+
+    /// Wait before the next attempt. Gives up instead when no attempt is left
+    /// or the wait would run past the deadline.
+    pub async fn sleep(&mut self) {
+        let random = RandomState::new().hash_one(Instant::now());
+        let delay = self.policy.delay(self.attempts, random);
+        if self.can_retry() && self.fits(delay) {
+            tokio::time::sleep(delay).await;
+        } else {
+            self.exhausted = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delays_and_overrides() {
+        let policy = RetryPolicy {
+            max_backoff: Duration::from_secs(5),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        let delays: Vec<u64> = (1..=5).map(|n| policy.delay(n, 0).as_millis() as u64).collect();
+        assert_eq!(delays, vec![1000, 2000, 4000, 5000, 5000]);
+
+        // Half jitter: between half and all of the base delay
+        let jittered = RetryPolicy { jitter: 0.5, ..policy.clone() };
+        assert_eq!(jittered.delay(2, 0), Duration::from_millis(1000));
+        assert_eq!(jittered.delay(2, 1000), Duration::from_millis(2000));
+        assert_eq!(jittered.delay(2, 1001), Duration::from_millis(1000));
+
+        let mut backoff = policy.backoff();
+        assert_eq!(backoff.next_attempt(), Some(1));
+        assert!(backoff.can_retry());
+        assert_eq!(backoff.next_attempt(), Some(2));
+        assert_eq!(backoff.next_attempt(), Some(3));
+        assert!(!backoff.can_retry());
+        assert_eq!(backoff.next_attempt(), None);
+
+        let deadline = RetryPolicy {
+            deadline: Some(Duration::from_secs(10)),
+            ..policy.clone()
+        };
+        let backoff = deadline.backoff();
+        assert!(backoff.fits(Duration::from_secs(9)));
+        assert!(!backoff.fits(Duration::from_secs(11)));
+
+        let settings = RetrySettings {
+            policy,
+            overrides: vec!["*/registration-token=5/60".parse().unwrap()],
+        };
+        let token = settings.for_endpoint("/repos/o/r/actions/runners/registration-token");
+        assert_eq!((token.max_attempts, token.deadline), (5, Some(Duration::from_secs(60))));
+        let runners = settings.for_endpoint("/repos/o/r/actions/runners?per_page=100");
+        assert_eq!(runners.max_attempts, 3);
+
+        assert!("*/jobs/*=0".parse::<RetryOverride>().is_err());
+        assert!("*/jobs/*".parse::<RetryOverride>().is_err());
+        assert_eq!("*/jobs/*=1".parse::<RetryOverride>().unwrap().deadline, None);
+    }
+}
@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::secrets::SecretString;
+use crate::state::parse_utc_timestamp;
+
+/// Response from /repos/{owner}/{repo}/actions/runners
+#[derive(Debug, Deserialize)]
+pub struct RunnersResponse {
+    pub runners: Vec<Runner>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Runner {
+    pub id: u64,
+    pub name: String,
+    /// "online" or "offline"
+    #[serde(default)]
+    pub status: String,
+    /// Whether the runner is currently executing a job
+    #[serde(default)]
+    pub busy: bool,
+    #[serde(default)]
+    pub labels: Vec<RunnerLabel>,
+    /// Whether GitHub removes the runner after one job; `None` if not reported
+    #[serde(default)]
+    pub ephemeral: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RunnerLabel {
+    pub name: String,
+}
+
+impl Runner {
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels.iter().any(|l| l.name.eq_ignore_ascii_case(label))
+    }
+}
+
+/// Response from /repos/{owner}/{repo}/actions/runners/registration-token
+#[derive(Debug, Deserialize)]
+pub struct RegistrationTokenResponse {
+    pub token: SecretString,
+}
+
+/// Response from /repos/{owner}/{repo}/actions/jobs/{job_id}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WorkflowJob {
+    #[serde(default)]
+    pub id: u64,
+    #[serde(default)]
+    pub name: String,
+    /// "queued", "in_progress", "completed", ...
+    pub status: String,
+    /// "success", "failure", "cancelled", ... once completed
+    #[serde(default)]
+    pub conclusion: Option<String>,
+    /// Runner the job was assigned to, once picked up
+    #[serde(default)]
+    pub runner_name: Option<String>,
+    /// Labels the job requested (`runs-on`)
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// When the job was created, e.g. `2024-01-15T10:23:45Z`
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// When the job started; GitHub sets this when the job is queued, too
+    #[serde(default)]
+    pub started_at: Option<String>,
+    /// Commit the job runs for
+    #[serde(default)]
+    pub head_sha: String,
+    /// Workflow run the job belongs to
+    #[serde(default)]
+    pub run_id: u64,
+    #[serde(default)]
+    pub workflow_name: Option<String>,
+}
+
+impl WorkflowJob {
+    /// Unix time the job has been waiting since
+    pub fn queued_at(&self) -> Option<u64> {
+        self.created_at
+            .as_deref()
+            .or(self.started_at.as_deref())
+            .and_then(parse_utc_timestamp)
+    }
+}
+
+/// Order jobs by how long they have been queued, oldest first. Jobs without a
+/// timestamp go last; ties keep GitHub's order.
+pub fn sort_oldest_first(jobs: &mut [WorkflowJob]) {
+    jobs.sort_by_key(|j| j.queued_at().unwrap_or(u64::MAX));
+}
+
+/// Response from /repos/{owner}/{repo}
+#[derive(Debug, Deserialize)]
+pub struct RepositoryResponse {
+    pub default_branch: String,
+    /// True for private and internal repositories
+    pub private: bool,
+    #[serde(default)]
+    pub visibility: Option<String>,
+}
+
+/// Response from /repos/{owner}/{repo}/actions/permissions
+#[derive(Debug, Deserialize)]
+pub struct ActionsPermissionsResponse {
+    pub enabled: bool,
+}
+
+/// Cached repository facts used by policy decisions
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RepoMetadata {
+    pub default_branch: String,
+    /// `public`, `private` or `internal`
+    pub visibility: String,
+    pub private: bool,
+    /// Whether GitHub Actions is enabled; `None` if the token may not read it
+    pub actions_enabled: Option<bool>,
+    pub fetched_at: u64, // unix timestamp
+}
+
+/// Release of the actions runner, from /repos/actions/runner/releases
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunnerRelease {
+    /// e.g. `v2.321.0`
+    pub tag_name: String,
+    #[serde(default)]
+    pub published_at: Option<String>,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub draft: bool,
+}
+
+/// Response from /repos/{owner}/{repo}/actions/runs
+#[derive(Debug, Deserialize)]
+pub struct WorkflowRunsResponse {
+    pub workflow_runs: Vec<WorkflowRun>,
+}
+
+/// Response from /repos/{owner}/{repo}/actions/runs/{run_id}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WorkflowRun {
+    pub id: u64,
+    /// Workflow file, e.g. `.github/workflows/ci.yml`
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub head_branch: Option<String>,
+    /// User whose action started this attempt of the run (differs from the
+    /// original actor for re-runs)
+    #[serde(default)]
+    pub triggering_actor: Option<Account>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Account {
+    pub login: String,
+}
+
+/// Response from /repos/{owner}/{repo}/actions/runs/{run_id}/jobs
+#[derive(Debug, Deserialize)]
+pub struct WorkflowJobsResponse {
+    pub jobs: Vec<WorkflowJob>,
+}
+
+/// Name of the check run showing a job's place in the self-hosted queue
+pub const CHECK_RUN_NAME: &str = "self-hosted queue";
+
+/// Context of the commit status reporting runner provisioning failures
+pub const COMMIT_STATUS_CONTEXT: &str = "self-hosted runner";
+
+/// Longest commit status description GitHub accepts
+pub const COMMIT_STATUS_DESCRIPTION_MAX: usize = 140;
+
+/// Response from /repos/{repo}/check-runs
+#[derive(Debug, Deserialize)]
+pub struct CheckRun {
+    pub id: u64,
+}
+
+/// What a check run shows in the pull request UI
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckRunOutput {
+    pub title: String,
+    pub summary: String,
+}
+
+/// Response from /app/installations/{id}/access_tokens
+#[derive(Debug, Deserialize)]
+pub struct InstallationToken {
+    pub token: SecretString,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_oldest_first() {
+        let job = |id, created_at: Option<&str>| WorkflowJob {
+            id,
+            created_at: created_at.map(str::to_string),
+            ..Default::default()
+        };
+        let mut jobs = vec![
+            job(1, Some("2024-01-15T10:05:00Z")),
+            job(2, None),
+            job(3, Some("2024-01-15T10:00:00Z")),
+        ];
+        sort_oldest_first(&mut jobs);
+
+        let ids: Vec<u64> = jobs.iter().map(|j| j.id).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+        assert_eq!(jobs[0].queued_at(), Some(1705312800));
+    }
+}
@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::state::ContainerState;
+
+/// Lifecycle events operators can hook commands into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Spawn,
+    Complete,
+    Timeout,
+    CleanupFailed,
+}
+
+impl HookEvent {
+    /// The hook run for a history event, if any
+    pub fn for_history_event(event: &str) -> Option<Self> {
+        Some(match event {
+            "spawned" => Self::Spawn,
+            "completed" => Self::Complete,
+            "timed_out" => Self::Timeout,
+            "cleanup_failed" => Self::CleanupFailed,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Spawn => "on_spawn",
+            Self::Complete => "on_complete",
+            Self::Timeout => "on_timeout",
+            Self::CleanupFailed => "on_cleanup_failed",
+        }
+    }
+}
+
+/// Shell commands run on lifecycle events (`HOOK_ON_*`)
+#[derive(Debug, Clone, Serialize)]
+pub struct HookConfig {
+    pub on_spawn: Option<String>,
+    pub on_complete: Option<String>,
+    pub on_timeout: Option<String>,
+    pub on_cleanup_failed: Option<String>,
+    /// A hook still running after this long is killed
+    pub timeout: Duration,
+}
+
+impl HookConfig {
+    fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::Spawn => self.on_spawn.as_deref(),
+            HookEvent::Complete => self.on_complete.as_deref(),
+            HookEvent::Timeout => self.on_timeout.as_deref(),
+            HookEvent::CleanupFailed => self.on_cleanup_failed.as_deref(),
+        }
+    }
+}
+
+/// Runs the configured hooks in the background, with the container's job
+/// metadata in `HOOK_*` environment variables
+#[derive(Debug, Clone)]
+pub struct Hooks {
+    config: HookConfig,
+}
+
+impl Hooks {
+    pub fn new(config: HookConfig) -> Self {
+        Self { config }
+    }
+
+    /// Start the hook for `event` on container `name`, if one is configured.
+    /// Failures are logged and counted, never returned.
+    pub fn run(
+        &self,
+        event: HookEvent,
+        name: &str,
+        state: Option<&ContainerState>,
+        detail: Option<&str>,
+    ) -> Option<JoinHandle<()>> {
+        let command = self.config.command(event)?;
+        let env = hook_env(event, name, state, detail);
+        let mut child = Command::new("/bin/sh");
+        child.arg("-c").arg(command).envs(env).kill_on_drop(true);
+        let timeout = self.config.timeout;
+        let name = name.to_string();
+
+        Some(tokio::spawn(async move {
+            let hook = event.name();
+            debug!(hook, name = %name, "Running hook");
+            let outcome = match tokio::time::timeout(timeout, child.output()).await {
+                Ok(Ok(output)) if output.status.success() => "success",
+                Ok(Ok(output)) => {
+                    warn!(
+                        hook,
+                        name = %name,
+                        status = %output.status,
+                        stderr = %String::from_utf8_lossy(&output.stderr).trim(),
+                        "Hook failed"
+                    );
+                    "failure"
+                }
+                Ok(Err(e)) => {
+                    warn!(hook, name = %name, error = %e, "Failed to run hook");
+                    "failure"
+                }
+                Err(_) => {
+                    info!(hook, name = %name, ?timeout, "Hook timed out, killed");
+                    "timeout"
+                }
+            };
+            metrics::counter!(
+                "runner_controller_hook_runs_total",
+                "hook" => hook,
+                "outcome" => outcome
+            )
+            .increment(1);
+        }))
+    }
+}
+
+/// Environment of a hook: the event, the container and what is known of its job
+fn hook_env(
+    event: HookEvent,
+    name: &str,
+    state: Option<&ContainerState>,
+    detail: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    let mut env = vec![
+        ("HOOK_EVENT", event.name().to_string()),
+        ("HOOK_CONTAINER", name.to_string()),
+    ];
+    if let Some(detail) = detail {
+        env.push(("HOOK_DETAIL", detail.to_string()));
+    }
+    let Some(state) = state else {
+        return env;
+    };
+    let optional = [
+        ("HOOK_JOB_ID", state.job_id.map(|id| id.to_string())),
+        ("HOOK_RUN_ID", state.run_id.map(|id| id.to_string())),
+        ("HOOK_WORKFLOW", state.workflow_name.clone()),
+        ("HOOK_JOB_NAME", state.job_name.clone()),
+        ("HOOK_REPOSITORY", state.repository.clone()),
+        ("HOOK_SLOT", state.slot.map(|slot| slot.to_string())),
+    ];
+    env.extend(optional.into_iter().filter_map(|(key, value)| Some((key, value?))));
+    env.push(("HOOK_LABELS", state.labels.join(",")));
+    env.push(("HOOK_RUNNING_SECONDS", state.running_seconds().to_string()));
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_env() {
+        let dir = std::env::temp_dir().join(format!("hooks-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out");
+        let hooks = Hooks::new(HookConfig {
+            on_spawn: None,
+            on_complete: Some(format!(
+                "echo \"$HOOK_EVENT $HOOK_CONTAINER $HOOK_JOB_ID $HOOK_REPOSITORY\" > {}",
+                out.display()
+            )),
+            on_timeout: Some("sleep 5".to_string()),
+            on_cleanup_failed: None,
+            timeout: Duration::from_millis(100),
+        });
+        let mut state = ContainerState::new(0);
+        state.job_id = Some(42);
+        state.repository = Some("owner/repo".to_string());
+
+        assert!(hooks.run(HookEvent::Spawn, "r0", Some(&state), None).is_none());
+        tokio_test::block_on(async {
+            let complete = hooks.run(HookEvent::Complete, "r0", Some(&state), None).unwrap();
+            complete.await.unwrap();
+            // Killed after the timeout rather than holding up the controller
+            let timeout = hooks.run(HookEvent::Timeout, "r0", None, Some("7300s")).unwrap();
+            tokio::time::timeout(Duration::from_secs(2), timeout).await.unwrap().unwrap();
+        });
+        let written = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(written.trim(), "on_complete r0 42 owner/repo");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,2372 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, LinesStream};
+use tokio_stream::StreamExt;
+use tokio_util::io::ReaderStream;
+use metrics_exporter_prometheus::PrometheusHandle;
+use tracing::info;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+use crate::arch::{ArchCapacity, ArchRoutes};
+use crate::config::{Config, ContainerBackend};
+use crate::container::{Backend, ContainerManager, Fleet, HostCapacity, ResourceUsage};
+use crate::fairness::FairShareExceeded;
+use crate::github::{BreakerState, BudgetStatus, GitHubApi, RepoMetadata};
+use crate::leader::{LeaderStatus, NotLeader};
+use crate::listener::{ControlRequest, DispatchPaused, UnknownJob};
+use crate::load::{disk_usage, DiskUsage};
+use crate::maintenance::{active_window, MaintenanceWindow};
+use crate::notify::{NotificationEvent, Notifier};
+use crate::policy::{JobContext, PolicyRejection};
+use crate::runner_version::VersionStatus;
+use crate::secrets::SecretString;
+use crate::starvation::labels_match;
+use crate::state::{
+    unix_now, AuditRecord, CleanupKind, ConfigSnapshot, ContainerPhase, HistoryEntry, LogQuery,
+    PauseState, PendingCleanup, PoolSpec, PoolUpdate, SpawnTimings, StateDb, UnmatchedJob,
+    DEFAULT_POOL, MAX_LOG_LIMIT,
+};
+use crate::tenant::{Tenant, Viewer};
+use crate::throttle::SpawnThrottled;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub state_db: Arc<StateDb>,
+    pub containers: Arc<dyn Backend>,
+    pub start_time: Instant,
+    pub poll_interval_seconds: u64,
+    pub reconcile_interval_seconds: u64,
+    /// Watts per busy CPU core for energy estimates; `None` omits them
+    pub cpu_watts: Option<f64>,
+    pub job_timeout_seconds: u64,
+    pub api_token: Option<SecretString>,
+    pub control_tx: mpsc::Sender<ControlRequest>,
+    pub metrics: PrometheusHandle,
+    pub notifier: Notifier,
+    pub github: Arc<dyn GitHubApi>,
+    pub github_repo: String,
+    /// The warm pool as configured, served until a spec is stored
+    pub configured_pool: PoolSpec,
+    /// Tenant-scoped read tokens; when non-empty, read views require a token
+    pub tenants: Arc<Vec<Tenant>>,
+    /// Secret for GitHub webhook signatures; `None` disables `POST /webhook`
+    pub webhook_secret: Option<SecretString>,
+    /// Filesystems whose free space is reported
+    pub disk_paths: Vec<PathBuf>,
+    /// Role from leader election; `None` without a lease
+    pub leader: Option<watch::Receiver<LeaderStatus>>,
+    /// Weekly windows in which no runners are spawned
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    /// Pools for foreign architectures, for capacity reporting
+    pub arch_routes: ArchRoutes,
+    /// Remote hosts external runners are placed on, for capacity reporting
+    pub fleet: Option<Arc<Fleet>>,
+    /// Requests arrive on the Unix socket, whose file mode already limits who
+    /// can connect: they get admin access without a token
+    pub local: bool,
+}
+
+impl AppState {
+    /// State for serving a controller built from `config`, rebuilt with it on
+    /// each reload. Without remote hosts or a leader lease, and up from now.
+    pub fn new(
+        config: &Config,
+        state_db: Arc<StateDb>,
+        containers: Arc<dyn Backend>,
+        github: Arc<dyn GitHubApi>,
+        notifier: Notifier,
+        control_tx: mpsc::Sender<ControlRequest>,
+        metrics: PrometheusHandle,
+    ) -> Self {
+        Self {
+            state_db,
+            containers,
+            start_time: Instant::now(),
+            poll_interval_seconds: config.poll_interval.as_secs(),
+            reconcile_interval_seconds: config.reconcile_interval.as_secs(),
+            cpu_watts: config.cpu_watts,
+            job_timeout_seconds: config.job_timeout.as_secs(),
+            api_token: config.api_token.clone(),
+            control_tx,
+            metrics,
+            notifier,
+            github,
+            github_repo: config.github_repo.clone(),
+            configured_pool: PoolSpec::from_config(config),
+            tenants: Arc::new(config.tenants.clone()),
+            webhook_secret: config.webhook_secret.clone(),
+            disk_paths: config.load_limits.disk_paths.clone(),
+            maintenance_windows: config.maintenance_windows.clone(),
+            arch_routes: config.arch_routes.clone(),
+            fleet: None,
+            leader: None,
+            local: false,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StatusResponse {
+    pub pool_size: usize,
+    pub active_containers: usize,
+    pub containers: Vec<ContainerInfo>,
+    pub poll_interval_seconds: u64,
+    pub reconcile_interval_seconds: u64,
+    pub job_timeout_seconds: u64,
+    pub uptime_seconds: u64,
+    /// Seconds since the controller last completed a pool maintenance pass
+    pub last_snapshot_age_seconds: Option<u64>,
+    /// Hash of the effective configuration (see `GET /config/{hash}`)
+    pub config_hash: Option<String>,
+    /// GitHub API circuit breaker; polling is paused unless `closed`
+    pub github_circuit: BreakerState,
+    /// Seconds until an open circuit is probed again
+    pub github_circuit_retry_seconds: Option<u64>,
+    /// GitHub rate limit budget as of the last response
+    pub github_rate_budget: Option<BudgetStatus>,
+    /// Label carried by every runner this controller instance registered
+    pub instance_label: Option<String>,
+    /// Whether this controller holds the leader lease; `None` without
+    /// `LEADER_LEASE_FILE`
+    pub leader: Option<LeaderStatus>,
+    /// Set while an operator has paused dispatch (`POST /pause`)
+    pub paused: Option<PauseState>,
+    /// Maintenance window in effect (`MAINTENANCE_WINDOWS`), if any
+    pub maintenance_window: Option<String>,
+    /// Cached metadata of the configured repository, if fetched yet
+    pub repository: Option<RepoMetadata>,
+    /// Failed deregistrations and container destructions awaiting a retry
+    pub pending_cleanups: Vec<PendingCleanup>,
+    /// Free space on the state directory, Nix store and container roots
+    pub disk: Vec<DiskUsage>,
+    /// Queued jobs requesting labels no pool or registered runner offers
+    pub unmatched_jobs: Vec<UnmatchedJob>,
+    /// Last comparison of `RUNNER_VERSION` with GitHub's runner releases
+    pub runner_version: Option<VersionStatus>,
+    /// Runners per architecture; empty without `ARCH_TEMPLATES`
+    pub architectures: Vec<ArchCapacity>,
+    /// External runners per host; empty without `REMOTE_HOSTS`
+    pub hosts: Vec<HostCapacity>,
+    /// Problems needing operator attention, one line each
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ContainerInfo {
+    pub name: String,
+    pub slot: Option<usize>,
+    pub job_id: Option<u64>,
+    pub run_id: Option<u64>,
+    pub workflow_name: Option<String>,
+    pub job_name: Option<String>,
+    pub repository: Option<String>,
+    pub labels: Vec<String>,
+    /// GitHub runner ID, once the runner has registered
+    pub runner_id: Option<u64>,
+    pub running_seconds: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ContainerDetail {
+    pub name: String,
+    pub runner_name: String,
+    pub slot: Option<usize>,
+    pub job_id: Option<u64>,
+    pub run_id: Option<u64>,
+    pub workflow_name: Option<String>,
+    pub job_name: Option<String>,
+    pub repository: Option<String>,
+    pub labels: Vec<String>,
+    pub runner_id: Option<u64>,
+    pub phase: ContainerPhase,
+    /// When the runner was started for the current job (unix timestamp)
+    pub started_at: u64,
+    /// When the container was created, if it was reused since (unix timestamp)
+    pub container_started_at: Option<u64>,
+    pub running_seconds: u64,
+    /// Jobs this container finished before its current one
+    pub jobs_served: u32,
+    /// Duration of each spawn phase observed so far
+    pub timings: SpawnTimings,
+    pub template: Option<String>,
+    pub gpu: Option<usize>,
+    /// `None` if the container's unit couldn't be queried
+    pub resources: Option<ResourceUsage>,
+    /// Last lines of the runner journal; empty without an API token
+    pub log_tail: Vec<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DetailQuery {
+    /// Number of trailing runner journal lines to include
+    #[serde(default = "default_detail_tail")]
+    pub tail: usize,
+}
+
+fn default_detail_tail() -> usize {
+    20
+}
+
+/// Longer tails belong to `GET /containers/{name}/logs`
+const MAX_DETAIL_TAIL: usize = 1000;
+
+#[derive(Serialize, ToSchema)]
+pub struct QueuedJob {
+    pub job_id: u64,
+    pub name: String,
+    pub labels: Vec<String>,
+    /// Whether this controller's runners can take the job
+    pub matches_labels: bool,
+    /// How long the job has been waiting
+    pub queued_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SpawnRequest {
+    #[serde(flatten)]
+    pub job: JobContext,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SpawnResponse {
+    pub name: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ReleaseRequest {
+    pub job_id: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct QuarantineRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct QuarantineResponse {
+    /// Where the container's root filesystem was preserved
+    pub path: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NotifyTestRequest {
+    /// Event kind to render with sample values
+    #[serde(default = "default_test_event")]
+    pub event: String,
+}
+
+fn default_test_event() -> String {
+    "test".to_string()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogsQuery {
+    /// Number of trailing lines to return
+    #[serde(default = "default_log_tail")]
+    pub tail: usize,
+    /// Stream new lines as server-sent events (live containers only)
+    #[serde(default)]
+    pub follow: bool,
+    /// Return the whole captured log (cleaned-up containers only), honoring
+    /// `Range` and `Accept-Encoding: zstd`
+    #[serde(default)]
+    pub download: bool,
+}
+
+fn default_log_tail() -> usize {
+    200
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReadyQuery {
+    /// Make live requests instead of relying on cached health signals
+    #[serde(default)]
+    pub deep: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    pub checks: BTreeMap<&'static str, ReadyCheck>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadyCheck {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl<E: std::fmt::Display> From<Result<(), E>> for ReadyCheck {
+    fn from(result: Result<(), E>) -> Self {
+        Self {
+            ok: result.is_ok(),
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// GET /health, GET /livez - the process is alive and serving requests
+#[utoipa::path(
+    get, path = "/health", tag = "health",
+    responses((status = 200, description = "Alive; `/livez` is an alias"))
+)]
+async fn health() -> impl IntoResponse {
+    StatusCode::OK
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VersionResponse {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub build_date: &'static str,
+    /// Container backends this build supports
+    pub backends: &'static [&'static str],
+    /// Backend in use
+    pub backend: &'static str,
+    pub config_hash: Option<String>,
+}
+
+/// GET /version - build info, for telling apart controllers running mixed versions
+#[utoipa::path(
+    get, path = "/version", tag = "status", responses((status = 200, body = VersionResponse))
+)]
+async fn version(State(state): State<AppState>) -> impl IntoResponse {
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("RUNNER_CONTROLLER_GIT_COMMIT"),
+        build_date: env!("RUNNER_CONTROLLER_BUILD_DATE"),
+        backends: ContainerBackend::SUPPORTED,
+        backend: state.containers.name(),
+        config_hash: state.state_db.current_config_hash(),
+    })
+}
+
+/// Container backend calls slower than this count as unresponsive
+const READY_BACKEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// GET /readyz - whether the controller can do its job: GitHub reachable,
+/// state database writable, container backend responsive. `?deep=true` also
+/// makes a live GitHub request instead of trusting the circuit breaker.
+#[utoipa::path(
+    get, path = "/readyz", tag = "health", params(ReadyQuery),
+    responses(
+        (status = 200, description = "Ready", body = ReadyResponse),
+        (status = 503, description = "A check failed", body = ReadyResponse),
+    )
+)]
+async fn readyz(State(state): State<AppState>, Query(query): Query<ReadyQuery>) -> impl IntoResponse {
+    let mut checks = BTreeMap::new();
+
+    let github = if query.deep {
+        state.github.probe().await.map_err(anyhow::Error::from)
+    } else if state.github.circuit_state() == BreakerState::Open {
+        Err(anyhow::anyhow!("circuit breaker open"))
+    } else {
+        Ok(())
+    };
+    checks.insert("github", ReadyCheck::from(github));
+
+    checks.insert("state_db", ReadyCheck::from(state.state_db.check_writable()));
+
+    let backend = match tokio::time::timeout(READY_BACKEND_TIMEOUT, state.containers.list_all()).await {
+        Ok(result) => result.map(|_| ()).map_err(anyhow::Error::from),
+        Err(_) => Err(anyhow::anyhow!("timed out after {:?}", READY_BACKEND_TIMEOUT)),
+    };
+    checks.insert("container_backend", ReadyCheck::from(backend));
+
+    let ready = checks.values().all(|c| c.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(ReadyResponse { ready, checks })).into_response()
+}
+
+/// GET /status - JSON status of pool containers
+#[utoipa::path(
+    get, path = "/status", tag = "status", security((), ("api_token" = [])),
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 401, description = "Tenant token required"),
+    )
+)]
+async fn status(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    let viewer = match viewer(&state, &headers) {
+        Ok(viewer) => viewer,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let db_containers = match state.state_db.list_containers() {
+        Ok(c) => c,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list containers").into_response(),
+    };
+
+    // Tenants get nothing about other repositories or the host itself
+    let admin = matches!(viewer, Viewer::Admin);
+    let db_containers: Vec<_> = db_containers
+        .into_iter()
+        .filter(|(_, s)| viewer.can_see(s.repository.as_deref()))
+        .collect();
+
+    let architectures = if state.arch_routes.is_empty() {
+        Vec::new()
+    } else {
+        let states = db_containers.iter().map(|(_, s)| s);
+        state.arch_routes.capacity(pool_spec(&state).size, states)
+    };
+    let containers: Vec<ContainerInfo> = db_containers
+        .into_iter()
+        .map(|(name, container_state)| ContainerInfo {
+            name,
+            slot: container_state.slot,
+            job_id: container_state.job_id,
+            run_id: container_state.run_id,
+            running_seconds: container_state.running_seconds(),
+            workflow_name: container_state.workflow_name,
+            job_name: container_state.job_name,
+            repository: container_state.repository,
+            labels: container_state.labels,
+            runner_id: container_state.runner_id,
+        })
+        .collect();
+
+    let unmatched_jobs = if viewer.can_see(Some(&state.github_repo)) {
+        state.state_db.list_unmatched_jobs().unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    let runner_version = state.state_db.get_runner_version().ok().flatten();
+    let mut warnings: Vec<String> = unmatched_jobs
+        .iter()
+        .map(|job| {
+            format!(
+                "Job {} ({}) requests labels [{}] that no runner offers",
+                job.job_id,
+                job.job_name,
+                job.labels.join(", ")
+            )
+        })
+        .collect();
+    warnings.extend(runner_version.as_ref().and_then(|v| v.warning(unix_now())));
+    let hosts = match &state.fleet {
+        Some(fleet) if admin => fleet.capacity(),
+        _ => Vec::new(),
+    };
+    warnings.extend(
+        hosts
+            .iter()
+            .filter(|h| !h.reachable)
+            .map(|h| format!("Remote host {} unreachable, no runners placed on it", h.host)),
+    );
+    let paused = state.state_db.get_pause().ok().flatten();
+    if let Some(pause) = &paused {
+        warnings.push(match &pause.reason {
+            Some(reason) => format!("Dispatch paused: {}", reason),
+            None => "Dispatch paused".to_string(),
+        });
+    }
+    let maintenance_window =
+        active_window(&state.maintenance_windows, unix_now()).map(ToString::to_string);
+    if let Some(window) = &maintenance_window {
+        warnings.push(format!("In maintenance window {}, no runners are spawned", window));
+    }
+    let mut github_rate_budget = state.github.rate_budget();
+    if let (Viewer::Tenant(tenant), Some(budget)) = (viewer, &mut github_rate_budget) {
+        budget.spent.retain(|repository, _| tenant.owns(repository));
+    }
+    if let Some(budget) = github_rate_budget.as_ref().filter(|b| b.poll_stretch > 1.0) {
+        warnings.push(format!(
+            "GitHub rate limit budget tight ({} of {} left), polling {:.1}x slower",
+            budget.remaining, budget.limit, budget.poll_stretch
+        ));
+    }
+
+    let response = StatusResponse {
+        pool_size: pool_spec(&state).size,
+        active_containers: containers.len(),
+        containers,
+        poll_interval_seconds: state.poll_interval_seconds,
+        reconcile_interval_seconds: state.reconcile_interval_seconds,
+        job_timeout_seconds: state.job_timeout_seconds,
+        uptime_seconds: state.start_time.elapsed().as_secs(),
+        last_snapshot_age_seconds: state
+            .state_db
+            .get_pool_snapshot()
+            .ok()
+            .flatten()
+            .map(|s| s.age_seconds()),
+        config_hash: state.state_db.current_config_hash(),
+        github_circuit: state.github.circuit_state(),
+        github_circuit_retry_seconds: state.github.circuit_retry_in(),
+        github_rate_budget,
+        instance_label: admin
+            .then(|| state.containers.naming().instance_label().map(str::to_string))
+            .flatten(),
+        leader: admin
+            .then(|| state.leader.as_ref().map(|leader| leader.borrow().clone()))
+            .flatten(),
+        paused,
+        maintenance_window,
+        repository: admin
+            .then(|| state.github.cached_repo_metadata(&state.github_repo))
+            .flatten(),
+        pending_cleanups: state
+            .state_db
+            .list_cleanups()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|cleanup| admin || viewer.can_see(cleanup_repository(&state, cleanup).as_deref()))
+            .collect(),
+        disk: if admin { disk_usage(&state.disk_paths) } else { Vec::new() },
+        unmatched_jobs,
+        runner_version,
+        architectures,
+        hosts,
+        warnings,
+    };
+
+    Json(response).into_response()
+}
+
+/// GET /history - controller events, newest first unless `order=asc`
+#[utoipa::path(
+    get, path = "/history", tag = "status", params(LogQuery), security((), ("api_token" = [])),
+    responses(
+        (status = 200, body = Vec<HistoryEntry>),
+        (status = 401, description = "Tenant token required"),
+    )
+)]
+async fn history(
+    State(state): State<AppState>,
+    Query(mut query): Query<LogQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let viewer = match viewer(&state, &headers) {
+        Ok(viewer) => viewer,
+        Err(rejection) => return rejection.into_response(),
+    };
+    query.limit = query.limit.min(MAX_LOG_LIMIT);
+
+    // Tenants see a filtered view; filtering while scanning keeps pages full
+    let visible = |e: &HistoryEntry| viewer.can_see(e.repository.as_deref());
+    match state.state_db.query_history(&query, visible) {
+        Ok(entries) => Json(entries).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read history").into_response(),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SummaryQuery {
+    /// Only jobs cleaned up at or after this unix timestamp
+    pub since: Option<u64>,
+    /// Only jobs cleaned up before this unix timestamp
+    pub until: Option<u64>,
+    /// Repository (`owner/repo`, case-insensitive)
+    pub repo: Option<String>,
+}
+
+/// Job outcomes from the retained history
+#[derive(Debug, Default, PartialEq, Serialize, ToSchema)]
+pub struct HistorySummary {
+    /// Jobs with a known conclusion
+    pub jobs: u64,
+    /// Jobs per conclusion, e.g. `success`, `failure`, `cancelled`
+    pub conclusions: BTreeMap<String, u64>,
+    /// Fraction of jobs that succeeded; `None` without jobs
+    pub success_rate: Option<f64>,
+    /// Fraction of jobs that failed or timed out
+    pub failure_rate: Option<f64>,
+}
+
+impl HistorySummary {
+    fn from_entries(entries: &[HistoryEntry]) -> Self {
+        let mut summary = Self::default();
+        for conclusion in entries.iter().filter_map(|e| e.conclusion.as_ref()) {
+            summary.jobs += 1;
+            *summary.conclusions.entry(conclusion.clone()).or_default() += 1;
+        }
+        if summary.jobs > 0 {
+            let rate = |names: &[&str]| {
+                let count: u64 = names.iter().filter_map(|n| summary.conclusions.get(*n)).sum();
+                count as f64 / summary.jobs as f64
+            };
+            summary.success_rate = Some(rate(&["success"]));
+            summary.failure_rate = Some(rate(&["failure", "timed_out"]));
+        }
+        summary
+    }
+}
+
+/// Resources used by the containers of one repository or workflow
+#[derive(Debug, Default, PartialEq, Serialize, ToSchema)]
+pub struct UsageTotals {
+    pub repository: Option<String>,
+    /// `None` in the per-repository totals
+    pub workflow: Option<String>,
+    /// Containers cleaned up
+    pub containers: u64,
+    /// CPU time, from the containers' cgroups
+    pub cpu_seconds: f64,
+    /// Time the containers existed
+    pub wall_seconds: u64,
+    /// Estimated energy from `CPU_WATTS`; `None` if unset
+    pub energy_wh: Option<f64>,
+}
+
+/// Resource usage from the retained history, most CPU time first
+#[derive(Debug, Default, PartialEq, Serialize, ToSchema)]
+pub struct UsageSummary {
+    pub repositories: Vec<UsageTotals>,
+    pub workflows: Vec<UsageTotals>,
+}
+
+impl UsageSummary {
+    fn from_entries(entries: &[HistoryEntry], cpu_watts: Option<f64>) -> Self {
+        type Key = (Option<String>, Option<String>);
+        let mut repositories: BTreeMap<Key, UsageTotals> = BTreeMap::new();
+        let mut workflows: BTreeMap<Key, UsageTotals> = BTreeMap::new();
+        for entry in entries {
+            let repository = entry.repository.clone();
+            for (totals, workflow) in [
+                (&mut repositories, None),
+                (&mut workflows, entry.workflow.clone()),
+            ] {
+                let key = (repository.clone(), workflow.clone());
+                let totals = totals.entry(key).or_insert_with(|| UsageTotals {
+                    repository: repository.clone(),
+                    workflow,
+                    ..Default::default()
+                });
+                totals.containers += 1;
+                totals.cpu_seconds += entry.cpu_seconds.unwrap_or_default();
+                totals.wall_seconds += entry.wall_seconds.unwrap_or_default();
+            }
+        }
+
+        let finish = |totals: BTreeMap<Key, UsageTotals>| {
+            let mut totals: Vec<UsageTotals> = totals
+                .into_values()
+                .map(|t| UsageTotals {
+                    energy_wh: cpu_watts.map(|watts| t.cpu_seconds * watts / 3600.0),
+                    ..t
+                })
+                .collect();
+            totals.sort_by(|a, b| b.cpu_seconds.total_cmp(&a.cpu_seconds));
+            totals
+        };
+        Self {
+            repositories: finish(repositories),
+            workflows: finish(workflows),
+        }
+    }
+}
+
+/// GET /history/usage - CPU and wall time per repository and workflow
+#[utoipa::path(
+    get, path = "/history/usage", tag = "status", params(SummaryQuery),
+    security((), ("api_token" = [])),
+    responses(
+        (status = 200, body = UsageSummary),
+        (status = 401, description = "Tenant token required"),
+    )
+)]
+async fn history_usage(
+    State(state): State<AppState>,
+    Query(query): Query<SummaryQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let viewer = match viewer(&state, &headers) {
+        Ok(viewer) => viewer,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let query = LogQuery {
+        limit: usize::MAX,
+        since: query.since,
+        until: query.until,
+        repo: query.repo,
+        event: Some("cleaned_up".to_string()),
+        ..Default::default()
+    };
+    let visible = |e: &HistoryEntry| viewer.can_see(e.repository.as_deref());
+    match state.state_db.query_history(&query, visible) {
+        Ok(entries) => Json(UsageSummary::from_entries(&entries, state.cpu_watts)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read history").into_response(),
+    }
+}
+
+/// GET /history/summary - conclusions of the jobs in the retained history
+#[utoipa::path(
+    get, path = "/history/summary", tag = "status", params(SummaryQuery),
+    security((), ("api_token" = [])),
+    responses(
+        (status = 200, body = HistorySummary),
+        (status = 401, description = "Tenant token required"),
+    )
+)]
+async fn history_summary(
+    State(state): State<AppState>,
+    Query(query): Query<SummaryQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let viewer = match viewer(&state, &headers) {
+        Ok(viewer) => viewer,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let query = LogQuery {
+        limit: usize::MAX,
+        since: query.since,
+        until: query.until,
+        repo: query.repo,
+        event: Some("cleaned_up".to_string()),
+        ..Default::default()
+    };
+    let visible = |e: &HistoryEntry| viewer.can_see(e.repository.as_deref());
+    match state.state_db.query_history(&query, visible) {
+        Ok(entries) => Json(HistorySummary::from_entries(&entries)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read history").into_response(),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    /// Comma-separated event names to stream; all events if omitted
+    pub event: Option<String>,
+    /// Repository (`owner/repo`, case-insensitive)
+    pub repo: Option<String>,
+}
+
+/// GET /events - history entries as Server-Sent Events, as they are recorded
+#[utoipa::path(
+    get, path = "/events", tag = "status", params(EventsQuery), security((), ("api_token" = [])),
+    responses(
+        (status = 200, description = "History entries as server-sent events",
+            content_type = "text/event-stream"),
+        (status = 401, description = "Tenant token required"),
+    )
+)]
+async fn events(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    // The stream outlives this request's borrow of the state, so keep an owned tenant
+    let tenant: Option<Tenant> = match viewer(&state, &headers) {
+        Ok(Viewer::Admin) => None,
+        Ok(Viewer::Tenant(tenant)) => Some(tenant.clone()),
+        Err(rejection) => return rejection.into_response(),
+    };
+    let wanted: Option<Vec<String>> = query
+        .event
+        .as_ref()
+        .map(|e| e.split(',').map(|e| e.trim().to_string()).collect());
+
+    let stream = BroadcastStream::new(state.state_db.subscribe_events()).filter_map(move |item| {
+        let entry = match item {
+            Ok(entry) => entry,
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                let event = Event::default().event("lagged").data(missed.to_string());
+                return Some(Ok::<_, Infallible>(event));
+            }
+        };
+        let viewer = tenant.as_ref().map_or(Viewer::Admin, Viewer::Tenant);
+        if !viewer.can_see(entry.repository.as_deref()) {
+            return None;
+        }
+        if wanted.as_ref().is_some_and(|w| !w.contains(&entry.event)) {
+            return None;
+        }
+        let repository = entry.repository.as_deref();
+        if query.repo.as_ref().is_some_and(|repo| {
+            !repository.is_some_and(|r| r.eq_ignore_ascii_case(repo))
+        }) {
+            return None;
+        }
+        let event = Event::default()
+            .event(entry.event.clone())
+            .id(entry.id.to_string())
+            .json_data(&entry)
+            .ok()?;
+        Some(Ok(event))
+    });
+
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// GET /audit - policy rejections, newest first unless `order=asc`
+#[utoipa::path(
+    get, path = "/audit", tag = "status", params(LogQuery), security((), ("api_token" = [])),
+    responses(
+        (status = 200, body = Vec<AuditRecord>),
+        (status = 401, description = "Tenant token required"),
+    )
+)]
+async fn audit(
+    State(state): State<AppState>,
+    Query(mut query): Query<LogQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let viewer = match viewer(&state, &headers) {
+        Ok(viewer) => viewer,
+        Err(rejection) => return rejection.into_response(),
+    };
+    query.limit = query.limit.min(MAX_LOG_LIMIT);
+
+    let visible = |r: &AuditRecord| viewer.can_see(r.repository.as_deref());
+    match state.state_db.query_rejections(&query, visible) {
+        Ok(records) => Json(records).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read audit log").into_response(),
+    }
+}
+
+/// GET /queue - jobs waiting for a runner in the configured repository, oldest first
+#[utoipa::path(
+    get, path = "/queue", tag = "status", security((), ("api_token" = [])),
+    responses(
+        (status = 200, body = Vec<QueuedJob>),
+        (status = 401, description = "Tenant token required"),
+        (status = 502, description = "GitHub request failed"),
+    )
+)]
+async fn queue(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    let viewer = match viewer(&state, &headers) {
+        Ok(viewer) => viewer,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if !viewer.can_see(Some(&state.github_repo)) {
+        return Json(Vec::<QueuedJob>::new()).into_response();
+    }
+
+    let spec = pool_spec(&state);
+    let now = unix_now();
+    match state.github.list_queued_jobs().await {
+        Ok(jobs) => {
+            let jobs: Vec<QueuedJob> = jobs
+                .into_iter()
+                .map(|j| QueuedJob {
+                    matches_labels: labels_match(&j.labels, &spec.labels),
+                    queued_seconds: j.queued_at().map(|at| now.saturating_sub(at)),
+                    job_id: j.id,
+                    name: j.name,
+                    labels: j.labels,
+                })
+                .collect();
+            Json(jobs).into_response()
+        }
+        Err(e) => (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    }
+}
+
+/// Desired warm pool state, falling back to the startup configuration
+fn pool_spec(state: &AppState) -> PoolSpec {
+    state
+        .state_db
+        .get_pool_spec(DEFAULT_POOL)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| state.configured_pool.clone())
+}
+
+/// GET /pool - desired state of the warm pool; environment values need a token
+#[utoipa::path(
+    get, path = "/pool", tag = "pool", security((), ("api_token" = [])),
+    responses(
+        (status = 200, body = PoolSpec),
+        (status = 401, description = "Tenant token required"),
+    )
+)]
+async fn get_pool(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(rejection) = viewer(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    // Names only: values may be credentials
+    let mut spec = pool_spec(&state);
+    if authorize(&state, &headers).is_err() {
+        for var in &mut spec.env {
+            var.value = "[redacted]".to_string();
+        }
+    }
+    Json(spec).into_response()
+}
+
+/// PUT /pool - change the warm pool's size, labels and/or template at runtime
+#[utoipa::path(
+    put, path = "/pool", tag = "pool", request_body = PoolUpdate, security(("api_token" = [])),
+    responses(
+        (status = 200, description = "The new pool spec", body = PoolSpec),
+        (status = 400, description = "Invalid update"),
+        (status = 401, description = "Invalid or missing API token"),
+    )
+)]
+async fn put_pool(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(update): Json<PoolUpdate>,
+) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let (reply, rx) = oneshot::channel();
+    let request = ControlRequest::SetPool { update, reply };
+    if state.control_tx.send(request).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(spec)) => Json(spec).into_response(),
+        Ok(Err(e)) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response(),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct ReloadResponse {
+    config_hash: String,
+}
+
+/// POST /admin/reload - rebuild the controller from freshly loaded configuration,
+/// keeping state and running containers
+#[utoipa::path(
+    post, path = "/admin/reload", tag = "admin", security(("api_token" = [])),
+    responses(
+        (status = 200, body = ReloadResponse),
+        (status = 401, description = "Invalid or missing API token"),
+        (status = 422, description = "New configuration is invalid; the old one stays in effect"),
+    )
+)]
+async fn reload(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let (reply, rx) = oneshot::channel();
+    if state
+        .control_tx
+        .send(ControlRequest::Reload { reply })
+        .await
+        .is_err()
+    {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(config_hash)) => Json(ReloadResponse { config_hash }).into_response(),
+        Ok(Err(e)) => (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response(),
+    }
+}
+
+/// GET /state/backup - consistent snapshot of the state database, restored
+/// with `runnerctl state restore`
+#[utoipa::path(
+    get, path = "/state/backup", tag = "admin", security(("api_token" = [])),
+    responses(
+        (status = 200, description = "redb database file",
+            content_type = "application/octet-stream"),
+        (status = 401, description = "Invalid or missing API token"),
+        (status = 500, description = "Snapshot failed"),
+    )
+)]
+async fn state_backup(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let db = state.state_db.clone();
+    let file = match tokio::task::spawn_blocking(move || db.backup()).await {
+        Ok(Ok(file)) => file,
+        Ok(Err(e)) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    info!("Streaming state database backup");
+    let body = Body::from_stream(ReaderStream::new(tokio::fs::File::from_std(file)));
+    (
+        [
+            (header::CONTENT_TYPE, "application/octet-stream"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"state.redb\""),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// POST /wake - leave idle mode and poll right away
+#[utoipa::path(
+    post, path = "/wake", tag = "admin", security(("api_token" = [])),
+    responses(
+        (status = 202, description = "Woken up"),
+        (status = 401, description = "Invalid or missing API token"),
+    )
+)]
+async fn wake(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    send_wake(&state, "wake request").await
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PauseRequest {
+    /// Shown in `GET /status` and recorded in the history
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// POST /pause - stop spawning runners, e.g. for host maintenance. Runners
+/// already up stay and take jobs but aren't replaced, and reconciliation and
+/// cleanups go on. The pause lasts across restarts until `POST /resume`.
+#[utoipa::path(
+    post, path = "/pause", tag = "admin", request_body(content = Option<PauseRequest>),
+    security(("api_token" = [])),
+    responses(
+        (status = 200, description = "The pause in effect", body = PauseState),
+        (status = 401, description = "Invalid or missing API token"),
+    )
+)]
+async fn pause(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    request: Option<Json<PauseRequest>>,
+) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let reason = request.and_then(|Json(r)| r.reason);
+    let (reply, rx) = oneshot::channel();
+    if state.control_tx.send(ControlRequest::Pause { reason, reply }).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(pause)) => Json(pause).into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response(),
+    }
+}
+
+/// POST /resume - spawn runners again after `POST /pause`
+#[utoipa::path(
+    post, path = "/resume", tag = "admin", security(("api_token" = [])),
+    responses(
+        (status = 204, description = "Resumed, or not paused"),
+        (status = 401, description = "Invalid or missing API token"),
+    )
+)]
+async fn resume(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let (reply, rx) = oneshot::channel();
+    if state.control_tx.send(ControlRequest::Resume { reply }).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response(),
+    }
+}
+
+/// POST /webhook - GitHub `workflow_job` deliveries; a queued job the pool
+/// could serve wakes an idle controller
+#[utoipa::path(
+    post, path = "/webhook", tag = "admin",
+    request_body(content = String, description = "GitHub webhook delivery",
+        content_type = "application/json"),
+    params(("X-Hub-Signature-256" = String, Header, description = "HMAC-SHA256 of the body")),
+    responses(
+        (status = 202, description = "A queued job the pool serves woke the controller"),
+        (status = 204, description = "Delivery ignored"),
+        (status = 401, description = "Invalid webhook signature"),
+        (status = 404, description = "Webhooks disabled"),
+    )
+)]
+async fn webhook(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+    let Some(secret) = &state.webhook_secret else {
+        return (StatusCode::NOT_FOUND, "Webhooks disabled").into_response();
+    };
+    let signature = headers
+        .get("x-hub-signature-256")
+        .and_then(|v| v.to_str().ok());
+    if !signature.is_some_and(|s| verify_signature(secret.expose(), &body, s)) {
+        return (StatusCode::UNAUTHORIZED, "Invalid webhook signature").into_response();
+    }
+
+    // Other events (including the initial `ping`) are acknowledged and ignored
+    let event = headers.get("x-github-event").and_then(|v| v.to_str().ok());
+    if event != Some("workflow_job") {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    let payload: serde_json::Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid payload: {}", e)).into_response(),
+    };
+    let labels: Vec<String> = payload["workflow_job"]["labels"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|l| l.as_str().map(str::to_string))
+        .collect();
+    if payload["action"] != "queued" || !labels_match(&labels, &pool_spec(&state).labels) {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    send_wake(&state, "webhook").await
+}
+
+async fn send_wake(state: &AppState, reason: &str) -> axum::response::Response {
+    let request = ControlRequest::Wake {
+        reason: reason.to_string(),
+    };
+    if state.control_tx.send(request).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+    StatusCode::ACCEPTED.into_response()
+}
+
+/// Check a `sha256=<hex>` HMAC signature over the raw request body
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(tag) = signature.strip_prefix("sha256=").and_then(decode_hex) else {
+        return false;
+    };
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    ring::hmac::verify(&key, body, &tag).is_ok()
+}
+
+/// Whether a bearer token is `expected`, in constant time so response
+/// timings give nothing away
+fn token_matches(expected: &SecretString, provided: &str) -> bool {
+    expected.expose().as_bytes().ct_eq(provided.as_bytes()).into()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// GET /metrics - Prometheus metrics
+#[utoipa::path(
+    get, path = "/metrics", tag = "status",
+    responses(
+        (status = 200, description = "Prometheus text format", body = String,
+            content_type = "text/plain"),
+    )
+)]
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    state.metrics.render()
+}
+
+/// GET /config/{hash} - configuration snapshot referenced by history entries;
+/// admin only, as it describes every tenant's setup
+#[utoipa::path(
+    get, path = "/config/{hash}", tag = "status", params(("hash" = String, Path)),
+    security(("api_token" = [])),
+    responses(
+        (status = 200, body = ConfigSnapshot),
+        (status = 401, description = "Invalid or missing API token"),
+        (status = 404, description = "Unknown config hash"),
+    )
+)]
+async fn config_snapshot(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    match state.state_db.get_config_snapshot(&hash) {
+        Ok(Some(snapshot)) => Json::<ConfigSnapshot>(snapshot).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Unknown config hash").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read config").into_response(),
+    }
+}
+
+/// GET /containers/{name} - details of a live container: job, lifecycle phase,
+/// spawn phase timings, resource usage and the end of the runner journal
+#[utoipa::path(
+    get, path = "/containers/{name}", tag = "containers",
+    params(("name" = String, Path), DetailQuery), security((), ("api_token" = [])),
+    responses(
+        (status = 200, body = ContainerDetail),
+        (status = 404, description = "Container not running"),
+    )
+)]
+async fn container_detail(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<DetailQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let viewer = match viewer(&state, &headers) {
+        Ok(viewer) => viewer,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let container_state = match state.state_db.get_container(&name) {
+        Ok(Some(s)) if viewer.can_see(s.repository.as_deref()) => s,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Container not running").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read container").into_response(),
+    };
+
+    // The journal needs a token, as with `/containers/{name}/logs`; the
+    // rest of the detail doesn't
+    let tail = match viewer {
+        Viewer::Admin if authorize(&state, &headers).is_err() => 0,
+        _ => query.tail.min(MAX_DETAIL_TAIL),
+    };
+    let (completed, resources, logs) = tokio::join!(
+        state.containers.is_runner_completed(&name),
+        state.containers.resource_usage(&name),
+        state.containers.runner_logs(&name, tail),
+    );
+    let log_tail = match logs {
+        Ok(logs) if tail > 0 => logs.lines().map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+
+    Json(ContainerDetail {
+        runner_name: state.containers.runner_name(&name),
+        name,
+        slot: container_state.slot,
+        job_id: container_state.job_id,
+        run_id: container_state.run_id,
+        phase: container_state.phase(completed.unwrap_or(false)),
+        started_at: container_state.started_at,
+        container_started_at: container_state.container_started_at,
+        running_seconds: container_state.running_seconds(),
+        jobs_served: container_state.jobs_served,
+        workflow_name: container_state.workflow_name,
+        job_name: container_state.job_name,
+        repository: container_state.repository,
+        labels: container_state.labels,
+        runner_id: container_state.runner_id,
+        timings: container_state.timings,
+        template: container_state.template,
+        gpu: container_state.gpu,
+        resources: resources.ok(),
+        log_tail,
+    })
+    .into_response()
+}
+
+/// GET /containers/{name}/logs - runner journal for a live or cleaned-up container
+#[utoipa::path(
+    get, path = "/containers/{name}/logs", tag = "containers",
+    params(("name" = String, Path), LogsQuery), security(("api_token" = [])),
+    responses(
+        (status = 200, description = "Log lines, or server-sent events with `follow`",
+            body = String, content_type = "text/plain"),
+        (status = 206, description = "Requested byte range of a downloaded log", body = String,
+            content_type = "text/plain"),
+        (status = 404, description = "No logs available"),
+        (status = 416, description = "Range not satisfiable"),
+    )
+)]
+async fn container_logs(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<LogsQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if !ContainerManager::is_runner_name(&name) {
+        return (StatusCode::BAD_REQUEST, "Invalid container name").into_response();
+    }
+    let viewer = match viewer(&state, &headers) {
+        Ok(viewer) => viewer,
+        Err(rejection) => return rejection.into_response(),
+    };
+    // Runner journals carry job output and whatever workflows printed, so
+    // unlike the other read views they always need a token
+    if matches!(viewer, Viewer::Admin) {
+        if let Err(rejection) = authorize(&state, &headers) {
+            return rejection.into_response();
+        }
+    }
+
+    let live = state.state_db.get_container(&name).ok().flatten();
+    // Cleaned-up containers belong to the repository of their last job
+    let repository = match &live {
+        Some(container) => container.repository.clone(),
+        None => last_repository(&state, &name),
+    };
+    if !viewer.can_see(repository.as_deref()) {
+        return (StatusCode::NOT_FOUND, "No logs available").into_response();
+    }
+    let live = live.is_some();
+
+    if query.follow {
+        if !live {
+            return (StatusCode::NOT_FOUND, "Container not running").into_response();
+        }
+
+        let mut child = match state.containers.follow_runner_logs(&name, query.tail) {
+            Ok(child) => child,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        let Some(stdout) = child.stdout.take() else {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "No log output").into_response();
+        };
+
+        // The stream owns the child so journalctl is killed when the client disconnects
+        let stream = LinesStream::new(BufReader::new(stdout).lines()).map(move |line| {
+            let _ = &child;
+            Ok::<_, Infallible>(Event::default().data(line.unwrap_or_default()))
+        });
+
+        return Sse::new(stream)
+            .keep_alive(KeepAlive::default())
+            .into_response();
+    }
+
+    if live {
+        if let Ok(logs) = state.containers.runner_logs(&name, query.tail).await {
+            return logs.into_response();
+        }
+    }
+
+    // Fall back to logs captured at cleanup time, read and decompressed off
+    // the async runtime
+    let containers = Arc::clone(&state.containers);
+    let read = tokio::task::spawn_blocking(move || {
+        if query.download {
+            return download_captured_logs(containers.as_ref(), &name, &headers);
+        }
+        match containers.read_captured_logs(&name) {
+            Some(logs) => {
+                let lines: Vec<&str> = logs.lines().collect();
+                let start = lines.len().saturating_sub(query.tail);
+                lines[start..].join("\n").into_response()
+            }
+            None => (StatusCode::NOT_FOUND, "No logs available").into_response(),
+        }
+    });
+    read.await.unwrap_or_else(|_| {
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read logs").into_response()
+    })
+}
+
+/// Repository of the container a cleanup is for: a runner's container or
+/// the container itself, live or from the history
+fn cleanup_repository(state: &AppState, cleanup: &PendingCleanup) -> Option<String> {
+    let name = match cleanup.kind {
+        CleanupKind::Deregister => state.containers.naming().container_name(&cleanup.target)?,
+        CleanupKind::Destroy => cleanup.target.as_str(),
+    };
+    match state.state_db.get_container(name).ok().flatten() {
+        Some(container) => container.repository,
+        None => last_repository(state, name),
+    }
+}
+
+/// Repository of the last job a cleaned-up container ran, from its history
+fn last_repository(state: &AppState, name: &str) -> Option<String> {
+    let query = LogQuery {
+        limit: 1,
+        container: Some(name.to_string()),
+        ..Default::default()
+    };
+    let entries = state.state_db.query_history(&query, |e| e.repository.is_some()).ok()?;
+    entries.into_iter().next()?.repository
+}
+
+/// Serve a whole captured log: the stored zstd file as-is when the client accepts
+/// it, otherwise decompressed, optionally limited to a single byte range
+fn download_captured_logs(
+    containers: &dyn Backend,
+    name: &str,
+    headers: &HeaderMap,
+) -> axum::response::Response {
+    let accepts_zstd = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|e| e.trim().starts_with("zstd")));
+    let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+
+    if accepts_zstd && range.is_none() {
+        if let Some(Ok(compressed)) = containers.captured_logs_path(name).map(std::fs::read) {
+            return (
+                [
+                    (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+                    (header::CONTENT_ENCODING, "zstd"),
+                ],
+                compressed,
+            )
+                .into_response();
+        }
+    }
+
+    let Some(logs) = containers.read_captured_logs(name) else {
+        return (StatusCode::NOT_FOUND, "No logs available").into_response();
+    };
+    let bytes = logs.into_bytes();
+
+    let Some(range) = range else {
+        return ([(header::ACCEPT_RANGES, "bytes")], bytes).into_response();
+    };
+
+    match parse_range(range, bytes.len()) {
+        Some((start, end)) => (
+            StatusCode::PARTIAL_CONTENT,
+            [(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, bytes.len()),
+            )],
+            bytes[start..=end].to_vec(),
+        )
+            .into_response(),
+        None => (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", bytes.len()))],
+        )
+            .into_response(),
+    }
+}
+
+/// Parse a single `bytes=` range into inclusive offsets within `len`
+fn parse_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    if len == 0 {
+        return None;
+    }
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        // Suffix range: the last N bytes
+        ("", suffix) => {
+            let n: usize = suffix.parse().ok()?;
+            (len.saturating_sub(n), len - 1)
+        }
+        (start, "") => (start.parse().ok()?, len - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<usize>().ok()?.min(len - 1)),
+    };
+
+    (start <= end && start < len).then_some((start, end))
+}
+
+/// Work out whose view a read request gets. Without tenants every reader is an
+/// admin; with tenants, a token is required and tenant tokens get a scoped view.
+fn viewer<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+) -> Result<Viewer<'a>, (StatusCode, &'static str)> {
+    if state.local || state.tenants.is_empty() {
+        return Ok(Viewer::Admin);
+    }
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let Some(token) = provided else {
+        return Err((StatusCode::UNAUTHORIZED, "Missing API token"));
+    };
+
+    if state.api_token.as_ref().is_some_and(|t| token_matches(t, token)) {
+        return Ok(Viewer::Admin);
+    }
+
+    state
+        .tenants
+        .iter()
+        .find(|t| token_matches(&t.token, token))
+        .map(Viewer::Tenant)
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid API token"))
+}
+
+/// Check the bearer token for the external scheduler endpoints; requests on
+/// the Unix socket need none
+fn authorize(state: &AppState, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    if state.local {
+        return Ok(());
+    }
+    let Some(expected) = &state.api_token else {
+        return Err((StatusCode::FORBIDDEN, "External scheduler API disabled"));
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match provided {
+        Some(token) if token_matches(expected, token) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "Invalid or missing API token")),
+    }
+}
+
+/// POST /spawn - provision a runner for a job on behalf of an external scheduler
+#[utoipa::path(
+    post, path = "/spawn", tag = "scheduler", request_body = SpawnRequest,
+    security(("api_token" = [])),
+    responses(
+        (status = 201, body = SpawnResponse),
+        (status = 403, description = "Rejected by a runner-usage policy, or the API is disabled"),
+        (status = 409, description = "No capacity"),
+        (status = 429, description = "Spawn rate or fair share exceeded"),
+        (status = 503, description = "Standby controller, or the controller is not running"),
+    )
+)]
+async fn spawn(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SpawnRequest>,
+) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let (reply, rx) = oneshot::channel();
+    let request = ControlRequest::Spawn {
+        job: request.job,
+        labels: request.labels,
+        reply,
+    };
+    if state.control_tx.send(request).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(name)) => (StatusCode::CREATED, Json(SpawnResponse { name })).into_response(),
+        Ok(Err(e)) if e.is::<PolicyRejection>() => {
+            (StatusCode::FORBIDDEN, e.to_string()).into_response()
+        }
+        Ok(Err(e)) if e.is::<SpawnThrottled>() || e.is::<FairShareExceeded>() => {
+            (StatusCode::TOO_MANY_REQUESTS, e.to_string()).into_response()
+        }
+        Ok(Err(e)) if e.is::<NotLeader>() || e.is::<DispatchPaused>() => {
+            (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response()
+        }
+        Ok(Err(e)) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response(),
+    }
+}
+
+/// POST /release - tear down a runner provisioned via /spawn
+#[utoipa::path(
+    post, path = "/release", tag = "scheduler", request_body = ReleaseRequest,
+    security(("api_token" = [])),
+    responses(
+        (status = 204, description = "Released"),
+        (status = 401, description = "Invalid or missing API token"),
+        (status = 404, description = "No runner for the job"),
+    )
+)]
+async fn release(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ReleaseRequest>,
+) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let (reply, rx) = oneshot::channel();
+    let request = ControlRequest::Release {
+        job_id: request.job_id,
+        reply,
+    };
+    if state.control_tx.send(request).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(e)) if e.is::<UnknownJob>() => {
+            (StatusCode::NOT_FOUND, e.to_string()).into_response()
+        }
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response(),
+    }
+}
+
+/// DELETE /containers/{name} - tear a container down; pool slots are refilled
+#[utoipa::path(
+    delete, path = "/containers/{name}", tag = "containers", params(("name" = String, Path)),
+    security(("api_token" = [])),
+    responses(
+        (status = 204, description = "Torn down"),
+        (status = 400, description = "Invalid container name"),
+        (status = 404, description = "Container not found"),
+    )
+)]
+async fn remove_container(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    if !ContainerManager::is_runner_name(&name) {
+        return (StatusCode::BAD_REQUEST, "Invalid container name").into_response();
+    }
+    if !matches!(state.state_db.get_container(&name), Ok(Some(_))) {
+        return (StatusCode::NOT_FOUND, "Container not found").into_response();
+    }
+
+    let (reply, rx) = oneshot::channel();
+    if state
+        .control_tx
+        .send(ControlRequest::Remove { name, reply })
+        .await
+        .is_err()
+    {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response(),
+    }
+}
+
+/// POST /containers/{name}/quarantine - cut a suspicious container off and preserve it
+#[utoipa::path(
+    post, path = "/containers/{name}/quarantine", tag = "containers",
+    params(("name" = String, Path)),
+    request_body(content = Option<QuarantineRequest>), security(("api_token" = [])),
+    responses(
+        (status = 200, body = QuarantineResponse),
+        (status = 400, description = "Invalid container name"),
+    )
+)]
+async fn quarantine(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    request: Option<Json<QuarantineRequest>>,
+) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    if !ContainerManager::is_runner_name(&name) {
+        return (StatusCode::BAD_REQUEST, "Invalid container name").into_response();
+    }
+
+    let reason = request
+        .and_then(|Json(r)| r.reason)
+        .unwrap_or_else(|| "manual quarantine".to_string());
+
+    let (reply, rx) = oneshot::channel();
+    let request = ControlRequest::Quarantine {
+        name,
+        reason,
+        reply,
+    };
+    if state.control_tx.send(request).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(path)) => Json(QuarantineResponse {
+            path: path.display().to_string(),
+        })
+        .into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Controller not running").into_response(),
+    }
+}
+
+/// POST /notify/test - render and deliver a sample notification to every destination
+#[utoipa::path(
+    post, path = "/notify/test", tag = "admin", request_body = NotifyTestRequest,
+    security(("api_token" = [])),
+    responses(
+        (status = 204, description = "Delivered"),
+        (status = 400, description = "Unknown event kind"),
+        (status = 502, description = "A destination failed"),
+    )
+)]
+async fn notify_test(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<NotifyTestRequest>,
+) -> impl IntoResponse {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let Some(event) = NotificationEvent::sample(&request.event) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown event kind '{}'", request.event),
+        )
+            .into_response();
+    };
+
+    match state.notifier.test(event).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (StatusCode::BAD_GATEWAY, format!("{:#}", e)).into_response(),
+    }
+}
+
+/// OpenAPI description of the HTTP API, served at `/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        health, readyz, status, version, metrics, history, history_summary, history_usage,
+        events, audit,
+        queue, get_pool, put_pool, config_snapshot, container_detail, container_logs,
+        remove_container, spawn, release, quarantine, notify_test, reload, state_backup, wake,
+        pause, resume, webhook,
+    ),
+    modifiers(&ApiTokenAuth),
+)]
+struct ApiDoc;
+
+/// Declares the bearer token (`API_TOKEN` or a tenant token) the paths refer to
+struct ApiTokenAuth;
+
+impl Modify for ApiTokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// GET /openapi.json - OpenAPI document of this API
+async fn openapi() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI loads its assets from a CDN, so the binary stays small; offline
+/// clients can use `/openapi.json` directly
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>runner-controller API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
+
+/// GET /docs - Swagger UI for the OpenAPI document
+async fn swagger_ui() -> impl IntoResponse {
+    Html(SWAGGER_UI)
+}
+
+/// Serve the API on a TCP address and/or a Unix socket until shutdown
+pub async fn run_server(
+    addr: Option<SocketAddr>,
+    socket: Option<(PathBuf, u32)>,
+    state: AppState,
+    shutdown_rx: watch::Receiver<bool>,
+) {
+    let tcp = async {
+        if let Some(addr) = addr {
+            serve_tcp(addr, router(state.clone()), shutdown_rx.clone()).await;
+        }
+    };
+    let unix = async {
+        if let Some((path, mode)) = &socket {
+            let state = AppState {
+                local: true,
+                ..state.clone()
+            };
+            serve_unix(path, *mode, router(state), shutdown_rx.clone()).await;
+        }
+    };
+    tokio::join!(tcp, unix);
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/livez", get(health))
+        .route("/readyz", get(readyz))
+        .route("/status", get(status))
+        .route("/version", get(version))
+        .route("/metrics", get(metrics))
+        .route("/history", get(history))
+        .route("/history/summary", get(history_summary))
+        .route("/history/usage", get(history_usage))
+        .route("/events", get(events))
+        .route("/audit", get(audit))
+        .route("/queue", get(queue))
+        .route("/pool", get(get_pool).put(put_pool))
+        .route("/config/{hash}", get(config_snapshot))
+        .route("/containers/{name}", get(container_detail).delete(remove_container))
+        .route("/containers/{name}/logs", get(container_logs))
+        .route("/spawn", post(spawn))
+        .route("/release", post(release))
+        .route("/containers/{name}/quarantine", post(quarantine))
+        .route("/notify/test", post(notify_test))
+        .route("/admin/reload", post(reload))
+        .route("/state/backup", get(state_backup))
+        .route("/wake", post(wake))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/webhook", post(webhook))
+        .route("/openapi.json", get(openapi))
+        .route("/docs", get(swagger_ui))
+        .with_state(state)
+}
+
+async fn serve_tcp(addr: SocketAddr, app: Router, shutdown_rx: watch::Receiver<bool>) {
+    info!(addr = %addr, "Starting HTTP server");
+
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to bind HTTP server");
+            return;
+        }
+    };
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(stopped(shutdown_rx))
+        .await
+        .ok();
+}
+
+/// Serve on a Unix socket with file mode `mode`. The mode is applied right
+/// after binding; a parent directory only the intended users can enter closes
+/// that window.
+async fn serve_unix(
+    path: &std::path::Path,
+    mode: u32,
+    app: Router,
+    shutdown_rx: watch::Receiver<bool>,
+) {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    // A socket left behind by an earlier run would make binding fail
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        let _ = std::fs::remove_file(path);
+    }
+
+    let listener = match tokio::net::UnixListener::bind(path) {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!(path = %path.display(), error = %e, "Failed to bind HTTP socket");
+            return;
+        }
+    };
+    if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)) {
+        tracing::error!(path = %path.display(), error = %e, "Failed to set HTTP socket mode");
+        let _ = std::fs::remove_file(path);
+        return;
+    }
+    info!(
+        path = %path.display(),
+        mode = format!("{:o}", mode),
+        "Starting HTTP server on Unix socket"
+    );
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(stopped(shutdown_rx))
+        .await
+        .ok();
+    let _ = std::fs::remove_file(path);
+}
+
+/// Resolves once shutdown is requested
+async fn stopped(mut shutdown_rx: watch::Receiver<bool>) {
+    loop {
+        if shutdown_rx.changed().await.is_err() || *shutdown_rx.borrow() {
+            break;
+        }
+    }
+    info!("HTTP server shutting down");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use metrics_exporter_prometheus::PrometheusBuilder;
+
+    use crate::container::{FakeBackend, RunnerNaming};
+    use crate::github::FakeGitHub;
+
+    #[test]
+    fn test_verify_signature() {
+        // Example from GitHub's webhook documentation
+        let secret = "It's a Secret to Everybody";
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(verify_signature(secret, b"Hello, World!", signature));
+        assert!(!verify_signature(secret, b"Hello, World?", signature));
+        assert!(!verify_signature("wrong", b"Hello, World!", signature));
+        assert!(!verify_signature(secret, b"Hello, World!", "sha256=zz"));
+
+        let token = SecretString::from("api-token");
+        assert!(token_matches(&token, "api-token"));
+        assert!(!token_matches(&token, "api-tokem"));
+        assert!(!token_matches(&token, "api"));
+    }
+
+    /// API state of a test controller and what it was built from
+    struct App {
+        state: AppState,
+        config: Config,
+        github: Arc<FakeGitHub>,
+        backend: Arc<FakeBackend>,
+        control_rx: mpsc::Receiver<ControlRequest>,
+        dir: PathBuf,
+    }
+
+    /// Configuration from `vars` for `owner/repo`, with the state directory
+    /// and token files in `dir`; the API token is `api-token`
+    fn config(dir: &std::path::Path, vars: &[(&str, &str)]) -> Config {
+        let mut all: HashMap<&str, String> = HashMap::from([
+            ("GITHUB_REPO", "owner/repo".to_string()),
+            ("GITHUB_TOKEN_FILE", dir.join("token").display().to_string()),
+            ("API_TOKEN_FILE", dir.join("api-token").display().to_string()),
+            ("STATE_DIR", dir.display().to_string()),
+        ]);
+        all.extend(vars.iter().map(|(name, value)| (*name, value.to_string())));
+        Config::from_vars(|name| all.get(name).cloned()).unwrap()
+    }
+
+    /// API state for a controller configured from `vars`, with a fresh state
+    /// directory and a fake GitHub and container backend
+    fn app(test: &str, vars: &[(&str, &str)]) -> App {
+        let dir = std::env::temp_dir().join(format!("http-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("token"), "test-token").unwrap();
+        std::fs::write(dir.join("api-token"), "api-token").unwrap();
+
+        let config = config(&dir, vars);
+        let state_db = Arc::new(StateDb::open(&dir, config.state_durability).unwrap());
+        let github = Arc::new(FakeGitHub::new());
+        let backend = Arc::new(FakeBackend::new(RunnerNaming::default()));
+        let (control_tx, control_rx) = mpsc::channel(1);
+        let state = AppState::new(
+            &config,
+            state_db,
+            backend.clone(),
+            github.clone(),
+            Notifier::new(&config).unwrap(),
+            control_tx,
+            PrometheusBuilder::new().build_recorder().handle(),
+        );
+        App {
+            state,
+            config,
+            github,
+            backend,
+            control_rx,
+            dir,
+        }
+    }
+
+    async fn json(response: axum::response::Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_reload_swaps_config_and_github() {
+        use crate::github::WorkflowJob;
+        use crate::listener::{PoolController, RunExit};
+
+        let App {
+            state: old_state,
+            config: old_config,
+            github: old_github,
+            backend,
+            control_rx,
+            dir,
+        } = app("reload", &[("MAX_CONCURRENT", "1")]);
+        let old_state = AppState {
+            local: true,
+            ..old_state
+        };
+        let state_db = Arc::clone(&old_state.state_db);
+        let controller = |config: Config, github: Arc<FakeGitHub>, control_rx| {
+            let (_, shutdown_rx) = watch::channel(false);
+            let notifier = Notifier::new(&config).unwrap();
+            PoolController::new(
+                config,
+                github,
+                backend.clone(),
+                Arc::clone(&state_db),
+                shutdown_rx,
+                control_rx,
+                notifier,
+            )
+        };
+        let mut old_controller = controller(old_config, old_github.clone(), control_rx);
+
+        let new_github = Arc::new(FakeGitHub::new());
+        new_github.add_job(WorkflowJob {
+            id: 7,
+            status: "queued".to_string(),
+            labels: vec!["self-hosted".to_string(), "gpu".to_string()],
+            ..Default::default()
+        });
+        let new_config =
+            config(&dir, &[("MAX_CONCURRENT", "2"), ("RUNNER_LABELS", "self-hosted,gpu")]);
+        let new_hash = new_config.hash();
+
+        tokio_test::block_on(async {
+            // The running controller hands the reload to the binary, which
+            // answers once the new wiring is built
+            let serve = async {
+                let RunExit::Reload(reply) = old_controller.serve().await.unwrap() else {
+                    panic!("controller exited without a reload");
+                };
+                reply.send(Ok(new_hash.clone())).unwrap();
+            };
+            let (response, ()) =
+                tokio::join!(reload(State(old_state.clone()), HeaderMap::new()), serve);
+            let response = response.into_response();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(json(response).await["config_hash"], new_hash);
+
+            // What the binary builds on each reload: the API answers from the
+            // new configuration and GitHub client
+            let (control_tx, control_rx) = mpsc::channel(1);
+            let new_state = AppState {
+                local: true,
+                ..AppState::new(
+                    &new_config,
+                    Arc::clone(&state_db),
+                    backend.clone(),
+                    new_github.clone(),
+                    Notifier::new(&new_config).unwrap(),
+                    control_tx,
+                    old_state.metrics.clone(),
+                )
+            };
+            let mut new_controller = controller(new_config, new_github.clone(), control_rx);
+            let queued = |state: AppState| queue(State(state), HeaderMap::new());
+            assert_eq!(json(queued(old_state).await.into_response()).await, serde_json::json!([]));
+            let jobs = json(queued(new_state).await.into_response()).await;
+            assert_eq!(jobs[0]["job_id"], 7);
+            assert_eq!(jobs[0]["matches_labels"], true);
+
+            // And so does the controller: its larger pool registers through
+            // the new client
+            new_controller.run_once().await.unwrap();
+            assert_eq!(backend.containers(), ["r0", "r1"]);
+            assert_eq!(old_github.registration_tokens(), 1);
+            assert_eq!(new_github.registration_tokens(), 1);
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_container_detail_log_tail() {
+        use crate::state::ContainerState;
+
+        let App {
+            state,
+            backend,
+            dir,
+            ..
+        } = app("detail", &[]);
+        state.state_db.put_container("r0", &ContainerState::new(0)).unwrap();
+        backend.insert("r0");
+
+        let log_tail = |headers: HeaderMap| {
+            let state = state.clone();
+            async move {
+                let query = Query(DetailQuery { tail: 10 });
+                let response =
+                    container_detail(State(state), Path("r0".to_string()), query, headers)
+                        .await
+                        .into_response();
+                assert_eq!(response.status(), StatusCode::OK);
+                json(response).await["log_tail"].clone()
+            }
+        };
+        tokio_test::block_on(async {
+            // The detail is public, the journal isn't
+            assert_eq!(log_tail(HeaderMap::new()).await, serde_json::json!([]));
+            let log_tail = log_tail(bearer("api-token")).await;
+            assert_eq!(log_tail, serde_json::json!(["runner log of r0"]));
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_events_filtered_by_repo() {
+        let App { state, dir, .. } = app("events", &[]);
+        tokio_test::block_on(async {
+            let query = Query(EventsQuery {
+                event: None,
+                repo: Some("Org/A".to_string()),
+            });
+            let response = events(State(state.clone()), query, bearer("api-token"))
+                .await
+                .into_response();
+            assert_eq!(response.status(), StatusCode::OK);
+            let db = &state.state_db;
+            db.record_event("spawned", Some("r0"), Some("org/b"), None).unwrap();
+            db.record_event("spawned", Some("r1"), None, None).unwrap();
+            db.record_event("spawned", Some("r2"), Some("org/a"), None).unwrap();
+
+            let mut body = response.into_body().into_data_stream();
+            let frame = body.next().await.unwrap().unwrap();
+            let frame = String::from_utf8(frame.to_vec()).unwrap();
+            assert!(frame.contains("\"container\":\"r2\""), "{}", frame);
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pool_env_values_need_token() {
+        let App { state, dir, .. } = app("pool", &[("POOL_ENV", "REGISTRY_PASSWORD=hunter2")]);
+
+        let env = |headers: HeaderMap| {
+            let state = state.clone();
+            async move {
+                let response = get_pool(State(state), headers).await.into_response();
+                assert_eq!(response.status(), StatusCode::OK);
+                json(response).await["env"][0].clone()
+            }
+        };
+        tokio_test::block_on(async {
+            let anonymous = env(HeaderMap::new()).await;
+            assert_eq!(anonymous["name"], "REGISTRY_PASSWORD");
+            assert_eq!(anonymous["value"], "[redacted]");
+            assert_eq!(env(bearer("api-token")).await["value"], "hunter2");
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tenant_status_view() {
+        use crate::arch::Arch;
+        use crate::state::ContainerState;
+
+        let foreign = match Arch::native() {
+            Arch::X64 => "arm64",
+            Arch::Arm64 => "x64",
+        };
+        let templates = format!("{}=/etc/nixos/ci-container-{}.nix", foreign, foreign);
+        let App {
+            state,
+            github,
+            backend,
+            dir,
+            ..
+        } = app("tenant-status", &[("ARCH_TEMPLATES", &templates)]);
+        let mut ours = ContainerState::new(0);
+        ours.repository = Some("org/a".to_string());
+        state.state_db.put_container("r0", &ours).unwrap();
+        state.state_db.put_container("r1", &ContainerState::new(1)).unwrap();
+        for (kind, target) in [
+            (CleanupKind::Deregister, backend.runner_name("r0")),
+            (CleanupKind::Destroy, "r0".to_string()),
+            (CleanupKind::Destroy, "r1".to_string()),
+        ] {
+            let cleanup = PendingCleanup::new(kind, &target, "failed".into());
+            state.state_db.put_cleanup(&cleanup).unwrap();
+        }
+        github.set_repo_metadata(
+            "owner/repo",
+            RepoMetadata {
+                default_branch: "main".to_string(),
+                visibility: "private".to_string(),
+                private: true,
+                actions_enabled: Some(true),
+                fetched_at: unix_now(),
+            },
+        );
+        github.set_rate_budget(Some(BudgetStatus {
+            limit: 5000,
+            remaining: 4000,
+            reset_at: unix_now() + 600,
+            poll_stretch: 1.0,
+            spent: BTreeMap::from([("org/a".to_string(), 3), ("org/b".to_string(), 7)]),
+        }));
+        let state = AppState {
+            tenants: Arc::new(vec![Tenant {
+                name: "team-a".to_string(),
+                token: "tenant-token".into(),
+                repositories: vec!["org/a".to_string()],
+            }]),
+            ..state
+        };
+
+        let status = tokio_test::block_on(async {
+            let response = status(State(state), bearer("tenant-token")).await.into_response();
+            assert_eq!(response.status(), StatusCode::OK);
+            json(response).await
+        });
+        // Only cleanups of the tenant's container, nothing about the controller's repository
+        let targets: Vec<&str> = status["pending_cleanups"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["target"].as_str().unwrap())
+            .collect();
+        assert_eq!(targets.len(), 2);
+        assert!(!targets.contains(&"r1"));
+        assert!(status["repository"].is_null());
+        assert_eq!(status["hosts"], serde_json::json!([]));
+        assert_eq!(status["disk"], serde_json::json!([]));
+        // Other repositories' runners and API usage stay hidden
+        assert_eq!(status["architectures"][0]["running"], 1);
+        assert_eq!(status["github_rate_budget"]["spent"], serde_json::json!({ "org/a": 3 }));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_openapi_document() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(doc["paths"]["/containers/{name}"]["delete"].is_object());
+        assert_eq!(
+            doc["paths"]["/spawn"]["post"]["security"][0]["api_token"],
+            serde_json::json!([])
+        );
+        let schemas = &doc["components"]["schemas"];
+        assert!(schemas["PoolUpdate"]["properties"]["egress"].is_object());
+        assert!(schemas["ContainerDetail"]["properties"]["phase"].is_object());
+
+        // Every referenced schema is included
+        let text = doc.to_string();
+        for reference in text.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas[name].is_object(), "missing schema {}", name);
+        }
+    }
+
+    #[test]
+    fn test_history_summary() {
+        let entry = |conclusion: Option<&str>| HistoryEntry {
+            id: 0,
+            timestamp: 0,
+            event: "cleaned_up".to_string(),
+            container: Some("r0".to_string()),
+            repository: None,
+            detail: None,
+            config_hash: None,
+            conclusion: conclusion.map(str::to_string),
+            workflow: None,
+            labels: Vec::new(),
+            cpu_seconds: None,
+            wall_seconds: None,
+        };
+        assert_eq!(HistorySummary::from_entries(&[entry(None)]), HistorySummary::default());
+
+        let entries = [
+            entry(Some("success")),
+            entry(Some("success")),
+            entry(Some("failure")),
+            entry(Some("cancelled")),
+            entry(None),
+        ];
+        let summary = HistorySummary::from_entries(&entries);
+        assert_eq!(summary.jobs, 4);
+        assert_eq!(summary.conclusions["success"], 2);
+        assert_eq!(summary.success_rate, Some(0.5));
+        assert_eq!(summary.failure_rate, Some(0.25));
+    }
+
+    #[test]
+    fn test_usage_summary() {
+        let entry = |repo: &str, workflow: &str, cpu_seconds: f64| HistoryEntry {
+            id: 0,
+            timestamp: 0,
+            event: "cleaned_up".to_string(),
+            container: Some("r0".to_string()),
+            repository: Some(repo.to_string()),
+            detail: None,
+            config_hash: None,
+            conclusion: None,
+            workflow: Some(workflow.to_string()),
+            labels: Vec::new(),
+            cpu_seconds: Some(cpu_seconds),
+            wall_seconds: Some(600),
+        };
+        let entries = [
+            entry("owner/a", "CI", 1800.0),
+            entry("owner/a", "CI", 1800.0),
+            entry("owner/a", "Lint", 60.0),
+            entry("owner/b", "CI", 7200.0),
+        ];
+        let usage = UsageSummary::from_entries(&entries, Some(100.0));
+        assert_eq!(usage.repositories.len(), 2);
+        assert_eq!(usage.repositories[0].repository.as_deref(), Some("owner/b"));
+        assert_eq!(usage.repositories[0].energy_wh, Some(200.0));
+        let a = &usage.repositories[1];
+        assert_eq!((a.containers, a.cpu_seconds, a.wall_seconds), (3, 3660.0, 1800));
+        let workflows: Vec<_> = usage
+            .workflows
+            .iter()
+            .map(|t| (t.repository.as_deref().unwrap(), t.workflow.as_deref().unwrap()))
+            .collect();
+        assert_eq!(workflows, [("owner/b", "CI"), ("owner/a", "CI"), ("owner/a", "Lint")]);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-9", 100), Some((0, 9)));
+        assert_eq!(parse_range("bytes=90-", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=50-500", 100), Some((50, 99)));
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(parse_range("items=0-1", 100), None);
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+
+/// Tracks when a job was last seen, so the controller can drop into an idle
+/// mode after a quiet period: the warm pool shrinks to zero and polling slows
+/// down (or stops, with webhooks) until a job, webhook or `POST /wake` shows up.
+#[derive(Debug)]
+pub struct IdleTracker {
+    /// `None` disables idle mode
+    after: Option<Duration>,
+    last_activity: Instant,
+    idle: bool,
+}
+
+impl IdleTracker {
+    pub fn new(after: Option<Duration>) -> Self {
+        Self {
+            after,
+            last_activity: Instant::now(),
+            idle: false,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Record job activity. Returns whether this woke the controller up.
+    pub fn activity(&mut self) -> bool {
+        self.last_activity = Instant::now();
+        std::mem::replace(&mut self.idle, false)
+    }
+
+    /// Enter idle mode once the quiet period is over. Returns whether this
+    /// call did so.
+    pub fn check(&mut self) -> bool {
+        let Some(after) = self.after else {
+            return false;
+        };
+        if self.idle || self.last_activity.elapsed() < after {
+            return false;
+        }
+        self.idle = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_after_quiet_period() {
+        let mut tracker = IdleTracker::new(Some(Duration::from_secs(60)));
+        assert!(!tracker.check());
+
+        tracker.last_activity = Instant::now() - Duration::from_secs(61);
+        assert!(tracker.check());
+        assert!(tracker.is_idle());
+        // Reported once
+        assert!(!tracker.check());
+
+        assert!(tracker.activity());
+        assert!(!tracker.is_idle());
+        assert!(!tracker.activity());
+        assert!(!tracker.check());
+
+        let mut disabled = IdleTracker::new(None);
+        disabled.last_activity = Instant::now() - Duration::from_secs(3600);
+        assert!(!disabled.check());
+    }
+}
@@ -0,0 +1,373 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+
+/// Shared lease deciding which controller of an active/standby pair
+/// dispatches (`LEADER_LEASE_FILE`)
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaseConfig {
+    /// Lease file on storage both controllers mount
+    pub path: PathBuf,
+    /// A leader whose heartbeat stopped this long ago is replaced
+    pub ttl: Duration,
+}
+
+/// A spawn request sent to the standby controller
+#[derive(Debug, Clone, Error)]
+#[error("this controller is on standby; spawn requests go to the leader ({leader})")]
+pub struct NotLeader {
+    pub leader: String,
+}
+
+/// This controller's role as last decided
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct LeaderStatus {
+    /// Whether this controller holds the lease and dispatches jobs
+    pub leader: bool,
+    /// Instance holding the lease, if any
+    pub holder: Option<String>,
+}
+
+/// Contents of the lease file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LeaseRecord {
+    holder: String,
+    /// Bumped on every renewal; the standby only compares it with what it saw
+    /// before, so the hosts' clocks don't matter
+    heartbeat: u64,
+}
+
+/// Outcome of one renewal
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Renewal {
+    status: LeaderStatus,
+    /// Holder whose lease was taken over because its heartbeat stopped
+    took_over_from: Option<String>,
+}
+
+/// The lease as one controller sees it
+#[derive(Debug)]
+struct Lease {
+    path: PathBuf,
+    holder: String,
+    ttl: Duration,
+    /// Another holder's record and when this controller first saw it
+    observed: Option<(LeaseRecord, Instant)>,
+}
+
+impl Lease {
+    fn new(config: &LeaseConfig, holder: String) -> Self {
+        Self {
+            path: config.path.clone(),
+            holder,
+            ttl: config.ttl,
+            observed: None,
+        }
+    }
+
+    /// Whether this controller holds the lease given the `current` record:
+    /// it is free, already ours, or its holder's heartbeat has not moved for
+    /// the whole TTL
+    fn decide(&mut self, current: Option<&LeaseRecord>, now: Instant) -> Renewal {
+        let mut took_over_from = None;
+        if let Some(record) = current.filter(|r| r.holder != self.holder) {
+            let since = match &self.observed {
+                Some((seen, since)) if seen == record => *since,
+                _ => now,
+            };
+            if now.saturating_duration_since(since) < self.ttl {
+                self.observed = Some((record.clone(), since));
+                return Renewal {
+                    status: LeaderStatus {
+                        leader: false,
+                        holder: Some(record.holder.clone()),
+                    },
+                    took_over_from: None,
+                };
+            }
+            took_over_from = Some(record.holder.clone());
+        }
+        self.observed = None;
+        Renewal {
+            status: LeaderStatus {
+                leader: true,
+                holder: Some(self.holder.clone()),
+            },
+            took_over_from,
+        }
+    }
+
+    /// Read the lease under an exclusive lock and renew or take it if due
+    fn renew(&mut self, now: Instant) -> Result<Renewal> {
+        let mut file = lock(&self.path)?;
+        let current = read_record(&mut file)?;
+        let renewal = self.decide(current.as_ref(), now);
+        if renewal.status.leader {
+            let record = LeaseRecord {
+                holder: self.holder.clone(),
+                heartbeat: current.map_or(0, |r| r.heartbeat.wrapping_add(1)),
+            };
+            write_record(&mut file, Some(&record))?;
+        }
+        Ok(renewal)
+    }
+
+    /// Give the lease up so the standby takes over without waiting
+    fn release(&mut self) -> Result<()> {
+        let mut file = lock(&self.path)?;
+        if read_record(&mut file)?.is_some_and(|r| r.holder == self.holder) {
+            write_record(&mut file, None)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps renewing the lease in the background and publishes this
+/// controller's role. The task stops when this is dropped, e.g. after a
+/// reload built a new one.
+pub struct LeaderElection {
+    lease: Arc<Mutex<Lease>>,
+    status: watch::Receiver<LeaderStatus>,
+    task: JoinHandle<()>,
+}
+
+impl LeaderElection {
+    /// Decide the role once, then renew the lease every third of its TTL.
+    /// `holder` identifies this controller in the lease (the instance ID).
+    pub async fn start(config: &LeaseConfig, holder: String) -> Result<Self> {
+        let lease = Arc::new(Mutex::new(Lease::new(config, holder)));
+        let first = renew(&lease).await?;
+        if let Some(previous) = &first.took_over_from {
+            info!(previous = %previous, "Took over the leader lease");
+        }
+        report_role(&first.status);
+        let (tx, status) = watch::channel(first.status);
+        let task = tokio::spawn(renew_loop(Arc::clone(&lease), config.ttl, tx));
+        Ok(Self {
+            lease,
+            status,
+            task,
+        })
+    }
+
+    /// Follows this controller's role
+    pub fn subscribe(&self) -> watch::Receiver<LeaderStatus> {
+        self.status.clone()
+    }
+
+    /// Stop renewing and give the lease up, so the standby takes over at
+    /// once instead of after the TTL
+    pub async fn release(self) {
+        self.task.abort();
+        let lease = Arc::clone(&self.lease);
+        let released = tokio::task::spawn_blocking(move || lease.lock().unwrap().release()).await;
+        match released {
+            Ok(Ok(())) => info!("Released the leader lease"),
+            Ok(Err(e)) => warn!(error = %e, "Failed to release the leader lease"),
+            Err(e) => warn!(error = %e, "Failed to release the leader lease"),
+        }
+    }
+}
+
+impl Drop for LeaderElection {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Renew on a blocking thread: locks on shared storage can hang. A renewal
+/// still stuck from an earlier round fails instead of queueing behind it.
+async fn renew(lease: &Arc<Mutex<Lease>>) -> Result<Renewal> {
+    let lease = Arc::clone(lease);
+    tokio::task::spawn_blocking(move || {
+        let mut lease = lease
+            .try_lock()
+            .map_err(|_| anyhow::anyhow!("previous lease renewal still pending"))?;
+        lease.renew(Instant::now())
+    })
+    .await?
+}
+
+/// Renew every third of the TTL. A leader that can't renew stands by half a
+/// TTL after its last heartbeat: the standby waits a whole TTL after seeing
+/// the heartbeat stop, so the two never dispatch at once.
+async fn renew_loop(lease: Arc<Mutex<Lease>>, ttl: Duration, tx: watch::Sender<LeaderStatus>) {
+    let interval = ttl / 3;
+    let mut last_renewed = tokio::time::Instant::now();
+    loop {
+        let leading = tx.borrow().leader;
+        let step_down_at = last_renewed + ttl / 2;
+        let mut wake = tokio::time::Instant::now() + interval;
+        if leading {
+            wake = wake.min(step_down_at);
+        }
+        tokio::time::sleep_until(wake).await;
+
+        // Neither waiting nor a hanging renewal may keep a leader past its deadline
+        let started = tokio::time::Instant::now();
+        let budget = match leading {
+            true => interval.min(step_down_at.saturating_duration_since(started)),
+            false => interval,
+        };
+        let status = match tokio::time::timeout(budget, renew(&lease)).await {
+            Ok(Ok(renewal)) => {
+                if let Some(previous) = &renewal.took_over_from {
+                    warn!(previous = %previous, "Leader heartbeat stopped, taking over");
+                }
+                if renewal.status.leader {
+                    last_renewed = started;
+                }
+                renewal.status
+            }
+            failed => {
+                let error = match failed {
+                    Ok(Err(e)) => e.to_string(),
+                    _ => "timed out".to_string(),
+                };
+                let current = tx.borrow().clone();
+                if current.leader && tokio::time::Instant::now() >= step_down_at {
+                    warn!(error = %error, "Could not renew the leader lease, standing by");
+                    LeaderStatus {
+                        leader: false,
+                        holder: None,
+                    }
+                } else {
+                    debug!(error = %error, "Lease renewal failed");
+                    current
+                }
+            }
+        };
+        if *tx.borrow() != status {
+            report_role(&status);
+        }
+        metrics::gauge!("runner_controller_leader").set(if status.leader { 1.0 } else { 0.0 });
+        tx.send_replace(status);
+    }
+}
+
+fn report_role(status: &LeaderStatus) {
+    if status.leader {
+        info!("Leader: dispatching jobs");
+    } else {
+        info!(leader = ?status.holder, "Standing by");
+    }
+}
+
+/// Open the lease file and take an exclusive `flock` on it, released when
+/// the file is closed
+fn lock(path: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("open lease file {}", path.display()))?;
+    // SAFETY: flock on a descriptor we own
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("lock lease file {}", path.display()));
+    }
+    Ok(file)
+}
+
+fn read_record(file: &mut File) -> Result<Option<LeaseRecord>> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut contents)?;
+    if contents.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&contents).context("parse lease file")?))
+}
+
+/// Rewrite the file in place: a rename would leave the other controller
+/// locking the replaced file
+fn write_record(file: &mut File, record: Option<&LeaseRecord>) -> Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    if let Some(record) = record {
+        serde_json::to_writer(&mut *file, record)?;
+    }
+    file.sync_data()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_failover() {
+        let dir = std::env::temp_dir().join(format!("leader-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = LeaseConfig {
+            path: dir.join("lease"),
+            ttl: Duration::from_secs(30),
+        };
+        let mut a = Lease::new(&config, "a".to_string());
+        let mut b = Lease::new(&config, "b".to_string());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(a.renew(at(0)).unwrap().status.leader);
+        let standby = b.renew(at(1)).unwrap();
+        assert_eq!(standby.status.holder.as_deref(), Some("a"));
+        assert!(!standby.status.leader);
+
+        // A keeps renewing: B's wait restarts with every heartbeat
+        assert!(a.renew(at(20)).unwrap().status.leader);
+        assert!(!b.renew(at(40)).unwrap().status.leader);
+
+        // A's heartbeat stops: B takes over a TTL after it last saw it move
+        assert!(!b.renew(at(69)).unwrap().status.leader);
+        let takeover = b.renew(at(71)).unwrap();
+        assert!(takeover.status.leader);
+        assert_eq!(takeover.took_over_from.as_deref(), Some("a"));
+
+        // A comes back and stands by; B hands the lease back on release
+        assert!(!a.renew(at(72)).unwrap().status.leader);
+        b.release().unwrap();
+        let handover = a.renew(at(73)).unwrap();
+        assert!(handover.status.leader && handover.took_over_from.is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_leader_steps_down_before_takeover() {
+        let ttl = Duration::from_secs(30);
+        let config = LeaseConfig {
+            path: PathBuf::from("/nonexistent/lease"),
+            ttl,
+        };
+        let lease = Arc::new(Mutex::new(Lease::new(&config, "a".to_string())));
+        let (tx, mut rx) = watch::channel(LeaderStatus {
+            leader: true,
+            holder: Some("a".to_string()),
+        });
+
+        tokio_test::block_on(async {
+            tokio::time::pause();
+            let start = tokio::time::Instant::now();
+            // Every renewal fails: the lease file is unreachable
+            let task = tokio::spawn(renew_loop(lease, ttl, tx));
+            rx.wait_for(|s| !s.leader).await.unwrap();
+            let stepped_down = start.elapsed();
+            task.abort();
+            // Half a TTL after the last heartbeat (give or take a timer
+            // tick), while the standby waits a whole TTL before taking over
+            assert_eq!(stepped_down.as_secs(), ttl.as_secs() / 2, "{:?}", stepped_down);
+        });
+    }
+}
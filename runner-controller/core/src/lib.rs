@@ -0,0 +1,123 @@
+//! Core of the runner controller: keeps a warm pool of ephemeral GitHub
+//! Actions runners in NixOS or systemd-nspawn containers and provisions
+//! runners for single jobs on request.
+//!
+//! The `runner-controller` binary only loads the configuration and wires
+//! these pieces together; daemons embedding the controller do the same:
+//!
+//! - [`config::Config`] is read from the environment (see
+//!   `docs/runner-controller.md` for every variable)
+//! - [`github::GitHubClient`] talks to the GitHub REST API, with retries,
+//!   rate limit handling and a circuit breaker
+//! - [`container::ContainerManager`] creates, inspects and destroys runner
+//!   containers on the host; the controller uses it as a [`container::Backend`]
+//! - [`state::StateDb`] persists container state, history and the pool's
+//!   desired state
+//! - [`listener::PoolController`] is the scheduler keeping the pool and
+//!   external runners in shape, driven by the timers and container watcher
+//!   of a [`bus::Bus`]; [`listener::ControlRequest`]s steer it
+//! - [`http::run_server`] serves the HTTP API on top of these
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use runner_controller_core::config::Config;
+//! use runner_controller_core::container::{ContainerManager, RunnerNaming};
+//! use runner_controller_core::github::GitHubClient;
+//! use runner_controller_core::listener::PoolController;
+//! use runner_controller_core::notify::Notifier;
+//! use runner_controller_core::state::StateDb;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let config = Config::from_env()?;
+//! let github = Arc::new(GitHubClient::new(
+//!     config.github_repo.clone(),
+//!     config.github_token.clone(),
+//!     &config.network,
+//! )?);
+//! let state_db = Arc::new(StateDb::open(&config.state_dir, config.state_durability)?);
+//! let naming = RunnerNaming::new(&config.runner_name_template, "my-host")?;
+//! let containers = Arc::new(ContainerManager::new(
+//!     config.state_dir.clone(),
+//!     config.container_backend.clone(),
+//!     naming,
+//! ));
+//! let notifier = Notifier::new(&config)?;
+//!
+//! let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+//! let (_control_tx, control_rx) = tokio::sync::mpsc::channel(16);
+//! let mut controller = PoolController::new(
+//!     config, github, containers, state_db, shutdown_rx, control_rx, notifier,
+//! );
+//! controller.run().await?;
+//! controller.shutdown().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+/// Queue position comments and provisioning failure annotations on pull requests
+pub mod annotate;
+/// Routing of jobs to pools by CPU architecture
+pub mod arch;
+/// Job log upload to S3-compatible storage
+pub mod archive;
+/// Events driving the pool controller and the tasks producing them
+pub mod bus;
+/// Host capability detection (KVM, CPU features, GPUs) for runner labels
+pub mod capabilities;
+/// `--check-config` validation of the configuration against the host and GitHub
+pub mod check;
+/// Configuration from environment variables
+pub mod config;
+/// Startup audit of containers, state and GitHub runners against each other
+pub mod consistency;
+/// Runner containers on the host: creation, inspection, logs and cleanup
+pub mod container;
+/// Runner worker log parsing for job summaries
+pub mod diag;
+/// `runner-controller doctor` connectivity diagnostics
+pub mod doctor;
+/// Per-container nftables egress policies
+pub mod egress;
+/// Fair sharing of external runner slots between repositories
+pub mod fairness;
+/// GitHub REST API client and GitHub App authentication
+pub mod github;
+/// HTTP API: status, scheduler endpoints, metrics and OpenAPI document
+pub mod http;
+/// Operator commands run on container lifecycle events
+pub mod hooks;
+/// Idle mode: slower polling while nothing happens
+pub mod idle;
+/// Leader election between an active and a standby controller
+pub mod leader;
+/// The pool controller: the scheduler loop and everything it dispatches
+pub mod listener;
+/// Host load and disk usage limits
+pub mod load;
+/// Weekly windows in which no runners are spawned
+pub mod maintenance;
+/// Webhook and email notifications
+pub mod notify;
+/// Runner-usage policies deciding which jobs get runners
+pub mod policy;
+/// Warm pool pre-warming from learned demand and schedules
+pub mod prewarm;
+/// Gradual spawn ramp after startup
+pub mod ramp;
+/// Reloading rotated credentials from disk
+pub mod rotation;
+/// Runner version pinning and upgrade checks against GitHub's releases
+pub mod runner_version;
+/// Detection of jobs left queued while runners idle
+pub mod starvation;
+/// Redacted, zeroized secrets and GitHub credentials from a secrets store (Vault)
+pub mod secrets;
+/// Persistent controller state (redb)
+pub mod state;
+/// Job claims shared between controller replicas (in memory or Redis)
+pub mod store;
+/// Tenant-scoped API tokens
+pub mod tenant;
+/// Spawn rate limits per repository and overall
+pub mod throttle;
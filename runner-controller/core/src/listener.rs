@@ -0,0 +1,4384 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures_util::stream::{self, StreamExt};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{debug, info, warn};
+
+use crate::annotate::{
+    provision_failure_output, Annotation, FailureAnnotation, Posted, ProvisionFailures,
+    QueueAnnotator,
+};
+use crate::arch::{Arch, UnsupportedArch};
+use crate::archive::LogArchive;
+use crate::bus::{random_jitter, Bus, Event};
+use crate::config::Config;
+use crate::container::{
+    captured_logs_dir, check_secret_name, prune_captured_logs, runner_env_files, Backend,
+    BackendError, BindMount, ContainerManager, FileMode, RunnerNaming, SpawnOptions,
+    JOB_TOKEN_PATH, RUNNER_ALLOW_UPDATE_PATH,
+};
+use crate::consistency::{find_deviations, Deviation, DeviationKind, Observed, StartupAudit};
+use crate::diag::parse_worker_log;
+use crate::fairness::{FairShare, FairShareExceeded};
+use crate::github::{
+    BreakerState, CheckRunOutput, GitHubApi, GitHubError, RepoMetadata, Runner, WorkflowJob,
+};
+use crate::hooks::{HookEvent, Hooks};
+use crate::idle::IdleTracker;
+use crate::leader::{LeaderStatus, NotLeader};
+use crate::load::disk_usage;
+use crate::maintenance::{active_window, MaintenanceWindow};
+use crate::notify::{NotificationEvent, Notifier};
+use crate::policy::{JobContext, JobPolicy, Placement, PolicyRejection};
+use crate::prewarm::Prewarmer;
+use crate::throttle::{SpawnThrottle, SpawnThrottled};
+use crate::ramp::SpawnRamp;
+use crate::secrets::SecretString;
+use crate::runner_version::{VersionChecker, VersionStatus};
+use crate::starvation::{labels_match, labels_match_any, StarvationTracker};
+use crate::store::{Claim, LocalStore, StateStore};
+use crate::state::{
+    unix_now, AuditRecord, CleanupKind, ContainerState, LogQuery, PendingCleanup, PoolSnapshot,
+    PauseState, PoolSource, PoolSpec, PoolUpdate, SpawnTimings, StateDb, StateError, UnmatchedJob,
+    DEFAULT_POOL,
+};
+
+/// Requests from the external scheduler API, handled by the pool controller loop
+pub enum ControlRequest {
+    /// Provision a runner for a specific job; replies with the container name
+    Spawn {
+        job: JobContext,
+        labels: Vec<String>,
+        reply: oneshot::Sender<Result<String>>,
+    },
+    /// Tear down the runner previously provisioned for a job
+    Release {
+        job_id: u64,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    /// Tear down a container; a pool slot is refilled on the next poll
+    Remove {
+        name: String,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    /// Isolate a suspicious container, preserving it for investigation;
+    /// replies with where its root filesystem was moved
+    Quarantine {
+        name: String,
+        reason: String,
+        reply: oneshot::Sender<Result<PathBuf>>,
+    },
+    /// Change the warm pool's desired state; replies with the stored spec
+    /// and converges the pool right away
+    SetPool {
+        update: PoolUpdate,
+        reply: oneshot::Sender<Result<PoolSpec>>,
+    },
+    /// Rebuild the controller from freshly loaded configuration; replies with
+    /// the new configuration hash once the replacement is ready
+    Reload {
+        reply: oneshot::Sender<Result<String>>,
+    },
+    /// Leave idle mode and poll right away (webhook or `POST /wake`)
+    Wake { reason: String },
+    /// Stop spawning runners until resumed; runners already up stay and take
+    /// jobs, but aren't replaced. Replies with the pause in effect.
+    Pause {
+        reason: Option<String>,
+        reply: oneshot::Sender<Result<PauseState>>,
+    },
+    /// Spawn runners again after a pause and refill the pool right away
+    Resume {
+        reply: oneshot::Sender<Result<()>>,
+    },
+}
+
+/// Why the poll loop stopped
+pub enum RunExit {
+    Shutdown,
+    /// A reload was requested; the caller builds the replacement controller
+    Reload(oneshot::Sender<Result<String>>),
+}
+
+pub struct PoolController {
+    config: Config,
+    github: Arc<dyn GitHubApi>,
+    containers: Arc<dyn Backend>,
+    state_db: Arc<StateDb>,
+    shutdown_rx: watch::Receiver<bool>,
+    control_rx: mpsc::Receiver<ControlRequest>,
+    notifier: Notifier,
+    hooks: Hooks,
+    policy: JobPolicy,
+    last_reap: Option<Instant>,
+    last_reconcile: Option<Instant>,
+    /// Whether poll intervals are stretched to save rate limit budget
+    poll_stretched: bool,
+    archive: Option<LogArchive>,
+    last_archive_prune: Option<Instant>,
+    last_log_prune: Option<Instant>,
+    last_compaction: Option<Instant>,
+    /// Running disk garbage collection, if any
+    gc_task: Option<JoinHandle<Result<()>>>,
+    last_gc: Option<Instant>,
+    auth_alerted: bool,
+    /// Non-ephemeral runners already alerted on
+    non_ephemeral: HashSet<u64>,
+    starvation: StarvationTracker,
+    /// Queued jobs whose labels no runner offers
+    unmatched: StarvationTracker,
+    runner_version: VersionChecker,
+    annotator: QueueAnnotator,
+    provision_failures: ProvisionFailures,
+    idle: IdleTracker,
+    prewarm: Prewarmer,
+    /// Pool size wanted ahead of expected demand
+    prewarm_target: usize,
+    ramp: SpawnRamp,
+    throttle: SpawnThrottle,
+    fair_share: FairShare,
+    /// GitHub was unavailable on the previous poll
+    github_down: bool,
+    /// Role from leader election; `None` without a lease
+    leader: Option<watch::Receiver<LeaderStatus>>,
+    /// Role as of the last check
+    role: Option<LeaderStatus>,
+    /// Job claims shared with other replicas
+    store: Arc<dyn StateStore>,
+    /// Dispatch paused by an operator
+    paused: Option<PauseState>,
+    /// Maintenance window in effect as of the last check
+    maintenance: Option<MaintenanceWindow>,
+    /// Spawn requests waiting out their claim jitter
+    claim_delays: JoinSet<SpawnRequest>,
+}
+
+/// A spawn request from the external scheduler API
+type SpawnRequest = (JobContext, Vec<String>, oneshot::Sender<Result<String>>);
+
+/// Consecutive GitHub auth failures before an operator is notified
+const AUTH_FAILURE_ALERT_THRESHOLD: u32 = 3;
+
+/// Installation tokens live for an hour; re-mint well before that for idle pool containers
+const JOB_TOKEN_REFRESH_SECS: u64 = 45 * 60;
+
+/// Free space in the state database below which compaction isn't worth it
+const COMPACT_MIN_FREE_BYTES: u64 = 1024 * 1024;
+
+/// A job nobody asked a runner for in this long is no longer retried
+const PROVISION_RETRY_WINDOW: Duration = Duration::from_secs(3600);
+
+/// A job claim outlives the job timeout by this much, covering the spawn
+const CLAIM_TTL_MARGIN: Duration = Duration::from_secs(600);
+
+/// With remote hosts each watcher check is an ssh round trip, so the watcher
+/// looks this many times less often
+const REMOTE_WATCH_FACTOR: u32 = 6;
+
+impl PoolController {
+    pub fn new(
+        config: Config,
+        github: Arc<dyn GitHubApi>,
+        containers: Arc<dyn Backend>,
+        state_db: Arc<StateDb>,
+        shutdown_rx: watch::Receiver<bool>,
+        control_rx: mpsc::Receiver<ControlRequest>,
+        notifier: Notifier,
+    ) -> Self {
+        let paused = state_db.get_pause().unwrap_or_else(|e| {
+            warn!(error = %e, "Failed to read the pause state, dispatching");
+            None
+        });
+        metrics::gauge!("runner_controller_paused").set(if paused.is_some() { 1.0 } else { 0.0 });
+        Self {
+            paused,
+            maintenance: None,
+            hooks: Hooks::new(config.hooks.clone()),
+            policy: JobPolicy::new(&config),
+            starvation: StarvationTracker::new(config.starvation_cycles),
+            unmatched: StarvationTracker::new(config.unmatched_queue_cycles),
+            runner_version: VersionChecker::new(
+                config.runner_version.as_deref().and_then(|v| v.parse().ok()),
+                config.runner_version_check_interval,
+            ),
+            annotator: QueueAnnotator::new(config.queue_annotations),
+            provision_failures: ProvisionFailures::new(
+                config.provision_failure_annotation,
+                config.provision_failure_attempts,
+            ),
+            idle: IdleTracker::new(config.idle_after),
+            prewarm: Prewarmer::new(
+                config.prewarm_max,
+                config.prewarm_lead,
+                config.prewarm_lookback,
+                config.prewarm_schedule.clone(),
+            ),
+            prewarm_target: 0,
+            ramp: SpawnRamp::new(config.spawn_ramp_interval),
+            throttle: SpawnThrottle::new(
+                config.max_jobs_per_hour,
+                config.max_jobs_per_hour_per_repo,
+            ),
+            archive: config.archive.clone().and_then(|archive| {
+                LogArchive::new(archive)
+                    .inspect_err(|e| warn!(error = %e, "Log archiving disabled"))
+                    .ok()
+            }),
+            fair_share: FairShare::new(
+                config.fair_share,
+                &config.repo_weights,
+                config.fair_share_window,
+            ),
+            config,
+            github,
+            containers,
+            state_db,
+            shutdown_rx,
+            control_rx,
+            notifier,
+            last_reap: None,
+            last_reconcile: None,
+            poll_stretched: false,
+            last_archive_prune: None,
+            last_log_prune: None,
+            last_compaction: None,
+            gc_task: None,
+            last_gc: None,
+            auth_alerted: false,
+            non_ephemeral: HashSet::new(),
+            github_down: false,
+            leader: None,
+            role: None,
+            store: Arc::new(LocalStore::new("local")),
+            claim_delays: JoinSet::new(),
+        }
+    }
+
+    /// Claim jobs in `store`, shared with the other replicas, before
+    /// spawning runners for them
+    pub fn with_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Dispatch only while holding the lease of `leader`; on standby the pool
+    /// drains and spawn requests are refused
+    pub fn with_leader(mut self, leader: watch::Receiver<LeaderStatus>) -> Self {
+        self.role = Some(leader.borrow().clone());
+        self.leader = Some(leader);
+        self
+    }
+
+    /// Pick up role changes from leader election
+    fn update_role(&mut self) {
+        let Some(leader) = &self.leader else {
+            return;
+        };
+        let status = leader.borrow().clone();
+        let previous = self.role.replace(status.clone());
+        let Some(previous) = previous.filter(|p| p.leader != status.leader) else {
+            return;
+        };
+        if status.leader {
+            info!("Took over as leader, filling the pool");
+            if let Some(holder) = previous.holder {
+                self.notifier
+                    .notify(NotificationEvent::LeaderTakeover { previous: holder });
+            }
+        } else {
+            warn!(leader = ?status.holder, "Lost the leader lease, draining the pool");
+        }
+    }
+
+    /// Lease holder while this controller stands by
+    fn standby_for(&self) -> Option<String> {
+        let role = self.role.as_ref().filter(|r| !r.leader)?;
+        Some(role.holder.clone().unwrap_or_else(|| "unknown".to_string()))
+    }
+
+    /// Compare containers, state and GitHub, report every deviation and, in
+    /// `fix` mode, repair what can be repaired
+    pub async fn startup_audit(&self) -> Result<()> {
+        let mode = self.config.startup_audit;
+        if mode == StartupAudit::Off {
+            return Ok(());
+        }
+        info!(mode = ?mode, "Running startup consistency audit");
+
+        let containers: HashSet<String> = self
+            .containers
+            .list()
+            .await?
+            .into_iter()
+            .chain(self.containers.list_external().await?)
+            .collect();
+        let state: HashSet<String> = self
+            .state_db
+            .list_containers()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let runners = self.github.list_runners().await?;
+        let in_progress_jobs = self.github.list_in_progress_jobs().await?;
+
+        let deviations = find_deviations(&Observed {
+            containers: &containers,
+            state: &state,
+            runners: &runners,
+            in_progress_jobs: &in_progress_jobs,
+            naming: self.containers.naming(),
+        });
+
+        let mut fixed = 0;
+        let mut cleaned = HashSet::new();
+        for deviation in &deviations {
+            let repaired = if mode == StartupAudit::Fix {
+                self.fix_deviation(deviation, &runners, &mut cleaned).await
+            } else {
+                false
+            };
+            if repaired {
+                fixed += 1;
+            }
+
+            warn!(
+                kind = deviation.kind.as_str(),
+                subject = %deviation.subject,
+                detail = %deviation.detail,
+                fixed = repaired,
+                "Consistency deviation"
+            );
+            let detail = format!(
+                "{}: {}{}",
+                deviation.kind.as_str(),
+                deviation.detail,
+                if repaired { " (fixed)" } else { "" }
+            );
+            self.record_event("inconsistency", &deviation.subject, Some(detail));
+        }
+
+        metrics::gauge!("runner_controller_startup_deviations").set(deviations.len() as f64);
+        let summary = format!("{} deviations, {} fixed", deviations.len(), fixed);
+        info!(deviations = deviations.len(), fixed, "Startup consistency audit complete");
+        if let Err(e) = self
+            .state_db
+            .record_event("consistency_audit", None, None, Some(summary))
+        {
+            debug!(error = %e, "Failed to record history event");
+        }
+
+        Ok(())
+    }
+
+    /// Repair a single deviation; returns whether it was fixed
+    async fn fix_deviation(
+        &self,
+        deviation: &Deviation,
+        runners: &[Runner],
+        cleaned: &mut HashSet<String>,
+    ) -> bool {
+        let name = deviation.subject.as_str();
+        let busy = |runner_name: &str| runners.iter().any(|r| r.name == runner_name && r.busy);
+
+        let result = match deviation.kind {
+            DeviationKind::ContainerWithoutState | DeviationKind::ContainerWithoutRunner => {
+                if cleaned.contains(name) {
+                    return true;
+                }
+                // Never interrupt a job in progress
+                if busy(&self.containers.runner_name(name)) {
+                    return false;
+                }
+                cleaned.insert(name.to_string());
+                self.cleanup_container_full(name).await
+            }
+            DeviationKind::StateWithoutContainer => {
+                self.state_db.remove_container(name).map_err(Into::into)
+            }
+            DeviationKind::RunnerWithoutContainer => match deviation.runner_id {
+                Some(id) if !busy(name) => self.github.delete_runner(id).await.map_err(Into::into),
+                _ => return false,
+            },
+            // GitHub fails the job once the runner stops heartbeating
+            DeviationKind::OrphanedJob => return false,
+        };
+
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(subject = %name, error = %e, "Failed to fix consistency deviation");
+                false
+            }
+        }
+    }
+
+    /// Reconcile state on startup - clean up old containers and stale state
+    pub async fn reconcile_on_startup(&self) -> Result<()> {
+        info!("Reconciling pool on startup");
+
+        // Get all containers (both old j* and new r* style)
+        let all_containers = self.containers.list_all().await?;
+
+        // Clean up any old-style j* containers (migration from job-based to pool-based)
+        for name in all_containers.iter().filter(|n| n.starts_with('j')) {
+            info!(name = %name, "Cleaning up old-style job container");
+            self.cleanup_container_full(name).await?;
+        }
+
+        // Check pool (r*) and external (x*) containers
+        let mut pool_containers = self.containers.list().await?;
+        pool_containers.extend(self.containers.list_external().await?);
+        for name in &pool_containers {
+            let result = match self.containers.is_runner_completed(name).await {
+                Ok(true) => {
+                    info!(name = %name, "Cleaning up completed container from previous run");
+                    self.cleanup_container_full(name).await
+                }
+                Ok(false) => {
+                    info!(name = %name, "Container still has active runner");
+                    Ok(())
+                }
+                Err(e) => {
+                    warn!(name = %name, error = %e, "Failed to check container, cleaning up");
+                    self.cleanup_container_full(name).await
+                }
+            };
+            // A busy runner is left to finish its job and checked again by the poll loop
+            match result {
+                Err(e) if !e.is::<RunnerBusy>() => return Err(e),
+                _ => {}
+            }
+        }
+
+        // Clean up stale state entries (containers in DB but not in nixos-container list)
+        let db_containers = self.state_db.list_containers()?;
+        let active_set: HashSet<&str> = pool_containers.iter().map(|s| s.as_str()).collect();
+
+        for (name, _) in db_containers {
+            if !active_set.contains(name.as_str()) {
+                info!(name = %name, "Removing stale state entry");
+                self.state_db.remove_container(&name)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Full cleanup, unless GitHub reports the container's runner busy and
+    /// the job timeout hasn't passed: then completion detection misfired and
+    /// the cleanup fails with `RunnerBusy`
+    async fn cleanup_container_full(&self, name: &str) -> Result<()> {
+        if let Some(runner) = self.busy_runner(name).await {
+            info!(name = %name, runner = %runner, "Runner is busy, not cleaning up its container");
+            metrics::counter!("runner_controller_busy_cleanup_skips_total").increment(1);
+            return Err(RunnerBusy { runner }.into());
+        }
+        self.force_cleanup_container_full(name).await
+    }
+
+    /// The container's runner name if GitHub reports it busy and the container
+    /// is within the job timeout. Lookup failures don't block the cleanup.
+    async fn busy_runner(&self, name: &str) -> Option<String> {
+        let timed_out = matches!(
+            self.state_db.get_container(name),
+            Ok(Some(state)) if state.running_seconds() > self.config.job_timeout.as_secs()
+        );
+        if timed_out {
+            return None;
+        }
+
+        let runner_name = self.containers.runner_name(name);
+        match self.github.list_runners().await {
+            Ok(runners) => runners
+                .iter()
+                .any(|r| r.name == runner_name && r.busy)
+                .then_some(runner_name),
+            Err(e) => {
+                debug!(name = %name, error = %e, "Could not check whether the runner is busy");
+                None
+            }
+        }
+    }
+
+    /// Full cleanup: deregister from GitHub, destroy container, remove state
+    async fn force_cleanup_container_full(&self, name: &str) -> Result<()> {
+        // Deregister from GitHub
+        let runner_name = self.containers.runner_name(name);
+        if let Err(e) = self.github.delete_runner_by_name(&runner_name).await {
+            warn!(name = %name, error = %e, "Failed to deregister runner from GitHub, will retry");
+            self.queue_cleanup(CleanupKind::Deregister, &runner_name, &e);
+        }
+
+        // Revoke the job-scoped token before the container (and its copy) disappears
+        if let Some(token) = self.containers.read_container_file(name, JOB_TOKEN_PATH) {
+            if let Err(e) = self.github.revoke_job_token(token.trim()).await {
+                warn!(name = %name, error = %e, "Failed to revoke job token");
+            }
+        }
+
+        self.record_job_summary(name);
+        let worker_log = match &self.archive {
+            Some(_) => self.containers.read_worker_log(name),
+            None => None,
+        };
+        // The cgroup's accounting goes away with the container
+        let cpu_seconds = match self.containers.resource_usage(name).await {
+            Ok(usage) => usage.cpu_seconds,
+            Err(e) => {
+                debug!(name = %name, error = %e, "Could not read container resource usage");
+                None
+            }
+        };
+
+        // Destroy container
+        if let Err(e) = self.containers.cleanup_container(name).await {
+            self.record_event("cleanup_failed", name, Some(e.to_string()));
+            self.queue_cleanup(CleanupKind::Destroy, name, &e);
+            return Err(e.into());
+        }
+        self.state_db.remove_cleanup(CleanupKind::Destroy, name)?;
+
+        // The runner journal is captured while the container is destroyed
+        self.archive_logs(name, worker_log).await;
+
+        // Record before removing the state, which holds the container's job
+        // and repository
+        let state = self.state_db.get_container(name).ok().flatten();
+        let conclusion = match state.as_ref().and_then(|s| s.job_id) {
+            Some(job_id) => self.job_conclusion(job_id).await,
+            None => None,
+        };
+        if let Err(e) = self
+            .state_db
+            .record_cleanup(name, state.as_ref(), conclusion, cpu_seconds)
+        {
+            debug!(name = %name, error = %e, "Failed to record history event");
+        }
+        self.state_db.remove_container(name)?;
+        // Only external runners claim their job up front
+        if let Some(job_id) = state.filter(|s| s.slot.is_none()).and_then(|s| s.job_id) {
+            self.release_shared(job_id).await;
+        }
+
+        Ok(())
+    }
+
+    /// Final conclusion of a job, counted by outcome. `None` while the job is
+    /// still running or if it can't be looked up.
+    async fn job_conclusion(&self, job_id: u64) -> Option<String> {
+        let conclusion = match self.github.get_job(job_id).await {
+            Ok(job) => job.conclusion?,
+            Err(e) => {
+                debug!(job_id, error = %e, "Could not look up job conclusion");
+                return None;
+            }
+        };
+        let label = conclusion.clone();
+        metrics::counter!("runner_controller_job_conclusions_total", "conclusion" => label)
+            .increment(1);
+        Some(conclusion)
+    }
+
+    /// Persist a failed cleanup step so later polls retry it with backoff
+    fn queue_cleanup(&self, kind: CleanupKind, target: &str, error: &impl std::fmt::Display) {
+        let cleanup = match self.state_db.get_cleanup(kind, target) {
+            Ok(Some(mut cleanup)) => {
+                cleanup.failed(error.to_string());
+                cleanup
+            }
+            _ => PendingCleanup::new(kind, target, error.to_string()),
+        };
+        if let Err(e) = self.state_db.put_cleanup(&cleanup) {
+            warn!(target = %target, error = %e, "Failed to queue cleanup retry");
+        }
+    }
+
+    /// Whether the container's destruction failed and awaits a retry; such
+    /// containers are left alone until the retry queue has dealt with them
+    fn destroy_pending(&self, name: &str) -> bool {
+        matches!(self.state_db.get_cleanup(CleanupKind::Destroy, name), Ok(Some(_)))
+    }
+
+    /// Retry failed deregistrations and container destructions that are due
+    async fn retry_cleanups(&self) -> Result<()> {
+        let cleanups = self.state_db.list_cleanups()?;
+        metrics::gauge!("runner_controller_pending_cleanups").set(cleanups.len() as f64);
+
+        for mut cleanup in cleanups.into_iter().filter(|c| c.is_due()) {
+            let target = cleanup.target.clone();
+
+            // Runner names are reused by respawned containers: never delete
+            // the registration of a container that exists again
+            if cleanup.kind == CleanupKind::Deregister {
+                let respawned = self
+                    .containers
+                    .naming()
+                    .container_name(&target)
+                    .is_some_and(|c| matches!(self.state_db.get_container(c), Ok(Some(_))));
+                if respawned {
+                    debug!(target = %target, "Runner name in use again, dropping deregistration retry");
+                    self.state_db.remove_cleanup(cleanup.kind, &target)?;
+                    continue;
+                }
+            }
+
+            let result: Result<()> = match cleanup.kind {
+                CleanupKind::Deregister => {
+                    self.github.delete_runner_by_name(&target).await.map_err(Into::into)
+                }
+                CleanupKind::Destroy => {
+                    self.containers.cleanup_container(&target).await.map_err(Into::into)
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    info!(
+                        kind = cleanup.kind.as_str(),
+                        target = %target,
+                        attempts = cleanup.attempts,
+                        "Cleanup retry succeeded"
+                    );
+                    if cleanup.kind == CleanupKind::Destroy {
+                        self.record_event("cleaned_up", &target, Some("after retry".to_string()));
+                        self.state_db.remove_container(&target)?;
+                    }
+                    self.state_db.remove_cleanup(cleanup.kind, &target)?;
+                }
+                // The runner or container is gone, nothing left to clean up
+                Err(e) if failure_action(&e) == FailureAction::Abort => {
+                    info!(target = %target, error = %e, "Cleanup target gone, dropping retry");
+                    self.state_db.remove_cleanup(cleanup.kind, &target)?;
+                }
+                Err(e) => {
+                    cleanup.failed(e.to_string());
+                    warn!(
+                        kind = cleanup.kind.as_str(),
+                        target = %target,
+                        attempts = cleanup.attempts,
+                        error = %e,
+                        "Cleanup retry failed"
+                    );
+                    self.state_db.put_cleanup(&cleanup)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Binary cache settings and keys for a new container. Untrusted (fork)
+    /// jobs get none: they could poison the cache or steal the signing key.
+    fn cache_push_files(&self, isolated: bool) -> Vec<(&'static str, String, FileMode)> {
+        match &self.config.cache_push {
+            Some(push) if !isolated => push.files(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Marker letting a new container's runner update itself, while
+    /// `RUNNER_AUTO_UPGRADE` is on and a newer runner release is out
+    fn upgrade_files(&self) -> Vec<(&'static str, String, FileMode)> {
+        let upgrade = self.config.runner_auto_upgrade
+            && self
+                .state_db
+                .get_runner_version()
+                .ok()
+                .flatten()
+                .is_some_and(|status| status.upgrade_available());
+        if upgrade {
+            vec![(RUNNER_ALLOW_UPDATE_PATH, String::new(), FileMode::Public)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// GPU for a new runner with `labels`: `None` when it doesn't ask for one
+    /// or the host has none, an error when every GPU is taken
+    fn allocate_gpu(&self, labels: &[String]) -> Result<Option<usize>> {
+        let Some(gpus) = self.config.gpus.as_ref().filter(|g| g.requested(labels)) else {
+            return Ok(None);
+        };
+        let in_use = self.gpus_in_use()?;
+        match (0..gpus.count()).find(|index| !in_use.contains(index)) {
+            Some(index) => Ok(Some(index)),
+            None => anyhow::bail!("All {} GPUs are in use", gpus.count()),
+        }
+    }
+
+    fn gpus_in_use(&self) -> Result<HashSet<usize>> {
+        Ok(self
+            .state_db
+            .list_containers()?
+            .into_iter()
+            .filter_map(|(_, state)| state.gpu)
+            .collect())
+    }
+
+    /// Device nodes and driver paths to pass into a container holding `gpu`
+    fn gpu_binds(&self, gpu: Option<usize>) -> (Vec<String>, Vec<BindMount>) {
+        match (&self.config.gpus, gpu) {
+            (Some(gpus), Some(index)) => (gpus.device_nodes(index), gpus.driver_binds()),
+            _ => (Vec::new(), Vec::new()),
+        }
+    }
+
+    /// Upload a finished job's runner journal and worker diagnostic log to the archive
+    async fn archive_logs(&self, name: &str, worker_log: Option<String>) {
+        let Some(archive) = &self.archive else {
+            return;
+        };
+        let Some(state) = self.state_db.get_container(name).ok().flatten() else {
+            return;
+        };
+        // Containers that never ran a job have nothing worth keeping
+        let Some(job_id) = state.job_id else {
+            return;
+        };
+        let repository = state
+            .repository
+            .unwrap_or_else(|| self.config.github_repo.clone());
+        let run_id = state.run_id.unwrap_or(0);
+
+        let mut files = Vec::new();
+        if let Some(Ok(journal)) = self.containers.captured_logs_path(name).map(std::fs::read) {
+            files.push(("runner.log.zst", journal));
+        }
+        if let Some(log) = worker_log {
+            match zstd::encode_all(log.as_bytes(), 0) {
+                Ok(compressed) => files.push(("worker.log.zst", compressed)),
+                Err(e) => debug!(name = %name, error = %e, "Failed to compress worker log"),
+            }
+        }
+
+        for (file, body) in files {
+            let key = archive.key(&repository, run_id, job_id, file);
+            match archive.put(&key, body, "application/zstd").await {
+                Ok(()) => {
+                    debug!(name = %name, key = %key, "Archived job log");
+                    metrics::counter!("runner_controller_log_uploads_total", "outcome" => "ok")
+                        .increment(1);
+                    if archive.retention().is_some() {
+                        if let Err(e) = self.state_db.put_archived_log(&key, unix_now()) {
+                            warn!(key = %key, error = %e, "Failed to record archived log");
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!(name = %name, key = %key, error = %e, "Failed to archive job log");
+                    metrics::counter!("runner_controller_log_uploads_total", "outcome" => "failed")
+                        .increment(1);
+                }
+            }
+        }
+    }
+
+    /// Delete archived logs past their retention period, at most hourly
+    async fn prune_archive(&mut self) -> Result<()> {
+        let Some(retention) = self.archive.as_ref().and_then(|a| a.retention()) else {
+            return Ok(());
+        };
+        if self
+            .last_archive_prune
+            .is_some_and(|t| t.elapsed() < Duration::from_secs(3600))
+        {
+            return Ok(());
+        }
+        self.last_archive_prune = Some(Instant::now());
+
+        let Some(archive) = &self.archive else {
+            return Ok(());
+        };
+        let before = unix_now().saturating_sub(retention.as_secs());
+        for key in self.state_db.expired_archived_logs(before)? {
+            match archive.delete(&key).await {
+                Ok(()) => {
+                    debug!(key = %key, "Deleted expired archived log");
+                    self.state_db.remove_archived_log(&key)?;
+                }
+                // Retried on the next prune
+                Err(e) => warn!(key = %key, error = %e, "Failed to delete expired archived log"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete runner logs captured at cleanup past their retention, at most hourly
+    fn prune_captured_logs(&mut self) {
+        let Some(retention) = self.config.captured_log_retention else {
+            return;
+        };
+        if self
+            .last_log_prune
+            .is_some_and(|t| t.elapsed() < Duration::from_secs(3600))
+        {
+            return;
+        }
+        self.last_log_prune = Some(Instant::now());
+
+        match prune_captured_logs(&captured_logs_dir(&self.config.state_dir), retention) {
+            Ok(0) => {}
+            Ok(pruned) => info!(pruned, "Deleted expired captured runner logs"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!(error = %e, "Failed to prune captured runner logs"),
+        }
+    }
+
+    /// Store step timings and the failure category of the container's last job
+    fn record_job_summary(&self, name: &str) {
+        let Some(summary) = self
+            .containers
+            .read_worker_log(name)
+            .and_then(|log| parse_worker_log(&log))
+        else {
+            return;
+        };
+
+        debug!(name = %name, result = ?summary.result, steps = summary.steps.len(), "Harvested job summary");
+        if let Some(category) = summary.failure_category {
+            metrics::counter!("runner_controller_job_failures_total", "category" => category)
+                .increment(1);
+        }
+        match serde_json::to_string(&summary) {
+            Ok(detail) => self.record_event("job_summary", name, Some(detail)),
+            Err(e) => debug!(name = %name, error = %e, "Failed to serialize job summary"),
+        }
+    }
+
+    /// Quarantine a container and forget it, freeing its slot (see `ContainerManager::quarantine`)
+    async fn quarantine_container_full(&self, name: &str, reason: &str) -> Result<PathBuf> {
+        if !self.containers.list_all().await?.iter().any(|c| c == name) {
+            anyhow::bail!("Container {} does not exist", name);
+        }
+
+        // The runner must not pick up further jobs
+        let runner_name = self.containers.runner_name(name);
+        if let Err(e) = self.github.delete_runner_by_name(&runner_name).await {
+            warn!(name = %name, error = %e, "Failed to deregister runner from GitHub");
+        }
+
+        // The token may have leaked; revoke it before preserving the filesystem
+        if let Some(token) = self.containers.read_container_file(name, JOB_TOKEN_PATH) {
+            if let Err(e) = self.github.revoke_job_token(token.trim()).await {
+                warn!(name = %name, error = %e, "Failed to revoke job token");
+            }
+        }
+
+        let path = self.containers.quarantine(name).await?;
+
+        self.record_event(
+            "quarantined",
+            name,
+            Some(format!("{} (preserved at {})", reason, path.display())),
+        );
+        self.state_db.remove_container(name)?;
+        self.notifier.notify(NotificationEvent::Quarantined {
+            name: name.to_string(),
+            reason: reason.to_string(),
+        });
+        metrics::counter!("runner_controller_quarantined_total").increment(1);
+
+        Ok(path)
+    }
+
+    /// Append a lifecycle event to the history (failures are only logged) and
+    /// run the operator's hook for it
+    fn record_event(&self, event: &str, name: &str, detail: Option<String>) {
+        let state = self.state_db.get_container(name).ok().flatten();
+        if let Some(hook) = HookEvent::for_history_event(event) {
+            self.hooks.run(hook, name, state.as_ref(), detail.as_deref());
+        }
+        let repository = state.and_then(|s| s.repository);
+        if let Err(e) = self
+            .state_db
+            .record_event(event, Some(name), repository.as_deref(), detail)
+        {
+            debug!(event, name = %name, error = %e, "Failed to record history event");
+        }
+    }
+
+    /// Desired state of the warm pool, falling back to the configuration
+    fn pool_spec(&self) -> PoolSpec {
+        let configured = || PoolSpec::from_config(&self.config);
+        match self.state_db.get_pool_spec(DEFAULT_POOL) {
+            Ok(Some(spec)) => spec,
+            Ok(None) => configured(),
+            Err(e) => {
+                warn!(error = %e, "Failed to read pool spec, using configuration");
+                configured()
+            }
+        }
+    }
+
+    /// Persist a new desired state for the warm pool
+    fn update_pool_spec(&self, update: PoolUpdate) -> Result<PoolSpec> {
+        let current = self.pool_spec();
+        let labels = update.labels.unwrap_or(current.labels);
+        if labels.is_empty() {
+            anyhow::bail!("pool labels must not be empty");
+        }
+        // An empty template reverts to LABEL_TEMPLATES and the backend default
+        let template = match update.template {
+            Some(template) => Some(template).filter(|t| !t.is_empty()),
+            None => current.template,
+        };
+        let binds = update.binds.unwrap_or(current.binds);
+        for bind in &binds {
+            bind.check_allowed(&self.config.bind_allowlist)?;
+        }
+        let env = update.env.unwrap_or(current.env);
+        for var in &env {
+            var.validate()?;
+        }
+        let secrets = update.secrets.unwrap_or(current.secrets);
+        for name in &secrets {
+            check_secret_name(name)?;
+        }
+        // Fail now rather than on every spawn if a secret can't be read
+        runner_env_files(&env, &secrets, self.config.secrets_dir.as_deref())?;
+
+        let spec = PoolSpec {
+            template,
+            binds,
+            egress: update.egress.unwrap_or(current.egress),
+            env,
+            secrets,
+            ..PoolSpec::new(update.size.unwrap_or(current.size), labels, PoolSource::Api)
+        };
+        self.state_db.put_pool_spec(DEFAULT_POOL, &spec)?;
+
+        let mut detail = format!("size {} -> {}, labels {}", current.size, spec.size, spec.labels.join(","));
+        if let Some(template) = &spec.template {
+            detail.push_str(&format!(", template {}", template));
+        }
+        if !spec.binds.is_empty() {
+            let binds: Vec<&str> = spec.binds.iter().map(|b| b.source.as_str()).collect();
+            detail.push_str(&format!(", binds {}", binds.join(",")));
+        }
+        // Names only: values may be credentials
+        if !spec.env.is_empty() {
+            let names: Vec<&str> = spec.env.iter().map(|v| v.name.as_str()).collect();
+            detail.push_str(&format!(", env {}", names.join(",")));
+        }
+        if !spec.secrets.is_empty() {
+            detail.push_str(&format!(", secrets {}", spec.secrets.join(",")));
+        }
+        if spec.egress != current.egress {
+            detail.push_str(&format!(", egress {:?} -> {:?}", current.egress, spec.egress));
+        }
+        if let Err(e) = self
+            .state_db
+            .record_event("pool_changed", None, None, Some(detail))
+        {
+            debug!(error = %e, "Failed to record history event");
+        }
+        info!(size = spec.size, labels = ?spec.labels, "Pool spec updated");
+
+        Ok(spec)
+    }
+
+    /// Whether a pool slot may be filled now: the host isn't saturated, a GPU
+    /// is free if one is wanted, and the ramp and hourly budget have room (a
+    /// spawn is taken from both)
+    fn pool_spawn_allowed(&self, slot: usize, labels: &[String]) -> bool {
+        if let Some(reason) = self.config.load_limits.saturation() {
+            info!(slot, reason = %reason, "Host saturated, deferring spawn");
+            return false;
+        }
+        if let Err(e) = self.allocate_gpu(labels) {
+            debug!(slot, error = %e, "No GPU for the pool runner, deferring spawn");
+            return false;
+        }
+        if !self.ramp.try_acquire() {
+            debug!(slot, limit = ?self.ramp.limit(), "Spawn ramp limit reached, deferring spawn");
+            return false;
+        }
+        if !self.acquire_spawn(None) {
+            debug!(slot, "Hourly spawn budget used up, deferring spawn");
+            return false;
+        }
+        true
+    }
+
+    /// Spawn containers for empty pool slots, `SPAWN_CONCURRENCY` at a time,
+    /// so a burst of demand doesn't wait for one container boot after another.
+    /// The gates are checked right before each spawn, so the ones already
+    /// under way count; once one closes, the remaining slots wait.
+    async fn spawn_pool_slots(&self, slots: &[usize], labels: &[String]) {
+        // Concurrent spawns could pick the same free GPU
+        let concurrency = if self.config.gpus.as_ref().is_some_and(|g| g.requested(labels)) {
+            1
+        } else {
+            self.config.spawn_concurrency
+        };
+        // Set when a gate closed or a spawn failed in a way the remaining
+        // slots would hit too
+        let blocked = &AtomicBool::new(false);
+
+        stream::iter(slots.iter().copied())
+            .map(|slot| async move {
+                if blocked.load(Ordering::Relaxed) {
+                    return;
+                }
+                if !self.pool_spawn_allowed(slot, labels) {
+                    blocked.store(true, Ordering::Relaxed);
+                    return;
+                }
+                info!(slot, "Spawning container for empty pool slot");
+                match self.spawn_pool_container(slot).await {
+                    Ok(name) => {
+                        info!(slot, name = %name, "Pool container spawned successfully");
+                    }
+                    Err(e) => {
+                        let name = ContainerManager::slot_to_container_name(slot);
+                        warn!(slot, error = %e, "Failed to spawn pool container");
+                        self.record_event("spawn_failed", &name, Some(e.to_string()));
+                        if failure_action(&e) == FailureAction::Alert {
+                            self.notifier.notify(NotificationEvent::SpawnFailed {
+                                name,
+                                error: e.to_string(),
+                            });
+                            blocked.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect::<()>()
+            .await;
+    }
+
+    /// Spawn a container for a pool slot with the pool's current labels
+    async fn spawn_pool_container(&self, slot: usize) -> Result<String> {
+        let result = self.try_spawn_pool_container(slot).await;
+        record_spawn_outcome("pool", result.is_ok());
+        result
+    }
+
+    async fn try_spawn_pool_container(&self, slot: usize) -> Result<String> {
+        let spec = self.pool_spec();
+        let (labels, template) = match self.config.arch_routes.pool_of_slot(slot) {
+            Some(pool) => (pool.labels(&spec.labels), Some(pool.template.clone())),
+            None => {
+                let template = spec.template.clone().or_else(|| {
+                    self.config.template_for(&spec.labels).map(str::to_string)
+                });
+                (spec.labels.clone(), template)
+            }
+        };
+        // Read before any tokens are minted, so an unreadable secret wastes none
+        let env_files =
+            runner_env_files(&spec.env, &spec.secrets, self.config.secrets_dir.as_deref())?;
+
+        // Get registration token
+        let token_started = Instant::now();
+        let token = self.github.get_registration_token().await?;
+        let job_token = self.mint_job_token().await;
+        let token_elapsed = token_started.elapsed();
+
+        // Spawn container
+        let mut extra_files = self.cache_push_files(false);
+        extra_files.extend(self.upgrade_files());
+        let files: Vec<(&str, &str, FileMode)> = job_token
+            .as_ref()
+            .map(|t| (JOB_TOKEN_PATH, t.expose(), FileMode::Runner))
+            .into_iter()
+            .chain(extra_files.iter().map(|(path, body, mode)| (*path, body.as_str(), *mode)))
+            .chain(env_files.iter().map(|(path, body, mode)| (path.as_str(), body.as_str(), *mode)))
+            .collect();
+        let gpu = self.allocate_gpu(&labels)?;
+        let (devices, mut binds) = self.gpu_binds(gpu);
+        binds.extend(spec.binds);
+        let create_started = Instant::now();
+        let options = SpawnOptions {
+            labels: &labels,
+            files: &files,
+            template: template.as_deref(),
+            devices: &devices,
+            binds: &binds,
+            egress: spec.egress,
+            ..Default::default()
+        };
+        let name = self
+            .containers
+            .spawn_pool_container(slot, &token, &options)
+            .await?;
+
+        // Record in state DB
+        let mut state = ContainerState::new(slot);
+        state.labels = labels;
+        state.template = template;
+        state.gpu = gpu;
+        state.timings = spawn_timings(token_elapsed, create_started.elapsed());
+        state.repository = Some(self.config.github_repo.clone());
+        if job_token.is_some() {
+            state.mark_job_token_minted();
+        }
+        self.state_db.put_container(&name, &state)?;
+        self.record_event("spawned", &name, None);
+
+        Ok(name)
+    }
+
+    /// Mint a job-scoped GitHub token if a GitHub App is configured.
+    /// Failures are logged; the runner is still spawned without a token.
+    async fn mint_job_token(&self) -> Option<SecretString> {
+        match self
+            .github
+            .mint_job_token(&self.config.job_token_permissions)
+            .await
+        {
+            Ok(token) => token,
+            Err(e) => {
+                warn!(error = %e, "Failed to mint job token");
+                None
+            }
+        }
+    }
+
+    /// Restart the clock of containers whose start time the wall clock was
+    /// stepped under, before timeouts are enforced: a step can make them look
+    /// days old, and a wrongly killed job costs more than a late timeout
+    fn guard_clock_skew(&self) -> Result<()> {
+        for (name, mut state) in self.state_db.list_containers()? {
+            if state.start_time_plausible() {
+                continue;
+            }
+            warn!(
+                name = %name,
+                started_at = state.started_at,
+                "Implausible container start time, restarting its timeout clock"
+            );
+            metrics::counter!("runner_controller_clock_skew_resets_total").increment(1);
+            state.restart_clock();
+            self.state_db.put_container(&name, &state)?;
+        }
+        Ok(())
+    }
+
+    /// Re-mint job tokens for containers whose token is close to expiring
+    async fn refresh_job_tokens(&self) -> Result<()> {
+        for (name, mut state) in self.state_db.list_containers()? {
+            if state
+                .job_token_age_seconds()
+                .is_none_or(|age| age <= JOB_TOKEN_REFRESH_SECS)
+            {
+                continue;
+            }
+
+            let Some(token) = self.mint_job_token().await else {
+                continue;
+            };
+
+            debug!(name = %name, "Refreshing job token");
+            let old = self.containers.read_container_file(&name, JOB_TOKEN_PATH);
+            let token = token.expose();
+            self.containers.write_container_file(&name, JOB_TOKEN_PATH, token, FileMode::Runner)?;
+            state.mark_job_token_minted();
+            self.state_db.put_container(&name, &state)?;
+
+            // Revoked only once replaced, so the container is never left without one
+            if let Some(old) = old {
+                if let Err(e) = self.github.revoke_job_token(old.trim()).await {
+                    warn!(name = %name, error = %e, "Failed to revoke job token");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Let a finished pool container's runner register for another job instead
+    /// of respawning the container, if container reuse allows it. Returns
+    /// whether the container was reused.
+    async fn reuse_pool_container(&self, name: &str) -> Result<bool> {
+        // A paused controller registers no new runners
+        if self.config.reuse_jobs <= 1 || self.paused.is_some() {
+            return Ok(false);
+        }
+        let Some(state) = self.state_db.get_container(name)? else {
+            return Ok(false);
+        };
+        let served = state.jobs_served + 1;
+        if served >= self.config.reuse_jobs {
+            debug!(name = %name, served, "Container served its jobs, recycling");
+            return Ok(false);
+        }
+        if state.container_age_seconds() >= self.config.reuse_max_age.as_secs() {
+            debug!(name = %name, "Container reached its maximum age, recycling");
+            return Ok(false);
+        }
+        // Pool changes (labels, template, binds) apply to fresh containers only
+        let created = state.container_started_at.unwrap_or(state.started_at);
+        if self.pool_spec().updated_at > created {
+            debug!(name = %name, "Pool spec changed since the container started, recycling");
+            return Ok(false);
+        }
+        // A public repository's next job could come from anyone's fork and
+        // find what the previous one left behind
+        let repo = &self.config.github_repo;
+        let private = self
+            .repo_metadata(repo)
+            .await
+            .is_some_and(|m| m.visibility != "public");
+        if !private {
+            debug!(repo = %repo, "Repository not known to be private, not reusing containers");
+            return Ok(false);
+        }
+        // A new registration passes the same gates as a fresh spawn. No
+        // labels: the container keeps the GPU it holds.
+        if self.pool_resting() || !self.pool_repo_allowed().await {
+            return Ok(false);
+        }
+        let slot = state.slot.unwrap_or_default();
+        if !self.pool_spawn_allowed(slot, &[]) {
+            return Ok(false);
+        }
+
+        let token = self.github.get_registration_token().await?;
+
+        // Wrap up the finished job as a teardown would
+        if let Err(e) = self.containers.capture_logs(name).await {
+            warn!(name = %name, error = %e, "Failed to capture runner logs");
+        }
+        let worker_log = match &self.archive {
+            Some(_) => self.containers.read_worker_log(name),
+            None => None,
+        };
+        self.archive_logs(name, worker_log).await;
+        self.record_job_summary(name);
+        if let Some(old) = self.containers.read_container_file(name, JOB_TOKEN_PATH) {
+            if let Err(e) = self.github.revoke_job_token(old.trim()).await {
+                warn!(name = %name, error = %e, "Failed to revoke job token");
+            }
+        }
+
+        let mut next = state.next_job();
+        if let Some(job_token) = self.mint_job_token().await {
+            let token = job_token.expose();
+            self.containers.write_container_file(name, JOB_TOKEN_PATH, token, FileMode::Runner)?;
+            next.mark_job_token_minted();
+        }
+        self.containers.reregister(name, &token).await?;
+        self.state_db.put_container(name, &next)?;
+
+        info!(name = %name, jobs_served = served, "Reusing container for another job");
+        self.record_event("reused", name, Some(format!("{} jobs served", served)));
+        metrics::counter!("runner_controller_container_reuses_total").increment(1);
+        Ok(true)
+    }
+
+    /// Whether the pool keeps no warm runners: while idle, on standby or
+    /// during a maintenance window. A paused pool keeps them, but fills no
+    /// empty slots.
+    fn pool_resting(&self) -> bool {
+        self.idle.is_idle() || self.standby_for().is_some() || self.maintenance.is_some()
+    }
+
+    /// Respawn a container in a pool slot (cleanup old, spawn new)
+    async fn respawn_pool_container(&self, name: &str, slot: usize) -> Result<()> {
+        self.cleanup_container_full(name).await?;
+        if self.paused.is_some() || self.pool_resting() {
+            info!(slot, "Dispatch paused or pool resting, leaving the slot empty");
+            return Ok(());
+        }
+        // Before the gates, so a refused respawn takes no ramp slot or budget
+        if !self.pool_repo_allowed().await {
+            return Ok(());
+        }
+        if !self.pool_spawn_allowed(slot, &self.pool_spec().labels) {
+            return Ok(());
+        }
+        self.spawn_pool_container(slot).await?;
+        Ok(())
+    }
+
+    /// Take a spawn from the hourly budgets, counting refusals
+    fn acquire_spawn(&self, repository: Option<&str>) -> bool {
+        match self.throttle.try_acquire(repository) {
+            Ok(()) => true,
+            Err(throttled) => {
+                record_throttled(&throttled);
+                false
+            }
+        }
+    }
+
+    /// Audit and count a policy rejection, returning it as the spawn error
+    fn reject_job(&self, job: &JobContext, rejection: PolicyRejection) -> anyhow::Error {
+        let job_id = job.job_id;
+        warn!(job_id, rule = rejection.rule, reason = %rejection.reason, "Job rejected by policy");
+        let record = AuditRecord::new(
+            job_id,
+            rejection.rule,
+            &rejection.reason,
+            job.repository.clone(),
+            job.actor.clone(),
+        );
+        if let Err(e) = self.state_db.record_rejection(&record) {
+            warn!(job_id, error = %e, "Failed to persist policy rejection");
+        }
+        metrics::counter!("runner_controller_job_rejections_total", "rule" => rejection.rule)
+            .increment(1);
+        rejection.into()
+    }
+
+    /// Fill in the workflow, ref and actor the scheduler didn't pass from the
+    /// job's workflow run, if policies need them. Lookup failures leave them
+    /// unknown, which allowlists refuse.
+    async fn complete_job_context(&self, mut job: JobContext) -> JobContext {
+        let complete = job.workflow.is_some() && job.head_ref.is_some() && job.actor.is_some();
+        if !self.policy.needs_run_metadata() || complete {
+            return job;
+        }
+        let run = match self.github.get_job(job.job_id).await {
+            Ok(gh_job) => self.github.get_run(gh_job.run_id).await,
+            Err(e) => Err(e),
+        };
+        match run {
+            Ok(run) => {
+                job.workflow = job.workflow.or(run.path);
+                job.head_ref = job.head_ref.or(run.head_branch);
+                job.actor = job.actor.or(run.triggering_actor.map(|a| a.login));
+            }
+            Err(e) => {
+                warn!(job_id = job.job_id, error = %e, "Failed to look up the job's workflow run");
+            }
+        }
+        job
+    }
+
+    /// Cached repository metadata; `None` if it could not be fetched
+    async fn repo_metadata(&self, repo: &str) -> Option<RepoMetadata> {
+        match self.github.repo_metadata(repo).await {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                warn!(repo = %repo, error = %e, "Failed to fetch repository metadata");
+                None
+            }
+        }
+    }
+
+    /// Whether policy allows pool runners for the configured repository.
+    /// Uses cached metadata, so this costs no API call on most polls.
+    async fn pool_repo_allowed(&self) -> bool {
+        if !self.policy.needs_repo_metadata() {
+            return true;
+        }
+
+        let repo = &self.config.github_repo;
+        let metadata = self.repo_metadata(repo).await;
+        match self.policy.check_visibility(0, repo, metadata.as_ref()) {
+            Ok(()) => true,
+            Err(rejection) => {
+                warn!(reason = %rejection.reason, "Pool spawns refused by policy");
+                false
+            }
+        }
+    }
+
+    /// External container provisioned for `job_id`, if any
+    fn external_container(&self, job_id: u64) -> Result<Option<String>> {
+        Ok(self
+            .state_db
+            .list_containers()?
+            .into_iter()
+            .find(|(name, state)| name.starts_with('x') && state.job_id == Some(job_id))
+            .map(|(name, _)| name))
+    }
+
+    /// Name for a new external container: the job ID's last seven digits,
+    /// or the next free name if another job's container has those. Names are
+    /// looked up in the state, never derived from a job ID again.
+    fn external_container_name(&self, job_id: u64) -> Result<String> {
+        let taken: HashSet<String> = self
+            .state_db
+            .list_containers()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        (0..10_000_000u64)
+            .map(|offset| ContainerManager::job_to_container_name(job_id.wrapping_add(offset)))
+            .find(|name| !taken.contains(name))
+            .ok_or_else(|| anyhow::anyhow!("No free external container name"))
+    }
+
+    /// Spawn a runner for an external scheduler request
+    async fn spawn_external_container(&self, job: JobContext, labels: Vec<String>) -> Result<String> {
+        let job_id = job.job_id;
+        if let Some(name) = self.external_container(job_id)? {
+            anyhow::bail!("Runner for job {} already exists: {}", job_id, name);
+        }
+        let name = self.external_container_name(job_id)?;
+        let job = self.complete_job_context(job).await;
+
+        let placement = match self.policy.evaluate(&job) {
+            Ok(placement) => placement,
+            Err(rejection) => return Err(self.reject_job(&job, rejection)),
+        };
+        if self.policy.needs_repo_metadata() {
+            let repo = job.repository.as_deref().unwrap_or(&self.config.github_repo);
+            let metadata = self.repo_metadata(repo).await;
+            if let Err(rejection) = self.policy.check_visibility(job_id, repo, metadata.as_ref()) {
+                return Err(self.reject_job(&job, rejection));
+            }
+        }
+        let isolated = placement == Placement::Isolated;
+        let repository = job.repository.clone();
+
+        let gh_job = self.claim_check(job_id, &name).await?;
+
+        // Register with exactly the labels the job asked for, so GitHub routes
+        // it back to this runner; fall back to the configured set
+        let job_labels = gh_job.as_ref().map(|j| j.labels.clone()).filter(|l| !l.is_empty());
+        let labels = match (labels.is_empty(), job_labels) {
+            (false, _) => labels,
+            (true, Some(job_labels)) => {
+                debug!(job_id, labels = ?job_labels, "Using the job's requested labels");
+                job_labels
+            }
+            (true, None) => self.config.runner_labels.clone(),
+        };
+
+        // Jobs for a foreign architecture run in its pool, within its limit
+        let arch_pool = self.config.arch_routes.route(&labels)?;
+        if let Some((arch, max)) = arch_pool.and_then(|p| Some((p.arch, p.max_runners?))) {
+            let running = self
+                .state_db
+                .list_containers()?
+                .iter()
+                .filter(|(_, state)| Arch::of(&state.labels) == Some(arch))
+                .count();
+            if running >= max {
+                anyhow::bail!("{} runner limit reached ({}/{})", arch, running, max);
+            }
+        }
+
+        let external = self.containers.list_external().await?;
+        if let Some(repo) = repository.as_deref() {
+            let mut running: HashMap<String, usize> = HashMap::new();
+            for name in &external {
+                if let Some(repo) = self.state_db.get_container(name)?.and_then(|s| s.repository) {
+                    *running.entry(repo).or_default() += 1;
+                }
+            }
+            let capacity = self.config.max_external_runners;
+            if let Err(exceeded) = self.fair_share.check(repo, &running, capacity) {
+                info!(
+                    job_id,
+                    repo,
+                    running = exceeded.running,
+                    share = exceeded.share,
+                    "Fair share exceeded"
+                );
+                metrics::counter!("runner_controller_fair_share_deferrals_total").increment(1);
+                return Err(exceeded.into());
+            }
+        }
+
+        let active = external.len();
+        if active >= self.config.max_external_runners {
+            anyhow::bail!(
+                "External runner limit reached ({}/{})",
+                active,
+                self.config.max_external_runners
+            );
+        }
+
+        if let Some(reason) = self.config.load_limits.saturation() {
+            anyhow::bail!("Host saturated: {}", reason);
+        }
+
+        let gpu = self.allocate_gpu(&labels)?;
+
+        let token_started = Instant::now();
+        let token = self.github.get_registration_token().await?;
+        // Untrusted (fork) jobs never get a repository token
+        let job_token = if isolated {
+            info!(job_id, head = ?job.head_repository, "Fork job, provisioning isolated runner");
+            None
+        } else {
+            self.mint_job_token().await
+        };
+        let token_elapsed = token_started.elapsed();
+        let mut extra_files = self.cache_push_files(isolated);
+        extra_files.extend(self.upgrade_files());
+        let files: Vec<(&str, &str, FileMode)> = job_token
+            .as_ref()
+            .map(|t| (JOB_TOKEN_PATH, t.expose(), FileMode::Runner))
+            .into_iter()
+            .chain(extra_files.iter().map(|(path, body, mode)| (*path, body.as_str(), *mode)))
+            .collect();
+        // Last look before the runner registers: tokens took a while and the
+        // job may have been picked up by another runner meanwhile
+        let claimed = match self.claim_check(job_id, &name).await {
+            Ok(_) => self.claim_shared(job_id).await,
+            Err(e) => Err(e),
+        };
+        // The hourly budget is taken last, so a job skipped on the way doesn't
+        // use it up
+        let claimed = match claimed.map(|()| self.throttle.try_acquire(repository.as_deref())) {
+            Ok(Err(throttled)) => {
+                warn!(job_id, scope = %throttled.scope, "Spawn throttled");
+                record_throttled(&throttled);
+                self.release_shared(job_id).await;
+                Err(throttled.into())
+            }
+            Ok(Ok(())) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = claimed {
+            if let Some(token) = &job_token {
+                if let Err(e) = self.github.revoke_job_token(token.expose()).await {
+                    warn!(job_id, error = %e, "Failed to revoke unused job token");
+                }
+            }
+            return Err(e);
+        }
+
+        let template = match arch_pool {
+            Some(pool) => Some(pool.template.clone()),
+            None => self.config.template_for(&labels).map(str::to_string),
+        };
+        let (devices, binds) = self.gpu_binds(gpu);
+        let create_started = Instant::now();
+        let options = SpawnOptions {
+            labels: &labels,
+            files: &files,
+            isolated,
+            template: template.as_deref(),
+            devices: &devices,
+            binds: &binds,
+            egress: self.config.egress_mode,
+        };
+        if let Err(e) = self.containers.spawn_container(&name, &token, &options).await {
+            self.release_shared(job_id).await;
+            return Err(e.into());
+        }
+
+        let mut state = ContainerState::new_external(job_id, labels);
+        if let Some(gh_job) = &gh_job {
+            state.set_job(gh_job);
+        }
+        state.template = template;
+        state.gpu = gpu;
+        state.repository = repository;
+        state.timings = spawn_timings(token_elapsed, create_started.elapsed());
+        if job_token.is_some() {
+            state.mark_job_token_minted();
+        }
+        self.state_db.put_container(&name, &state)?;
+        let detail = if isolated {
+            format!("job {} (isolated)", job_id)
+        } else {
+            format!("job {}", job_id)
+        };
+        self.record_event("spawned", &name, Some(detail));
+
+        Ok(name)
+    }
+
+    /// Re-fetch a job and fail with `JobUnavailable` if it no longer waits for
+    /// a runner of ours or doesn't exist. Other lookup errors don't block the
+    /// spawn; the job is returned when it could be fetched.
+    async fn claim_check(&self, job_id: u64, name: &str) -> Result<Option<WorkflowJob>> {
+        let gh_job = match self.github.get_job(job_id).await {
+            Ok(job) => job,
+            Err(GitHubError::NotFound(_)) => {
+                let reason = "does not exist".to_string();
+                return Err(JobUnavailable { job_id, reason }.into());
+            }
+            Err(e) => {
+                debug!(job_id, error = %e, "Could not look up job before claiming");
+                return Ok(None);
+            }
+        };
+
+        let own_runner = self.containers.runner_name(name);
+        if let Some(reason) = unavailable_reason(&gh_job, &own_runner) {
+            info!(job_id, reason = %reason, "Job no longer needs a runner, skipping spawn");
+            metrics::counter!("runner_controller_claim_check_skips_total").increment(1);
+            return Err(JobUnavailable { job_id, reason }.into());
+        }
+
+        Ok(Some(gh_job))
+    }
+
+    /// Claim a job in the store shared with other replicas, failing with
+    /// `JobUnavailable` if one of them got it first. An unreachable store
+    /// doesn't block the spawn; the GitHub claim checks still apply.
+    async fn claim_shared(&self, job_id: u64) -> Result<()> {
+        let ttl = self.config.job_timeout + CLAIM_TTL_MARGIN;
+        match self.store.claim_job(job_id, ttl).await {
+            Ok(Claim::Acquired) => Ok(()),
+            Ok(Claim::HeldBy(holder)) => {
+                info!(job_id, holder = %holder, "Job claimed by another replica, skipping spawn");
+                metrics::counter!("runner_controller_shared_claim_skips_total").increment(1);
+                let reason = format!("claimed by controller {}", holder);
+                Err(JobUnavailable { job_id, reason }.into())
+            }
+            Err(e) => {
+                warn!(job_id, store = self.store.name(), error = %e, "Failed to claim job");
+                metrics::counter!("runner_controller_shared_store_errors_total").increment(1);
+                Ok(())
+            }
+        }
+    }
+
+    /// Give up our shared claim on a job; an expired claim needs no release
+    async fn release_shared(&self, job_id: u64) {
+        if let Err(e) = self.store.release_job(job_id).await {
+            warn!(job_id, store = self.store.name(), error = %e, "Failed to release job claim");
+            metrics::counter!("runner_controller_shared_store_errors_total").increment(1);
+        }
+    }
+
+    /// Spawn a runner for a request from the external scheduler API, unless
+    /// this controller is on standby, paused or in a maintenance window
+    async fn spawn_requested(&mut self, (job, labels, reply): SpawnRequest) {
+        self.update_role();
+        if let Some(leader) = self.standby_for() {
+            debug!(job_id = job.job_id, "On standby, refusing spawn request");
+            let _ = reply.send(Err(NotLeader { leader }.into()));
+            return;
+        }
+        if let Some(pause) = &self.paused {
+            debug!(job_id = job.job_id, "Dispatch paused, refusing spawn request");
+            let reason = pause.reason.clone();
+            let _ = reply.send(Err(DispatchPaused { reason }.into()));
+            return;
+        }
+        self.update_maintenance();
+        if let Some(window) = &self.maintenance {
+            debug!(job_id = job.job_id, "In maintenance window, refusing spawn request");
+            let reason = Some(format!("maintenance window {}", window));
+            let _ = reply.send(Err(DispatchPaused { reason }.into()));
+            return;
+        }
+        let job_id = job.job_id;
+        info!(job_id, labels = ?labels, "External spawn requested");
+        self.wake("spawn request");
+        if let Err(e) = self.state_db.record_event(
+            "job_detected",
+            None,
+            job.repository.as_deref(),
+            Some(format!("job {}", job_id)),
+        ) {
+            debug!(job_id, error = %e, "Failed to record history event");
+        }
+        let result = self.spawn_external_container(job, labels).await;
+        // Policy rejections are audited, skipped jobs are expected and
+        // throttling is counted; none is an operator-facing failure
+        let expected = |e: &anyhow::Error| {
+            e.is::<PolicyRejection>()
+                || e.is::<JobUnavailable>()
+                || e.is::<SpawnThrottled>()
+                || e.is::<FairShareExceeded>()
+                || e.is::<UnsupportedArch>()
+        };
+        if !result.as_ref().is_err_and(expected) {
+            record_spawn_outcome("external", result.is_ok());
+        }
+        if result.is_ok() {
+            self.complete_annotation(job_id).await;
+            self.resolve_provision_failures(job_id).await;
+        }
+        if let Err(e) = result.as_ref() {
+            if !expected(e) {
+                warn!(job_id, error = %e, "External spawn failed");
+                self.annotate_provision_failure(job_id, &e.to_string()).await;
+            }
+            if !expected(e) && failure_action(e) == FailureAction::Alert {
+                self.notifier.notify(NotificationEvent::SpawnFailed {
+                    name: ContainerManager::job_to_container_name(job_id),
+                    error: e.to_string(),
+                });
+            }
+        }
+        let _ = reply.send(result);
+    }
+
+    /// Handle a request from the external scheduler API
+    async fn handle_control_request(&mut self, request: ControlRequest) {
+        match request {
+            ControlRequest::Spawn { job, labels, reply } => {
+                // Another controller may already be serving this job: wait a
+                // random while, without holding up the loop, so racing
+                // controllers see each other's claims
+                if self.config.claim_jitter.is_zero() {
+                    self.spawn_requested((job, labels, reply)).await;
+                } else {
+                    let delay = random_jitter(self.config.claim_jitter);
+                    self.claim_delays.spawn(async move {
+                        tokio::time::sleep(delay).await;
+                        (job, labels, reply)
+                    });
+                }
+            }
+            ControlRequest::Release { job_id, reply } => {
+                let result = match self.external_container(job_id) {
+                    Ok(Some(name)) => {
+                        info!(job_id, name = %name, "External release requested");
+                        self.force_cleanup_container_full(&name).await
+                    }
+                    Ok(None) => Err(UnknownJob { job_id }.into()),
+                    Err(e) => Err(e),
+                };
+                let _ = reply.send(result);
+            }
+            ControlRequest::Remove { name, reply } => {
+                let result = match self.state_db.get_container(&name) {
+                    Ok(Some(_)) => {
+                        info!(name = %name, "Container removal requested");
+                        self.record_event("removed", &name, None);
+                        self.force_cleanup_container_full(&name).await
+                    }
+                    Ok(None) => Err(anyhow::anyhow!("Unknown container {}", name)),
+                    Err(e) => Err(e.into()),
+                };
+                let _ = reply.send(result);
+            }
+            ControlRequest::Quarantine {
+                name,
+                reason,
+                reply,
+            } => {
+                warn!(name = %name, reason = %reason, "Quarantine requested");
+                let _ = reply.send(self.quarantine_container_full(&name, &reason).await);
+            }
+            ControlRequest::SetPool { update, reply } => {
+                let result = self.update_pool_spec(update);
+                let updated = result.is_ok();
+                let _ = reply.send(result);
+                if updated {
+                    if let Err(e) = self.maintain_pool().await {
+                        warn!(error = %e, "Error converging pool to new spec");
+                    }
+                }
+            }
+            ControlRequest::Reload { reply } => {
+                // Intercepted by the poll loop, which hands it to the caller
+                let _ = reply.send(Err(anyhow::anyhow!("Reload not supported here")));
+            }
+            ControlRequest::Wake { reason } => self.wake(&reason),
+            ControlRequest::Pause { reason, reply } => {
+                let _ = reply.send(self.pause(reason));
+            }
+            ControlRequest::Resume { reply } => {
+                let result = self.resume();
+                let resumed = result.is_ok();
+                let _ = reply.send(result);
+                if resumed {
+                    if let Err(e) = self.maintain_pool().await {
+                        warn!(error = %e, "Error refilling the pool after resuming");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pause dispatch; pausing again keeps the original pause
+    fn pause(&mut self, reason: Option<String>) -> Result<PauseState> {
+        if let Some(pause) = &self.paused {
+            return Ok(pause.clone());
+        }
+        let pause = PauseState::new(reason);
+        self.state_db.set_pause(Some(&pause))?;
+        warn!(reason = ?pause.reason, "Dispatch paused, no runners will be spawned");
+        if let Err(e) = self.state_db.record_event("paused", None, None, pause.reason.clone()) {
+            debug!(error = %e, "Failed to record history event");
+        }
+        metrics::gauge!("runner_controller_paused").set(1.0);
+        self.paused = Some(pause.clone());
+        Ok(pause)
+    }
+
+    /// Resume dispatch after a pause
+    fn resume(&mut self) -> Result<()> {
+        if self.paused.is_none() {
+            return Ok(());
+        }
+        self.state_db.set_pause(None)?;
+        info!("Dispatch resumed");
+        if let Err(e) = self.state_db.record_event("resumed", None, None, None) {
+            debug!(error = %e, "Failed to record history event");
+        }
+        metrics::gauge!("runner_controller_paused").set(0.0);
+        self.paused = None;
+        Ok(())
+    }
+
+    /// Pick up maintenance windows opening or closing
+    fn update_maintenance(&mut self) {
+        let window = active_window(&self.config.maintenance_windows, unix_now()).cloned();
+        if window == self.maintenance {
+            return;
+        }
+        let (event, detail) = match (&window, &self.maintenance) {
+            (Some(window), _) => {
+                warn!(window = %window, "Maintenance window opened, no runners will be spawned");
+                ("maintenance_started", window.to_string())
+            }
+            (None, Some(previous)) => {
+                info!(window = %previous, "Maintenance window closed, filling the pool");
+                ("maintenance_ended", previous.to_string())
+            }
+            (None, None) => return,
+        };
+        if let Err(e) = self.state_db.record_event(event, None, None, Some(detail)) {
+            debug!(error = %e, "Failed to record history event");
+        }
+        let open = if window.is_some() { 1.0 } else { 0.0 };
+        metrics::gauge!("runner_controller_maintenance").set(open);
+        self.maintenance = window;
+    }
+
+    /// Check which runner GitHub assigned an external container's job to.
+    /// Returns true if the container is a duplicate and was cleaned up.
+    async fn check_job_claim(&self, name: &str) -> Result<bool> {
+        let Some(mut state) = self.state_db.get_container(name)? else {
+            return Ok(false);
+        };
+        let Some(job_id) = state.job_id.filter(|_| !state.claim_confirmed) else {
+            return Ok(false);
+        };
+
+        let gh_job = self.github.get_job(job_id).await?;
+        match job_claim(&gh_job, &self.containers.runner_name(name)) {
+            JobClaim::Unclaimed => Ok(false),
+            JobClaim::Ours => {
+                debug!(name = %name, job_id, "Job claim confirmed");
+                state.claim_confirmed = true;
+                self.state_db.put_container(name, &state)?;
+                Ok(false)
+            }
+            JobClaim::Theirs(runner) => {
+                // GitHub may have handed our runner another job with the same
+                // labels; only an idle runner is left over
+                let own = self.containers.runner_name(name);
+                let runners = self.github.list_runners().await?;
+                if runners.iter().any(|r| r.name == own && r.busy) {
+                    debug!(name = %name, job_id, "Job ran elsewhere, runner busy with another");
+                    state.claim_confirmed = true;
+                    self.state_db.put_container(name, &state)?;
+                    return Ok(false);
+                }
+                info!(
+                    name = %name,
+                    job_id,
+                    runner = %runner,
+                    "Job claimed by another runner, removing duplicate container"
+                );
+                metrics::counter!("runner_controller_duplicate_claims_total").increment(1);
+                let detail = format!("job {} ran on {}", job_id, runner);
+                self.record_event("duplicate", name, Some(detail));
+                self.cleanup_container_full(name).await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Check external containers for completion or timeout (they are never respawned)
+    async fn maintain_external(&self) -> Result<()> {
+        for name in self.containers.list_external().await? {
+            self.maintain_external_container(&name).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Clean up one external container if its runner completed or its job
+    /// timed out, or if it turns out to be a duplicate
+    async fn maintain_external_container(&self, name: &str) -> Result<()> {
+        if self.destroy_pending(name) {
+            return Ok(());
+        }
+        match self.check_job_claim(name).await {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(e) => debug!(name = %name, error = %e, "Failed to check job claim"),
+        }
+
+        let running_secs = self.state_db.get_container(name)?.map(|s| s.running_seconds());
+        let timed_out = match running_secs {
+            Some(secs) => secs > self.config.job_timeout.as_secs(),
+            None => true,
+        };
+
+        let completed = match self.containers.is_runner_completed(name).await {
+            Ok(completed) => completed,
+            Err(e) => {
+                warn!(name = %name, error = %e, "Failed to check external runner status");
+                true
+            }
+        };
+
+        if completed || timed_out {
+            info!(name = %name, completed, timed_out, "Cleaning up external container");
+            if completed {
+                self.record_event("completed", name, None);
+            }
+            if let (false, Some(running_secs)) = (completed, running_secs) {
+                self.notifier.notify(NotificationEvent::JobTimeout {
+                    name: name.to_string(),
+                    running_secs,
+                });
+                self.record_event("timed_out", name, Some(format!("{}s", running_secs)));
+
+                if self.config.quarantine_on_timeout {
+                    if let Err(e) = self.quarantine_container_full(name, "job timeout").await {
+                        warn!(name = %name, error = %e, "Failed to quarantine external container");
+                    }
+                    return Ok(());
+                }
+            }
+            if let Err(e) = self.cleanup_container_full(name).await {
+                warn!(name = %name, error = %e, "Failed to clean up external container");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Maintain the warm pool - ensure all slots have running containers
+    async fn maintain_pool(&self) -> Result<()> {
+        let current_containers: HashSet<String> =
+            self.containers.list_all().await?.into_iter().collect();
+
+        let spec = self.pool_spec();
+        let resting = self.pool_resting();
+        let size = if resting {
+            0
+        } else {
+            spec.size.max(self.prewarm_target)
+        };
+        // Native slots first, then those of the architecture pools
+        let slots: Vec<usize> = if resting {
+            Vec::new()
+        } else {
+            (0..size).chain(self.config.arch_routes.slots()).collect()
+        };
+        self.ramp.begin_cycle();
+        let repo_allowed = self.pool_repo_allowed().await;
+        // Empty slots, provisioned together below
+        let mut empty_slots = Vec::new();
+        if self.config.gpus.is_some() {
+            metrics::gauge!("runner_controller_gpus_in_use").set(self.gpus_in_use()?.len() as f64);
+        }
+
+        for &slot in &slots {
+            let name = ContainerManager::slot_to_container_name(slot);
+
+            if !current_containers.contains(&name) {
+                // Slot is empty - spawn a new container, gates permitting
+                if repo_allowed && self.paused.is_none() {
+                    empty_slots.push(slot);
+                }
+            } else if self.destroy_pending(&name) {
+                debug!(slot, name = %name, "Container destruction pending retry");
+            } else {
+                // Container exists - check if runner completed or timed out
+                match self.containers.is_runner_completed(&name).await {
+                    Ok(true) => {
+                        info!(slot, name = %name, "Runner completed");
+                        self.record_event("completed", &name, None);
+                        let reused = match self.reuse_pool_container(&name).await {
+                            Ok(reused) => reused,
+                            Err(e) => {
+                                warn!(slot, name = %name, error = %e, "Failed to reuse container, respawning");
+                                false
+                            }
+                        };
+                        if !reused {
+                            if let Err(e) = self.respawn_pool_container(&name, slot).await {
+                                warn!(slot, name = %name, error = %e, "Failed to respawn container");
+                            }
+                        }
+                    }
+                    Ok(false) => {
+                        // Runner still active - check for timeout
+                        if let Some(state) = self.state_db.get_container(&name)? {
+                            let running_secs = state.running_seconds();
+                            let timeout_secs = self.config.job_timeout.as_secs();
+
+                            if running_secs > timeout_secs {
+                                warn!(
+                                    slot,
+                                    name = %name,
+                                    running_secs,
+                                    timeout_secs,
+                                    "Container exceeded timeout, respawning"
+                                );
+                                self.notifier.notify(NotificationEvent::JobTimeout {
+                                    name: name.clone(),
+                                    running_secs,
+                                });
+                                self.record_event(
+                                    "timed_out",
+                                    &name,
+                                    Some(format!("{}s", running_secs)),
+                                );
+                                if self.config.quarantine_on_timeout {
+                                    // The slot is refilled on the next pass
+                                    if let Err(e) =
+                                        self.quarantine_container_full(&name, "job timeout").await
+                                    {
+                                        warn!(slot, name = %name, error = %e, "Failed to quarantine timed out container");
+                                    }
+                                } else if let Err(e) = self.respawn_pool_container(&name, slot).await {
+                                    warn!(slot, name = %name, error = %e, "Failed to respawn timed out container");
+                                }
+                            } else {
+                                debug!(slot, name = %name, running_secs, "Container healthy");
+                            }
+                        } else {
+                            // Container exists but no state - orphaned, respawn
+                            warn!(slot, name = %name, "Orphaned container (no state), respawning");
+                            if let Err(e) = self.respawn_pool_container(&name, slot).await {
+                                warn!(slot, name = %name, error = %e, "Failed to respawn orphaned container");
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        warn!(slot, name = %name, error = %e, "Failed to check runner status, respawning");
+                        if let Err(e) = self.respawn_pool_container(&name, slot).await {
+                            warn!(slot, name = %name, error = %e, "Failed to respawn container after check failure");
+                        }
+                    }
+                }
+            }
+        }
+        self.spawn_pool_slots(&empty_slots, &spec.labels).await;
+
+        if let Err(e) = self.scale_down_pool(&slots).await {
+            warn!(error = %e, "Error scaling down pool");
+        }
+
+        // Clean up stale state entries (in DB but container no longer exists).
+        // Listed again: containers spawned above are missing from the first list.
+        let current_containers: HashSet<String> =
+            self.containers.list_all().await?.into_iter().collect();
+        let db_containers = self.state_db.list_containers()?;
+        for (name, _) in db_containers {
+            if !current_containers.contains(&name) {
+                info!(name = %name, "Removing stale state entry (container no longer exists)");
+                self.state_db.remove_container(&name)?;
+            }
+        }
+
+        let containers = self.state_db.list_containers()?;
+        let snapshot = PoolSnapshot::new(spec.size, containers.len());
+        if let Err(e) = self.state_db.put_pool_snapshot(&snapshot) {
+            debug!(error = %e, "Failed to write pool snapshot");
+        }
+        if !self.config.arch_routes.is_empty() {
+            let states = containers.iter().map(|(_, state)| state);
+            for arch in self.config.arch_routes.capacity(size, states) {
+                let label = arch.arch.to_string();
+                metrics::gauge!("runner_controller_arch_runners", "arch" => label)
+                    .set(arch.running as f64);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record when runners first come online and first pick up a job
+    async fn track_runner_startup(&self) -> Result<()> {
+        let pending: Vec<(String, ContainerState)> = self
+            .state_db
+            .list_containers()?
+            .into_iter()
+            .filter(|(_, s)| s.timings.is_pending())
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let runners = self.github.list_runners().await?;
+        // Jobs pool runners picked up, fetched once a pool runner turns busy
+        let mut in_progress: Option<Vec<WorkflowJob>> = None;
+        for (name, mut state) in pending {
+            let runner_name = self.containers.runner_name(&name);
+            let runner = runners.iter().find(|r| r.name == runner_name);
+            let online = runner.is_some_and(|r| r.status == "online" || r.busy);
+            if !online && state.timings.registered_after_secs.is_none() {
+                let elapsed = state.running_seconds();
+                if self.config.startup_timeout.is_some_and(|t| elapsed > t.as_secs()) {
+                    self.handle_startup_timeout(&name, &state, elapsed).await;
+                    continue;
+                }
+            }
+            let Some(runner) = runner else {
+                continue;
+            };
+
+            let mut changed = false;
+            if state.runner_id != Some(runner.id) {
+                state.runner_id = Some(runner.id);
+                changed = true;
+            }
+            if runner.busy && state.job_id.is_none() {
+                if in_progress.is_none() {
+                    in_progress = Some(self.github.list_in_progress_jobs().await?);
+                }
+                let jobs = in_progress.as_deref().unwrap_or_default();
+                if let Some(job) = jobs.iter().find(|j| j.runner_name.as_ref() == Some(&runner_name)) {
+                    state.set_job(job);
+                    state.repository.get_or_insert_with(|| self.config.github_repo.clone());
+                    changed = true;
+                }
+            }
+
+            let elapsed = state.running_seconds();
+            let timings = &mut state.timings;
+            if timings.registered_after_secs.is_none() && (runner.status == "online" || runner.busy) {
+                timings.registered_after_secs = Some(elapsed);
+                metrics::histogram!("runner_controller_spawn_phase_seconds", "phase" => "registration")
+                    .record(elapsed as f64);
+                changed = true;
+            }
+            if timings.job_started_after_secs.is_none() && runner.busy {
+                timings.job_started_after_secs = Some(elapsed);
+                metrics::histogram!("runner_controller_spawn_phase_seconds", "phase" => "job_pickup")
+                    .record(elapsed as f64);
+                changed = true;
+            }
+
+            if changed {
+                debug!(name = %name, timings = ?state.timings, "Runner startup progressed");
+                self.state_db.put_container(&name, &state)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tear down a container whose runner never came online. Pool slots are
+    /// respawned; external containers are left for the scheduler to request again.
+    async fn handle_startup_timeout(&self, name: &str, state: &ContainerState, elapsed: u64) {
+        warn!(name = %name, elapsed, "Runner did not register in time, tearing down container");
+        metrics::counter!("runner_controller_startup_timeouts_total").increment(1);
+        self.record_event("startup_timeout", name, Some(format!("{}s", elapsed)));
+
+        let result = match state.slot {
+            Some(slot) => self.respawn_pool_container(name, slot).await,
+            None => self.cleanup_container_full(name).await,
+        };
+        if let Err(e) = result {
+            warn!(name = %name, error = %e, "Failed to replace container after startup timeout");
+        }
+        self.notifier.notify(NotificationEvent::SpawnFailed {
+            name: name.to_string(),
+            error: format!("runner not online after {}s", elapsed),
+        });
+    }
+
+    /// Remove pool containers in slots no longer wanted. Idle runners go
+    /// right away; busy ones are left to finish their job.
+    async fn scale_down_pool(&self, slots: &[usize]) -> Result<()> {
+        let size = slots.len();
+        let surplus: Vec<String> = self
+            .state_db
+            .list_containers()?
+            .into_iter()
+            .filter(|(_, s)| s.slot.is_some_and(|slot| !slots.contains(&slot)))
+            .map(|(name, _)| name)
+            .collect();
+        if surplus.is_empty() {
+            return Ok(());
+        }
+
+        let runners = self.github.list_runners().await?;
+        for name in surplus {
+            let runner_name = self.containers.runner_name(&name);
+            if runners.iter().any(|r| r.name == runner_name && r.busy) {
+                debug!(name = %name, "Surplus pool container busy, removing after its job");
+                continue;
+            }
+
+            info!(name = %name, size, "Removing container beyond desired pool size");
+            self.record_event("scaled_down", &name, None);
+            if let Err(e) = self.cleanup_container_full(&name).await {
+                warn!(name = %name, error = %e, "Failed to remove surplus pool container");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete offline GitHub runners that have no local container
+    async fn reap_stale_runners(&self) -> Result<()> {
+        let naming = self.containers.naming();
+        let prefix = self.config.reaper_name_prefix.as_deref().unwrap_or(naming.prefix());
+        // Without a prefix or an instance label, every host's runners would
+        // look like ours
+        if prefix.is_empty() && naming.instance_label().is_none() {
+            debug!("No runner name prefix or instance label, not reaping stale runners");
+            return Ok(());
+        }
+        let runners = self.github.list_runners().await?;
+        let local: HashSet<String> = self.containers.list_all().await?.into_iter().collect();
+
+        for runner in stale_runners(&runners, &local, prefix, naming) {
+            info!(name = %runner.name, runner_id = runner.id, "Reaping stale offline runner");
+            if let Err(e) = self.github.delete_runner(runner.id).await {
+                warn!(name = %runner.name, error = %e, "Failed to delete stale runner");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the stale runner reaper if its interval has elapsed
+    async fn maybe_reap_stale_runners(&mut self) {
+        let Some(interval) = self.config.reaper_interval else {
+            return;
+        };
+
+        if self.last_reap.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+        self.last_reap = Some(Instant::now());
+
+        if let Err(e) = self.reap_stale_runners().await {
+            warn!(error = %e, "Error reaping stale runners");
+        }
+    }
+
+    /// Make sure GitHub sees our runners as ephemeral. A runner registered
+    /// without `--ephemeral` would take job after job in the same container:
+    /// alert once, and remove its container while it is idle so the slot is
+    /// refilled.
+    async fn verify_ephemeral_runners(&mut self) -> Result<()> {
+        let runners = self.github.list_runners().await?;
+        let naming = self.containers.naming();
+        let offending: Vec<&Runner> = runners
+            .iter()
+            .filter(|r| r.ephemeral == Some(false) && naming.owns(r))
+            .collect();
+        metrics::gauge!("runner_controller_non_ephemeral_runners").set(offending.len() as f64);
+
+        self.non_ephemeral
+            .retain(|id| offending.iter().any(|r| r.id == *id));
+        for runner in offending {
+            if self.non_ephemeral.insert(runner.id) {
+                warn!(name = %runner.name, runner_id = runner.id, "Runner is not ephemeral");
+                self.notifier.notify(NotificationEvent::NonEphemeralRunner {
+                    name: runner.name.clone(),
+                    runner_id: runner.id,
+                });
+            }
+            if runner.busy {
+                continue;
+            }
+
+            let Some(name) = naming.container_name(&runner.name) else {
+                continue;
+            };
+            if self.state_db.get_container(name)?.is_none() {
+                continue;
+            }
+            info!(name = %name, "Removing idle non-ephemeral runner");
+            self.record_event("non_ephemeral", name, Some(format!("runner {}", runner.id)));
+            if let Err(e) = self.cleanup_container_full(name).await {
+                warn!(name = %name, error = %e, "Failed to remove non-ephemeral runner");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Notify once when GitHub keeps rejecting our token
+    fn check_auth_failures(&mut self) {
+        let consecutive = self.github.consecutive_auth_failures();
+        if consecutive >= AUTH_FAILURE_ALERT_THRESHOLD && !self.auth_alerted {
+            self.notifier
+                .notify(NotificationEvent::AuthFailures { consecutive });
+            self.auth_alerted = true;
+        } else if consecutive == 0 {
+            self.auth_alerted = false;
+        }
+    }
+
+    /// Look at the jobs we could serve that are still queued
+    async fn check_queue(&mut self) -> Result<()> {
+        if !self.starvation.is_enabled()
+            && !self.annotator.is_enabled()
+            && !self.unmatched.is_enabled()
+        {
+            return Ok(());
+        }
+
+        let labels = self.pool_spec().labels;
+        let (queued, others): (Vec<WorkflowJob>, Vec<WorkflowJob>) = self
+            .github
+            .list_queued_jobs()
+            .await?
+            .into_iter()
+            .partition(|j| labels_match(&j.labels, &labels));
+
+        if self.annotator.is_enabled() {
+            self.annotate_queue(&queued).await;
+        }
+        if self.starvation.is_enabled() {
+            self.check_starvation(&queued).await?;
+        }
+        if self.unmatched.is_enabled() {
+            self.check_unmatched(&labels, &others).await?;
+        }
+        Ok(())
+    }
+
+    /// Warn about queued jobs that request labels neither our pool nor any
+    /// runner registered to the repository offers: nothing will ever run them
+    async fn check_unmatched(
+        &mut self,
+        pool_labels: &[String],
+        jobs: &[WorkflowJob],
+    ) -> Result<()> {
+        let registered: Vec<Vec<String>> = if jobs.is_empty() {
+            Vec::new()
+        } else {
+            self.github
+                .list_runners()
+                .await?
+                .into_iter()
+                .map(|r| r.labels.into_iter().map(|l| l.name).collect())
+                .collect()
+        };
+        let unmatched: Vec<&WorkflowJob> = jobs
+            .iter()
+            .filter(|j| {
+                let offered =
+                    std::iter::once(pool_labels).chain(registered.iter().map(Vec::as_slice));
+                !labels_match_any(&j.labels, offered)
+            })
+            .collect();
+
+        let ids: Vec<u64> = unmatched.iter().map(|j| j.id).collect();
+        for (job_id, cycles) in self.unmatched.observe(&ids, true) {
+            let Some(job) = unmatched.iter().find(|j| j.id == job_id) else {
+                continue;
+            };
+            warn!(
+                job_id,
+                job_name = %job.name,
+                labels = ?job.labels,
+                pool_labels = ?pool_labels,
+                cycles,
+                "Queued job requests labels no runner offers"
+            );
+            let detail = format!(
+                "job {} queued {} cycles, labels [{}]",
+                job_id,
+                cycles,
+                job.labels.join(", ")
+            );
+            if let Err(e) = self.state_db.record_event("unmatched_job", None, None, Some(detail)) {
+                debug!(error = %e, "Failed to record history event");
+            }
+            if self.config.unmatched_queue_notify {
+                self.notifier.notify(NotificationEvent::UnmatchedJob {
+                    job_id,
+                    job_name: job.name.clone(),
+                    labels: job.labels.clone(),
+                    cycles,
+                });
+            }
+        }
+
+        let stuck: Vec<UnmatchedJob> = self
+            .unmatched
+            .starved()
+            .into_iter()
+            .filter_map(|(job_id, cycles)| {
+                let job = unmatched.iter().find(|j| j.id == job_id)?;
+                Some(UnmatchedJob {
+                    job_id,
+                    job_name: job.name.clone(),
+                    labels: job.labels.clone(),
+                    cycles,
+                })
+            })
+            .collect();
+        metrics::gauge!("runner_controller_unmatched_jobs").set(stuck.len() as f64);
+        self.state_db.put_unmatched_jobs(&stuck)?;
+        Ok(())
+    }
+
+    /// Compare the pinned runner version with GitHub's releases, every
+    /// `RUNNER_VERSION_CHECK_INTERVAL`
+    async fn check_runner_version(&mut self) -> Result<()> {
+        let Some(pinned) = self.runner_version.due() else {
+            return Ok(());
+        };
+        let releases = self.github.list_runner_releases().await?;
+        let now = unix_now();
+        let Some(status) = VersionStatus::new(pinned, &releases, now) else {
+            debug!("No runner releases to compare the pinned version with");
+            return Ok(());
+        };
+        metrics::gauge!("runner_controller_runner_upgrade_available")
+            .set(if status.upgrade_available() { 1.0 } else { 0.0 });
+        metrics::gauge!("runner_controller_runner_version_refused")
+            .set(if status.is_refused(now) { 1.0 } else { 0.0 });
+
+        if let Some(warning) = status.warning(now) {
+            warn!(
+                pinned = %status.pinned,
+                latest = %status.latest,
+                minimum = %status.minimum,
+                auto_upgrade = self.config.runner_auto_upgrade,
+                "{}",
+                warning
+            );
+            if self.runner_version.should_alert(&status, now) {
+                if let Err(e) =
+                    self.state_db
+                        .record_event("runner_outdated", None, None, Some(warning))
+                {
+                    debug!(error = %e, "Failed to record history event");
+                }
+                let refused_in = status.refused_at.unwrap_or(now).saturating_sub(now);
+                self.notifier.notify(NotificationEvent::RunnerOutdated {
+                    pinned: status.pinned.clone(),
+                    latest: status.latest.clone(),
+                    refused_in_days: refused_in.div_ceil(86400),
+                });
+            }
+        }
+        self.state_db.put_runner_version(&status)?;
+        Ok(())
+    }
+
+    /// Note whether jobs are around this cycle and go idle after a quiet period.
+    /// An idle controller still polls (slowly), so a queued job we could serve
+    /// wakes it up even without webhooks.
+    async fn observe_activity(&mut self) -> Result<()> {
+        if self.config.idle_after.is_none() {
+            return Ok(());
+        }
+
+        let naming = self.containers.naming();
+        let busy = self
+            .github
+            .list_runners()
+            .await?
+            .iter()
+            .any(|r| r.busy && naming.owns(r));
+        let assigned = self
+            .state_db
+            .list_containers()?
+            .iter()
+            .any(|(_, state)| state.job_id.is_some());
+
+        if busy || assigned {
+            self.wake("job activity");
+        } else if self.idle.is_idle() {
+            let labels = self.pool_spec().labels;
+            let queued = self.github.list_queued_jobs().await?;
+            if queued.iter().any(|j| labels_match(&j.labels, &labels)) {
+                self.wake("queued job");
+            }
+        }
+
+        if self.idle.check() {
+            info!(idle_after = ?self.config.idle_after, "No jobs seen, going idle");
+            metrics::gauge!("runner_controller_idle").set(1.0);
+            if let Err(e) = self.state_db.record_event("idle", None, None, None) {
+                debug!(error = %e, "Failed to record history event");
+            }
+        }
+        Ok(())
+    }
+
+    /// Export free disk space and start garbage collection when it runs low
+    async fn maintain_disk(&mut self) {
+        let usage = disk_usage(&self.config.load_limits.disk_paths);
+        for disk in &usage {
+            let path = disk.path.display().to_string();
+            metrics::gauge!("runner_controller_disk_free_bytes", "path" => path.clone())
+                .set(disk.free_bytes as f64);
+            metrics::gauge!("runner_controller_disk_total_bytes", "path" => path)
+                .set(disk.total_bytes as f64);
+        }
+
+        let Some(gc) = &self.config.disk_gc else {
+            return;
+        };
+        if let Some(task) = self.gc_task.take_if(|t| t.is_finished()) {
+            let detail = match task.await {
+                Ok(Ok(())) => {
+                    info!("Disk garbage collection finished");
+                    None
+                }
+                Ok(Err(e)) => {
+                    warn!(error = %e, "Disk garbage collection failed");
+                    Some(e.to_string())
+                }
+                Err(e) => Some(e.to_string()),
+            };
+            if let Err(e) = self.state_db.record_event("disk_gc", None, None, detail) {
+                debug!(error = %e, "Failed to record history event");
+            }
+        }
+        if self.gc_task.is_some() || self.last_gc.is_some_and(|t| t.elapsed() < gc.interval) {
+            return;
+        }
+
+        let Some(low) = gc.needed(&usage) else {
+            return;
+        };
+        info!(
+            path = %low.path.display(),
+            free_gb = low.free_bytes / (1024 * 1024 * 1024),
+            threshold_gb = gc.threshold_gb,
+            "Low disk space, running garbage collection"
+        );
+        metrics::counter!("runner_controller_disk_gc_runs_total").increment(1);
+        self.last_gc = Some(Instant::now());
+        self.gc_task = Some(tokio::spawn(run_command(gc.command.clone())));
+    }
+
+    /// Grow the pool ahead of demand learned from job history
+    fn update_prewarm(&mut self) -> Result<()> {
+        if !self.prewarm.is_enabled() {
+            return Ok(());
+        }
+
+        let now = unix_now();
+        if self.prewarm.needs_learning() {
+            let query = LogQuery {
+                limit: usize::MAX,
+                since: Some(now.saturating_sub(self.prewarm.lookback().as_secs())),
+                event: Some("completed".to_string()),
+                ..Default::default()
+            };
+            let completions: Vec<u64> = self
+                .state_db
+                .query_history(&query, |_| true)?
+                .iter()
+                .map(|e| e.timestamp)
+                .collect();
+            debug!(jobs = completions.len(), "Learning job demand from history");
+            self.prewarm.learn(&completions, now);
+        }
+
+        let target = self.prewarm.target(now);
+        if target != self.prewarm_target {
+            info!(from = self.prewarm_target, to = target, "Pre-warm target changed");
+            self.prewarm_target = target;
+        }
+        metrics::gauge!("runner_controller_prewarm_target").set(target as f64);
+        // Expected demand keeps the controller out of idle mode
+        if target > 0 {
+            self.wake("pre-warm");
+        }
+        Ok(())
+    }
+
+    /// Record job activity, leaving idle mode if the controller was idle
+    fn wake(&mut self, reason: &str) {
+        if !self.idle.activity() {
+            return;
+        }
+        info!(reason, "Leaving idle mode");
+        metrics::gauge!("runner_controller_idle").set(0.0);
+        metrics::counter!("runner_controller_wakeups_total").increment(1);
+        if let Err(e) = self
+            .state_db
+            .record_event("woken", None, None, Some(reason.to_string()))
+        {
+            debug!(error = %e, "Failed to record history event");
+        }
+    }
+
+    /// Post each queued job's place in the queue as a check run
+    async fn annotate_queue(&mut self, queued: &[WorkflowJob]) {
+        for change in self.annotator.plan(queued) {
+            let result = match change {
+                Annotation::Create {
+                    job_id,
+                    head_sha,
+                    position,
+                    output,
+                } => match self.github.create_check_run(&head_sha, output).await {
+                    Ok(check_run_id) => {
+                        self.annotator.created(job_id, check_run_id, position);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                },
+                Annotation::Update {
+                    check_run_id,
+                    output,
+                } => self.github.update_check_run(check_run_id, output, None).await,
+                Annotation::Complete {
+                    check_run_id,
+                    output,
+                    conclusion,
+                } => {
+                    self.github
+                        .update_check_run(check_run_id, output, Some(conclusion))
+                        .await
+                }
+            };
+            if let Err(e) = result {
+                warn!(error = %e, "Failed to update queue check run");
+            }
+        }
+    }
+
+    /// Complete a job's queue check run once its runner container is up
+    async fn complete_annotation(&mut self, job_id: u64) {
+        let Some(check_run_id) = self.annotator.complete(job_id) else {
+            return;
+        };
+        let output = CheckRunOutput {
+            title: "Runner container started".to_string(),
+            summary: "A self-hosted runner was spawned for this job.".to_string(),
+        };
+        if let Err(e) = self
+            .github
+            .update_check_run(check_run_id, output, Some("success"))
+            .await
+        {
+            warn!(job_id, error = %e, "Failed to complete queue check run");
+        }
+    }
+
+    /// Tell the job's developers on its commit that runners for it keep
+    /// failing to start, once enough attempts failed
+    async fn annotate_provision_failure(&mut self, job_id: u64, error: &str) {
+        let Some((attempts, posted)) = self.provision_failures.failed(job_id) else {
+            return;
+        };
+        let output = provision_failure_output(attempts, error);
+        let result = match posted {
+            Some(Posted::CheckRun(check_run_id)) => {
+                self.github.update_check_run(check_run_id, output, None).await
+            }
+            Some(Posted::Status { head_sha }) => {
+                self.post_provision_failure(job_id, head_sha, output).await
+            }
+            None => {
+                let head_sha = match self.github.get_job(job_id).await {
+                    Ok(job) if !job.head_sha.is_empty() => job.head_sha,
+                    Ok(_) => return,
+                    Err(e) => {
+                        debug!(job_id, error = %e, "Could not look up the job's commit");
+                        return;
+                    }
+                };
+                self.post_provision_failure(job_id, head_sha, output).await
+            }
+        };
+        match result {
+            Ok(()) => {
+                info!(job_id, attempts, "Annotated the job's commit with provisioning failures");
+                metrics::counter!("runner_controller_provision_failure_annotations_total")
+                    .increment(1);
+            }
+            Err(e) => warn!(job_id, error = %e, "Failed to annotate provisioning failure"),
+        }
+    }
+
+    /// Create the configured kind of annotation on `head_sha`
+    async fn post_provision_failure(
+        &mut self,
+        job_id: u64,
+        head_sha: String,
+        output: CheckRunOutput,
+    ) -> Result<()> {
+        let posted = match self.provision_failures.mode() {
+            FailureAnnotation::Off => return Ok(()),
+            FailureAnnotation::CheckRun => {
+                Posted::CheckRun(self.github.create_check_run(&head_sha, output).await?)
+            }
+            FailureAnnotation::Status => {
+                self.github
+                    .create_commit_status(&head_sha, "error", &output.title)
+                    .await?;
+                Posted::Status { head_sha }
+            }
+        };
+        self.provision_failures.posted(job_id, posted);
+        Ok(())
+    }
+
+    /// A runner finally started: resolve the job's provisioning failure annotation
+    async fn resolve_provision_failures(&mut self, job_id: u64) {
+        let Some((posted, attempts)) = self.provision_failures.succeeded(job_id) else {
+            return;
+        };
+        let description = format!("Runner started after {} failed attempts", attempts);
+        let result = match posted {
+            Posted::CheckRun(check_run_id) => {
+                let output = CheckRunOutput {
+                    title: description,
+                    summary: "A self-hosted runner was spawned for this job.".to_string(),
+                };
+                self.github
+                    .update_check_run(check_run_id, output, Some("success"))
+                    .await
+            }
+            Posted::Status { head_sha } => {
+                self.github
+                    .create_commit_status(&head_sha, "success", &description)
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            warn!(job_id, error = %e, "Failed to resolve provisioning failure annotation");
+        }
+    }
+
+    /// Complete the check runs of jobs no longer retried. Commit statuses
+    /// stay as they are: nothing is known about how such a job ended.
+    async fn prune_provision_failures(&mut self) {
+        for posted in self.provision_failures.abandoned(PROVISION_RETRY_WINDOW) {
+            let Posted::CheckRun(check_run_id) = posted else {
+                continue;
+            };
+            let output = CheckRunOutput {
+                title: "No longer retried".to_string(),
+                summary: "No runner was requested for this job for an hour.".to_string(),
+            };
+            if let Err(e) = self
+                .github
+                .update_check_run(check_run_id, output, Some("neutral"))
+                .await
+            {
+                warn!(
+                    check_run_id,
+                    error = %e,
+                    "Failed to complete provisioning failure check run"
+                );
+            }
+        }
+    }
+
+    /// Alert on jobs we could serve that stay queued while our runners sit idle
+    async fn check_starvation(&mut self, queued: &[WorkflowJob]) -> Result<()> {
+        let naming = self.containers.naming();
+        let idle_runners = self
+            .github
+            .list_runners()
+            .await?
+            .iter()
+            .filter(|r| r.status == "online" && !r.busy)
+            .filter(|r| naming.owns(r))
+            .count();
+
+        let oldest = queued.iter().find_map(|j| j.queued_at());
+        let oldest_secs = oldest.map_or(0, |at| unix_now().saturating_sub(at));
+        metrics::gauge!("runner_controller_oldest_queued_job_seconds").set(oldest_secs as f64);
+
+        // Jobs are listed oldest first, so the longest-waiting ones are reported first
+        let ids: Vec<u64> = queued.iter().map(|j| j.id).collect();
+        for (job_id, cycles) in self.starvation.observe(&ids, idle_runners > 0) {
+            let Some(job) = queued.iter().find(|j| j.id == job_id) else {
+                continue;
+            };
+            warn!(
+                job_id,
+                job_name = %job.name,
+                labels = ?job.labels,
+                cycles,
+                idle_runners,
+                "Queue starvation: matching job stays queued despite idle runners"
+            );
+            let detail = format!("job {} queued {} cycles, {} idle", job_id, cycles, idle_runners);
+            // About a queued job of the configured repository, not a container
+            let repository = Some(self.config.github_repo.as_str());
+            if let Err(e) =
+                self.state_db.record_event("queue_starvation", None, repository, Some(detail))
+            {
+                debug!(error = %e, "Failed to record history event");
+            }
+            self.notifier.notify(NotificationEvent::QueueStarvation {
+                job_id,
+                job_name: job.name.clone(),
+                labels: job.labels.clone(),
+                cycles,
+                idle_runners,
+            });
+        }
+
+        metrics::gauge!("runner_controller_starved_jobs")
+            .set(self.starvation.starved_count() as f64);
+        Ok(())
+    }
+
+    /// Whether to run this poll cycle. While the GitHub circuit breaker is
+    /// open polling pauses; once its cooldown passes a single probe decides.
+    async fn github_available(&mut self) -> bool {
+        let state = self.github.circuit_state();
+        metrics::gauge!("runner_controller_github_circuit_open")
+            .set(if state == BreakerState::Closed { 0.0 } else { 1.0 });
+
+        let available = match state {
+            BreakerState::Closed => true,
+            BreakerState::Open => {
+                debug!(
+                    retry_in_secs = ?self.github.circuit_retry_in(),
+                    "GitHub circuit breaker open, skipping poll"
+                );
+                false
+            }
+            BreakerState::HalfOpen => match self.github.probe().await {
+                Ok(()) => true,
+                Err(e) => {
+                    debug!(error = %e, "GitHub API probe failed");
+                    false
+                }
+            },
+        };
+
+        // Containers all went away or finished during the outage; don't refill at once
+        if available && self.github_down {
+            info!("GitHub available again, ramping up spawns");
+            self.ramp.restart();
+        }
+        self.github_down = !available;
+        available
+    }
+
+    /// Main run loop
+    pub async fn run(&mut self) -> Result<RunExit> {
+        info!(
+            poll_interval = ?self.config.poll_interval,
+            reconcile_interval = ?self.config.reconcile_interval,
+            pool_size = self.config.max_concurrent_jobs,
+            "Pool controller starting"
+        );
+
+        self.start().await?;
+        self.serve().await
+    }
+
+    /// Audit and reconcile once on startup, before the first poll
+    async fn start(&mut self) -> Result<()> {
+        self.ramp.restart();
+
+        if let Err(e) = self.startup_audit().await {
+            warn!(error = %e, "Startup consistency audit failed");
+        }
+
+        // Reconcile on startup
+        self.reconcile_on_startup().await
+    }
+
+    /// Start up and run a single poll, including a reconcile pass, then
+    /// return. Containers are left running for the next invocation.
+    pub async fn run_once(&mut self) -> Result<()> {
+        info!(pool_size = self.config.max_concurrent_jobs, "Running a single poll");
+        self.start().await?;
+        if self.github_available().await {
+            self.poll().await;
+            self.reconcile().await;
+        } else {
+            warn!("GitHub unavailable, skipped the poll");
+        }
+        Ok(())
+    }
+
+    /// Scheduler loop; returns when shutdown or a reload is requested. It
+    /// handles one event at a time from the producers on the bus (poll and
+    /// reconcile timers, the container watcher) and the control channel
+    /// (webhooks, API requests). Every poll dispatches: it refills the pool
+    /// and looks at queued jobs. Sweeps over all external containers and
+    /// GitHub runners run on their own `reconcile_interval` timer, and
+    /// finished runners seen by the watcher are handled shortly after.
+    pub async fn serve(&mut self) -> Result<RunExit> {
+        let reconcile_period = (!self.reconcile_with_polls()).then(|| self.reconcile_period());
+        let watch_interval = self.config.container_watch_interval.map(|interval| {
+            if self.config.remote.is_some() {
+                interval * REMOTE_WATCH_FACTOR
+            } else {
+                interval
+            }
+        });
+        let mut bus = Bus::start(
+            Arc::clone(&self.containers),
+            self.poll_period(),
+            reconcile_period,
+            self.config.poll_jitter,
+            watch_interval,
+        );
+        self.dispatch(&bus).await;
+
+        loop {
+            // Check for shutdown signal
+            if *self.shutdown_rx.borrow() {
+                info!("Shutdown signal received");
+                return Ok(RunExit::Shutdown);
+            }
+
+            tokio::select! {
+                Some(event) = bus.recv() => match event {
+                    Event::Poll => self.dispatch(&bus).await,
+                    Event::Reconcile => {
+                        if self.github_available().await {
+                            self.last_reconcile = Some(Instant::now());
+                            self.reconcile().await;
+                        }
+                        bus.set_reconcile_period(self.reconcile_period());
+                    }
+                    Event::ContainerExited { name } => self.container_exited(&name, &bus).await,
+                },
+                Some(request) = self.control_rx.recv() => match request {
+                    ControlRequest::Reload { reply } => return Ok(RunExit::Reload(reply)),
+                    ControlRequest::Wake { reason } => {
+                        self.wake(&reason);
+                        self.dispatch(&bus).await;
+                    }
+                    request => self.handle_control_request(request).await,
+                },
+                Some(Ok(request)) = self.claim_delays.join_next() => {
+                    self.spawn_requested(request).await;
+                }
+                Ok(()) = self.shutdown_rx.changed() => {}
+                else => return Ok(RunExit::Shutdown),
+            }
+        }
+    }
+
+    /// A dispatch pass unless GitHub is unavailable, then the poll timer
+    /// starts over
+    async fn dispatch(&mut self, bus: &Bus) {
+        if self.github_available().await {
+            self.poll().await;
+            if self.reconcile_with_polls() && self.reconcile_due() {
+                self.reconcile().await;
+            }
+        }
+        bus.set_poll_period(self.poll_period());
+    }
+
+    /// The watcher saw a container's runner finish: clean up an external
+    /// container right away, or refill the pool with a poll shortly after,
+    /// which the pool runners finishing until then share
+    async fn container_exited(&mut self, name: &str, bus: &Bus) {
+        debug!(name = %name, "Runner finished");
+        if self.state_db.get_container(name).ok().flatten().is_some_and(|s| s.slot.is_none()) {
+            if let Err(e) = self.maintain_external_container(name).await {
+                warn!(name = %name, error = %e, "Error maintaining external container");
+            }
+        } else {
+            bus.poll_soon();
+        }
+    }
+
+    /// One pass of the poll loop; errors are logged and retried next poll
+    async fn poll(&mut self) {
+        self.update_role();
+        self.update_maintenance();
+        self.maintain_disk().await;
+
+        if let Err(e) = self.update_prewarm() {
+            warn!(error = %e, "Error updating pre-warm target");
+        }
+
+        if let Err(e) = self.guard_clock_skew() {
+            warn!(error = %e, "Error checking container start times");
+        }
+
+        // Maintain the warm pool
+        if let Err(e) = self.maintain_pool().await {
+            warn!(error = %e, "Error maintaining pool");
+        }
+
+        if let Err(e) = self.track_runner_startup().await {
+            warn!(error = %e, "Error tracking runner startup");
+        }
+
+        if let Err(e) = self.refresh_job_tokens().await {
+            warn!(error = %e, "Error refreshing job tokens");
+        }
+
+        if let Err(e) = self.retry_cleanups().await {
+            warn!(error = %e, "Error retrying failed cleanups");
+        }
+
+        self.check_auth_failures();
+
+        // The leader watches the queue; a standby would alert twice
+        if self.standby_for().is_none() {
+            if let Err(e) = self.check_queue().await {
+                warn!(error = %e, "Error checking the job queue");
+            }
+        }
+
+        if let Err(e) = self.observe_activity().await {
+            warn!(error = %e, "Error checking for job activity");
+        }
+    }
+
+    /// Factor to stretch the poll and reconcile intervals by so GitHub's
+    /// remaining rate limit lasts until the reset
+    fn poll_stretch(&mut self) -> f64 {
+        let budget = self.github.rate_budget();
+        let stretch = budget.as_ref().map_or(1.0, |b| b.poll_stretch);
+        metrics::gauge!("runner_controller_poll_stretch").set(stretch);
+        if let Some(budget) = &budget {
+            metrics::gauge!("runner_controller_github_rate_remaining").set(budget.remaining as f64);
+        }
+
+        let stretched = stretch > 1.0;
+        if stretched && !self.poll_stretched {
+            warn!(
+                remaining = budget.as_ref().map(|b| b.remaining),
+                stretch,
+                "GitHub rate limit budget tight, polling less often"
+            );
+        } else if !stretched && self.poll_stretched {
+            info!("GitHub rate limit budget recovered, polling at the usual pace");
+        }
+        self.poll_stretched = stretched;
+        stretch
+    }
+
+    /// Whether a reconcile pass is due, starting its interval if so
+    fn reconcile_due(&mut self) -> bool {
+        let interval = self.config.reconcile_interval.mul_f64(self.poll_stretch());
+        if self.last_reconcile.is_some_and(|t| t.elapsed() < interval) {
+            return false;
+        }
+        self.last_reconcile = Some(Instant::now());
+        true
+    }
+
+    /// Sweep external containers and every registered GitHub runner
+    async fn reconcile(&mut self) {
+        debug!("Reconciling containers and runners");
+
+        if let Err(e) = self.maintain_external().await {
+            warn!(error = %e, "Error maintaining external containers");
+        }
+
+        if let Err(e) = self.prune_archive().await {
+            warn!(error = %e, "Error pruning archived logs");
+        }
+
+        self.prune_captured_logs();
+
+        self.maybe_reap_stale_runners().await;
+
+        if let Err(e) = self.verify_ephemeral_runners().await {
+            warn!(error = %e, "Error verifying runners are ephemeral");
+        }
+
+        if let Err(e) = self.check_runner_version().await {
+            warn!(error = %e, "Error checking for runner upgrades");
+        }
+
+        self.prune_provision_failures().await;
+
+        self.maintain_state_db().await;
+    }
+
+    /// Export the state database's size and, every `STATE_COMPACT_INTERVAL`,
+    /// compact it: history churns through pages that otherwise stay
+    /// allocated in the file
+    async fn maintain_state_db(&mut self) {
+        let due = self
+            .config
+            .state_compact_interval
+            .is_some_and(|interval| self.last_compaction.is_none_or(|t| t.elapsed() >= interval));
+        if due {
+            self.last_compaction = Some(Instant::now());
+        }
+
+        let db = self.state_db.clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<_, StateError> {
+            let before = db.stats()?;
+            if !due || before.fragmented_bytes < COMPACT_MIN_FREE_BYTES {
+                return Ok((before, None));
+            }
+            db.compact()?;
+            Ok((before, Some(db.stats()?)))
+        })
+        .await;
+        let (before, compacted) = match result {
+            Ok(Ok(stats)) => stats,
+            Ok(Err(e)) => {
+                warn!(error = %e, "Failed to maintain the state database");
+                return;
+            }
+            Err(e) => {
+                warn!(error = %e, "State database maintenance panicked");
+                return;
+            }
+        };
+
+        if let Some(after) = compacted {
+            info!(
+                before_bytes = before.file_bytes,
+                after_bytes = after.file_bytes,
+                "Compacted the state database"
+            );
+            metrics::counter!("runner_controller_state_db_compactions_total").increment(1);
+        }
+        let stats = compacted.unwrap_or(before);
+        metrics::gauge!("runner_controller_state_db_size_bytes").set(stats.file_bytes as f64);
+        metrics::gauge!("runner_controller_state_db_stored_bytes").set(stats.stored_bytes as f64);
+        metrics::gauge!("runner_controller_state_db_fragmented_bytes")
+            .set(stats.fragmented_bytes as f64);
+    }
+
+    /// Time from one dispatch pass to the next, stretched to save rate limit
+    /// budget; while idle without an idle poll interval, until woken up
+    fn poll_period(&mut self) -> Duration {
+        let stretch = self.poll_stretch();
+        if self.idle.is_idle() {
+            self.config
+                .idle_poll_interval
+                .map_or(Duration::MAX, |i| i.mul_f64(stretch))
+        } else {
+            self.config.poll_interval.mul_f64(stretch)
+        }
+    }
+
+    /// Time between reconcile passes, stretched like the poll interval
+    fn reconcile_period(&mut self) -> Duration {
+        self.config.reconcile_interval.mul_f64(self.poll_stretch())
+    }
+
+    /// Whether reconcile passes follow dispatch passes instead of running
+    /// on their own timer: a reconcile interval shorter than the poll
+    /// interval reconciles on every poll
+    fn reconcile_with_polls(&self) -> bool {
+        self.config.reconcile_interval <= self.config.poll_interval
+    }
+
+    /// Graceful shutdown - kill all containers
+    pub async fn shutdown(&self) -> Result<()> {
+        info!("Shutting down, cleaning up all containers");
+
+        // Get all containers (both old and new style for thorough cleanup)
+        let containers = self.containers.list_all().await?;
+        info!(count = containers.len(), "Containers to clean up");
+
+        for name in containers {
+            info!(name = %name, "Cleaning up container on shutdown");
+            if let Err(e) = self.force_cleanup_container_full(&name).await {
+                warn!(name = %name, error = %e, "Failed to cleanup container on shutdown");
+            }
+        }
+
+        // Clear all state
+        self.state_db.clear_all()?;
+
+        info!("Shutdown complete");
+        Ok(())
+    }
+}
+
+/// A runner GitHub reports busy although its container looked finished
+#[derive(Debug, Clone, Error)]
+#[error("runner {runner} is busy with a job")]
+pub struct RunnerBusy {
+    pub runner: String,
+}
+
+/// A spawn request while an operator has paused dispatch or a maintenance
+/// window is in effect
+#[derive(Debug, Clone, Error)]
+#[error("dispatch is paused{}", reason.as_deref().map(|r| format!(": {}", r)).unwrap_or_default())]
+pub struct DispatchPaused {
+    pub reason: Option<String>,
+}
+
+/// A release request for a job without a runner from this controller
+#[derive(Debug, Clone, Error)]
+#[error("no runner for job {job_id}")]
+pub struct UnknownJob {
+    pub job_id: u64,
+}
+
+/// A job that no longer needs a runner from this controller
+#[derive(Debug, Clone, Error)]
+#[error("job {job_id} {reason}")]
+pub struct JobUnavailable {
+    pub job_id: u64,
+    pub reason: String,
+}
+
+/// Why a job should not get a new runner from us, if it shouldn't
+fn unavailable_reason(job: &WorkflowJob, own_runner: &str) -> Option<String> {
+    match job_claim(job, own_runner) {
+        JobClaim::Theirs(runner) => Some(format!("already claimed by runner {}", runner)),
+        JobClaim::Ours => None,
+        JobClaim::Unclaimed => match job.status.as_str() {
+            "queued" | "waiting" | "pending" | "requested" => None,
+            status => Some(format!("is no longer queued ({})", status)),
+        },
+    }
+}
+
+/// Who a workflow job has been assigned to, relative to our runner
+#[derive(Debug, PartialEq, Eq)]
+enum JobClaim {
+    Unclaimed,
+    Ours,
+    Theirs(String),
+}
+
+fn job_claim(job: &WorkflowJob, own_runner: &str) -> JobClaim {
+    match job.runner_name.as_deref() {
+        None | Some("") => JobClaim::Unclaimed,
+        Some(runner) if runner == own_runner => JobClaim::Ours,
+        Some(runner) => JobClaim::Theirs(runner.to_string()),
+    }
+}
+
+/// Timings of the token and container creation phases, also recorded as metrics
+fn spawn_timings(token: Duration, create: Duration) -> SpawnTimings {
+    metrics::histogram!("runner_controller_spawn_phase_seconds", "phase" => "token")
+        .record(token.as_secs_f64());
+    metrics::histogram!("runner_controller_spawn_phase_seconds", "phase" => "create")
+        .record(create.as_secs_f64());
+
+    SpawnTimings {
+        token_ms: Some(token.as_millis() as u64),
+        create_ms: Some(create.as_millis() as u64),
+        ..Default::default()
+    }
+}
+
+fn record_spawn_outcome(kind: &'static str, ok: bool) {
+    let outcome = if ok { "success" } else { "failure" };
+    metrics::counter!("runner_controller_spawns_total", "kind" => kind, "outcome" => outcome)
+        .increment(1);
+}
+
+/// Run a host command to completion, failing with its stderr
+async fn run_command(command: Vec<String>) -> Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// How the controller reacts to a failed operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FailureAction {
+    /// Likely to clear up by itself; a later poll tries again
+    Retry,
+    /// The job or runner is gone, so trying again can't succeed
+    Abort,
+    /// The host, the state database or the credentials need an operator
+    Alert,
+}
+
+/// Classify a failure by its typed cause. Anything unclassified alerts, so
+/// no kind of failure goes unnoticed.
+fn failure_action(error: &anyhow::Error) -> FailureAction {
+    // A full fleet has room again once runners finish
+    if let Some(BackendError::NoCapacity { .. }) = error.downcast_ref::<BackendError>() {
+        return FailureAction::Retry;
+    }
+    match error.downcast_ref::<GitHubError>() {
+        Some(GitHubError::RateLimited(_) | GitHubError::Transient(_)) => FailureAction::Retry,
+        Some(GitHubError::NotFound(_)) => FailureAction::Abort,
+        Some(
+            GitHubError::Unauthorized
+            | GitHubError::Rejected { .. }
+            | GitHubError::InvalidResponse(_),
+        ) => FailureAction::Alert,
+        // Backend and state failures (`BackendError`, `StateError`) need
+        // someone to look at the host
+        None => FailureAction::Alert,
+    }
+}
+
+fn record_throttled(throttled: &SpawnThrottled) {
+    let scope = if throttled.scope == "global" { "global" } else { "repo" };
+    metrics::counter!("runner_controller_spawns_throttled_total", "scope" => scope).increment(1);
+}
+
+/// Offline runners matching `prefix` that don't correspond to a local container.
+/// Runners not owned by this instance (see `RunnerNaming::owns`) are skipped.
+fn stale_runners<'a>(
+    runners: &'a [Runner],
+    local_containers: &HashSet<String>,
+    prefix: &str,
+    naming: &RunnerNaming,
+) -> Vec<&'a Runner> {
+    runners
+        .iter()
+        .filter(|r| r.status == "offline")
+        .filter(|r| r.name.starts_with(prefix))
+        .filter(|r| naming.owns(r))
+        .filter(|r| {
+            naming
+                .container_name(&r.name)
+                .is_some_and(|c| !local_containers.contains(c))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{BackendCall, BackendError, FakeBackend, ResourceUsage};
+    use crate::github::{BudgetStatus, FakeGitHub};
+
+    #[test]
+    fn test_failure_action() {
+        let action = |e: GitHubError| failure_action(&e.into());
+        assert_eq!(action(GitHubError::RateLimited(60)), FailureAction::Retry);
+        assert_eq!(action(GitHubError::NotFound("/x".to_string())), FailureAction::Abort);
+        assert_eq!(action(GitHubError::Unauthorized), FailureAction::Alert);
+        let rejected = GitHubError::Rejected {
+            status: 422,
+            message: "Validation Failed".to_string(),
+        };
+        assert_eq!(action(rejected), FailureAction::Alert);
+
+        // Typed causes survive added context
+        let gone = anyhow::Error::from(GitHubError::NotFound("/x".to_string()));
+        let gone = gone.context("deregister");
+        assert_eq!(failure_action(&gone), FailureAction::Abort);
+        assert_eq!(failure_action(&anyhow::anyhow!("disk full")), FailureAction::Alert);
+    }
+
+    #[test]
+    fn test_slot_to_container_name() {
+        assert_eq!(ContainerManager::slot_to_container_name(0), "r0");
+        assert_eq!(ContainerManager::slot_to_container_name(5), "r5");
+        assert_eq!(ContainerManager::slot_to_container_name(42), "r42");
+    }
+
+    #[test]
+    fn test_job_to_container_name() {
+        assert_eq!(ContainerManager::job_to_container_name(41234567890), "x4567890");
+        assert_eq!(ContainerManager::job_to_container_name(42), "x0000042");
+    }
+
+    #[test]
+    fn test_external_names_of_colliding_jobs() {
+        let Sim { mut controller, .. } = simulate("external-names", &[]);
+        let state = ContainerState::new_external(41234567890, Vec::new());
+        controller.state_db.put_container("x4567890", &state).unwrap();
+
+        // Job 51234567890 shares the last seven digits but gets its own container
+        assert_eq!(controller.external_container_name(51234567890).unwrap(), "x4567891");
+        assert_eq!(controller.external_container(41234567890).unwrap().unwrap(), "x4567890");
+        assert!(controller.external_container(51234567890).unwrap().is_none());
+
+        // Releasing the other job leaves this one's container alone
+        let (reply, rx) = oneshot::channel();
+        let release = ControlRequest::Release {
+            job_id: 51234567890,
+            reply,
+        };
+        tokio_test::block_on(controller.handle_control_request(release));
+        assert!(rx.blocking_recv().unwrap().unwrap_err().is::<UnknownJob>());
+        assert!(controller.state_db.get_container("x4567890").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_job_claim() {
+        let job = |runner: Option<&str>| WorkflowJob {
+            status: "in_progress".to_string(),
+            runner_name: runner.map(|r| r.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(job_claim(&job(None), "ci1-x0000001"), JobClaim::Unclaimed);
+        assert_eq!(job_claim(&job(Some("")), "ci1-x0000001"), JobClaim::Unclaimed);
+        assert_eq!(job_claim(&job(Some("ci1-x0000001")), "ci1-x0000001"), JobClaim::Ours);
+        assert_eq!(
+            job_claim(&job(Some("ci2-x0000001")), "ci1-x0000001"),
+            JobClaim::Theirs("ci2-x0000001".to_string())
+        );
+
+        let queued = WorkflowJob {
+            status: "queued".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(unavailable_reason(&queued, "ci1-x0000001"), None);
+        assert!(unavailable_reason(&job(None), "ci1-x0000001")
+            .unwrap()
+            .contains("no longer queued"));
+        assert!(unavailable_reason(&job(Some("ci2-x0000001")), "ci1-x0000001").is_some());
+    }
+
+    #[test]
+    fn test_reaper_needs_a_prefix() {
+        let Sim {
+            mut controller,
+            github,
+            ..
+        } = simulate("reaper-prefix", &[]);
+        github.add_runner("r5", &[]);
+        github.set_runner_offline("r5");
+
+        // `{container}` names runners alike on every host: another host's r5
+        // is no stale runner of ours
+        tokio_test::block_on(controller.reap_stale_runners()).unwrap();
+        assert!(github.deleted_runners().is_empty());
+
+        controller.config.reaper_name_prefix = Some("r".to_string());
+        tokio_test::block_on(controller.reap_stale_runners()).unwrap();
+        assert_eq!(github.deleted_runners(), ["r5"]);
+    }
+
+    #[test]
+    fn test_stale_runners() {
+        let runner = |id, name: &str, status: &str| Runner {
+            id,
+            name: name.to_string(),
+            status: status.to_string(),
+            ..Default::default()
+        };
+        let runners = vec![
+            runner(1, "r0", "offline"),
+            runner(2, "r1", "online"),
+            runner(3, "r2", "offline"),
+            runner(4, "other-host", "offline"),
+        ];
+        let local: HashSet<String> = ["r0".to_string()].into_iter().collect();
+
+        let naming = RunnerNaming::default();
+        let ids: Vec<u64> = stale_runners(&runners, &local, "r", &naming)
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec![3]);
+
+        let ids: Vec<u64> = stale_runners(&runners, &local, "", &naming)
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        // "other-host" doesn't follow the naming scheme
+        assert_eq!(ids, vec![3]);
+
+        // With a host prefix only this host's runners are considered
+        let naming = RunnerNaming::new("{hostname}-{container}", "hosta").unwrap();
+        let runners = vec![
+            runner(1, "hosta-r0", "offline"),
+            runner(2, "hosta-r2", "offline"),
+            runner(3, "hostb-r2", "offline"),
+        ];
+        let ids: Vec<u64> = stale_runners(&runners, &local, "", &naming)
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec![2]);
+        assert_eq!(naming.runner_name("x0000042"), "hosta-x0000042");
+
+        // With an instance label only runners carrying it are ours
+        let naming = RunnerNaming::default().with_instance("abc");
+        let tagged: Runner = serde_json::from_str(
+            r#"{"id": 1, "name": "r2", "status": "offline", "busy": false,
+                "labels": [{"name": "self-hosted"}, {"name": "rc-abc"}]}"#,
+        )
+        .unwrap();
+        let runners = vec![tagged, runner(2, "r3", "offline")];
+        let ids: Vec<u64> = stale_runners(&runners, &local, "", &naming)
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec![1]);
+    }
+
+    /// A controller on a fake GitHub and container backend, configured by
+    /// `vars` and keeping its state in a fresh directory
+    struct Sim {
+        controller: PoolController,
+        github: Arc<FakeGitHub>,
+        backend: Arc<FakeBackend>,
+    }
+
+    fn simulate(test: &str, vars: &[(&'static str, &str)]) -> Sim {
+        let dir = std::env::temp_dir()
+            .join(format!("runner-controller-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let token_file = dir.join("token");
+        std::fs::write(&token_file, "test-token").unwrap();
+
+        let mut vars: HashMap<&str, String> =
+            vars.iter().map(|(name, value)| (*name, value.to_string())).collect();
+        vars.insert("GITHUB_REPO", "owner/repo".to_string());
+        vars.insert("GITHUB_TOKEN_FILE", token_file.display().to_string());
+        vars.insert("STATE_DIR", dir.display().to_string());
+        let config = Config::from_vars(|name| vars.get(name).cloned()).unwrap();
+        let state_db = Arc::new(StateDb::open(&dir, config.state_durability).unwrap());
+        let naming = RunnerNaming::new(&config.runner_name_template, "host").unwrap();
+        let github = Arc::new(FakeGitHub::new());
+        let backend = Arc::new(FakeBackend::new(naming));
+        let notifier = Notifier::new(&config).unwrap();
+        let (_, shutdown_rx) = watch::channel(false);
+        let (_, control_rx) = mpsc::channel(1);
+        let controller = PoolController::new(
+            config,
+            github.clone(),
+            backend.clone(),
+            state_db,
+            shutdown_rx,
+            control_rx,
+            notifier,
+        );
+        Sim {
+            controller,
+            github,
+            backend,
+        }
+    }
+
+    fn job(id: u64, status: &str, runner_name: Option<&str>) -> WorkflowJob {
+        WorkflowJob {
+            id,
+            status: status.to_string(),
+            runner_name: runner_name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_claim_check() {
+        let Sim {
+            controller, github, ..
+        } = simulate("claim", &[]);
+        let own_runner = controller.containers.runner_name("x0000001");
+        github.add_job(job(1, "queued", None));
+        github.add_job(job(2, "in_progress", Some("elsewhere")));
+        github.add_job(job(3, "in_progress", Some(&own_runner)));
+        github.add_job(job(4, "completed", None));
+
+        let unavailable = |job_id: u64| {
+            let result = tokio_test::block_on(controller.claim_check(job_id, "x0000001"));
+            result.unwrap_err().downcast::<JobUnavailable>().unwrap().reason
+        };
+        assert!(tokio_test::block_on(controller.claim_check(1, "x0000001")).unwrap().is_some());
+        assert!(tokio_test::block_on(controller.claim_check(3, "x0000001")).unwrap().is_some());
+        assert_eq!(unavailable(2), "already claimed by runner elsewhere");
+        assert_eq!(unavailable(4), "is no longer queued (completed)");
+        assert_eq!(unavailable(5), "does not exist");
+
+        // A lookup failure doesn't block the spawn
+        github.fail_next(GitHubError::Transient("timeout".to_string()));
+        assert!(tokio_test::block_on(controller.claim_check(2, "x0000001")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_duplicate_claims() {
+        let Sim {
+            controller,
+            github,
+            backend,
+        } = simulate("duplicates", &[]);
+        let own_runner = controller.containers.runner_name("x0000001");
+        github.add_runner(&own_runner, &[]);
+        github.add_job(job(1, "in_progress", Some("elsewhere")));
+        backend.insert("x0000001");
+        let state = ContainerState::new_external(1, Vec::new());
+        controller.state_db.put_container("x0000001", &state).unwrap();
+
+        // Our runner got another job with the same labels: not a duplicate
+        github.set_runner_busy(&own_runner, true);
+        assert!(!tokio_test::block_on(controller.check_job_claim("x0000001")).unwrap());
+        assert_eq!(backend.containers(), ["x0000001"]);
+        let state = controller.state_db.get_container("x0000001").unwrap().unwrap();
+        assert!(state.claim_confirmed);
+
+        let state = ContainerState::new_external(1, Vec::new());
+        controller.state_db.put_container("x0000001", &state).unwrap();
+        github.set_runner_busy(&own_runner, false);
+        assert!(tokio_test::block_on(controller.check_job_claim("x0000001")).unwrap());
+        assert!(backend.containers().is_empty());
+    }
+
+    #[test]
+    fn test_skipped_jobs_keep_the_spawn_budget() {
+        let Sim {
+            mut controller,
+            github,
+            ..
+        } = simulate("spawn-budget", &[("MAX_JOBS_PER_HOUR", "1")]);
+        github.add_job(job(1, "completed", None));
+        github.add_job(job(2, "queued", None));
+        github.add_job(job(3, "queued", None));
+        let mut spawn = |job_id: u64| {
+            let (reply, rx) = oneshot::channel();
+            let job = JobContext {
+                job_id,
+                ..Default::default()
+            };
+            let labels = Vec::new();
+            let request = ControlRequest::Spawn { job, labels, reply };
+            tokio_test::block_on(controller.handle_control_request(request));
+            rx.blocking_recv().unwrap()
+        };
+
+        // Only the last look before the runner registers sees the job is done
+        github.fail_next(GitHubError::Transient("timeout".to_string()));
+        assert!(spawn(1).unwrap_err().is::<JobUnavailable>());
+        assert_eq!(spawn(2).unwrap(), "x0000002");
+        assert!(spawn(3).unwrap_err().is::<SpawnThrottled>());
+    }
+
+    #[test]
+    fn test_claim_jitter_is_scheduled() {
+        let Sim {
+            mut controller,
+            github,
+            backend,
+        } = simulate("claim-jitter", &[("CLAIM_JITTER_MS", "5000")]);
+        github.add_job(job(7, "queued", None));
+        let (reply, mut rx) = oneshot::channel();
+        let job = JobContext {
+            job_id: 7,
+            ..Default::default()
+        };
+        let spawn = ControlRequest::Spawn {
+            job,
+            labels: Vec::new(),
+            reply,
+        };
+
+        tokio_test::block_on(async {
+            tokio::time::pause();
+            // The request waits out its jitter without holding up the loop
+            controller.handle_control_request(spawn).await;
+            assert!(rx.try_recv().is_err());
+            assert!(backend.calls().is_empty());
+
+            let request = controller.claim_delays.join_next().await.unwrap().unwrap();
+            controller.spawn_requested(request).await;
+        });
+        assert_eq!(rx.try_recv().unwrap().unwrap(), "x0000007");
+    }
+
+    #[test]
+    fn test_retry_cleanups() {
+        let Sim {
+            controller, github, ..
+        } = simulate("cleanups", &[]);
+        github.add_runner("r3", &["self-hosted"]);
+        github.add_runner("r4", &["self-hosted"]);
+        let queue = |target: &str| {
+            let mut cleanup = PendingCleanup::new(CleanupKind::Deregister, target, "failed".into());
+            cleanup.next_attempt_at = 0;
+            controller.state_db.put_cleanup(&cleanup).unwrap();
+        };
+
+        // Still failing: the retry is kept with another attempt counted
+        queue("r3");
+        github.fail_next(GitHubError::Transient("502 Bad Gateway".to_string()));
+        tokio_test::block_on(controller.retry_cleanups()).unwrap();
+        let pending = controller.state_db.list_cleanups().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].attempts, 2);
+        assert!(github.deleted_runners().is_empty());
+
+        // Succeeding, and gone for good: both retries are dropped
+        queue("r3");
+        queue("r4");
+        github.fail_next(GitHubError::NotFound("/runners".to_string()));
+        tokio_test::block_on(controller.retry_cleanups()).unwrap();
+        assert!(controller.state_db.list_cleanups().unwrap().is_empty());
+        assert_eq!(github.deleted_runners().len(), 1);
+        assert_eq!(github.runners().len(), 1);
+    }
+
+    #[test]
+    fn test_pool_reconciliation() {
+        let Sim {
+            controller,
+            backend,
+            ..
+        } = simulate("pool", &[("MAX_CONCURRENT", "2")]);
+        let spawned = |name: &str| controller.state_db.get_container(name).unwrap().is_some();
+
+        // Finished runners left by a previous run are cleaned up on startup
+        backend.insert("r7");
+        backend.complete("r7");
+        backend.insert("x0000042");
+        tokio_test::block_on(controller.reconcile_on_startup()).unwrap();
+        assert_eq!(backend.containers(), ["x0000042"]);
+
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0", "r1", "x0000042"]);
+        assert!(spawned("r0") && spawned("r1"));
+        assert_eq!(backend.token("r1").as_deref(), Some("fake-registration-token-2"));
+
+        // A finished runner is replaced; a failed respawn is retried next cycle
+        backend.complete("r0");
+        backend.complete("r1");
+        backend.fail_next_spawn(BackendError::Command {
+            command: "machinectl start r0".to_string(),
+            stderr: "timed out".to_string(),
+        });
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r1", "x0000042"]);
+        assert!(!spawned("r0") && spawned("r1"));
+
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0", "r1", "x0000042"]);
+        let spawns = backend
+            .calls()
+            .iter()
+            .filter(|c| matches!(c, BackendCall::Spawn(_)))
+            .count();
+        assert_eq!(spawns, 5);
+    }
+
+    #[test]
+    fn test_refused_respawn_takes_no_budget() {
+        let Sim {
+            controller,
+            github,
+            backend,
+        } = simulate(
+            "refused-respawn",
+            &[("MAX_CONCURRENT", "1"), ("PRIVATE_REPOS_ONLY", "true"), ("MAX_JOBS_PER_HOUR", "2")],
+        );
+        let visibility = |visibility: &str| RepoMetadata {
+            visibility: visibility.to_string(),
+            private: visibility == "private",
+            default_branch: "main".to_string(),
+            actions_enabled: Some(true),
+            fetched_at: unix_now(),
+        };
+        let repo = controller.config.github_repo.clone();
+        github.set_repo_metadata(&repo, visibility("private"));
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0"]);
+
+        // Refused while public, without spending the second spawn of the hour
+        github.set_repo_metadata(&repo, visibility("public"));
+        backend.complete("r0");
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert!(backend.containers().is_empty());
+        github.set_repo_metadata(&repo, visibility("private"));
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0"]);
+    }
+
+    #[test]
+    fn test_reuse_takes_spawn_budget() {
+        let vars = [
+            ("MAX_CONCURRENT", "1"),
+            ("CONTAINER_REUSE_JOBS", "5"),
+            ("MAX_JOBS_PER_HOUR", "2"),
+        ];
+        let Sim { controller, backend, .. } = simulate("reuse-budget", &vars);
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        backend.complete("r0");
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(
+            backend.calls(),
+            [BackendCall::Spawn("r0".into()), BackendCall::Reregister("r0".into())]
+        );
+
+        // With the hourly budget spent the container is recycled, not
+        // registered again, and the slot waits
+        backend.complete("r0");
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.calls().last(), Some(&BackendCall::Cleanup("r0".into())));
+        assert!(backend.containers().is_empty());
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let Sim {
+            mut controller,
+            backend,
+            ..
+        } = simulate("pause", &[("MAX_CONCURRENT", "2")]);
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0", "r1"]);
+
+        // Runners already up stay, but aren't replaced, and spawn requests
+        // are refused
+        let (reply, rx) = oneshot::channel();
+        let pause = ControlRequest::Pause {
+            reason: Some("kernel update".to_string()),
+            reply,
+        };
+        tokio_test::block_on(controller.handle_control_request(pause));
+        assert!(rx.blocking_recv().unwrap().unwrap().reason.is_some());
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0", "r1"]);
+        backend.complete("r0");
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r1"]);
+
+        let (reply, rx) = oneshot::channel();
+        let job = JobContext {
+            job_id: 7,
+            ..Default::default()
+        };
+        let labels = Vec::new();
+        let spawn = ControlRequest::Spawn { job, labels, reply };
+        tokio_test::block_on(controller.handle_control_request(spawn));
+        assert!(rx.blocking_recv().unwrap().unwrap_err().is::<DispatchPaused>());
+
+        // The pause outlives the controller until resumed
+        let mut controller = PoolController::new(
+            controller.config.clone(),
+            controller.github.clone(),
+            controller.containers.clone(),
+            controller.state_db.clone(),
+            watch::channel(false).1,
+            mpsc::channel(1).1,
+            controller.notifier.clone(),
+        );
+        assert!(controller.paused.is_some());
+        let (reply, rx) = oneshot::channel();
+        tokio_test::block_on(controller.handle_control_request(ControlRequest::Resume { reply }));
+        rx.blocking_recv().unwrap().unwrap();
+        assert_eq!(backend.containers(), ["r0", "r1"]);
+        assert!(controller.state_db.get_pause().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_maintenance_window() {
+        let Sim {
+            mut controller,
+            backend,
+            ..
+        } = simulate(
+            "maintenance",
+            &[
+                ("MAX_CONCURRENT", "2"),
+                ("MAINTENANCE_WINDOWS", "mon-sun 00-24"),
+                ("STARTUP_TIMEOUT", "60"),
+            ],
+        );
+        let windows = controller.config.maintenance_windows.clone();
+        controller.update_maintenance();
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert!(backend.containers().is_empty());
+
+        let (reply, rx) = oneshot::channel();
+        let labels = Vec::new();
+        let spawn = ControlRequest::Spawn {
+            job: JobContext::default(),
+            labels,
+            reply,
+        };
+        tokio_test::block_on(controller.handle_control_request(spawn));
+        let err = rx.blocking_recv().unwrap().unwrap_err();
+        assert!(err.to_string().contains("maintenance window mon-sun 00-24"));
+
+        controller.config.maintenance_windows.clear();
+        controller.update_maintenance();
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0", "r1"]);
+
+        // A runner missing its startup timeout once a window opened isn't
+        // replaced
+        let mut state = controller.state_db.get_container("r0").unwrap().unwrap();
+        state.started_at = unix_now() - 120;
+        state.boot_id = None;
+        state.started_monotonic = None;
+        controller.state_db.put_container("r0", &state).unwrap();
+        controller.config.maintenance_windows = windows;
+        controller.update_maintenance();
+        tokio_test::block_on(controller.track_runner_startup()).unwrap();
+        assert_eq!(backend.containers(), ["r1"]);
+    }
+
+    #[test]
+    fn test_job_timeout() {
+        let vars = [("MAX_CONCURRENT", "1"), ("JOB_TIMEOUT", "60")];
+        let Sim {
+            controller,
+            backend,
+            ..
+        } = simulate("timeout", &vars);
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        let mut state = controller.state_db.get_container("r0").unwrap().unwrap();
+        // Healthy within the timeout
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.calls(), [BackendCall::Spawn("r0".to_string())]);
+
+        state.started_at = unix_now() - 120;
+        state.boot_id = None;
+        state.started_monotonic = None;
+        controller.state_db.put_container("r0", &state).unwrap();
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(
+            backend.calls()[1..],
+            [
+                BackendCall::Cleanup("r0".to_string()),
+                BackendCall::Spawn("r0".to_string()),
+            ]
+        );
+        let state = controller.state_db.get_container("r0").unwrap().unwrap();
+        assert!(state.running_seconds() < 60);
+    }
+
+    #[test]
+    fn test_disk_gc() {
+        let marker = std::env::temp_dir().join(format!("disk-gc-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let command = format!("touch {}", marker.display());
+        // Any filesystem has less than a petabyte free
+        let vars = [("DISK_GC_THRESHOLD_GB", "1000000"), ("DISK_GC_COMMAND", command.as_str())];
+        let Sim { mut controller, .. } = simulate("disk-gc", &vars);
+
+        tokio_test::block_on(async {
+            controller.maintain_disk().await;
+            while !controller.gc_task.as_ref().is_some_and(|t| t.is_finished()) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            // The finished run is recorded on the next pass, and not started
+            // again within DISK_GC_INTERVAL
+            std::fs::remove_file(&marker).unwrap();
+            controller.maintain_disk().await;
+            assert!(controller.gc_task.is_none());
+            assert!(!marker.exists());
+        });
+        let query = LogQuery {
+            event: Some("disk_gc".to_string()),
+            ..Default::default()
+        };
+        let events = controller.state_db.query_history(&query, |_| true).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].detail, None);
+    }
+
+    #[test]
+    fn test_startup_timeout() {
+        let vars = [("MAX_CONCURRENT", "2"), ("STARTUP_TIMEOUT", "60")];
+        let Sim {
+            controller,
+            github,
+            backend,
+        } = simulate("startup-timeout", &vars);
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        backend.insert("x0000001");
+        let external = ContainerState::new_external(1, Vec::new());
+        controller.state_db.put_container("x0000001", &external).unwrap();
+        for name in ["r0", "r1", "x0000001"] {
+            let mut state = controller.state_db.get_container(name).unwrap().unwrap();
+            state.started_at = unix_now() - 120;
+            state.boot_id = None;
+            state.started_monotonic = None;
+            controller.state_db.put_container(name, &state).unwrap();
+        }
+        github.add_runner(&controller.containers.runner_name("r1"), &[]);
+
+        // The pool slot is respawned, the external container removed, and the
+        // runner that came online kept
+        tokio_test::block_on(controller.track_runner_startup()).unwrap();
+        assert_eq!(
+            backend.calls()[2..],
+            [
+                BackendCall::Cleanup("r0".to_string()),
+                BackendCall::Spawn("r0".to_string()),
+                BackendCall::Cleanup("x0000001".to_string()),
+            ]
+        );
+        let r1 = controller.state_db.get_container("r1").unwrap().unwrap();
+        assert!(r1.timings.registered_after_secs.is_some());
+        let query = LogQuery {
+            event: Some("startup_timeout".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(controller.state_db.query_history(&query, |_| true).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_job_token_refresh() {
+        let Sim {
+            controller,
+            github,
+            backend,
+        } = simulate("job-token-refresh", &[("MAX_CONCURRENT", "1")]);
+        github.enable_job_tokens();
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.file_mode("r0", JOB_TOKEN_PATH), Some(FileMode::Runner));
+
+        // Fresh tokens are left alone; one about to expire is replaced and revoked
+        tokio_test::block_on(controller.refresh_job_tokens()).unwrap();
+        assert!(github.revoked_tokens().is_empty());
+        let mut state = controller.state_db.get_container("r0").unwrap().unwrap();
+        state.job_token_minted_at = Some(unix_now() - JOB_TOKEN_REFRESH_SECS - 1);
+        controller.state_db.put_container("r0", &state).unwrap();
+        tokio_test::block_on(controller.refresh_job_tokens()).unwrap();
+        let token = backend.read_container_file("r0", JOB_TOKEN_PATH);
+        assert_eq!(token.as_deref(), Some("fake-job-token-2"));
+        assert_eq!(backend.file_mode("r0", JOB_TOKEN_PATH), Some(FileMode::Runner));
+        assert_eq!(github.revoked_tokens(), ["fake-job-token-1"]);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let Sim {
+            controller,
+            backend,
+            ..
+        } = simulate("shutdown", &[("MAX_CONCURRENT", "2")]);
+        backend.set_delay(Duration::from_millis(20));
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        let state = controller.state_db.get_container("r0").unwrap().unwrap();
+        assert!(state.timings.create_ms.is_some_and(|ms| ms >= 20));
+
+        // One container failing to go away doesn't stop the others
+        backend.insert("j1");
+        backend.fail_next_cleanup(BackendError::Command {
+            command: "nixos-container destroy j1".to_string(),
+            stderr: "busy".to_string(),
+        });
+        tokio_test::block_on(controller.shutdown()).unwrap();
+        assert_eq!(backend.containers(), ["j1"]);
+        assert!(controller.state_db.list_containers().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_busy_runner_not_cleaned_up() {
+        let vars = [("MAX_CONCURRENT", "1"), ("JOB_TIMEOUT", "60")];
+        let Sim {
+            controller,
+            github,
+            backend,
+        } = simulate("busy", &vars);
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        let runner = controller.containers.runner_name("r0");
+        github.add_runner(&runner, &["self-hosted"]);
+        github.set_runner_busy(&runner, true);
+
+        // Completion detection misfires while the runner still runs a job
+        backend.complete("r0");
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.calls(), [BackendCall::Spawn("r0".to_string())]);
+        assert!(github.deleted_runners().is_empty());
+
+        // Past the job timeout the container goes anyway
+        let mut state = controller.state_db.get_container("r0").unwrap().unwrap();
+        state.started_at = unix_now() - 120;
+        state.boot_id = None;
+        state.started_monotonic = None;
+        controller.state_db.put_container("r0", &state).unwrap();
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.calls()[1], BackendCall::Cleanup("r0".to_string()));
+        assert_eq!(github.deleted_runners(), [runner]);
+    }
+
+    #[test]
+    fn test_job_conclusion_recorded() {
+        let Sim {
+            controller,
+            github,
+            backend,
+        } = simulate("conclusion", &[("MAX_CONCURRENT", "1")]);
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        let mut state = controller.state_db.get_container("r0").unwrap().unwrap();
+        state.job_id = Some(7);
+        state.workflow_name = Some("CI".to_string());
+        controller.state_db.put_container("r0", &state).unwrap();
+        let usage = ResourceUsage {
+            cpu_seconds: Some(95.5),
+            ..Default::default()
+        };
+        backend.set_resource_usage("r0", usage);
+        github.add_job(WorkflowJob {
+            conclusion: Some("failure".to_string()),
+            ..job(7, "completed", None)
+        });
+
+        backend.complete("r0");
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        let query = LogQuery {
+            event: Some("cleaned_up".to_string()),
+            ..Default::default()
+        };
+        let history = controller.state_db.query_history(&query, |_| true).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].conclusion.as_deref(), Some("failure"));
+        assert_eq!(history[0].repository.as_deref(), Some("owner/repo"));
+        assert_eq!(history[0].workflow.as_deref(), Some("CI"));
+        assert_eq!(history[0].cpu_seconds, Some(95.5));
+    }
+
+    #[test]
+    fn test_parallel_spawns() {
+        let Sim {
+            controller,
+            backend,
+            ..
+        } = simulate("parallel", &[("MAX_CONCURRENT", "4"), ("SPAWN_CONCURRENCY", "4")]);
+        backend.set_delay(Duration::from_secs(10));
+
+        // On a paused clock, four 10s spawns one after another would take 40s
+        tokio_test::block_on(async {
+            tokio::time::pause();
+            let started = tokio::time::Instant::now();
+            controller.maintain_pool().await.unwrap();
+            assert!(started.elapsed() < Duration::from_secs(20), "spawns ran one after another");
+        });
+        assert_eq!(backend.containers(), ["r0", "r1", "r2", "r3"]);
+
+        // The hourly budget is checked per spawn: two go ahead, the rest wait
+        let Sim {
+            controller,
+            backend,
+            ..
+        } = simulate(
+            "parallel-budget",
+            &[("MAX_CONCURRENT", "4"), ("SPAWN_CONCURRENCY", "4"), ("MAX_JOBS_PER_HOUR", "2")],
+        );
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0", "r1"]);
+    }
+
+    #[test]
+    fn test_reconcile_interval() {
+        let Sim { mut controller, .. } = simulate("reconcile", &[("RECONCILE_INTERVAL", "3600")]);
+        assert!(controller.reconcile_due());
+        assert!(!controller.reconcile_due());
+
+        // 0 reconciles on every poll
+        let Sim { mut controller, .. } = simulate("reconcile-0", &[("RECONCILE_INTERVAL", "0")]);
+        assert!(controller.reconcile_due());
+        assert!(controller.reconcile_due());
+
+        // A tight rate limit budget stretches the intervals
+        let Sim {
+            mut controller,
+            github,
+            ..
+        } = simulate("reconcile-budget", &[("RECONCILE_INTERVAL", "0")]);
+        github.set_rate_budget(Some(BudgetStatus {
+            limit: 5000,
+            remaining: 600,
+            reset_at: unix_now() + 1800,
+            poll_stretch: 4.0,
+            spent: Default::default(),
+        }));
+        assert_eq!(controller.poll_stretch(), 4.0);
+        assert!(controller.poll_stretched);
+    }
+
+    #[test]
+    fn test_pool_env() {
+        let Sim {
+            controller,
+            backend,
+            ..
+        } = simulate("pool-env", &[("MAX_CONCURRENT", "1"), ("POOL_ENV", "SCCACHE_BUCKET=ci")]);
+        // Seeded on startup as by the binary, so the stored spec carries the env
+        let configured = PoolSpec::from_config(&controller.config);
+        let spec = controller.state_db.seed_pool_spec(DEFAULT_POOL, configured, true).unwrap();
+        assert_eq!(spec.env.len(), 1);
+        assert!(controller.state_db.get_pool_spec(DEFAULT_POOL).unwrap().is_some());
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        let env = backend.read_container_file("r0", "var/lib/github-runner-env");
+        assert_eq!(env.as_deref(), Some("SCCACHE_BUCKET='ci'\n"));
+
+        // Secrets must be readable before the pool accepts them
+        let update = PoolUpdate {
+            secrets: Some(vec!["missing".to_string()]),
+            ..Default::default()
+        };
+        assert!(controller.update_pool_spec(update).is_err());
+    }
+
+    #[test]
+    fn test_runner_upgrade() {
+        let Sim {
+            mut controller,
+            github,
+            backend,
+        } = simulate(
+            "runner-upgrade",
+            &[
+                ("MAX_CONCURRENT", "1"),
+                ("RUNNER_VERSION", "2.320.0"),
+                ("RUNNER_AUTO_UPGRADE", "true"),
+            ],
+        );
+        github.add_runner_release("2.320.0", unix_now() - 60 * 86400);
+        github.add_runner_release("2.321.0", unix_now() - 86400);
+
+        tokio_test::block_on(controller.check_runner_version()).unwrap();
+        let status = controller.state_db.get_runner_version().unwrap().unwrap();
+        assert_eq!(status.latest, "2.321.0");
+        assert!(status.upgrade_available() && !status.is_refused(unix_now()));
+
+        // New runners may update themselves
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert!(backend.read_container_file("r0", RUNNER_ALLOW_UPDATE_PATH).is_some());
+    }
+
+    #[test]
+    fn test_queue_starvation_event() {
+        let Sim {
+            mut controller,
+            github,
+            ..
+        } = simulate("starvation", &[("STARVATION_CYCLES", "1")]);
+        github.add_runner(&controller.containers.runner_name("r0"), &["self-hosted"]);
+        let mut queued = job(9, "queued", None);
+        queued.name = "build".to_string();
+
+        tokio_test::block_on(controller.check_starvation(&[queued])).unwrap();
+        let query = LogQuery {
+            event: Some("queue_starvation".to_string()),
+            ..Default::default()
+        };
+        let entries = controller.state_db.query_history(&query, |_| true).unwrap();
+        // Recorded against the repository, not as if the job were a container
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].container, None);
+        assert_eq!(entries[0].repository.as_deref(), Some("owner/repo"));
+    }
+
+    #[test]
+    fn test_unmatched_jobs() {
+        let Sim {
+            mut controller,
+            github,
+            ..
+        } = simulate("unmatched", &[("UNMATCHED_QUEUE_CYCLES", "2")]);
+        let queued = |id: u64, labels: &[&str]| WorkflowJob {
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            ..job(id, "queued", None)
+        };
+        github.add_runner("other-host", &["self-hosted", "gpu"]);
+        github.add_job(queued(1, &["self-hosted", "nix"]));
+        github.add_job(queued(2, &["self-hosted", "gpu"]));
+        github.add_job(queued(3, &["self-hosted", "arm64"]));
+
+        tokio_test::block_on(controller.check_queue()).unwrap();
+        assert!(controller.state_db.list_unmatched_jobs().unwrap().is_empty());
+        tokio_test::block_on(controller.check_queue()).unwrap();
+        let unmatched = controller.state_db.list_unmatched_jobs().unwrap();
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!((unmatched[0].job_id, unmatched[0].cycles), (3, 2));
+
+        // Cancelled: the warning goes away
+        github.set_job_status(3, "completed", None);
+        tokio_test::block_on(controller.check_queue()).unwrap();
+        assert!(controller.state_db.list_unmatched_jobs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_arch_pool() {
+        let foreign = match Arch::native() {
+            Arch::X64 => Arch::Arm64,
+            Arch::Arm64 => Arch::X64,
+        };
+        let template = format!("{}=/etc/nixos/ci-foreign.nix", foreign);
+        let size = format!("{}=1", foreign);
+        let vars = [
+            ("MAX_CONCURRENT", "1"),
+            ("ARCH_TEMPLATES", template.as_str()),
+            ("ARCH_POOL_SIZES", size.as_str()),
+        ];
+        let Sim {
+            controller,
+            backend,
+            ..
+        } = simulate("arch-pool", &vars);
+
+        tokio_test::block_on(controller.maintain_pool()).unwrap();
+        assert_eq!(backend.containers(), ["r0", "r1000"]);
+        let state = controller.state_db.get_container("r1000").unwrap().unwrap();
+        assert_eq!(Arch::of(&state.labels), Some(foreign));
+        assert_eq!(state.template.as_deref(), Some("/etc/nixos/ci-foreign.nix"));
+        let native = controller.state_db.get_container("r0").unwrap().unwrap();
+        assert_ne!(Arch::of(&native.labels), Some(foreign));
+    }
+
+    #[test]
+    fn test_provision_failure_status() {
+        let vars = [
+            ("PROVISION_FAILURE_ANNOTATION", "status"),
+            ("PROVISION_FAILURE_ATTEMPTS", "2"),
+        ];
+        let Sim {
+            mut controller,
+            github,
+            ..
+        } = simulate("provision-failure", &vars);
+        github.add_job(WorkflowJob {
+            head_sha: "abc123".to_string(),
+            ..job(7, "queued", None)
+        });
+
+        tokio_test::block_on(async {
+            controller.annotate_provision_failure(7, "no space left").await;
+            assert!(github.commit_statuses().is_empty());
+            controller.annotate_provision_failure(7, "no space left").await;
+            controller.resolve_provision_failures(7).await;
+        });
+        let states: Vec<_> = github
+            .commit_statuses()
+            .into_iter()
+            .map(|(sha, state, _)| (sha, state))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                ("abc123".to_string(), "error".to_string()),
+                ("abc123".to_string(), "success".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_finished_runner_refilled_between_polls() {
+        let vars = [
+            ("MAX_CONCURRENT", "2"),
+            ("POLL_INTERVAL", "3600"),
+            ("CONTAINER_WATCH_INTERVAL", "1"),
+        ];
+        let Sim {
+            mut controller,
+            backend,
+            ..
+        } = simulate("watcher", &vars);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        controller.shutdown_rx = shutdown_rx;
+
+        // On a paused clock the watcher ticks exactly once a second, with the
+        // next poll an hour away
+        tokio_test::block_on(async {
+            tokio::time::pause();
+            let driver = tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                backend.complete("r0");
+                tokio::time::sleep(Duration::from_millis(2500)).await;
+                shutdown_tx.send(true).unwrap();
+                backend.calls()
+            });
+            let exit = controller.serve().await.unwrap();
+            assert!(matches!(exit, RunExit::Shutdown));
+
+            // The watcher saw the runner finish long before the next poll
+            let calls = driver.await.unwrap();
+            let respawn = [BackendCall::Cleanup("r0".into()), BackendCall::Spawn("r0".into())];
+            assert!(calls.ends_with(&respawn), "{:?}", calls);
+        });
+    }
+}
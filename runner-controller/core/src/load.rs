@@ -0,0 +1,242 @@
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::debug;
+use utoipa::ToSchema;
+
+/// Host resource thresholds; new containers are deferred while any is exceeded
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadLimits {
+    /// 1-minute load average divided by the number of CPUs
+    pub max_load_per_cpu: Option<f64>,
+    pub min_free_memory_mb: Option<u64>,
+    pub min_free_disk_gb: Option<u64>,
+    /// Filesystems checked against `min_free_disk_gb`
+    pub disk_paths: Vec<PathBuf>,
+}
+
+/// Garbage collection run when free space on a monitored filesystem drops
+/// below a threshold, ideally above `min_free_disk_gb` so space is reclaimed
+/// before spawns are deferred
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskGc {
+    pub threshold_gb: u64,
+    /// Program and arguments, e.g. `nix-collect-garbage --delete-older-than 7d`
+    pub command: Vec<String>,
+    /// Minimum time between runs
+    pub interval: Duration,
+}
+
+impl DiskGc {
+    /// The first filesystem below the threshold, if any
+    pub fn needed<'a>(&self, usage: &'a [DiskUsage]) -> Option<&'a DiskUsage> {
+        usage
+            .iter()
+            .find(|d| d.free_bytes < self.threshold_gb * GIB)
+    }
+}
+
+/// Free and total space of the filesystem containing `path`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DiskUsage {
+    #[schema(value_type = String)]
+    pub path: PathBuf,
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+}
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// Usage of the filesystems containing `paths`; paths that can't be read are skipped
+pub fn disk_usage(paths: &[PathBuf]) -> Vec<DiskUsage> {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let (free_bytes, total_bytes) = statvfs(path)?;
+            Some(DiskUsage {
+                path: path.clone(),
+                free_bytes,
+                total_bytes,
+            })
+        })
+        .collect()
+}
+
+/// Point-in-time host resource usage
+#[derive(Debug, Clone, Default)]
+pub struct HostLoad {
+    pub load1: Option<f64>,
+    pub cpus: usize,
+    pub mem_available_mb: Option<u64>,
+    pub disk_free_gb: Vec<(PathBuf, u64)>,
+}
+
+impl HostLoad {
+    /// Sample the local host, including free space on `disk_paths`
+    pub fn sample(disk_paths: &[PathBuf]) -> Self {
+        let load1 = std::fs::read_to_string("/proc/loadavg")
+            .ok()
+            .and_then(|s| parse_load1(&s));
+
+        let mem_available_mb = std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|s| parse_mem_available_mb(&s));
+
+        let cpus = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+
+        let disk_free_gb = disk_usage(disk_paths)
+            .into_iter()
+            .map(|d| (d.path, d.free_bytes / GIB))
+            .collect();
+
+        Self {
+            load1,
+            cpus,
+            mem_available_mb,
+            disk_free_gb,
+        }
+    }
+}
+
+impl LoadLimits {
+    pub fn is_enabled(&self) -> bool {
+        self.max_load_per_cpu.is_some()
+            || self.min_free_memory_mb.is_some()
+            || self.min_free_disk_gb.is_some()
+    }
+
+    /// Sample the host and describe the first exceeded threshold, if any
+    pub fn saturation(&self) -> Option<String> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let load = HostLoad::sample(&self.disk_paths);
+        debug!(load = ?load, "Sampled host load");
+        self.check(&load)
+    }
+
+    /// Describe the first threshold exceeded by `load`, if any
+    pub fn check(&self, load: &HostLoad) -> Option<String> {
+        if let (Some(max), Some(load1)) = (self.max_load_per_cpu, load.load1) {
+            let per_cpu = load1 / load.cpus.max(1) as f64;
+            if per_cpu > max {
+                return Some(format!(
+                    "load average {:.2} per CPU exceeds {:.2}",
+                    per_cpu, max
+                ));
+            }
+        }
+
+        if let (Some(min), Some(free)) = (self.min_free_memory_mb, load.mem_available_mb) {
+            if free < min {
+                return Some(format!("{} MB memory available, need {} MB", free, min));
+            }
+        }
+
+        if let Some(min) = self.min_free_disk_gb {
+            for (path, free) in &load.disk_free_gb {
+                if *free < min {
+                    return Some(format!(
+                        "{} GB free on {}, need {} GB",
+                        free,
+                        path.display(),
+                        min
+                    ));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// First field of /proc/loadavg
+fn parse_load1(loadavg: &str) -> Option<f64> {
+    loadavg.split_whitespace().next()?.parse().ok()
+}
+
+/// `MemAvailable` from /proc/meminfo in MiB
+fn parse_mem_available_mb(meminfo: &str) -> Option<u64> {
+    let kb: u64 = meminfo
+        .lines()
+        .find(|l| l.starts_with("MemAvailable:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()?;
+
+    Some(kb / 1024)
+}
+
+/// Bytes available to unprivileged users and total bytes of the filesystem containing `path`
+fn statvfs(path: &Path) -> Option<(u64, u64)> {
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    // SAFETY: c_path is NUL-terminated and stat is a valid out-pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    let block = stat.f_frsize as u64;
+    Some((stat.f_bavail as u64 * block, stat.f_blocks as u64 * block))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_thresholds() {
+        assert_eq!(parse_load1("3.50 2.00 1.00 2/300 1234\n"), Some(3.5));
+        assert_eq!(
+            parse_mem_available_mb("MemTotal: 100 kB\nMemAvailable:    2097152 kB\n"),
+            Some(2048)
+        );
+
+        let limits = LoadLimits {
+            max_load_per_cpu: Some(1.5),
+            min_free_memory_mb: Some(1024),
+            min_free_disk_gb: Some(20),
+            disk_paths: vec![],
+        };
+        let load = HostLoad {
+            load1: Some(4.0),
+            cpus: 4,
+            mem_available_mb: Some(4096),
+            disk_free_gb: vec![(PathBuf::from("/nix/store"), 50)],
+        };
+        assert_eq!(limits.check(&load), None);
+
+        let busy = HostLoad {
+            load1: Some(8.0),
+            ..load.clone()
+        };
+        assert!(limits.check(&busy).unwrap().contains("load average"));
+
+        let full = HostLoad {
+            disk_free_gb: vec![(PathBuf::from("/nix/store"), 5)],
+            ..load
+        };
+        assert!(limits.check(&full).unwrap().contains("/nix/store"));
+
+        let gc = DiskGc {
+            threshold_gb: 30,
+            command: vec!["nix-collect-garbage".to_string()],
+            interval: Duration::from_secs(3600),
+        };
+        let usage = |path: &str, free_gb| DiskUsage {
+            path: PathBuf::from(path),
+            free_bytes: free_gb * GIB,
+            total_bytes: 100 * GIB,
+        };
+        assert!(gc.needed(&[usage("/nix/store", 50)]).is_none());
+        let low = [usage("/var/lib/runner-controller", 50), usage("/nix/store", 10)];
+        assert_eq!(gc.needed(&low).unwrap().path, PathBuf::from("/nix/store"));
+    }
+}
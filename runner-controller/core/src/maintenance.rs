@@ -0,0 +1,199 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer};
+
+use crate::prewarm::DAYS;
+
+const DAY_MINUTES: u32 = 24 * 60;
+const WEEK_MINUTES: u32 = 7 * DAY_MINUTES;
+
+/// Minute of the week of a unix timestamp, 0 being Monday 00:00 UTC
+fn minute_of_week(timestamp: u64) -> u32 {
+    let minutes = timestamp / 60;
+    let day_minutes = u64::from(DAY_MINUTES);
+    // 1970-01-01 was a Thursday
+    let weekday = (minutes / day_minutes + 3) % 7;
+    (weekday * day_minutes + minutes % day_minutes) as u32
+}
+
+/// Weekly period in which no runners are spawned, e.g. `sun 02:00-04:00`,
+/// `mon-fri 18-08` or `fri-mon 22-06 +02:00`: UTC unless an offset follows.
+/// It starts on each of its days; one ending before it starts runs past
+/// midnight into the next day. A day range may wrap past Sunday.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    spec: String,
+    /// Inclusive weekday range, 0 = Monday; `fri-mon` is (4, 0)
+    days: (u32, u32),
+    /// Offset of the window's times from UTC in minutes
+    offset: i32,
+    /// Minute of the day the window opens
+    start: u32,
+    /// Length in minutes
+    length: u32,
+}
+
+impl MaintenanceWindow {
+    /// Whether the window is open at `timestamp` (unix seconds)
+    pub fn is_active(&self, timestamp: u64) -> bool {
+        let now = minute_of_week(timestamp.saturating_add_signed(i64::from(self.offset) * 60));
+        let (first, last) = self.days;
+        (0..=(last + 7 - first) % 7).map(|i| (first + i) % 7).any(|day| {
+            let opens = day * DAY_MINUTES + self.start;
+            (now + WEEK_MINUTES - opens) % WEEK_MINUTES < self.length
+        })
+    }
+}
+
+/// The first of `windows` open at `timestamp`
+pub fn active_window(windows: &[MaintenanceWindow], timestamp: u64) -> Option<&MaintenanceWindow> {
+    windows.iter().find(|w| w.is_active(timestamp))
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "invalid maintenance window '{}', expected e.g. 'sun 02:00-04:00' or \
+                 'fri-mon 22-06 +02:00'",
+                s
+            )
+        };
+        let mut fields = s.split_whitespace();
+        let (Some(days), Some(times)) = (fields.next(), fields.next()) else {
+            return Err(invalid());
+        };
+        // `+HH[:MM]` or `-HH[:MM]` from UTC
+        let offset = match fields.next() {
+            Some(offset) => {
+                let (sign, offset) = match offset.split_at_checked(1) {
+                    Some(("+", offset)) => (1, offset),
+                    Some(("-", offset)) => (-1, offset),
+                    _ => return Err(invalid()),
+                };
+                let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+                let (Ok(hours), Ok(minutes)) = (hours.parse::<i32>(), minutes.parse::<i32>())
+                else {
+                    return Err(invalid());
+                };
+                if !(0..=14).contains(&hours) || !(0..60).contains(&minutes) {
+                    return Err(invalid());
+                }
+                sign * (hours * 60 + minutes)
+            }
+            None => 0,
+        };
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+
+        let day = |d: &str| DAYS.iter().position(|n| n.eq_ignore_ascii_case(d.trim()));
+        let days = match days.split_once('-') {
+            Some((from, to)) => (day(from), day(to)),
+            None => (day(days), day(days)),
+        };
+        let (Some(first_day), Some(last_day)) = days else {
+            return Err(invalid());
+        };
+
+        // `HH` or `HH:MM`; 24:00 only ends a window
+        let minute = |t: &str| -> Option<u32> {
+            let (hours, minutes) = t.trim().split_once(':').unwrap_or((t.trim(), "0"));
+            let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+            let minute = hours * 60 + minutes;
+            (minutes < 60 && minute <= DAY_MINUTES).then_some(minute)
+        };
+        let (from, to) = times.trim().split_once('-').ok_or_else(invalid)?;
+        let (Some(from), Some(to)) = (minute(from), minute(to)) else {
+            return Err(invalid());
+        };
+        if from == to || from == DAY_MINUTES {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            spec: s.trim().to_string(),
+            days: (first_day as u32, last_day as u32),
+            offset,
+            start: from,
+            length: if to > from { to - from } else { to + DAY_MINUTES - from },
+        })
+    }
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+impl Serialize for MaintenanceWindow {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_windows() {
+        // 2024-01-01 was a Monday
+        let monday = 1_704_067_200;
+        let at = |day: u64, hour: u64, minute: u64| {
+            monday + day * 86400 + hour * 3600 + minute * 60
+        };
+
+        let backup: MaintenanceWindow = "sun 02:30-04".parse().unwrap();
+        assert!(!backup.is_active(at(6, 2, 29)));
+        assert!(backup.is_active(at(6, 2, 30)));
+        assert!(!backup.is_active(at(6, 4, 0)));
+        assert!(!backup.is_active(at(5, 3, 0)));
+
+        // Past midnight: Friday evening runs into Saturday morning, but
+        // Saturday evening is free
+        let nights: MaintenanceWindow = "mon-fri 18-08".parse().unwrap();
+        assert!(nights.is_active(at(0, 19, 0)));
+        assert!(nights.is_active(at(5, 7, 59)));
+        assert!(!nights.is_active(at(5, 19, 0)));
+        assert!(!nights.is_active(at(0, 3, 0)));
+        assert!(!nights.is_active(at(2, 12, 0)));
+
+        let weekend: MaintenanceWindow = "sat-sun 00-24".parse().unwrap();
+        assert!(weekend.is_active(at(6, 23, 59)));
+        assert!(!weekend.is_active(at(0, 0, 0)));
+        let windows = [backup, weekend];
+        assert_eq!(active_window(&windows, at(6, 3, 0)).unwrap().to_string(), "sun 02:30-04");
+        assert!(active_window(&windows, at(1, 3, 0)).is_none());
+
+        // Wrapping past Sunday, in UTC+2: Friday 22:00 to Monday 06:00 local
+        let long_weekend: MaintenanceWindow = "fri-mon 22-06 +02:00".parse().unwrap();
+        assert!(!long_weekend.is_active(at(4, 19, 59)));
+        assert!(long_weekend.is_active(at(4, 20, 0)));
+        assert!(long_weekend.is_active(at(0, 3, 59)));
+        assert!(!long_weekend.is_active(at(0, 4, 0)));
+        assert!(long_weekend.is_active(at(6, 20, 0)));
+        assert!(!long_weekend.is_active(at(1, 20, 0)));
+        let evening: MaintenanceWindow = "mon 18-20 -05".parse().unwrap();
+        assert!(evening.is_active(at(0, 23, 0)));
+        assert!(!evening.is_active(at(0, 19, 0)));
+
+        let invalid = [
+            "",
+            "sun",
+            "sun 02-02",
+            "sun 24-02",
+            "sun 02:60-04",
+            "sun 02-04 02:00",
+            "sun 02-04 +15",
+            "sun 02-04 +02 x",
+        ];
+        for invalid in invalid {
+            assert!(invalid.parse::<MaintenanceWindow>().is_err(), "{}", invalid);
+        }
+    }
+}
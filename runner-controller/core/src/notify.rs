@@ -0,0 +1,769 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use tera::Tera;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::config::Config;
+
+/// Without a digest interval, events following a send closer than this are
+/// batched into the next one
+const MIN_SEND_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Room kept for the `... and N more event(s)` line of a cut digest
+const OMITTED_ROOM: usize = 40;
+
+/// Payload shape expected by the webhook receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// `{"text": ...}`
+    Slack,
+    /// `{"content": ...}`
+    Discord,
+    /// `{"events": [{"event": ..., "message": ...}]}`
+    Generic,
+}
+
+impl std::str::FromStr for WebhookFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "slack" => Ok(Self::Slack),
+            "discord" => Ok(Self::Discord),
+            "generic" => Ok(Self::Generic),
+            other => anyhow::bail!("unknown webhook format '{}'", other),
+        }
+    }
+}
+
+/// Events worth telling an operator about.
+///
+/// Serializes as `{"event": "<kind>", ...fields}` for use in templates.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum NotificationEvent {
+    /// Sent on demand via `POST /notify/test`
+    Test,
+    Startup,
+    Shutdown,
+    SpawnFailed { name: String, error: String },
+    JobTimeout { name: String, running_secs: u64 },
+    AuthFailures { consecutive: u32 },
+    Quarantined { name: String, reason: String },
+    /// A job matching our labels stayed queued while runners sat idle
+    QueueStarvation {
+        job_id: u64,
+        job_name: String,
+        labels: Vec<String>,
+        cycles: u32,
+        idle_runners: usize,
+    },
+    /// One of our runners is registered without `--ephemeral`
+    NonEphemeralRunner { name: String, runner_id: u64 },
+    /// A newer actions runner release is out; GitHub refuses the pinned
+    /// version from `refused_in_days` on
+    RunnerOutdated {
+        pinned: String,
+        latest: String,
+        refused_in_days: u64,
+    },
+    /// A queued job requests labels no pool or registered runner offers
+    UnmatchedJob {
+        job_id: u64,
+        job_name: String,
+        labels: Vec<String>,
+        cycles: u32,
+    },
+    /// This controller took the leader lease over from `previous`
+    LeaderTakeover { previous: String },
+}
+
+impl NotificationEvent {
+    /// Example event of the given kind, for test sends
+    pub fn sample(kind: &str) -> Option<Self> {
+        Some(match kind {
+            "test" => Self::Test,
+            "startup" => Self::Startup,
+            "shutdown" => Self::Shutdown,
+            "spawn_failed" => Self::SpawnFailed {
+                name: "r0".to_string(),
+                error: "example error".to_string(),
+            },
+            "job_timeout" => Self::JobTimeout {
+                name: "r0".to_string(),
+                running_secs: 7201,
+            },
+            "auth_failures" => Self::AuthFailures { consecutive: 3 },
+            "quarantined" => Self::Quarantined {
+                name: "r0".to_string(),
+                reason: "example reason".to_string(),
+            },
+            "queue_starvation" => Self::QueueStarvation {
+                job_id: 41234567890,
+                job_name: "build".to_string(),
+                labels: vec!["self-hosted".to_string(), "nix".to_string()],
+                cycles: 30,
+                idle_runners: 2,
+            },
+            "non_ephemeral_runner" => Self::NonEphemeralRunner {
+                name: "r0".to_string(),
+                runner_id: 812,
+            },
+            "runner_outdated" => Self::RunnerOutdated {
+                pinned: "2.320.0".to_string(),
+                latest: "2.321.0".to_string(),
+                refused_in_days: 30,
+            },
+            "unmatched_job" => Self::UnmatchedJob {
+                job_id: 41234567890,
+                job_name: "build".to_string(),
+                labels: vec!["self-hosted".to_string(), "arm64".to_string()],
+                cycles: 10,
+            },
+            "leader_takeover" => Self::LeaderTakeover {
+                previous: "controller-a".to_string(),
+            },
+            _ => return None,
+        })
+    }
+
+    /// Short machine-readable event kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Test => "test",
+            Self::Startup => "startup",
+            Self::Shutdown => "shutdown",
+            Self::SpawnFailed { .. } => "spawn_failed",
+            Self::JobTimeout { .. } => "job_timeout",
+            Self::AuthFailures { .. } => "auth_failures",
+            Self::Quarantined { .. } => "quarantined",
+            Self::QueueStarvation { .. } => "queue_starvation",
+            Self::NonEphemeralRunner { .. } => "non_ephemeral_runner",
+            Self::RunnerOutdated { .. } => "runner_outdated",
+            Self::UnmatchedJob { .. } => "unmatched_job",
+            Self::LeaderTakeover { .. } => "leader_takeover",
+        }
+    }
+
+    /// `high` for events that need prompt operator attention, otherwise `normal`
+    pub fn priority(&self) -> &'static str {
+        match self {
+            Self::QueueStarvation { .. }
+            | Self::AuthFailures { .. }
+            | Self::NonEphemeralRunner { .. }
+            | Self::RunnerOutdated {
+                refused_in_days: 0,
+                ..
+            } => "high",
+            _ => "normal",
+        }
+    }
+
+    /// Human-readable one-line message
+    pub fn message(&self) -> String {
+        match self {
+            Self::Test => "Test notification from runner-controller".to_string(),
+            Self::Startup => "runner-controller started".to_string(),
+            Self::Shutdown => "runner-controller shutting down".to_string(),
+            Self::SpawnFailed { name, error } => {
+                format!("Failed to spawn container {}: {}", name, error)
+            }
+            Self::JobTimeout { name, running_secs } => {
+                format!(
+                    "Container {} killed after {}s (job timeout)",
+                    name, running_secs
+                )
+            }
+            Self::AuthFailures { consecutive } => {
+                format!(
+                    "{} consecutive GitHub API auth failures - check token",
+                    consecutive
+                )
+            }
+            Self::Quarantined { name, reason } => {
+                format!("Container {} quarantined: {}", name, reason)
+            }
+            Self::QueueStarvation {
+                job_id,
+                job_name,
+                labels,
+                cycles,
+                idle_runners,
+            } => {
+                format!(
+                    "[HIGH] Job {} ({}) with labels [{}] queued for {} cycles while {} runner(s) idle - check spawn failures and label matching",
+                    job_id,
+                    job_name,
+                    labels.join(", "),
+                    cycles,
+                    idle_runners
+                )
+            }
+            Self::NonEphemeralRunner { name, runner_id } => {
+                format!(
+                    "[HIGH] Runner {} (id {}) is not ephemeral and may run several jobs - check the container template",
+                    name, runner_id
+                )
+            }
+            Self::RunnerOutdated {
+                pinned,
+                latest,
+                refused_in_days: 0,
+            } => {
+                format!(
+                    "[HIGH] Runner version {} is refused by GitHub - upgrade the container template to {}",
+                    pinned, latest
+                )
+            }
+            Self::RunnerOutdated {
+                pinned,
+                latest,
+                refused_in_days,
+            } => {
+                format!(
+                    "Runner {} is out; GitHub refuses {} in {} day(s) - upgrade the container template",
+                    latest, pinned, refused_in_days
+                )
+            }
+            Self::UnmatchedJob {
+                job_id,
+                job_name,
+                labels,
+                cycles,
+            } => {
+                format!(
+                    "Job {} ({}) with labels [{}] queued for {} cycles, but no runner offers these labels - check runs-on and RUNNER_LABELS",
+                    job_id,
+                    job_name,
+                    labels.join(", "),
+                    cycles
+                )
+            }
+            Self::LeaderTakeover { previous } => {
+                format!("Took over as leader from {}, now dispatching jobs", previous)
+            }
+        }
+    }
+}
+
+enum Command {
+    Event(NotificationEvent),
+    Flush(oneshot::Sender<()>),
+    /// Deliver one event immediately, reporting any delivery error
+    Test(NotificationEvent, oneshot::Sender<Result<()>>),
+}
+
+/// Posts notifications to the configured destinations from a background task.
+///
+/// Cheap to clone; sending never blocks the caller. With a digest interval,
+/// events are buffered and posted as a single message at most once per interval,
+/// otherwise at most once per `MIN_SEND_INTERVAL`.
+#[derive(Clone)]
+pub struct Notifier {
+    tx: Option<mpsc::UnboundedSender<Command>>,
+}
+
+impl Notifier {
+    /// Create a notifier; without any destination all events are dropped
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut destinations = Vec::new();
+
+        if let Some(url) = &config.notify_webhook_url {
+            let client = Client::builder()
+                .user_agent("runner-controller/0.1.0")
+                .timeout(Duration::from_secs(10))
+                .build()
+                .context("Failed to create webhook HTTP client")?;
+
+            destinations.push(Destination::Webhook {
+                client,
+                url: url.clone(),
+                format: config.notify_format,
+            });
+        }
+
+        if !config.notify_email_to.is_empty() {
+            destinations.push(Destination::Email {
+                to: config.notify_email_to.clone(),
+                sendmail: config.notify_sendmail.clone(),
+            });
+        }
+
+        if destinations.is_empty() {
+            return Ok(Self { tx: None });
+        }
+
+        let templates = Templates::load(config.notify_template_dir.as_deref())?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let sender = NotificationSender {
+            destinations,
+            templates,
+        };
+        tokio::spawn(sender.run(rx, config.notify_digest_interval));
+
+        Ok(Self { tx: Some(tx) })
+    }
+
+    /// Queue an event for delivery
+    pub fn notify(&self, event: NotificationEvent) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(Command::Event(event));
+        }
+    }
+
+    /// Deliver any buffered events and wait for them to be sent
+    pub async fn flush(&self) {
+        if let Some(tx) = &self.tx {
+            let (done, rx) = oneshot::channel();
+            if tx.send(Command::Flush(done)).is_ok() {
+                let _ = rx.await;
+            }
+        }
+    }
+
+    /// Deliver `event` to every destination right away, bypassing the digest
+    pub async fn test(&self, event: NotificationEvent) -> Result<()> {
+        let Some(tx) = &self.tx else {
+            anyhow::bail!("No notification destinations configured");
+        };
+
+        let (reply, rx) = oneshot::channel();
+        tx.send(Command::Test(event, reply))
+            .map_err(|_| anyhow::anyhow!("Notifier not running"))?;
+        rx.await.context("Notifier not running")?
+    }
+}
+
+/// Operator-provided Tera templates overriding the built-in payloads.
+///
+/// Templates are looked up by file name, most specific first:
+/// `<event>.<destination>.tera`, `<event>.tera`, `default.<destination>.tera`,
+/// `default.tera`. Batched digests use `digest` in place of the event kind.
+struct Templates {
+    tera: Option<Tera>,
+}
+
+impl Templates {
+    fn load(dir: Option<&Path>) -> Result<Self> {
+        let Some(dir) = dir else {
+            return Ok(Self { tera: None });
+        };
+
+        let glob = format!("{}/*.tera", dir.display());
+        let tera = Tera::new(&glob)
+            .with_context(|| format!("Failed to load notification templates from {:?}", dir))?;
+
+        debug!(
+            templates = ?tera.get_template_names().collect::<Vec<_>>(),
+            "Loaded notification templates"
+        );
+        Ok(Self { tera: Some(tera) })
+    }
+
+    /// Render the most specific template for `events` at `destination`, if any exists
+    fn render(&self, events: &[NotificationEvent], destination: &str) -> Result<Option<String>> {
+        let Some(tera) = &self.tera else {
+            return Ok(None);
+        };
+
+        let kind = match events {
+            [event] => event.kind(),
+            _ => "digest",
+        };
+
+        let candidates = [
+            format!("{}.{}.tera", kind, destination),
+            format!("{}.tera", kind),
+            format!("default.{}.tera", destination),
+            "default.tera".to_string(),
+        ];
+        let Some(name) = candidates
+            .iter()
+            .find(|name| tera.get_template_names().any(|t| t == name.as_str()))
+        else {
+            return Ok(None);
+        };
+
+        let context = tera::Context::from_value(template_context(events, text_limit(destination)))
+            .context("Failed to build template context")?;
+        let rendered = tera
+            .render(name, &context)
+            .with_context(|| format!("Failed to render notification template {}", name))?;
+
+        Ok(Some(rendered))
+    }
+}
+
+/// Template variables: `events` (each with `event`, `message` and its fields),
+/// `text` (all messages joined, within `limit` characters), and for a single
+/// event its fields at top level
+fn template_context(events: &[NotificationEvent], limit: Option<usize>) -> serde_json::Value {
+    let events_json: Vec<serde_json::Value> = events
+        .iter()
+        .map(|e| {
+            let mut value = serde_json::to_value(e).unwrap_or_else(|_| json!({}));
+            value["message"] = json!(e.message());
+            value["priority"] = json!(e.priority());
+            value
+        })
+        .collect();
+
+    let mut context = match events_json.as_slice() {
+        [single] => single.clone(),
+        _ => json!({}),
+    };
+    context["text"] = json!(digest_text(events, limit));
+    context["events"] = json!(events_json);
+    context
+}
+
+/// Longest message text a destination accepts, in characters
+fn text_limit(destination: &str) -> Option<usize> {
+    match destination {
+        "discord" => Some(2000),
+        "slack" => Some(40_000),
+        _ => None,
+    }
+}
+
+/// Messages of `events`, one per line. Beyond `limit` characters long
+/// messages are cut and the events that don't fit are only counted.
+fn digest_text(events: &[NotificationEvent], limit: Option<usize>) -> String {
+    let messages: Vec<String> = events.iter().map(|e| e.message()).collect();
+    let text = messages.join("\n");
+    let Some(limit) = limit.filter(|&limit| text.chars().count() > limit) else {
+        return text;
+    };
+
+    let room = limit.saturating_sub(OMITTED_ROOM);
+    let mut lines = Vec::new();
+    let mut used = 0;
+    for message in &messages {
+        let line = match message.char_indices().nth(room.saturating_sub(2)) {
+            Some((end, _)) => format!("{}…", &message[..end]),
+            None => message.clone(),
+        };
+        used += line.chars().count() + 1;
+        if used > room {
+            break;
+        }
+        lines.push(line);
+    }
+    let omitted = messages.len() - lines.len();
+    if omitted > 0 {
+        lines.push(format!("... and {} more event(s)", omitted));
+    }
+    lines.join("\n")
+}
+
+/// Where notifications are delivered
+enum Destination {
+    Webhook {
+        client: Client,
+        url: String,
+        format: WebhookFormat,
+    },
+    /// Piped to `sendmail -t`
+    Email { to: Vec<String>, sendmail: PathBuf },
+}
+
+impl Destination {
+    /// Name used to select templates
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Webhook {
+                format: WebhookFormat::Slack,
+                ..
+            } => "slack",
+            Self::Webhook {
+                format: WebhookFormat::Discord,
+                ..
+            } => "discord",
+            Self::Webhook {
+                format: WebhookFormat::Generic,
+                ..
+            } => "generic",
+            Self::Email { .. } => "email",
+        }
+    }
+
+    async fn deliver(&self, events: &[NotificationEvent], templates: &Templates) -> Result<()> {
+        let rendered = templates.render(events, self.name())?;
+
+        match self {
+            Self::Webhook {
+                client,
+                url,
+                format,
+            } => {
+                let request = match rendered {
+                    // Templates produce the raw request body
+                    Some(body) => client
+                        .post(url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .body(body),
+                    None => client.post(url).json(&webhook_payload(*format, events)),
+                };
+
+                let resp = request
+                    .send()
+                    .await
+                    .context("Failed to post webhook notification")?;
+                if !resp.status().is_success() {
+                    anyhow::bail!("Webhook notification rejected: {}", resp.status());
+                }
+            }
+            Self::Email { to, sendmail } => {
+                let subject = match events {
+                    [event] => format!("[runner-controller] {}", event.kind()),
+                    _ => format!("[runner-controller] {} events", events.len()),
+                };
+                let body = rendered.unwrap_or_else(|| digest_text(events, None));
+                let message = format!(
+                    "To: {}\nSubject: {}\nContent-Type: text/plain; charset=utf-8\n\n{}\n",
+                    to.join(", "),
+                    subject,
+                    body
+                );
+
+                let mut child = tokio::process::Command::new(sendmail)
+                    .arg("-t")
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::piped())
+                    .spawn()
+                    .with_context(|| format!("Failed to run {:?}", sendmail))?;
+
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(message.as_bytes()).await?;
+                }
+
+                let output = child.wait_with_output().await?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "sendmail failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Built-in webhook payload used when no template matches
+fn webhook_payload(format: WebhookFormat, events: &[NotificationEvent]) -> serde_json::Value {
+    match format {
+        WebhookFormat::Slack => json!({ "text": digest_text(events, text_limit("slack")) }),
+        WebhookFormat::Discord => json!({ "content": digest_text(events, text_limit("discord")) }),
+        WebhookFormat::Generic => json!({
+            "events": events
+                .iter()
+                .map(|e| {
+                    json!({ "event": e.kind(), "priority": e.priority(), "message": e.message() })
+                })
+                .collect::<Vec<_>>()
+        }),
+    }
+}
+
+struct NotificationSender {
+    destinations: Vec<Destination>,
+    templates: Templates,
+}
+
+impl NotificationSender {
+    async fn run(self, mut rx: mpsc::UnboundedReceiver<Command>, digest: Option<Duration>) {
+        let mut pending: Vec<NotificationEvent> = Vec::new();
+        let mut ticker = digest.map(tokio::time::interval);
+        let mut last_sent: Option<Instant> = None;
+
+        loop {
+            // Events held back by MIN_SEND_INTERVAL go out once it has passed
+            let held_until = last_sent
+                .map(|sent| sent + MIN_SEND_INTERVAL)
+                .filter(|_| !pending.is_empty());
+            let tick = async {
+                match (ticker.as_mut(), held_until) {
+                    (Some(t), _) => {
+                        t.tick().await;
+                    }
+                    (None, Some(until)) => tokio::time::sleep_until(until).await,
+                    (None, None) => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                command = rx.recv() => match command {
+                    Some(Command::Event(event)) if digest.is_some() => pending.push(event),
+                    Some(Command::Event(event))
+                        if last_sent.is_some_and(|sent| sent.elapsed() < MIN_SEND_INTERVAL) =>
+                    {
+                        pending.push(event)
+                    }
+                    Some(Command::Event(event)) => {
+                        self.send(&[event]).await;
+                        last_sent = Some(Instant::now());
+                    }
+                    Some(Command::Flush(done)) => {
+                        self.send(&std::mem::take(&mut pending)).await;
+                        let _ = done.send(());
+                    }
+                    Some(Command::Test(event, reply)) => {
+                        let _ = reply.send(self.deliver_all(&[event]).await);
+                    }
+                    None => break,
+                },
+                _ = tick => {
+                    self.send(&std::mem::take(&mut pending)).await;
+                    last_sent = Some(Instant::now());
+                }
+            }
+        }
+
+        self.send(&pending).await;
+    }
+
+    async fn send(&self, events: &[NotificationEvent]) {
+        if events.is_empty() {
+            return;
+        }
+
+        debug!(count = events.len(), "Sending notification");
+        if let Err(e) = self.deliver_all(events).await {
+            warn!(error = %e, "Failed to deliver notification");
+        }
+    }
+
+    /// Deliver to every destination, even if an earlier one fails
+    async fn deliver_all(&self, events: &[NotificationEvent]) -> Result<()> {
+        let mut errors = Vec::new();
+        for destination in &self.destinations {
+            if let Err(e) = destination.deliver(events, &self.templates).await {
+                errors.push(format!("{}: {:#}", destination.name(), e));
+            }
+        }
+
+        if !errors.is_empty() {
+            anyhow::bail!(errors.join("; "));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template_fallback() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("job_timeout.slack.tera", "{\"text\": {{ message | json_encode() }}}"),
+            ("default.tera", "{{ events | length }} event(s): {{ text }}"),
+        ])
+        .unwrap();
+        let templates = Templates { tera: Some(tera) };
+
+        let timeout = NotificationEvent::sample("job_timeout").unwrap();
+        let rendered = templates.render(std::slice::from_ref(&timeout), "slack").unwrap().unwrap();
+        assert_eq!(
+            rendered,
+            "{\"text\": \"Container r0 killed after 7201s (job timeout)\"}"
+        );
+
+        let rendered = templates
+            .render(&[timeout, NotificationEvent::Startup], "slack")
+            .unwrap()
+            .unwrap();
+        assert!(rendered.starts_with("2 event(s): Container r0"));
+
+        assert!(Templates { tera: None }
+            .render(&[NotificationEvent::Startup], "email")
+            .unwrap()
+            .is_none());
+    }
+
+    fn spawn_failed(error: String) -> NotificationEvent {
+        NotificationEvent::SpawnFailed {
+            name: "r0".to_string(),
+            error,
+        }
+    }
+
+    #[test]
+    fn test_digest_within_limits() {
+        let events: Vec<_> = (0..100).map(|i| spawn_failed(format!("error {}", i))).collect();
+        let content = webhook_payload(WebhookFormat::Discord, &events)["content"].clone();
+        let content = content.as_str().unwrap();
+        assert!(content.chars().count() <= 2000);
+        assert!(content.starts_with("Failed to spawn container r0: error 0\n"));
+        assert!(content.ends_with(" more event(s)"));
+        let text = webhook_payload(WebhookFormat::Slack, &events)["text"].clone();
+        assert_eq!(text.as_str().unwrap().lines().count(), 100);
+
+        // A single overlong message is cut
+        let long = [spawn_failed("x".repeat(5000))];
+        let content = webhook_payload(WebhookFormat::Discord, &long)["content"].clone();
+        assert!(content.as_str().unwrap().chars().count() <= 2000);
+        assert!(content.as_str().unwrap().ends_with("x…"));
+
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("digest.discord.tera", "{{ text }}"),
+            ("default.tera", "{{ event }}/{{ priority }}: {{ name }}"),
+        ])
+        .unwrap();
+        let templates = Templates { tera: Some(tera) };
+        let rendered = templates.render(&events, "discord").unwrap().unwrap();
+        assert!(rendered.chars().count() <= 2000);
+        let quarantined = NotificationEvent::sample("quarantined").unwrap();
+        let rendered = templates.render(&[quarantined], "email").unwrap().unwrap();
+        assert_eq!(rendered, "quarantined/normal: r0");
+    }
+
+    #[test]
+    fn test_min_send_interval() {
+        let dir = std::env::temp_dir().join(format!("notify-interval-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sendmail = dir.join("sendmail");
+        let script = format!("#!/bin/sh\ngrep ^Subject >> {}\n", dir.join("sent").display());
+        std::fs::write(&sendmail, script).unwrap();
+        std::fs::set_permissions(&sendmail, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+
+        let sender = NotificationSender {
+            destinations: vec![Destination::Email {
+                to: vec!["ops@example.com".to_string()],
+                sendmail,
+            }],
+            templates: Templates { tera: None },
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        for i in 0..3 {
+            tx.send(Command::Event(spawn_failed(format!("error {}", i)))).unwrap();
+        }
+        drop(tx);
+        tokio_test::block_on(sender.run(rx, None));
+
+        // The first event goes out right away, the ones right after it together
+        let sent = std::fs::read_to_string(dir.join("sent")).unwrap();
+        assert_eq!(
+            sent,
+            "Subject: [runner-controller] spawn_failed\nSubject: [runner-controller] 2 events\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
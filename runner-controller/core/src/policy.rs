@@ -0,0 +1,341 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::github::RepoMetadata;
+
+/// What the controller knows about a job when deciding whether to run it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct JobContext {
+    pub job_id: u64,
+    /// Repository the workflow runs in (`owner/repo`)
+    #[serde(default)]
+    pub repository: Option<String>,
+    /// User that triggered the workflow
+    #[serde(default)]
+    pub actor: Option<String>,
+    /// Repository the workflow run's head commit comes from (differs for forks)
+    #[serde(default)]
+    pub head_repository: Option<String>,
+    /// Event that triggered the workflow run (e.g. `pull_request`)
+    #[serde(default)]
+    pub event: Option<String>,
+    /// Workflow file of the run, e.g. `.github/workflows/ci.yml`
+    #[serde(default)]
+    pub workflow: Option<String>,
+    /// Branch or tag the run is for, as GitHub reports it (`head_branch`)
+    #[serde(default)]
+    pub head_ref: Option<String>,
+}
+
+impl JobContext {
+    /// Whether the job runs code from a fork of the repository
+    pub fn is_fork(&self) -> bool {
+        match (&self.repository, &self.head_repository) {
+            (Some(repo), Some(head)) => !repo.eq_ignore_ascii_case(head),
+            _ => false,
+        }
+    }
+}
+
+/// What to do with jobs from fork pull requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForkPolicy {
+    Allow,
+    /// Refuse to provision a runner
+    #[default]
+    Deny,
+    /// Provision an isolated runner: no host secrets, no job-scoped token
+    Isolate,
+}
+
+impl FromStr for ForkPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            "isolate" => Ok(Self::Isolate),
+            other => anyhow::bail!("unknown fork policy '{}'", other),
+        }
+    }
+}
+
+/// How an accepted job should be run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Standard,
+    Isolated,
+}
+
+/// A job refused by a runner-usage policy
+#[derive(Debug, Clone, Error)]
+#[error("job {job_id} rejected by {rule} policy: {reason}")]
+pub struct PolicyRejection {
+    pub job_id: u64,
+    pub rule: &'static str,
+    pub reason: String,
+}
+
+/// Runner-usage policies applied before a runner is provisioned for a job
+#[derive(Debug, Clone, Default)]
+pub struct JobPolicy {
+    deny_actors: Vec<String>,
+    deny_repos: Vec<String>,
+    fork_policy: ForkPolicy,
+    /// Refuse jobs from public repositories
+    private_repos_only: bool,
+    /// Glob allowlists; empty allows everything
+    allow_workflows: Vec<String>,
+    allow_refs: Vec<String>,
+    allow_actors: Vec<String>,
+}
+
+impl JobPolicy {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            deny_actors: config.deny_actors.clone(),
+            deny_repos: config.deny_repos.clone(),
+            fork_policy: config.fork_policy,
+            private_repos_only: config.private_repos_only,
+            allow_workflows: config.allow_workflows.clone(),
+            allow_refs: config.allow_refs.clone(),
+            allow_actors: config.allow_actors.clone(),
+        }
+    }
+
+    /// Whether decisions need the job's workflow, ref or actor, which the
+    /// caller may have to look up on GitHub
+    pub fn needs_run_metadata(&self) -> bool {
+        !(self.allow_workflows.is_empty()
+            && self.allow_refs.is_empty()
+            && self.allow_actors.is_empty())
+    }
+
+    /// Whether decisions need repository metadata (see `check_visibility`)
+    pub fn needs_repo_metadata(&self) -> bool {
+        self.private_repos_only
+    }
+
+    /// Check a repository's visibility. Unknown metadata is refused in
+    /// private-only mode, since the repository may be public.
+    pub fn check_visibility(
+        &self,
+        job_id: u64,
+        repo: &str,
+        metadata: Option<&RepoMetadata>,
+    ) -> Result<(), PolicyRejection> {
+        if !self.private_repos_only {
+            return Ok(());
+        }
+
+        let reason = match metadata {
+            Some(m) if m.actions_enabled == Some(false) => {
+                format!("repository '{}' has Actions disabled", repo)
+            }
+            Some(m) if m.private => return Ok(()),
+            Some(m) => format!("repository '{}' is {}", repo, m.visibility),
+            None => format!("visibility of repository '{}' is unknown", repo),
+        };
+        Err(PolicyRejection {
+            job_id,
+            rule: "private_only",
+            reason,
+        })
+    }
+
+    /// Check a job against all policies, returning the first rejection
+    pub fn evaluate(&self, job: &JobContext) -> Result<Placement, PolicyRejection> {
+        if let Some(actor) = &job.actor {
+            if self.deny_actors.iter().any(|a| a.eq_ignore_ascii_case(actor)) {
+                return Err(PolicyRejection {
+                    job_id: job.job_id,
+                    rule: "actor",
+                    reason: format!("actor '{}' is denied", actor),
+                });
+            }
+        }
+
+        let allowlists = [
+            ("workflow", "workflow_allowlist", &self.allow_workflows, &job.workflow),
+            ("ref", "ref_allowlist", &self.allow_refs, &job.head_ref),
+            ("actor", "actor_allowlist", &self.allow_actors, &job.actor),
+        ];
+        for (what, rule, patterns, value) in allowlists {
+            if patterns.is_empty() {
+                continue;
+            }
+            // Logins are case-insensitive on GitHub, paths and refs are not
+            let matches = |pattern: &String| match (what, value) {
+                ("actor", Some(actor)) => {
+                    glob_match(&pattern.to_ascii_lowercase(), &actor.to_ascii_lowercase())
+                }
+                (_, Some(value)) => glob_match(pattern, value),
+                (_, None) => false,
+            };
+            if !patterns.iter().any(matches) {
+                let reason = match value {
+                    Some(value) => format!("{} '{}' is not allowed", what, value),
+                    None => format!("{} is unknown", what),
+                };
+                return Err(PolicyRejection {
+                    job_id: job.job_id,
+                    rule,
+                    reason,
+                });
+            }
+        }
+
+        if let Some(repo) = &job.repository {
+            if self.deny_repos.iter().any(|r| r.eq_ignore_ascii_case(repo)) {
+                return Err(PolicyRejection {
+                    job_id: job.job_id,
+                    rule: "repo_denylist",
+                    reason: format!("repository '{}' is denied", repo),
+                });
+            }
+        }
+
+        if job.is_fork() {
+            let head = job.head_repository.as_deref().unwrap_or_default();
+            match self.fork_policy {
+                ForkPolicy::Allow => {}
+                ForkPolicy::Deny => {
+                    return Err(PolicyRejection {
+                        job_id: job.job_id,
+                        rule: "fork",
+                        reason: format!("jobs from fork '{}' are denied", head),
+                    });
+                }
+                ForkPolicy::Isolate => return Ok(Placement::Isolated),
+            }
+        }
+
+        Ok(Placement::Standard)
+    }
+}
+
+/// Match `text` against a glob: `*` matches any run of characters (`/`
+/// included), `?` any single character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_denylists() {
+        let policy = JobPolicy {
+            deny_actors: vec!["mallory".to_string()],
+            deny_repos: vec!["evil/repo".to_string()],
+            fork_policy: ForkPolicy::Deny,
+            private_repos_only: false,
+            ..Default::default()
+        };
+
+        let job = |repo: &str, actor: &str| JobContext {
+            job_id: 1,
+            repository: Some(repo.to_string()),
+            actor: Some(actor.to_string()),
+            ..Default::default()
+        };
+
+        assert!(policy.evaluate(&job("good/repo", "alice")).is_ok());
+        assert_eq!(
+            policy.evaluate(&job("good/repo", "Mallory")).unwrap_err().rule,
+            "actor"
+        );
+        assert_eq!(
+            policy.evaluate(&job("evil/repo", "alice")).unwrap_err().rule,
+            "repo_denylist"
+        );
+
+        let fork = JobContext {
+            head_repository: Some("mallory/repo".to_string()),
+            event: Some("pull_request".to_string()),
+            ..job("good/repo", "alice")
+        };
+        assert_eq!(policy.evaluate(&fork).unwrap_err().rule, "fork");
+
+        let policy = JobPolicy {
+            fork_policy: ForkPolicy::Isolate,
+            ..policy
+        };
+        assert_eq!(policy.evaluate(&fork).unwrap(), Placement::Isolated);
+        assert_eq!(
+            policy.evaluate(&job("good/repo", "alice")).unwrap(),
+            Placement::Standard
+        );
+    }
+
+    #[test]
+    fn test_evaluate_allowlists() {
+        let policy = JobPolicy {
+            allow_workflows: vec![".github/workflows/ci-*.yml".to_string()],
+            allow_refs: vec!["main".to_string(), "release/*".to_string()],
+            allow_actors: vec!["dependabot*".to_string(), "Alice".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.needs_run_metadata());
+
+        let job = |workflow: &str, head_ref: &str, actor: &str| JobContext {
+            job_id: 1,
+            workflow: Some(workflow.to_string()),
+            head_ref: Some(head_ref.to_string()),
+            actor: Some(actor.to_string()),
+            ..Default::default()
+        };
+        let ci = ".github/workflows/ci-linux.yml";
+        assert!(policy.evaluate(&job(ci, "main", "alice")).is_ok());
+        assert!(policy.evaluate(&job(ci, "release/1.2", "dependabot[bot]")).is_ok());
+
+        let rule = |job: JobContext| policy.evaluate(&job).unwrap_err().rule;
+        let deploy = ".github/workflows/deploy.yml";
+        assert_eq!(rule(job(deploy, "main", "alice")), "workflow_allowlist");
+        assert_eq!(rule(job(ci, "feature/x", "alice")), "ref_allowlist");
+        assert_eq!(rule(job(ci, "main", "mallory")), "actor_allowlist");
+        // Unknown values are refused while an allowlist is set
+        assert_eq!(
+            rule(JobContext { head_ref: None, ..job(ci, "main", "alice") }),
+            "ref_allowlist"
+        );
+
+        assert!(glob_match("a*b?d", "axxbcd"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a*b", "acbd"));
+        assert!(!JobPolicy::default().needs_run_metadata());
+    }
+}
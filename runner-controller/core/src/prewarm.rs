@@ -0,0 +1,189 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+const HOURS_PER_WEEK: usize = 7 * 24;
+const WEEK_SECS: u64 = 7 * 24 * 3600;
+pub(crate) const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Learned demand is recomputed from history this often
+const RELEARN_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Hour of the week of a unix timestamp, 0 being Monday 00:00-01:00 UTC
+fn hour_of_week(timestamp: u64) -> usize {
+    let hours = timestamp / 3600;
+    // 1970-01-01 was a Thursday
+    let weekday = (hours / 24 + 3) % 7;
+    (weekday * 24 + hours % 24) as usize
+}
+
+/// Fixed pool size for some hours of the week, e.g. `mon-fri 08-18=4`
+/// (UTC, end hour exclusive). Overrides learned demand.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScheduleEntry {
+    /// Inclusive weekday range, 0 = Monday
+    days: (usize, usize),
+    hours: (usize, usize),
+    pub size: usize,
+}
+
+impl ScheduleEntry {
+    fn covers(&self, hour_of_week: usize) -> bool {
+        let (day, hour) = (hour_of_week / 24, hour_of_week % 24);
+        (self.days.0..=self.days.1).contains(&day) && (self.hours.0..self.hours.1).contains(&hour)
+    }
+}
+
+impl FromStr for ScheduleEntry {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid schedule entry '{}', expected e.g. 'mon-fri 08-18=4'", s);
+        let (when, size) = s.split_once('=').ok_or_else(invalid)?;
+        let (days, hours) = when.trim().split_once(' ').ok_or_else(invalid)?;
+
+        let day = |d: &str| DAYS.iter().position(|n| n.eq_ignore_ascii_case(d.trim()));
+        let days = match days.split_once('-') {
+            Some((from, to)) => (day(from), day(to)),
+            None => (day(days), day(days)),
+        };
+        let (Some(first_day), Some(last_day)) = days else {
+            return Err(invalid());
+        };
+
+        let (from, to) = hours.trim().split_once('-').ok_or_else(invalid)?;
+        let from: usize = from.parse().map_err(|_| invalid())?;
+        let to: usize = to.parse().map_err(|_| invalid())?;
+        if first_day > last_day || from >= to || to > 24 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            days: (first_day, last_day),
+            hours: (from, to),
+            size: size.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Grows the warm pool ahead of expected demand. Demand is learned from when
+/// jobs completed over the last weeks, per hour of the week, so a pool that
+/// is busy on weekday mornings is warm before people start pushing.
+#[derive(Debug, Default)]
+pub struct Prewarmer {
+    /// Most standby containers to pre-warm; 0 disables pre-warming
+    max: usize,
+    lead: Duration,
+    lookback: Duration,
+    schedule: Vec<ScheduleEntry>,
+    /// Average jobs per hour of the week
+    demand: Vec<f64>,
+    learned_at: Option<Instant>,
+}
+
+impl Prewarmer {
+    pub fn new(max: usize, lead: Duration, lookback: Duration, schedule: Vec<ScheduleEntry>) -> Self {
+        Self {
+            max,
+            lead,
+            lookback,
+            schedule,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max > 0
+    }
+
+    /// How far back job history is considered
+    pub fn lookback(&self) -> Duration {
+        self.lookback
+    }
+
+    /// Whether learned demand is due to be recomputed
+    pub fn needs_learning(&self) -> bool {
+        self.learned_at
+            .is_none_or(|at| at.elapsed() >= RELEARN_INTERVAL)
+    }
+
+    /// Learn demand from job completion times (unix seconds)
+    pub fn learn(&mut self, completions: &[u64], now: u64) {
+        let mut counts = vec![0.0; HOURS_PER_WEEK];
+        for &at in completions {
+            counts[hour_of_week(at)] += 1.0;
+        }
+
+        // Average over the weeks actually covered, so a young history isn't diluted
+        let oldest = completions.iter().min().copied().unwrap_or(now);
+        let span = now.saturating_sub(oldest).min(self.lookback.as_secs());
+        let weeks = (span as f64 / WEEK_SECS as f64).max(1.0);
+        self.demand = counts.into_iter().map(|c| c / weeks).collect();
+        self.learned_at = Some(Instant::now());
+    }
+
+    /// Pool size wanted now: the larger of the demand expected this hour and
+    /// in `lead` from now, capped at the maximum
+    pub fn target(&self, now: u64) -> usize {
+        if !self.is_enabled() {
+            return 0;
+        }
+        [now, now + self.lead.as_secs()]
+            .into_iter()
+            .map(|at| self.expected(hour_of_week(at)))
+            .max()
+            .unwrap_or(0)
+            .min(self.max)
+    }
+
+    fn expected(&self, hour: usize) -> usize {
+        match self.schedule.iter().find(|e| e.covers(hour)) {
+            Some(entry) => entry.size,
+            None => self.demand.get(hour).map_or(0, |d| d.round() as usize),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_learned_demand_and_schedule() {
+        // Monday 2024-01-01 00:00 UTC
+        let monday = 1_704_067_200;
+        assert_eq!(hour_of_week(monday + 9 * 3600), 9);
+        assert_eq!(hour_of_week(monday + 6 * 86400 + 3600), 6 * 24 + 1);
+
+        // Two jobs every Monday 09:00-10:00 for four weeks
+        let completions: Vec<u64> = (0..4)
+            .flat_map(|week| {
+                let at = monday + week * WEEK_SECS + 9 * 3600;
+                [at, at + 600]
+            })
+            .collect();
+        let now = monday + 4 * WEEK_SECS + 8 * 3600 + 1800; // Monday 08:30
+
+        let lookback = Duration::from_secs(28 * 86400);
+        let mut prewarm = Prewarmer::new(3, Duration::from_secs(900), lookback, Vec::new());
+        assert!(prewarm.needs_learning());
+        prewarm.learn(&completions, now);
+        assert!(!prewarm.needs_learning());
+
+        // 08:30 + 15 min lead is still 08:xx; at 08:50 the 09:00 hour is in reach
+        assert_eq!(prewarm.target(now), 0);
+        assert_eq!(prewarm.target(now + 1200), 2);
+        // Tuesday: nothing learned
+        assert_eq!(prewarm.target(now + 86400), 0);
+
+        let schedule = vec!["tue-fri 08-18=5".parse().unwrap()];
+        let mut prewarm = Prewarmer::new(3, Duration::from_secs(900), lookback, schedule);
+        prewarm.learn(&completions, now);
+        assert_eq!(prewarm.target(now + 86400), 3);
+
+        assert!("mon-fri 18-08=1".parse::<ScheduleEntry>().is_err());
+        assert!("weekdays 08-18=1".parse::<ScheduleEntry>().is_err());
+        assert_eq!("sat 10-12=1".parse::<ScheduleEntry>().unwrap().days, (5, 5));
+    }
+}
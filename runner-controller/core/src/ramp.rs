@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Limits pool spawns per poll cycle after a restart or GitHub outage: one
+/// spawn per cycle at first, doubling every `interval`, so the host and the
+/// GitHub API don't see a burst of containers registering at once.
+#[derive(Debug, Default)]
+pub struct SpawnRamp {
+    /// `None` disables ramping
+    interval: Option<Duration>,
+    started: Option<Instant>,
+    /// Spawns made in the current poll cycle
+    spawned: AtomicUsize,
+}
+
+/// Doublings after which the ramp is over
+const MAX_RAMP_STEPS: u32 = 16;
+
+impl SpawnRamp {
+    pub fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            ..Default::default()
+        }
+    }
+
+    /// Start ramping from one spawn per cycle again
+    pub fn restart(&mut self) {
+        if self.interval.is_some() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    /// Spawns allowed per poll cycle, or `None` once the ramp is over
+    pub fn limit(&self) -> Option<usize> {
+        let interval = self.interval?;
+        let elapsed = self.started?.elapsed();
+        let steps = (elapsed.as_secs_f64() / interval.as_secs_f64()) as u32;
+        (steps < MAX_RAMP_STEPS).then(|| 1 << steps)
+    }
+
+    pub fn begin_cycle(&self) {
+        self.spawned.store(0, Ordering::Relaxed);
+    }
+
+    /// Take one spawn from this cycle's allowance
+    pub fn try_acquire(&self) -> bool {
+        match self.limit() {
+            None => true,
+            Some(limit) => self.spawned.fetch_add(1, Ordering::Relaxed) < limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramp_limits_spawns_per_cycle() {
+        let mut ramp = SpawnRamp::new(Some(Duration::from_secs(60)));
+        // Not restarted yet: no limit
+        assert!(ramp.try_acquire());
+
+        ramp.restart();
+        assert_eq!(ramp.limit(), Some(1));
+        ramp.begin_cycle();
+        assert!(ramp.try_acquire());
+        assert!(!ramp.try_acquire());
+        ramp.begin_cycle();
+        assert!(ramp.try_acquire());
+
+        ramp.started = Some(Instant::now() - Duration::from_secs(150));
+        assert_eq!(ramp.limit(), Some(4));
+        ramp.started = Some(Instant::now() - Duration::from_secs(60 * 20));
+        assert_eq!(ramp.limit(), None);
+
+        let mut disabled = SpawnRamp::new(None);
+        disabled.restart();
+        assert_eq!(disabled.limit(), None);
+    }
+}
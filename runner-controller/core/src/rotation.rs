@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::github::GitHubClient;
+
+/// How often credential files are checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Credential file whose contents can be swapped into a running client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Credential {
+    Token,
+    AppKey,
+}
+
+impl Credential {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Token => "github_token",
+            Self::AppKey => "github_app_key",
+        }
+    }
+}
+
+/// Modification time and size; a change in either means the file was replaced
+type Fingerprint = (Option<SystemTime>, u64);
+
+struct WatchedFile {
+    credential: Credential,
+    path: PathBuf,
+    fingerprint: Option<Fingerprint>,
+}
+
+fn fingerprint(path: &PathBuf) -> Option<Fingerprint> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok(), meta.len()))
+}
+
+/// Polls `GITHUB_TOKEN_FILE` and the GitHub App key for changes and swaps the
+/// new credentials into the client, so they can be rotated without a restart
+/// (which would kill running jobs). Stops once the client is dropped, e.g.
+/// after a reload replaced it.
+pub fn spawn_watcher(config: &Config, github: &Arc<GitHubClient>) {
+    let app_key_file = config.github_app.as_ref().and_then(|app| app.private_key_file.as_ref());
+    let mut files: Vec<WatchedFile> = [
+        (Credential::Token, config.github_token_file.as_ref()),
+        (Credential::AppKey, app_key_file),
+    ]
+    .into_iter()
+    .filter_map(|(credential, path)| {
+        let path = path?;
+        Some(WatchedFile {
+            credential,
+            path: path.clone(),
+            fingerprint: fingerprint(path),
+        })
+    })
+    .collect();
+    if files.is_empty() {
+        return;
+    }
+
+    let github = Arc::downgrade(github);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if !check_files(&mut files, &github) {
+                break;
+            }
+        }
+    });
+}
+
+/// Reload changed files; returns false once the client is gone
+fn check_files(files: &mut [WatchedFile], github: &Weak<GitHubClient>) -> bool {
+    let Some(github) = github.upgrade() else {
+        return false;
+    };
+
+    for file in files.iter_mut() {
+        let current = fingerprint(&file.path);
+        // A missing file is usually mid-replacement; keep the old credential
+        if current.is_none() || current == file.fingerprint {
+            continue;
+        }
+
+        match apply(file, &github) {
+            Ok(()) => {
+                info!(credential = file.credential.as_str(), path = ?file.path, "Credential rotated");
+                metrics::counter!(
+                    "runner_controller_credential_rotations_total",
+                    "credential" => file.credential.as_str()
+                )
+                .increment(1);
+                file.fingerprint = current;
+            }
+            Err(e) => {
+                // Retried on the next check, the file may still be being written
+                warn!(credential = file.credential.as_str(), error = %e, "Failed to rotate credential");
+            }
+        }
+    }
+
+    true
+}
+
+fn apply(file: &WatchedFile, github: &GitHubClient) -> Result<()> {
+    match file.credential {
+        Credential::Token => {
+            let token = std::fs::read_to_string(&file.path)
+                .with_context(|| format!("Failed to read GitHub token from {:?}", file.path))?;
+            let token = token.trim();
+            if token.is_empty() {
+                anyhow::bail!("GitHub token file {:?} is empty", file.path);
+            }
+            github.set_token(token.to_string());
+        }
+        Credential::AppKey => {
+            let key = std::fs::read(&file.path)
+                .with_context(|| format!("Failed to read GitHub App key from {:?}", file.path))?;
+            if let Some(app) = github.app() {
+                app.set_key(&key)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::github::RunnerRelease;
+use crate::state::parse_utc_timestamp;
+
+/// Runners started with `--disableupdate` are refused once a newer release
+/// has been out this long
+pub const UPDATE_GRACE_SECS: u64 = 30 * 24 * 3600;
+
+/// actions-runner version such as `2.321.0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RunnerVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl FromStr for RunnerVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<u32> = s
+            .trim()
+            .trim_start_matches('v')
+            .split('.')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| anyhow::anyhow!("invalid runner version '{}', expected e.g. 2.321.0", s))?;
+        match parts.as_slice() {
+            &[major, minor, patch] => Ok(Self {
+                major,
+                minor,
+                patch,
+            }),
+            _ => anyhow::bail!("invalid runner version '{}', expected e.g. 2.321.0", s),
+        }
+    }
+}
+
+impl fmt::Display for RunnerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The pinned runner version compared with GitHub's releases
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VersionStatus {
+    /// Version the container template ships (`RUNNER_VERSION`)
+    pub pinned: String,
+    /// Newest runner release
+    pub latest: String,
+    /// Oldest version GitHub still accepts from runners that don't update themselves
+    pub minimum: String,
+    /// When GitHub starts (or started) refusing the pinned version; `None`
+    /// while it is the latest release
+    pub refused_at: Option<u64>,
+    pub checked_at: u64,
+}
+
+impl VersionStatus {
+    /// Compare `pinned` with `releases`; `None` if none of them is usable.
+    /// Drafts and prereleases don't count.
+    pub fn new(pinned: RunnerVersion, releases: &[RunnerRelease], now: u64) -> Option<Self> {
+        let mut published: Vec<(RunnerVersion, u64)> = releases
+            .iter()
+            .filter(|r| !r.draft && !r.prerelease)
+            .filter_map(|r| {
+                let version = r.tag_name.parse().ok()?;
+                Some((version, parse_utc_timestamp(r.published_at.as_deref()?)?))
+            })
+            .collect();
+        published.sort_unstable();
+        let (latest, _) = *published.last()?;
+
+        // Everything older than the newest release past its grace period is
+        // refused; if no listed release is that old, the oldest one is a
+        // lower bound
+        let minimum = published
+            .iter()
+            .rev()
+            .find(|(_, at)| now.saturating_sub(*at) >= UPDATE_GRACE_SECS)
+            .unwrap_or(&published[0])
+            .0;
+        let refused_at = published
+            .iter()
+            .filter(|(version, _)| *version > pinned)
+            .map(|(_, at)| at + UPDATE_GRACE_SECS)
+            .min();
+
+        Some(Self {
+            pinned: pinned.to_string(),
+            latest: latest.to_string(),
+            minimum: minimum.to_string(),
+            refused_at,
+            checked_at: now,
+        })
+    }
+
+    /// Whether a newer release exists
+    pub fn upgrade_available(&self) -> bool {
+        self.refused_at.is_some()
+    }
+
+    /// Whether GitHub already refuses the pinned version
+    pub fn is_refused(&self, now: u64) -> bool {
+        self.refused_at.is_some_and(|at| at <= now)
+    }
+
+    /// Operator-facing description of a pending or overdue upgrade
+    pub fn warning(&self, now: u64) -> Option<String> {
+        let refused_at = self.refused_at?;
+        Some(if refused_at <= now {
+            format!(
+                "Runner version {} is refused by GitHub (minimum {}); upgrade to {}",
+                self.pinned, self.minimum, self.latest
+            )
+        } else {
+            format!(
+                "Runner version {} will be refused by GitHub in {} day(s); upgrade to {}",
+                self.pinned,
+                (refused_at - now).div_ceil(86400),
+                self.latest
+            )
+        })
+    }
+}
+
+/// Schedules upgrade checks of the pinned runner version and remembers which
+/// upgrade was already reported
+#[derive(Debug)]
+pub struct VersionChecker {
+    /// `None` disables checks
+    pinned: Option<RunnerVersion>,
+    interval: Duration,
+    last_check: Option<Instant>,
+    /// Latest release an upgrade was reported for, and whether the pinned
+    /// version was refused by then
+    alerted: Option<(String, bool)>,
+}
+
+impl VersionChecker {
+    pub fn new(pinned: Option<RunnerVersion>, interval: Duration) -> Self {
+        Self {
+            pinned,
+            interval,
+            last_check: None,
+            alerted: None,
+        }
+    }
+
+    /// The pinned version, if a check is due now
+    pub fn due(&mut self) -> Option<RunnerVersion> {
+        let pinned = self.pinned?;
+        if self.last_check.is_some_and(|at| at.elapsed() < self.interval) {
+            return None;
+        }
+        self.last_check = Some(Instant::now());
+        Some(pinned)
+    }
+
+    /// Whether `status` is an upgrade not reported yet. A pending upgrade is
+    /// reported again once GitHub starts refusing the pinned version.
+    pub fn should_alert(&mut self, status: &VersionStatus, now: u64) -> bool {
+        if !status.upgrade_available() {
+            return false;
+        }
+        let key = (status.latest.clone(), status.is_refused(now));
+        if self.alerted.as_ref() == Some(&key) {
+            return false;
+        }
+        self.alerted = Some(key);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::format_utc_timestamp;
+
+    #[test]
+    fn test_version_status() {
+        const DAY: u64 = 86400;
+        let now = 400 * DAY;
+        let release = |tag: &str, days_ago: u64| RunnerRelease {
+            tag_name: tag.to_string(),
+            published_at: Some(format_utc_timestamp(now - days_ago * DAY)),
+            prerelease: false,
+            draft: false,
+        };
+        let releases = vec![
+            release("v2.322.0", 10),
+            release("v2.321.0", 45),
+            release("v2.320.0", 90),
+            RunnerRelease {
+                prerelease: true,
+                ..release("v2.323.0", 1)
+            },
+        ];
+        assert!("2.321".parse::<RunnerVersion>().is_err());
+
+        let current = VersionStatus::new("2.322.0".parse().unwrap(), &releases, now).unwrap();
+        assert_eq!((current.latest.as_str(), current.minimum.as_str()), ("2.322.0", "2.321.0"));
+        assert!(current.warning(now).is_none());
+
+        // 2.322.0 came out 10 days ago: 20 days left
+        let due = VersionStatus::new("2.321.0".parse().unwrap(), &releases, now).unwrap();
+        assert_eq!(due.refused_at, Some(now + 20 * DAY));
+        assert!(!due.is_refused(now));
+        assert!(due.warning(now).unwrap().contains("in 20 day(s)"));
+
+        let refused = VersionStatus::new("v2.320.0".parse().unwrap(), &releases, now).unwrap();
+        assert!(refused.is_refused(now));
+
+        let mut checker = VersionChecker::new(Some("2.321.0".parse().unwrap()), Duration::MAX);
+        assert!(checker.due().is_some());
+        assert!(checker.due().is_none());
+        assert!(checker.should_alert(&due, now));
+        assert!(!checker.should_alert(&due, now));
+        assert!(checker.should_alert(&due, now + 20 * DAY));
+        assert!(!checker.should_alert(&current, now));
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Deserializer};
+use tracing::{info, warn};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::config::Config;
+use crate::github::GitHubClient;
+
+mod vault;
+
+pub use vault::{Vault, VaultAuth, VaultConfig};
+
+/// A token, key or password: wiped from memory when dropped, and shown as
+/// `[redacted]` by `Debug`. There is no `Display`, so it can't be formatted
+/// into log fields or error messages by accident; [`SecretString::expose`]
+/// hands out the value where it is actually used.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+/// GitHub credentials as fetched from a secrets provider
+#[derive(Clone, PartialEq, Eq)]
+pub struct GitHubSecrets {
+    pub token: SecretString,
+    /// PEM private key of the GitHub App; `None` if the provider has none
+    pub app_key: Option<Zeroizing<Vec<u8>>>,
+}
+
+/// A store the GitHub credentials are fetched from at runtime, instead of
+/// files on the host
+#[async_trait]
+pub trait SecretsProvider: Send + Sync {
+    /// Short name for logs, e.g. `vault`
+    fn name(&self) -> &'static str;
+    /// How often the credentials are fetched again to pick up rotations
+    fn refresh_interval(&self) -> Duration;
+    /// Current credentials, logging in to the provider or renewing its
+    /// session as needed
+    async fn fetch(&self) -> Result<GitHubSecrets>;
+}
+
+/// The configured secrets provider, if any
+pub fn provider(config: &Config) -> Result<Option<Arc<dyn SecretsProvider>>> {
+    Ok(match &config.vault {
+        Some(vault) => Some(Arc::new(Vault::new(vault.clone())?)),
+        None => None,
+    })
+}
+
+/// Fetch the credentials every refresh interval and swap changed ones into
+/// the client, like `rotation` does for files. A failed fetch keeps the
+/// current credentials. Stops once the client is dropped, e.g. after a
+/// reload replaced it.
+pub fn spawn_refresher(
+    provider: Arc<dyn SecretsProvider>,
+    github: &Arc<GitHubClient>,
+    mut current: GitHubSecrets,
+) {
+    let github = Arc::downgrade(github);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(provider.refresh_interval());
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let fetched = provider.fetch().await;
+            let Some(github) = github.upgrade() else {
+                break;
+            };
+            match fetched {
+                Ok(secrets) => apply(provider.name(), &github, &mut current, secrets),
+                Err(e) => warn!(
+                    provider = provider.name(),
+                    error = %e,
+                    "Failed to refresh credentials, keeping the current ones"
+                ),
+            }
+        }
+    });
+}
+
+fn apply(
+    provider: &str,
+    github: &GitHubClient,
+    current: &mut GitHubSecrets,
+    fetched: GitHubSecrets,
+) {
+    if fetched.token != current.token {
+        github.set_token(fetched.token.clone());
+        rotated(provider, "github_token");
+        current.token = fetched.token;
+    }
+
+    if fetched.app_key.is_some() && fetched.app_key != current.app_key {
+        let Some(app) = github.app() else {
+            return;
+        };
+        let key = fetched.app_key.unwrap_or_default();
+        match app.set_key(&key) {
+            Ok(()) => {
+                rotated(provider, "github_app_key");
+                current.app_key = Some(key);
+            }
+            // Retried on the next refresh
+            Err(e) => warn!(provider, error = %e, "Failed to rotate GitHub App key"),
+        }
+    }
+}
+
+fn rotated(provider: &str, credential: &'static str) {
+    info!(provider, credential, "Credential rotated");
+    metrics::counter!(
+        "runner_controller_credential_rotations_total",
+        "credential" => credential
+    )
+    .increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_string_is_redacted() {
+        let token = SecretString::from("ghp_secret");
+        assert_eq!(format!("{:?}", token), "[redacted]");
+        assert_eq!(format!("{:?}", Some(&token)), "Some([redacted])");
+        assert_eq!(token.expose(), "ghp_secret");
+    }
+}
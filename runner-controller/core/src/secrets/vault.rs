@@ -0,0 +1,268 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::header::HeaderValue;
+use reqwest::{Client, RequestBuilder};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+use zeroize::Zeroizing;
+
+use super::{GitHubSecrets, SecretString, SecretsProvider};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const VAULT_TOKEN_HEADER: &str = "X-Vault-Token";
+
+/// How the controller logs in to Vault
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum VaultAuth {
+    /// A Vault token read from a file, e.g. one kept fresh by Vault Agent;
+    /// read again whenever the current one can't be renewed
+    Token { file: PathBuf },
+    /// AppRole login with a secret ID read from a file
+    AppRole {
+        role_id: String,
+        secret_id_file: PathBuf,
+    },
+}
+
+/// GitHub credentials kept in Vault (`VAULT_ADDR`)
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultConfig {
+    pub addr: String,
+    pub auth: VaultAuth,
+    /// Secret read with `GET /v1/<path>`, e.g. `secret/data/runner-controller`
+    /// for a KV v2 engine mounted at `secret`
+    pub secret_path: String,
+    /// Field of the secret holding the GitHub token
+    pub token_field: String,
+    /// Field of the secret holding the GitHub App private key
+    pub app_key_field: String,
+    pub refresh_interval: Duration,
+}
+
+/// A Vault login, renewed before its TTL runs out
+struct Session {
+    token: SecretString,
+    /// When to renew; `None` for tokens that don't expire
+    renew_at: Option<Instant>,
+    renewable: bool,
+}
+
+impl Session {
+    /// Session for the token described by `auth` (a login's `auth` or a
+    /// lookup's `data`), renewed after two thirds of its TTL
+    fn new(token: SecretString, auth: &Value) -> Self {
+        let ttl = auth["lease_duration"]
+            .as_u64()
+            .or_else(|| auth["ttl"].as_u64())
+            .unwrap_or(0);
+        Self {
+            token,
+            renew_at: (ttl > 0).then(|| Instant::now() + Duration::from_secs(ttl * 2 / 3)),
+            renewable: auth["renewable"].as_bool().unwrap_or(false),
+        }
+    }
+
+    fn due(&self) -> bool {
+        self.renew_at.is_some_and(|at| Instant::now() >= at)
+    }
+}
+
+/// Fetches the GitHub credentials from a KV secret in Vault. The Vault
+/// token lives in memory only.
+pub struct Vault {
+    config: VaultConfig,
+    client: Client,
+    session: Mutex<Option<Session>>,
+}
+
+impl Vault {
+    pub fn new(config: VaultConfig) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent("runner-controller/0.1.0")
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create Vault HTTP client")?;
+        Ok(Self {
+            config,
+            client,
+            session: Mutex::new(None),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/v1/{}",
+            self.config.addr.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// Send a request, returning the JSON body or the errors Vault reported
+    async fn call(&self, request: RequestBuilder) -> Result<Value> {
+        let resp = request.send().await.context("Vault unreachable")?;
+        let status = resp.status();
+        let body: Value = resp.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let errors: Vec<&str> = body["errors"]
+                .as_array()
+                .map(|errors| errors.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            anyhow::bail!("Vault returned {}: {}", status, errors.join("; "));
+        }
+        Ok(body)
+    }
+
+    async fn login(&self) -> Result<Session> {
+        match &self.config.auth {
+            VaultAuth::Token { file } => {
+                let token = SecretString::from(read_trimmed(file)?);
+                let url = self.url("auth/token/lookup-self");
+                let request = with_token(self.client.get(url), &token);
+                let body = self.call(request).await.context("Vault token lookup failed")?;
+                Ok(Session::new(token, &body["data"]))
+            }
+            VaultAuth::AppRole {
+                role_id,
+                secret_id_file,
+            } => {
+                let secret_id = read_trimmed(secret_id_file)?;
+                let request = self
+                    .client
+                    .post(self.url("auth/approle/login"))
+                    .json(&json!({ "role_id": role_id, "secret_id": secret_id }));
+                let body = self.call(request).await.context("Vault AppRole login failed")?;
+                let token = body["auth"]["client_token"]
+                    .as_str()
+                    .context("Vault login returned no token")?;
+                info!(addr = %self.config.addr, "Logged in to Vault");
+                Ok(Session::new(token.into(), &body["auth"]))
+            }
+        }
+    }
+
+    /// A token valid for now: logs in first and renews when due. When
+    /// renewing fails, e.g. at the token's max TTL, it logs in again.
+    async fn token(&self) -> Result<SecretString> {
+        let mut session = self.session.lock().await;
+        let renewable = match session.as_ref() {
+            Some(current) if !current.due() => return Ok(current.token.clone()),
+            Some(current) if current.renewable => Some(current.token.clone()),
+            _ => None,
+        };
+        if let Some(token) = renewable {
+            let request = with_token(self.client.post(self.url("auth/token/renew-self")), &token);
+            match self.call(request).await {
+                Ok(body) => {
+                    *session = Some(Session::new(token.clone(), &body["auth"]));
+                    return Ok(token);
+                }
+                Err(e) => debug!(error = %e, "Vault token renewal failed, logging in again"),
+            }
+        }
+
+        let fresh = self.login().await?;
+        let token = fresh.token.clone();
+        *session = Some(fresh);
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for Vault {
+    fn name(&self) -> &'static str {
+        "vault"
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        self.config.refresh_interval
+    }
+
+    async fn fetch(&self) -> Result<GitHubSecrets> {
+        let path = &self.config.secret_path;
+        let token = self.token().await?;
+        let request = with_token(self.client.get(self.url(path)), &token);
+        let body = match self.call(request).await {
+            Ok(body) => body,
+            Err(e) => {
+                // A revoked token only shows here; log in again next time
+                *self.session.lock().await = None;
+                return Err(e.context(format!("Failed to read {} from Vault", path)));
+            }
+        };
+
+        let fields = secret_fields(&body)
+            .with_context(|| format!("Vault secret {} has no data", path))?;
+        let token = fields
+            .get(&self.config.token_field)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .with_context(|| {
+                format!("Vault secret {} has no {} field", path, self.config.token_field)
+            })?;
+        let app_key = fields
+            .get(&self.config.app_key_field)
+            .and_then(Value::as_str)
+            .map(|key| Zeroizing::new(key.as_bytes().to_vec()));
+        Ok(GitHubSecrets {
+            token: token.into(),
+            app_key,
+        })
+    }
+}
+
+/// `request` with the Vault token, marked sensitive so it never shows in a
+/// debug print of the request
+fn with_token(request: RequestBuilder, token: &SecretString) -> RequestBuilder {
+    match HeaderValue::from_str(token.expose()) {
+        Ok(mut value) => {
+            value.set_sensitive(true);
+            request.header(VAULT_TOKEN_HEADER, value)
+        }
+        // Fails the request when it is sent
+        Err(_) => request.header(VAULT_TOKEN_HEADER, token.expose()),
+    }
+}
+
+fn read_trimmed(path: &Path) -> Result<String> {
+    Ok(std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .to_string())
+}
+
+/// Fields of a secret from a KV v2 (`data.data`) or KV v1 (`data`) engine
+fn secret_fields(body: &Value) -> Option<&Map<String, Value>> {
+    let data = body["data"].as_object()?;
+    match data.get("data").and_then(Value::as_object) {
+        // KV v2 wraps the fields with their metadata
+        Some(fields) if data.contains_key("metadata") => Some(fields),
+        _ => Some(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_fields() {
+        let v2 = json!({
+            "data": {
+                "data": { "github_token": "ghp_v2" },
+                "metadata": { "version": 3 }
+            }
+        });
+        let v1 = json!({ "data": { "github_token": "ghp_v1", "data": "unrelated" } });
+
+        assert_eq!(secret_fields(&v2).unwrap()["github_token"], "ghp_v2");
+        assert_eq!(secret_fields(&v1).unwrap()["github_token"], "ghp_v1");
+        assert!(secret_fields(&json!({ "errors": [] })).is_none());
+    }
+}
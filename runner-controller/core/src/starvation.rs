@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+
+/// Tracks how long jobs we could serve have stayed queued while runners were idle.
+///
+/// A job that keeps waiting despite idle capacity points at a scheduling bug or
+/// a silent spawn failure loop rather than ordinary load.
+#[derive(Debug, Default)]
+pub struct StarvationTracker {
+    threshold: u32,
+    /// Consecutive cycles each queued job has been waiting with capacity available
+    cycles: HashMap<u64, u32>,
+    alerted: HashSet<u64>,
+}
+
+impl StarvationTracker {
+    /// `threshold` of 0 disables detection
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.threshold > 0
+    }
+
+    /// Record one poll cycle. Returns jobs that just crossed the threshold,
+    /// with their cycle count; each job is reported once.
+    pub fn observe(&mut self, queued: &[u64], has_capacity: bool) -> Vec<(u64, u32)> {
+        // Forget jobs that were picked up or cancelled
+        self.cycles.retain(|id, _| queued.contains(id));
+        self.alerted.retain(|id| queued.contains(id));
+
+        if !has_capacity {
+            return Vec::new();
+        }
+
+        let mut starved = Vec::new();
+        for &id in queued {
+            let count = self.cycles.entry(id).or_insert(0);
+            *count += 1;
+            if *count >= self.threshold && self.alerted.insert(id) {
+                starved.push((id, *count));
+            }
+        }
+        starved
+    }
+
+    /// Number of jobs currently over the threshold
+    pub fn starved_count(&self) -> usize {
+        self.alerted.len()
+    }
+
+    /// Jobs currently over the threshold, with their cycle count
+    pub fn starved(&self) -> Vec<(u64, u32)> {
+        let mut starved: Vec<(u64, u32)> = self
+            .alerted
+            .iter()
+            .map(|id| (*id, self.cycles.get(id).copied().unwrap_or(0)))
+            .collect();
+        starved.sort_unstable();
+        starved
+    }
+}
+
+/// Whether any of the label sets in `offered` can take a job requesting `requested` labels
+pub fn labels_match_any<'a>(
+    requested: &[String],
+    mut offered: impl Iterator<Item = &'a [String]>,
+) -> bool {
+    offered.any(|labels| labels_match(requested, labels))
+}
+
+/// Whether a job requesting `requested` labels can run on a runner with `offered` labels
+pub fn labels_match(requested: &[String], offered: &[String]) -> bool {
+    !requested.is_empty()
+        && requested
+            .iter()
+            .all(|r| offered.iter().any(|o| o.eq_ignore_ascii_case(r)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe_alerts_once() {
+        let mut tracker = StarvationTracker::new(3);
+
+        assert!(tracker.observe(&[1, 2], true).is_empty());
+        // No capacity: the cycle doesn't count
+        assert!(tracker.observe(&[1, 2], false).is_empty());
+        assert!(tracker.observe(&[1, 2], true).is_empty());
+        assert_eq!(tracker.observe(&[1, 2], true), vec![(1, 3), (2, 3)]);
+        assert!(tracker.observe(&[1, 2], true).is_empty());
+        assert_eq!(tracker.starved_count(), 2);
+
+        // Job 1 was picked up
+        assert!(tracker.observe(&[2], true).is_empty());
+        assert_eq!(tracker.starved_count(), 1);
+
+        let offered = vec!["self-hosted".to_string(), "nix".to_string()];
+        assert!(labels_match(&["Self-Hosted".to_string()], &offered));
+        assert!(!labels_match(&["ubuntu-latest".to_string()], &offered));
+    }
+
+    #[test]
+    fn test_unmatched_jobs() {
+        let pool = vec!["self-hosted".to_string(), "nix".to_string()];
+        let registered = vec!["self-hosted".to_string(), "gpu".to_string()];
+        let offered = || [pool.as_slice(), registered.as_slice()].into_iter();
+        assert!(labels_match_any(&["GPU".to_string()], offered()));
+        assert!(!labels_match_any(&["nix".to_string(), "gpu".to_string()], offered()));
+
+        // Without a capacity condition every cycle counts
+        let mut tracker = StarvationTracker::new(2);
+        assert!(tracker.observe(&[7], true).is_empty());
+        assert_eq!(tracker.observe(&[7, 8], true), vec![(7, 2)]);
+        assert_eq!(tracker.observe(&[7, 8], true), vec![(8, 2)]);
+        assert_eq!(tracker.starved(), vec![(7, 3), (8, 2)]);
+    }
+}
@@ -0,0 +1,1933 @@
+use std::hash::{BuildHasher, RandomState};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, RwLock, RwLockReadGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use redb::{
+    Database, Durability, Key, ReadTransaction, ReadableTable, ReadableTableMetadata,
+    TableDefinition, Value, WriteTransaction,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::config::Config;
+use crate::container::{BindMount, EnvVar};
+use crate::egress::EgressMode;
+use crate::github::WorkflowJob;
+use crate::runner_version::VersionStatus;
+
+/// Database file inside the state directory
+const DB_FILE: &str = "state.redb";
+
+const CONTAINERS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("containers");
+const SNAPSHOTS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("snapshots");
+const META_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("meta");
+const HISTORY_TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("history");
+const AUDIT_TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("audit");
+const POOLS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("pools");
+const CLEANUP_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("cleanup_retries");
+/// Archived log object keys and when they were uploaded, for retention
+const ARCHIVE_TABLE: TableDefinition<&str, u64> = TableDefinition::new("archived_logs");
+
+/// Name of the warm pool managed from `MAX_CONCURRENT_JOBS`/`RUNNER_LABELS`
+pub const DEFAULT_POOL: &str = "default";
+
+const CURRENT_CONFIG_KEY: &str = "config_current";
+const READY_PROBE_KEY: &str = "ready_probe";
+const INSTANCE_ID_KEY: &str = "instance_id";
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+const PAUSE_KEY: &str = "pause";
+
+/// Why a state database operation failed. Storage failures need an operator
+/// (full or broken disk); undecodable entries won't get better by retrying.
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("failed to create state directory {path:?}: {source}")]
+    Directory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to open state database {path:?}: {source}")]
+    Open {
+        path: PathBuf,
+        source: Box<redb::DatabaseError>,
+    },
+    /// Reading, writing or committing failed
+    #[error("state database error: {0}")]
+    Database(#[source] Box<redb::Error>),
+    #[error("failed to encode or decode state entry: {0}")]
+    Codec(#[from] serde_json::Error),
+    /// Stored data this build can't make sense of
+    #[error("corrupt state database: {0}")]
+    Corrupt(String),
+    #[error(
+        "state database has schema version {stored}, newer than this build's {current}; \
+         refusing to downgrade"
+    )]
+    SchemaTooNew { stored: u32, current: u32 },
+    #[error("state migration {version} failed: {source}")]
+    Migration {
+        version: u32,
+        source: Box<StateError>,
+    },
+    #[error("failed to copy state database {path:?}: {source}")]
+    Copy {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("state database {path:?} is in use; stop the controller first")]
+    InUse { path: PathBuf },
+}
+
+macro_rules! database_error {
+    ($($error:ty),*) => {
+        $(impl From<$error> for StateError {
+            fn from(e: $error) -> Self {
+                Self::Database(Box::new(e.into()))
+            }
+        })*
+    };
+}
+
+database_error!(
+    redb::Error,
+    redb::CompactionError,
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError
+);
+
+/// Upgrades stored entries from the previous schema version. Migrations run
+/// in order on open, inside the same write transaction that records the new
+/// version, so a failed migration leaves the database untouched.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&WriteTransaction) -> Result<(), StateError>,
+}
+
+/// Append new migrations here whenever a stored type changes incompatibly;
+/// never edit or reorder existing ones.
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "add job and runner metadata to container state",
+    apply: |txn| {
+        migrate_json(txn, CONTAINERS_TABLE, |state| {
+            for field in ["run_id", "workflow_name", "job_name", "runner_id"] {
+                state.entry(field).or_insert(serde_json::Value::Null);
+            }
+        })
+    },
+}];
+
+/// Current schema version: the last migration's
+fn schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// Copy every entry of a table into another database
+fn copy_table<K: Key + 'static, V: Value + 'static>(
+    from: &ReadTransaction,
+    to: &WriteTransaction,
+    definition: TableDefinition<K, V>,
+) -> Result<(), StateError> {
+    let source = from.open_table(definition)?;
+    let mut target = to.open_table(definition)?;
+    for entry in source.iter()? {
+        let (key, value) = entry?;
+        target.insert(key.value(), value.value())?;
+    }
+    Ok(())
+}
+
+/// Rewrite every JSON object in a string-keyed table
+fn migrate_json(
+    txn: &WriteTransaction,
+    definition: TableDefinition<&str, &[u8]>,
+    upgrade: fn(&mut serde_json::Map<String, serde_json::Value>),
+) -> Result<(), StateError> {
+    let mut table = txn.open_table(definition)?;
+    let mut updated = Vec::new();
+    for entry in table.iter()? {
+        let (key, value) = entry?;
+        let mut json: serde_json::Value = serde_json::from_slice(value.value())
+            .map_err(|e| StateError::Corrupt(format!("undecodable entry {}: {}", key.value(), e)))?;
+        if let Some(object) = json.as_object_mut() {
+            upgrade(object);
+            updated.push((key.value().to_string(), serde_json::to_vec(&json)?));
+        }
+    }
+    for (key, data) in updated {
+        table.insert(key.as_str(), data.as_slice())?;
+    }
+    Ok(())
+}
+
+/// Oldest history entries are pruned beyond this count
+const MAX_HISTORY_ENTRIES: u64 = 10_000;
+
+/// Configuration snapshot stored in the meta table, keyed by hash
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ConfigSnapshot {
+    pub hash: String,
+    pub recorded_at: u64, // unix timestamp
+    pub config: serde_json::Value,
+}
+
+/// A job refused by a runner-usage policy; kept indefinitely for compliance
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditRecord {
+    /// Position in the audit log, usable as a pagination cursor
+    #[serde(default)]
+    pub id: u64,
+    pub timestamp: u64, // unix timestamp
+    pub job_id: u64,
+    pub rule: String,
+    pub reason: String,
+    pub repository: Option<String>,
+    pub actor: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(
+        job_id: u64,
+        rule: &str,
+        reason: &str,
+        repository: Option<String>,
+        actor: Option<String>,
+    ) -> Self {
+        Self {
+            id: 0,
+            timestamp: unix_now(),
+            job_id,
+            rule: rule.to_string(),
+            reason: reason.to_string(),
+            repository,
+            actor,
+        }
+    }
+}
+
+/// A controller event, tagged with the configuration in effect when it happened
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HistoryEntry {
+    /// Position in the history log, usable as a pagination cursor
+    #[serde(default)]
+    pub id: u64,
+    pub timestamp: u64, // unix timestamp
+    pub event: String,
+    pub container: Option<String>,
+    /// Repository the container served, used to scope tenant views
+    #[serde(default)]
+    pub repository: Option<String>,
+    pub detail: Option<String>,
+    pub config_hash: Option<String>,
+    /// `cleaned_up` only: final conclusion of the container's job, e.g.
+    /// `success`, `failure` or `cancelled`
+    #[serde(default)]
+    pub conclusion: Option<String>,
+    /// `cleaned_up` only: workflow of the container's last job
+    #[serde(default)]
+    pub workflow: Option<String>,
+    /// `cleaned_up` only: runner labels of the container's last job
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// `cleaned_up` only: CPU time the container used over its lifetime,
+    /// from its cgroup
+    #[serde(default)]
+    pub cpu_seconds: Option<f64>,
+    /// `cleaned_up` only: how long the container existed
+    #[serde(default)]
+    pub wall_seconds: Option<u64>,
+}
+
+const POOL_SNAPSHOT_KEY: &str = "pool";
+const UNMATCHED_JOBS_KEY: &str = "unmatched_jobs";
+const RUNNER_VERSION_KEY: &str = "runner_version";
+
+/// Order of log query results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Page through and filter the history and audit logs. Entry IDs are cursors:
+/// pass the last ID seen as `before` (newest first) or `after` (oldest first).
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogQuery {
+    #[serde(default = "default_log_limit")]
+    pub limit: usize,
+    #[serde(default)]
+    #[param(inline)]
+    pub order: SortOrder,
+    /// Only entries with a smaller ID
+    pub before: Option<u64>,
+    /// Only entries with a larger ID
+    pub after: Option<u64>,
+    /// Only entries at or after this unix timestamp
+    pub since: Option<u64>,
+    /// Only entries before this unix timestamp
+    pub until: Option<u64>,
+    /// Repository (`owner/repo`, case-insensitive)
+    pub repo: Option<String>,
+    /// History only: event kind (e.g. `timed_out`)
+    pub event: Option<String>,
+    /// History only: container name
+    pub container: Option<String>,
+    /// History only: runner label of the job (`cleaned_up` entries)
+    pub label: Option<String>,
+    /// History only: job conclusion, e.g. `failure` (`cleaned_up` entries)
+    pub conclusion: Option<String>,
+    /// Audit only: policy rule that rejected the job
+    pub rule: Option<String>,
+}
+
+fn default_log_limit() -> usize {
+    100
+}
+
+/// Most entries an API client may request per page
+pub const MAX_LOG_LIMIT: usize = 1000;
+
+impl Default for LogQuery {
+    fn default() -> Self {
+        Self {
+            limit: default_log_limit(),
+            order: SortOrder::default(),
+            before: None,
+            after: None,
+            since: None,
+            until: None,
+            repo: None,
+            event: None,
+            container: None,
+            label: None,
+            conclusion: None,
+            rule: None,
+        }
+    }
+}
+
+impl LogQuery {
+    /// Filters common to both logs
+    fn matches(&self, timestamp: u64, repository: Option<&str>) -> bool {
+        self.since.is_none_or(|since| timestamp >= since)
+            && self.until.is_none_or(|until| timestamp < until)
+            && self
+                .repo
+                .as_ref()
+                .is_none_or(|repo| repository.is_some_and(|r| r.eq_ignore_ascii_case(repo)))
+    }
+}
+
+/// Dispatch paused by an operator (`POST /pause`): no runners are spawned
+/// until it is resumed, across reloads and restarts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PauseState {
+    /// Unix timestamp of the pause
+    pub since: u64,
+    pub reason: Option<String>,
+}
+
+impl PauseState {
+    pub fn new(reason: Option<String>) -> Self {
+        Self {
+            since: unix_now(),
+            reason,
+        }
+    }
+}
+
+/// Periodic summary of the pool, written at low durability after each poll
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    pub taken_at: u64, // unix timestamp
+    pub pool_size: usize,
+    pub active_containers: usize,
+}
+
+impl PoolSnapshot {
+    pub fn new(pool_size: usize, active_containers: usize) -> Self {
+        Self {
+            taken_at: unix_now(),
+            pool_size,
+            active_containers,
+        }
+    }
+
+    /// Seconds since this snapshot was taken
+    pub fn age_seconds(&self) -> u64 {
+        unix_now().saturating_sub(self.taken_at)
+    }
+}
+
+/// Where a pool's desired state last came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PoolSource {
+    Config,
+    Api,
+}
+
+/// Cleanup step that failed and is retried on later polls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanupKind {
+    /// Remove a runner registration from GitHub
+    Deregister,
+    /// Destroy a local container
+    Destroy,
+}
+
+/// Queued job whose labels no pool or registered runner offers
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UnmatchedJob {
+    pub job_id: u64,
+    pub job_name: String,
+    pub labels: Vec<String>,
+    /// Poll cycles the job has been queued without a matching runner
+    pub cycles: u32,
+}
+
+/// A failed cleanup step waiting for its next attempt
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PendingCleanup {
+    pub kind: CleanupKind,
+    /// Runner name for `Deregister`, container name for `Destroy`
+    pub target: String,
+    pub attempts: u32,
+    pub first_failed_at: u64,
+    pub next_attempt_at: u64,
+    pub last_error: String,
+}
+
+/// First retry delay; doubled per failed attempt up to `MAX_CLEANUP_BACKOFF_SECS`
+const CLEANUP_BACKOFF_SECS: u64 = 30;
+const MAX_CLEANUP_BACKOFF_SECS: u64 = 3600;
+
+impl PendingCleanup {
+    pub fn new(kind: CleanupKind, target: &str, error: String) -> Self {
+        let now = unix_now();
+        let mut cleanup = Self {
+            kind,
+            target: target.to_string(),
+            attempts: 0,
+            first_failed_at: now,
+            next_attempt_at: now,
+            last_error: String::new(),
+        };
+        cleanup.failed(error);
+        cleanup
+    }
+
+    fn key(&self) -> String {
+        format!("{}:{}", self.kind.as_str(), self.target)
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.next_attempt_at <= unix_now()
+    }
+
+    /// Record a failed attempt and schedule the next one with exponential backoff
+    pub fn failed(&mut self, error: String) {
+        let delay = CLEANUP_BACKOFF_SECS
+            .saturating_mul(1 << self.attempts.min(16))
+            .min(MAX_CLEANUP_BACKOFF_SECS);
+        self.attempts += 1;
+        self.next_attempt_at = unix_now() + delay;
+        self.last_error = error;
+    }
+}
+
+impl CleanupKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Deregister => "deregister",
+            Self::Destroy => "destroy",
+        }
+    }
+}
+
+/// Desired state of a warm pool; the controller converges running containers to it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PoolSpec {
+    pub size: usize,
+    pub labels: Vec<String>,
+    /// Container configuration (config path or flake reference) or nspawn
+    /// image; unset uses `LABEL_TEMPLATES`, then the backend default
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Extra host paths bound into the pool's containers
+    #[serde(default)]
+    pub binds: Vec<BindMount>,
+    /// Where the pool's containers may connect to
+    #[serde(default)]
+    pub egress: EgressMode,
+    /// Environment variables for the runner process
+    #[serde(default)]
+    pub env: Vec<EnvVar>,
+    /// Files from `SECRETS_DIR` provisioned into the pool's containers
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    pub source: PoolSource,
+    pub updated_at: u64, // unix timestamp
+}
+
+impl PoolSpec {
+    pub fn new(size: usize, labels: Vec<String>, source: PoolSource) -> Self {
+        Self {
+            size,
+            labels,
+            template: None,
+            binds: Vec::new(),
+            egress: EgressMode::default(),
+            env: Vec::new(),
+            secrets: Vec::new(),
+            source,
+            updated_at: unix_now(),
+        }
+    }
+
+    /// The warm pool as configured, used until a spec is stored
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            binds: config.pool_binds.clone(),
+            egress: config.egress_mode,
+            env: config.pool_env.clone(),
+            secrets: config.pool_secrets.clone(),
+            ..Self::new(
+                config.max_concurrent_jobs,
+                config.runner_labels.clone(),
+                PoolSource::Config,
+            )
+        }
+    }
+}
+
+/// Fields of the pool spec to change; `None` keeps the current value
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct PoolUpdate {
+    #[serde(default)]
+    pub size: Option<usize>,
+    #[serde(default)]
+    pub labels: Option<Vec<String>>,
+    /// Empty string clears the template
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Replaces the extra bind mounts; each must be allowed by `BIND_ALLOWLIST`
+    #[serde(default)]
+    pub binds: Option<Vec<BindMount>>,
+    #[serde(default)]
+    pub egress: Option<EgressMode>,
+    /// Replaces the runner environment variables
+    #[serde(default)]
+    pub env: Option<Vec<EnvVar>>,
+    /// Replaces the secret files; each must exist in `SECRETS_DIR`
+    #[serde(default)]
+    pub secrets: Option<Vec<String>>,
+}
+
+/// Where a container is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerPhase {
+    /// Created, the runner is not online on GitHub yet
+    Provisioning,
+    /// The runner is online and waiting for a job
+    Registered,
+    /// The runner is busy with a job
+    Running,
+    /// The runner finished and the container awaits teardown or reuse
+    Completing,
+}
+
+/// How long each phase of bringing up a runner took
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct SpawnTimings {
+    /// Fetching the registration token and minting the job token
+    pub token_ms: Option<u64>,
+    /// Creating and starting the container
+    pub create_ms: Option<u64>,
+    /// From container start until the runner was online on GitHub
+    pub registered_after_secs: Option<u64>,
+    /// From container start until the runner picked up its first job
+    pub job_started_after_secs: Option<u64>,
+}
+
+impl SpawnTimings {
+    /// Whether registration or job pickup is still to be observed
+    pub fn is_pending(&self) -> bool {
+        self.registered_after_secs.is_none() || self.job_started_after_secs.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerState {
+    /// Pool slot, or `None` for containers spawned via the external scheduler API
+    pub slot: Option<usize>,
+    pub started_at: u64, // unix timestamp
+    /// Kernel boot ID at spawn time; the monotonic reference is only valid within the same boot
+    #[serde(default)]
+    pub boot_id: Option<String>,
+    /// Seconds since boot (CLOCK_BOOTTIME) at spawn time, immune to wall-clock steps
+    #[serde(default)]
+    pub started_monotonic: Option<u64>,
+    /// Job this container runs: requested via the external scheduler API,
+    /// or picked up by a pool runner
+    #[serde(default)]
+    pub job_id: Option<u64>,
+    /// Workflow run of the job, once known
+    #[serde(default)]
+    pub run_id: Option<u64>,
+    #[serde(default)]
+    pub workflow_name: Option<String>,
+    #[serde(default)]
+    pub job_name: Option<String>,
+    /// GitHub runner ID, once the runner has registered
+    #[serde(default)]
+    pub runner_id: Option<u64>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// When the job-scoped GitHub App token was last minted (unix timestamp)
+    #[serde(default)]
+    pub job_token_minted_at: Option<u64>,
+    /// Repository whose jobs this container serves
+    #[serde(default)]
+    pub repository: Option<String>,
+    /// GitHub assigned the job to this container's runner (external scheduler API only)
+    #[serde(default)]
+    pub claim_confirmed: bool,
+    #[serde(default)]
+    pub timings: SpawnTimings,
+    /// Template the container was created from, if not the backend default
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Index of the host GPU passed through to this container
+    #[serde(default)]
+    pub gpu: Option<usize>,
+    /// Jobs this container finished before its current one (container reuse)
+    #[serde(default)]
+    pub jobs_served: u32,
+    /// When a reused container was created; `started_at` then marks when its
+    /// runner re-registered for the current job
+    #[serde(default)]
+    pub container_started_at: Option<u64>,
+}
+
+impl ContainerState {
+    pub fn new(slot: usize) -> Self {
+        Self {
+            slot: Some(slot),
+            ..Self::started_now()
+        }
+    }
+
+    /// State for a container requested by an external scheduler
+    pub fn new_external(job_id: u64, labels: Vec<String>) -> Self {
+        Self {
+            job_id: Some(job_id),
+            labels,
+            ..Self::started_now()
+        }
+    }
+
+    /// Blank state stamped with the current wall and monotonic time
+    fn started_now() -> Self {
+        Self {
+            slot: None,
+            started_at: unix_now(),
+            boot_id: current_boot_id(),
+            started_monotonic: monotonic_now(),
+            job_id: None,
+            run_id: None,
+            workflow_name: None,
+            job_name: None,
+            runner_id: None,
+            labels: Vec::new(),
+            job_token_minted_at: None,
+            repository: None,
+            claim_confirmed: false,
+            timings: SpawnTimings::default(),
+            template: None,
+            gpu: None,
+            jobs_served: 0,
+            container_started_at: None,
+        }
+    }
+
+    /// State for the next job of a reused container: job details and timings
+    /// start over, the container's own settings carry over
+    pub fn next_job(&self) -> Self {
+        Self {
+            slot: self.slot,
+            labels: self.labels.clone(),
+            repository: self.repository.clone(),
+            template: self.template.clone(),
+            gpu: self.gpu,
+            jobs_served: self.jobs_served + 1,
+            container_started_at: Some(self.container_started_at.unwrap_or(self.started_at)),
+            ..Self::started_now()
+        }
+    }
+
+    /// Lifecycle phase; `completed` is whether the runner service has exited
+    pub fn phase(&self, completed: bool) -> ContainerPhase {
+        if completed {
+            ContainerPhase::Completing
+        } else if self.timings.job_started_after_secs.is_some() {
+            ContainerPhase::Running
+        } else if self.timings.registered_after_secs.is_some() || self.runner_id.is_some() {
+            ContainerPhase::Registered
+        } else {
+            ContainerPhase::Provisioning
+        }
+    }
+
+    /// Seconds since the container was created, across reuses
+    pub fn container_age_seconds(&self) -> u64 {
+        match self.container_started_at {
+            Some(created) => unix_now().saturating_sub(created),
+            None => self.running_seconds(),
+        }
+    }
+
+    /// Record what GitHub knows about the job this container runs
+    pub fn set_job(&mut self, job: &WorkflowJob) {
+        self.job_id = Some(job.id);
+        self.run_id = Some(job.run_id).filter(|&id| id != 0);
+        self.workflow_name = job.workflow_name.clone();
+        self.job_name = Some(job.name.clone()).filter(|n| !n.is_empty());
+    }
+
+    /// Record that a job token was (re)minted now
+    pub fn mark_job_token_minted(&mut self) {
+        self.job_token_minted_at = Some(unix_now());
+    }
+
+    /// Seconds since the job token was minted, if one was
+    pub fn job_token_age_seconds(&self) -> Option<u64> {
+        self.job_token_minted_at.map(|t| unix_now().saturating_sub(t))
+    }
+
+    /// Returns how long this container has been running in seconds.
+    ///
+    /// Uses the monotonic reference when it was recorded during the current boot,
+    /// so NTP steps or manual clock changes don't skew timeouts. Falls back to
+    /// wall-clock time for entries from older versions or a previous boot.
+    pub fn running_seconds(&self) -> u64 {
+        self.monotonic_running_seconds()
+            .unwrap_or_else(|| unix_now().saturating_sub(self.started_at))
+    }
+
+    /// Seconds since the monotonic reference, if it was recorded this boot
+    fn monotonic_running_seconds(&self) -> Option<u64> {
+        let (Some(boot_id), Some(started)) = (&self.boot_id, self.started_monotonic) else {
+            return None;
+        };
+        if current_boot_id().as_deref() != Some(boot_id.as_str()) {
+            return None;
+        }
+        monotonic_now().map(|now| now.saturating_sub(started))
+    }
+
+    /// Whether `running_seconds` can be trusted to enforce timeouts. Without
+    /// a monotonic reference from this boot the wall-clock start time is
+    /// checked: one in the future, older than any container lives, or after
+    /// this boot for a container spawned during an earlier one means the
+    /// clock was stepped since it was recorded.
+    pub fn start_time_plausible(&self) -> bool {
+        if self.monotonic_running_seconds().is_some() {
+            return true;
+        }
+        let now = unix_now();
+        if self.started_at > now + CLOCK_SKEW_TOLERANCE_SECS {
+            return false;
+        }
+        let age = now - self.started_at.min(now);
+        if age > MAX_PLAUSIBLE_AGE_SECS {
+            return false;
+        }
+        let earlier_boot = self.boot_id.is_some() && self.boot_id != current_boot_id();
+        !(earlier_boot && monotonic_now().is_some_and(|uptime| age < uptime))
+    }
+
+    /// Start the clock over from now, for a start time that can't be trusted
+    pub fn restart_clock(&mut self) {
+        self.started_at = unix_now();
+        self.boot_id = current_boot_id();
+        self.started_monotonic = monotonic_now();
+    }
+}
+
+/// Wall-clock start times this far ahead of now still count as clock jitter
+const CLOCK_SKEW_TOLERANCE_SECS: u64 = 300;
+/// Longer than any container lives, reuse included; GitHub cancels jobs on
+/// self-hosted runners after five days
+const MAX_PLAUSIBLE_AGE_SECS: u64 = 30 * 86400;
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+/// Unix time of a UTC timestamp such as `2024-01-15T10:23:45Z` (the date and
+/// time may also be separated by a space); anything after the seconds is ignored
+pub fn parse_utc_timestamp(stamp: &str) -> Option<u64> {
+    let stamp = stamp.get(..19)?;
+    let (date, time) = stamp.split_once(['T', ' '])?;
+
+    let mut date = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hh, mm, ss) = (time.next()??, time.next()??, time.next()??);
+
+    // Days since the epoch for a proleptic Gregorian date (Howard Hinnant's algorithm)
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    u64::try_from(days * 86400 + hh * 3600 + mm * 60 + ss).ok()
+}
+
+/// Format unix seconds as `2024-01-15T10:23:45Z`, the inverse of `parse_utc_timestamp`
+pub fn format_utc_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let secs = timestamp % 86400;
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        y,
+        m,
+        d,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Kernel boot ID, read once per process
+fn current_boot_id() -> Option<String> {
+    static BOOT_ID: OnceLock<Option<String>> = OnceLock::new();
+    BOOT_ID
+        .get_or_init(|| {
+            std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+                .ok()
+                .map(|s| s.trim().to_string())
+        })
+        .clone()
+}
+
+/// Bring the database up to the current schema version
+fn migrate(txn: &WriteTransaction) -> Result<(), StateError> {
+    let stored = {
+        let table = txn.open_table(META_TABLE)?;
+        let value = table.get(SCHEMA_VERSION_KEY)?;
+        match value {
+            Some(v) => {
+                let version = String::from_utf8_lossy(v.value()).to_string();
+                version.parse::<u32>().map_err(|_| {
+                    StateError::Corrupt(format!("invalid schema version '{}'", version))
+                })?
+            }
+            None => 0,
+        }
+    };
+
+    let current = schema_version();
+    if stored > current {
+        return Err(StateError::SchemaTooNew { stored, current });
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > stored) {
+        info!(
+            version = migration.version,
+            description = migration.description,
+            "Migrating state database"
+        );
+        (migration.apply)(txn).map_err(|e| StateError::Migration {
+            version: migration.version,
+            source: Box::new(e),
+        })?;
+    }
+
+    if stored != current {
+        let mut table = txn.open_table(META_TABLE)?;
+        table.insert(SCHEMA_VERSION_KEY, current.to_string().as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Seconds since boot from /proc/uptime (CLOCK_BOOTTIME, includes suspend)
+fn monotonic_now() -> Option<u64> {
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    let secs: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    Some(secs as u64)
+}
+
+/// Size of the state database, for metrics
+#[derive(Debug, Clone, Copy)]
+pub struct DbStats {
+    /// Size of the file on disk
+    pub file_bytes: u64,
+    /// Keys and values as stored, without indexing overhead
+    pub stored_bytes: u64,
+    /// Free space inside the file that only compaction gives back
+    pub fragmented_bytes: u64,
+}
+
+pub struct StateDb {
+    /// Transactions are begun under the read lock; compaction takes the
+    /// write lock, as it needs the database to itself
+    db: RwLock<Database>,
+    /// State directory the database file lives in
+    dir: PathBuf,
+    /// Durability for low-value writes (snapshots); lifecycle writes are always immediate
+    low_value_durability: Durability,
+    /// Hash of the configuration in effect, used to tag history entries
+    config_hash: Mutex<Option<String>>,
+    /// Every recorded history entry, for live subscribers (`GET /events`)
+    events: broadcast::Sender<HistoryEntry>,
+}
+
+/// History entries buffered per live subscriber before it starts missing some
+const EVENT_BUFFER: usize = 256;
+
+impl StateDb {
+    /// Open or create the state database
+    pub fn open(state_dir: &Path, low_value_durability: Durability) -> Result<Self, StateError> {
+        std::fs::create_dir_all(state_dir).map_err(|source| StateError::Directory {
+            path: state_dir.to_path_buf(),
+            source,
+        })?;
+
+        let db_path = state_dir.join(DB_FILE);
+        let db = Database::create(&db_path).map_err(|e| StateError::Open {
+            path: db_path.clone(),
+            source: Box::new(e),
+        })?;
+
+        // Ensure table exists
+        let write_txn = db.begin_write()?;
+        {
+            let _ = write_txn.open_table(CONTAINERS_TABLE)?;
+            let _ = write_txn.open_table(SNAPSHOTS_TABLE)?;
+            let _ = write_txn.open_table(META_TABLE)?;
+            let _ = write_txn.open_table(HISTORY_TABLE)?;
+            let _ = write_txn.open_table(AUDIT_TABLE)?;
+            let _ = write_txn.open_table(POOLS_TABLE)?;
+            let _ = write_txn.open_table(CLEANUP_TABLE)?;
+            let _ = write_txn.open_table(ARCHIVE_TABLE)?;
+        }
+        migrate(&write_txn)?;
+        write_txn.commit()?;
+
+        Ok(Self {
+            db: RwLock::new(db),
+            dir: state_dir.to_path_buf(),
+            low_value_durability,
+            config_hash: Mutex::new(None),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        })
+    }
+
+    /// Record the effective configuration; stores a new snapshot and a
+    /// `config_changed` history entry if it differs from the last one.
+    /// Returns true when the configuration changed.
+    pub fn record_config(&self, hash: &str, config: serde_json::Value) -> Result<bool, StateError> {
+        let previous = {
+            let read_txn = self.db().begin_read()?;
+            let table = read_txn.open_table(META_TABLE)?;
+            let value = table.get(CURRENT_CONFIG_KEY)?;
+            value.map(|v| String::from_utf8_lossy(v.value()).to_string())
+        };
+
+        *self.config_hash.lock().unwrap() = Some(hash.to_string());
+
+        if previous.as_deref() == Some(hash) {
+            return Ok(false);
+        }
+
+        let snapshot = ConfigSnapshot {
+            hash: hash.to_string(),
+            recorded_at: unix_now(),
+            config,
+        };
+        let data = serde_json::to_vec(&snapshot)?;
+
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(META_TABLE)?;
+            table.insert(format!("config:{}", hash).as_str(), data.as_slice())?;
+            table.insert(CURRENT_CONFIG_KEY, hash.as_bytes())?;
+        }
+        write_txn.commit()?;
+
+        let detail = match previous {
+            Some(prev) => format!("{} -> {}", prev, hash),
+            None => hash.to_string(),
+        };
+        self.record_event("config_changed", None, None, Some(detail))?;
+
+        Ok(true)
+    }
+
+    /// Look up a stored configuration snapshot by hash
+    pub fn get_config_snapshot(&self, hash: &str) -> Result<Option<ConfigSnapshot>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(META_TABLE)?;
+
+        match table.get(format!("config:{}", hash).as_str())? {
+            Some(data) => Ok(Some(serde_json::from_slice(data.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Hash of the configuration currently in effect
+    pub fn current_config_hash(&self) -> Option<String> {
+        self.config_hash.lock().unwrap().clone()
+    }
+
+    /// Append an event to the history, tagged with the current config hash
+    pub fn record_event(
+        &self,
+        event: &str,
+        container: Option<&str>,
+        repository: Option<&str>,
+        detail: Option<String>,
+    ) -> Result<(), StateError> {
+        self.append_history(HistoryEntry {
+            id: 0,
+            timestamp: unix_now(),
+            event: event.to_string(),
+            container: container.map(|c| c.to_string()),
+            repository: repository.map(|r| r.to_string()),
+            detail,
+            config_hash: self.current_config_hash(),
+            conclusion: None,
+            workflow: None,
+            labels: Vec::new(),
+            cpu_seconds: None,
+            wall_seconds: None,
+        })
+    }
+
+    /// Record a `cleaned_up` event with the conclusion of the container's job
+    /// and the resources the container used
+    pub fn record_cleanup(
+        &self,
+        container: &str,
+        state: Option<&ContainerState>,
+        conclusion: Option<String>,
+        cpu_seconds: Option<f64>,
+    ) -> Result<(), StateError> {
+        self.append_history(HistoryEntry {
+            id: 0,
+            timestamp: unix_now(),
+            event: "cleaned_up".to_string(),
+            container: Some(container.to_string()),
+            repository: state.and_then(|s| s.repository.clone()),
+            detail: None,
+            config_hash: self.current_config_hash(),
+            conclusion,
+            workflow: state.and_then(|s| s.workflow_name.clone()),
+            labels: state.map(|s| s.labels.clone()).unwrap_or_default(),
+            cpu_seconds,
+            wall_seconds: state.map(ContainerState::running_seconds),
+        })
+    }
+
+    fn append_history(&self, mut entry: HistoryEntry) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(HISTORY_TABLE)?;
+            let next_id = table.last()?.map(|(k, _)| k.value() + 1).unwrap_or(0);
+            entry.id = next_id;
+            let data = serde_json::to_vec(&entry)?;
+            table.insert(next_id, data.as_slice())?;
+
+            // Prune the oldest entries beyond the retention limit
+            if table.len()? > MAX_HISTORY_ENTRIES {
+                let cutoff = next_id.saturating_sub(MAX_HISTORY_ENTRIES);
+                table.retain_in(..=cutoff, |_, _| false)?;
+            }
+        }
+        write_txn.commit()?;
+
+        // No subscribers is fine
+        let _ = self.events.send(entry);
+        Ok(())
+    }
+
+    /// Receive history entries as they are recorded
+    pub fn subscribe_events(&self) -> broadcast::Receiver<HistoryEntry> {
+        self.events.subscribe()
+    }
+
+    /// Persist a policy rejection to the audit table
+    pub fn record_rejection(&self, record: &AuditRecord) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(AUDIT_TABLE)?;
+            let next_id = table.last()?.map(|(k, _)| k.value() + 1).unwrap_or(0);
+            let data = serde_json::to_vec(&AuditRecord {
+                id: next_id,
+                ..record.clone()
+            })?;
+            table.insert(next_id, data.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Audit records matching `query`, in the requested order
+    pub fn query_rejections(
+        &self,
+        query: &LogQuery,
+        visible: impl Fn(&AuditRecord) -> bool,
+    ) -> Result<Vec<AuditRecord>, StateError> {
+        self.query_log(AUDIT_TABLE, query, |id, record: &mut AuditRecord| {
+            record.id = id;
+            query.matches(record.timestamp, record.repository.as_deref())
+                && query.rule.as_ref().is_none_or(|r| *r == record.rule)
+                && visible(record)
+        })
+    }
+
+    /// History entries matching `query`, in the requested order
+    pub fn query_history(
+        &self,
+        query: &LogQuery,
+        visible: impl Fn(&HistoryEntry) -> bool,
+    ) -> Result<Vec<HistoryEntry>, StateError> {
+        self.query_log(HISTORY_TABLE, query, |id, entry: &mut HistoryEntry| {
+            entry.id = id;
+            query.matches(entry.timestamp, entry.repository.as_deref())
+                && query.event.as_ref().is_none_or(|e| *e == entry.event)
+                && query
+                    .container
+                    .as_ref()
+                    .is_none_or(|c| entry.container.as_ref() == Some(c))
+                && query
+                    .label
+                    .as_ref()
+                    .is_none_or(|l| entry.labels.iter().any(|e| e.eq_ignore_ascii_case(l)))
+                && query
+                    .conclusion
+                    .as_ref()
+                    .is_none_or(|c| entry.conclusion.as_ref() == Some(c))
+                && visible(entry)
+        })
+    }
+
+    /// Walk an append-only log between the query's cursors, keeping up to
+    /// `limit` entries accepted by `keep`
+    fn query_log<T: DeserializeOwned>(
+        &self,
+        definition: TableDefinition<u64, &[u8]>,
+        query: &LogQuery,
+        keep: impl Fn(u64, &mut T) -> bool,
+    ) -> Result<Vec<T>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(definition)?;
+
+        let start = query.after.map_or(0, |after| after.saturating_add(1));
+        let end = query.before.unwrap_or(u64::MAX);
+        let mut entries = Vec::new();
+        if start >= end {
+            return Ok(entries);
+        }
+
+        let mut range = table.range(start..end)?;
+        while entries.len() < query.limit {
+            let next = match query.order {
+                SortOrder::Desc => range.next_back(),
+                SortOrder::Asc => range.next(),
+            };
+            let Some(entry) = next else {
+                break;
+            };
+            let (key, value) = entry?;
+            let mut item: T = serde_json::from_slice(value.value())?;
+            if keep(key.value(), &mut item) {
+                entries.push(item);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Record the latest pool snapshot.
+    ///
+    /// Written at the configured low-value durability, so with `Eventual` these
+    /// commits are batched and flushed by the next lifecycle write.
+    pub fn put_pool_snapshot(&self, snapshot: &PoolSnapshot) -> Result<(), StateError> {
+        let data = serde_json::to_vec(snapshot)?;
+        let mut write_txn = self.db().begin_write()?;
+        write_txn.set_durability(self.low_value_durability);
+        {
+            let mut table = write_txn.open_table(SNAPSHOTS_TABLE)?;
+            table.insert(POOL_SNAPSHOT_KEY, data.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Get the latest pool snapshot, if any
+    pub fn get_pool_snapshot(&self) -> Result<Option<PoolSnapshot>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(SNAPSHOTS_TABLE)?;
+
+        match table.get(POOL_SNAPSHOT_KEY)? {
+            Some(data) => Ok(Some(serde_json::from_slice(data.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Replace the list of queued jobs no runner can take. Written at the
+    /// low-value durability, like the pool snapshot.
+    pub fn put_unmatched_jobs(&self, jobs: &[UnmatchedJob]) -> Result<(), StateError> {
+        let data = serde_json::to_vec(jobs)?;
+        let mut write_txn = self.db().begin_write()?;
+        write_txn.set_durability(self.low_value_durability);
+        {
+            let mut table = write_txn.open_table(SNAPSHOTS_TABLE)?;
+            table.insert(UNMATCHED_JOBS_KEY, data.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Queued jobs no runner can take, as of the last queue check
+    pub fn list_unmatched_jobs(&self) -> Result<Vec<UnmatchedJob>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(SNAPSHOTS_TABLE)?;
+
+        match table.get(UNMATCHED_JOBS_KEY)? {
+            Some(data) => Ok(serde_json::from_slice(data.value())?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Record the outcome of the last runner version check
+    pub fn put_runner_version(&self, status: &VersionStatus) -> Result<(), StateError> {
+        let data = serde_json::to_vec(status)?;
+        let mut write_txn = self.db().begin_write()?;
+        write_txn.set_durability(self.low_value_durability);
+        {
+            let mut table = write_txn.open_table(SNAPSHOTS_TABLE)?;
+            table.insert(RUNNER_VERSION_KEY, data.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Outcome of the last runner version check, if any
+    pub fn get_runner_version(&self) -> Result<Option<VersionStatus>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(SNAPSHOTS_TABLE)?;
+
+        match table.get(RUNNER_VERSION_KEY)? {
+            Some(data) => Ok(Some(serde_json::from_slice(data.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Identifier of this controller instance. Generated on first use and kept
+    /// across restarts, so runners registered before a restart stay ours.
+    pub fn instance_id(&self) -> Result<String, StateError> {
+        {
+            let read_txn = self.db().begin_read()?;
+            let table = read_txn.open_table(META_TABLE)?;
+            if let Some(value) = table.get(INSTANCE_ID_KEY)? {
+                return Ok(String::from_utf8_lossy(value.value()).to_string());
+            }
+        }
+
+        let random = RandomState::new().hash_one((unix_now(), std::process::id()));
+        let id = format!("{:08x}", random as u32);
+        self.set_instance_id(&id)?;
+        Ok(id)
+    }
+
+    /// Pin the instance ID (e.g. to keep it across a state directory wipe)
+    pub fn set_instance_id(&self, id: &str) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(META_TABLE)?;
+            table.insert(INSTANCE_ID_KEY, id.as_bytes())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// The operator's pause of dispatch, if paused
+    pub fn get_pause(&self) -> Result<Option<PauseState>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(META_TABLE)?;
+        match table.get(PAUSE_KEY)? {
+            Some(data) => Ok(Some(serde_json::from_slice(data.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record a pause of dispatch, or clear it with `None`
+    pub fn set_pause(&self, pause: Option<&PauseState>) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(META_TABLE)?;
+            match pause {
+                Some(pause) => {
+                    table.insert(PAUSE_KEY, serde_json::to_vec(pause)?.as_slice())?;
+                }
+                None => {
+                    table.remove(PAUSE_KEY)?;
+                }
+            }
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Verify the database accepts writes (readiness check)
+    pub fn check_writable(&self) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(META_TABLE)?;
+            table.insert(READY_PROBE_KEY, unix_now().to_string().as_bytes())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn db(&self) -> RwLockReadGuard<'_, Database> {
+        self.db.read().unwrap()
+    }
+
+    /// Current file size and how much of it is data or free space
+    pub fn stats(&self) -> Result<DbStats, StateError> {
+        let file_bytes = std::fs::metadata(self.dir.join(DB_FILE))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        let write_txn = self.db().begin_write()?;
+        let stats = write_txn.stats()?;
+        write_txn.abort()?;
+        Ok(DbStats {
+            file_bytes,
+            stored_bytes: stats.stored_bytes(),
+            fragmented_bytes: stats.fragmented_bytes(),
+        })
+    }
+
+    /// Give free space inside the file back to the filesystem. Waits for
+    /// transactions to begin until done; fails if one is still open, e.g. a
+    /// running backup, and can be retried later. Returns whether anything was
+    /// compacted.
+    pub fn compact(&self) -> Result<bool, StateError> {
+        Ok(self.db.write().unwrap().compact()?)
+    }
+
+    /// Consistent copy of the database for `GET /state/backup`: every table
+    /// is copied from one read transaction into a fresh database file, so
+    /// writes meanwhile neither block nor tear it. The file is unlinked once
+    /// open; the returned handle is all that is left of it.
+    pub fn backup(&self) -> Result<std::fs::File, StateError> {
+        let random = RandomState::new().hash_one((unix_now(), std::process::id()));
+        let path = self.dir.join(format!("{}.backup-{:08x}", DB_FILE, random as u32));
+        let file = self.copy_to(&path).and_then(|()| {
+            std::fs::File::open(&path).map_err(|source| StateError::Copy {
+                path: path.clone(),
+                source,
+            })
+        });
+        let _ = std::fs::remove_file(&path);
+        file
+    }
+
+    fn copy_to(&self, path: &Path) -> Result<(), StateError> {
+        let read_txn = self.db().begin_read()?;
+        let copy = Database::create(path).map_err(|e| StateError::Open {
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })?;
+        let write_txn = copy.begin_write()?;
+        copy_table(&read_txn, &write_txn, CONTAINERS_TABLE)?;
+        copy_table(&read_txn, &write_txn, SNAPSHOTS_TABLE)?;
+        copy_table(&read_txn, &write_txn, META_TABLE)?;
+        copy_table(&read_txn, &write_txn, HISTORY_TABLE)?;
+        copy_table(&read_txn, &write_txn, AUDIT_TABLE)?;
+        copy_table(&read_txn, &write_txn, POOLS_TABLE)?;
+        copy_table(&read_txn, &write_txn, CLEANUP_TABLE)?;
+        copy_table(&read_txn, &write_txn, ARCHIVE_TABLE)?;
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Replace the database in `state_dir` with a backup, keeping the
+    /// current one as `state.redb.before-restore`. The backup is opened and
+    /// migrated before anything is replaced, and a controller still holding
+    /// the database makes the restore fail.
+    pub fn restore(state_dir: &Path, backup: &Path) -> Result<(), StateError> {
+        let staging = state_dir.join("restore");
+        let _ = std::fs::remove_dir_all(&staging);
+        std::fs::create_dir_all(&staging).map_err(|source| StateError::Directory {
+            path: staging.clone(),
+            source,
+        })?;
+        let staged = staging.join(DB_FILE);
+        let checked = std::fs::copy(backup, &staged)
+            .map_err(|source| StateError::Copy {
+                path: backup.to_path_buf(),
+                source,
+            })
+            .and_then(|_| StateDb::open(&staging, Durability::Immediate).map(drop));
+        if let Err(e) = checked {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+
+        let current = state_dir.join(DB_FILE);
+        // Held until the new file is in place, so no controller starts on the old one
+        let _lock = match Database::open(&current) {
+            Ok(db) => Some(db),
+            Err(redb::DatabaseError::DatabaseAlreadyOpen) => {
+                let _ = std::fs::remove_dir_all(&staging);
+                return Err(StateError::InUse { path: current });
+            }
+            Err(_) if !current.exists() => None,
+            Err(e) => {
+                return Err(StateError::Open {
+                    path: current,
+                    source: Box::new(e),
+                })
+            }
+        };
+        let rename = |from: &Path, to: &Path| {
+            std::fs::rename(from, to).map_err(|source| StateError::Copy {
+                path: from.to_path_buf(),
+                source,
+            })
+        };
+        if current.exists() {
+            rename(&current, &state_dir.join(format!("{}.before-restore", DB_FILE)))?;
+        }
+        rename(&staged, &current)?;
+        let _ = std::fs::remove_dir_all(&staging);
+        Ok(())
+    }
+
+    /// Queue or update a failed cleanup step for retry
+    pub fn put_cleanup(&self, cleanup: &PendingCleanup) -> Result<(), StateError> {
+        let data = serde_json::to_vec(cleanup)?;
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(CLEANUP_TABLE)?;
+            table.insert(cleanup.key().as_str(), data.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// The queued failure for a cleanup step, if any
+    pub fn get_cleanup(
+        &self,
+        kind: CleanupKind,
+        target: &str,
+    ) -> Result<Option<PendingCleanup>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(CLEANUP_TABLE)?;
+        let key = format!("{}:{}", kind.as_str(), target);
+
+        match table.get(key.as_str())? {
+            Some(data) => Ok(Some(serde_json::from_slice(data.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Drop a cleanup step that succeeded
+    pub fn remove_cleanup(&self, kind: CleanupKind, target: &str) -> Result<(), StateError> {
+        let key = format!("{}:{}", kind.as_str(), target);
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(CLEANUP_TABLE)?;
+            table.remove(key.as_str())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// All queued cleanup steps
+    pub fn list_cleanups(&self) -> Result<Vec<PendingCleanup>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(CLEANUP_TABLE)?;
+
+        let mut cleanups = Vec::new();
+        for entry in table.iter()? {
+            let (_, value) = entry?;
+            cleanups.push(serde_json::from_slice(value.value())?);
+        }
+        Ok(cleanups)
+    }
+
+    /// Remember an archived log object so it can be deleted after the retention period
+    pub fn put_archived_log(&self, key: &str, uploaded_at: u64) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(ARCHIVE_TABLE)?;
+            table.insert(key, uploaded_at)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Keys of archived log objects uploaded before `before`
+    pub fn expired_archived_logs(&self, before: u64) -> Result<Vec<String>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(ARCHIVE_TABLE)?;
+
+        let mut keys = Vec::new();
+        for entry in table.iter()? {
+            let (key, uploaded_at) = entry?;
+            if uploaded_at.value() < before {
+                keys.push(key.value().to_string());
+            }
+        }
+        Ok(keys)
+    }
+
+    pub fn remove_archived_log(&self, key: &str) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(ARCHIVE_TABLE)?;
+            table.remove(key)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Store the desired state of a pool
+    pub fn put_pool_spec(&self, pool: &str, spec: &PoolSpec) -> Result<(), StateError> {
+        let data = serde_json::to_vec(spec)?;
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(POOLS_TABLE)?;
+            table.insert(pool, data.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Store `configured` as the desired state of a pool, unless a spec is
+    /// already stored and the configuration hasn't changed since: changes
+    /// made through the API persist until then. Returns the spec in effect.
+    pub fn seed_pool_spec(
+        &self,
+        pool: &str,
+        configured: PoolSpec,
+        config_changed: bool,
+    ) -> Result<PoolSpec, StateError> {
+        match self.get_pool_spec(pool)? {
+            Some(spec) if !config_changed => Ok(spec),
+            _ => {
+                self.put_pool_spec(pool, &configured)?;
+                Ok(configured)
+            }
+        }
+    }
+
+    /// Get the desired state of a pool, if one was stored
+    pub fn get_pool_spec(&self, pool: &str) -> Result<Option<PoolSpec>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(POOLS_TABLE)?;
+
+        match table.get(pool)? {
+            Some(data) => Ok(Some(serde_json::from_slice(data.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Insert or update a container state
+    pub fn put_container(&self, name: &str, state: &ContainerState) -> Result<(), StateError> {
+        let data = serde_json::to_vec(state)?;
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(CONTAINERS_TABLE)?;
+            table.insert(name, data.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Get a container state by name
+    pub fn get_container(&self, name: &str) -> Result<Option<ContainerState>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(CONTAINERS_TABLE)?;
+
+        match table.get(name)? {
+            Some(data) => {
+                let state: ContainerState = serde_json::from_slice(data.value())?;
+                Ok(Some(state))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a container state
+    pub fn remove_container(&self, name: &str) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(CONTAINERS_TABLE)?;
+            table.remove(name)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// List all container states
+    pub fn list_containers(&self) -> Result<Vec<(String, ContainerState)>, StateError> {
+        let read_txn = self.db().begin_read()?;
+        let table = read_txn.open_table(CONTAINERS_TABLE)?;
+
+        let mut containers = Vec::new();
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            let name = key.value().to_string();
+            let state: ContainerState = serde_json::from_slice(value.value())?;
+            containers.push((name, state));
+        }
+
+        Ok(containers)
+    }
+
+    /// Clear all container states (used during shutdown cleanup)
+    pub fn clear_all(&self) -> Result<(), StateError> {
+        let write_txn = self.db().begin_write()?;
+        {
+            let mut table = write_txn.open_table(CONTAINERS_TABLE)?;
+            // Collect keys first to avoid borrowing issues
+            let keys: Vec<String> = table
+                .iter()?
+                .filter_map(|e| e.ok().map(|(k, _)| k.value().to_string()))
+                .collect();
+
+            for key in keys {
+                table.remove(key.as_str())?;
+            }
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_seconds_ignores_wall_clock_within_boot() {
+        let mut state = ContainerState::new(0);
+        if state.started_monotonic.is_none() {
+            return; // no /proc/uptime on this host
+        }
+
+        // A wall-clock step far into the past must not inflate the runtime
+        state.started_at = 0;
+        assert!(state.running_seconds() < 60);
+
+        // Entries from a previous boot fall back to wall-clock time
+        state.boot_id = Some("previous-boot".to_string());
+        assert!(state.running_seconds() > 60);
+    }
+
+    #[test]
+    fn test_start_time_plausible() {
+        let mut state = ContainerState::new(0);
+        assert!(state.start_time_plausible());
+
+        // Without a monotonic reference only the wall clock is left to check
+        state.started_monotonic = None;
+        state.started_at = unix_now() - 3600;
+        assert!(state.start_time_plausible());
+        state.started_at = unix_now() + 3600;
+        assert!(!state.start_time_plausible());
+        state.started_at = 0;
+        assert!(!state.start_time_plausible());
+
+        state.restart_clock();
+        assert!(state.start_time_plausible());
+        assert!(state.running_seconds() < 60);
+    }
+
+    #[test]
+    fn test_next_job_of_reused_container() {
+        let mut state = ContainerState::new(2);
+        state.started_at = unix_now() - 600;
+        state.job_id = Some(42);
+        state.labels = vec!["nix".to_string()];
+
+        let next = state.next_job().next_job();
+        assert_eq!(next.slot, Some(2));
+        assert_eq!(next.jobs_served, 2);
+        assert_eq!(next.job_id, None);
+        assert_eq!(next.labels, state.labels);
+        assert_eq!(next.container_started_at, Some(state.started_at));
+        assert!(next.container_age_seconds() >= 600);
+        assert!(next.running_seconds() < 60);
+
+        assert_eq!(next.phase(false), ContainerPhase::Provisioning);
+        let mut running = next.clone();
+        running.runner_id = Some(7);
+        assert_eq!(running.phase(false), ContainerPhase::Registered);
+        running.timings.job_started_after_secs = Some(30);
+        assert_eq!(running.phase(false), ContainerPhase::Running);
+        assert_eq!(running.phase(true), ContainerPhase::Completing);
+    }
+
+    #[test]
+    fn test_record_config_tags_history() {
+        let dir = std::env::temp_dir().join(format!("runner-controller-test-{}", std::process::id()));
+        let db = StateDb::open(&dir, Durability::Immediate).unwrap();
+
+        assert!(db.record_config("aaaa", serde_json::json!({ "a": 1 })).unwrap());
+        assert!(!db.record_config("aaaa", serde_json::json!({ "a": 1 })).unwrap());
+        db.record_event("spawned", Some("r0"), None, None).unwrap();
+
+        let history = db.query_history(&LogQuery::default(), |_| true).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].event, "spawned");
+        assert_eq!(history[0].config_hash.as_deref(), Some("aaaa"));
+        assert_eq!(history[1].event, "config_changed");
+        assert!(db.get_config_snapshot("aaaa").unwrap().is_some());
+
+        // Paging and filtering
+        db.record_event("spawned", Some("r1"), Some("org/a"), None).unwrap();
+        let query = LogQuery {
+            event: Some("spawned".to_string()),
+            limit: 1,
+            ..Default::default()
+        };
+        let page = db.query_history(&query, |_| true).unwrap();
+        assert_eq!(page[0].container.as_deref(), Some("r1"));
+        let query = LogQuery {
+            before: Some(page[0].id),
+            ..query
+        };
+        let page = db.query_history(&query, |_| true).unwrap();
+        assert_eq!(page[0].container.as_deref(), Some("r0"));
+        let query = LogQuery {
+            repo: Some("ORG/A".to_string()),
+            order: SortOrder::Asc,
+            ..Default::default()
+        };
+        assert_eq!(db.query_history(&query, |_| true).unwrap().len(), 1);
+
+        // Cleanups carry the job's labels and conclusion
+        let mut gpu = ContainerState::new(2);
+        gpu.labels = vec!["self-hosted".to_string(), "GPU".to_string()];
+        db.record_cleanup("r2", Some(&gpu), Some("failure".to_string()), None).unwrap();
+        db.record_cleanup("r3", None, Some("success".to_string()), None).unwrap();
+        let query = LogQuery {
+            label: Some("gpu".to_string()),
+            ..Default::default()
+        };
+        let page = db.query_history(&query, |_| true).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].container.as_deref(), Some("r2"));
+        let query = LogQuery {
+            conclusion: Some("success".to_string()),
+            ..Default::default()
+        };
+        let page = db.query_history(&query, |_| true).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].container.as_deref(), Some("r3"));
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_query_rejections() {
+        let dir = std::env::temp_dir().join(format!("runner-audit-{}", std::process::id()));
+        let db = StateDb::open(&dir, Durability::Immediate).unwrap();
+        let rejections = [(1, "fork", "org/a"), (2, "branch", "org/b"), (3, "fork", "org/b")];
+        for (job_id, rule, repo) in rejections {
+            let record = AuditRecord::new(job_id, rule, "refused", Some(repo.to_string()), None);
+            db.record_rejection(&record).unwrap();
+        }
+        let jobs = |records: Vec<AuditRecord>| records.iter().map(|r| r.job_id).collect::<Vec<_>>();
+
+        // Oldest first, continuing after the last ID of the previous page
+        let query = LogQuery {
+            order: SortOrder::Asc,
+            limit: 2,
+            ..Default::default()
+        };
+        let page = db.query_rejections(&query, |_| true).unwrap();
+        assert_eq!(jobs(page.clone()), [1, 2]);
+        let next = LogQuery {
+            after: Some(page[1].id),
+            ..query.clone()
+        };
+        assert_eq!(jobs(db.query_rejections(&next, |_| true).unwrap()), [3]);
+
+        let fork = LogQuery {
+            rule: Some("fork".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(jobs(db.query_rejections(&fork, |_| true).unwrap()), [3, 1]);
+        // Hidden records don't shorten a page
+        let visible = |r: &AuditRecord| r.repository.as_deref() == Some("org/b");
+        assert_eq!(jobs(db.query_rejections(&query, visible).unwrap()), [2, 3]);
+        let none = LogQuery {
+            since: Some(unix_now() + 60),
+            ..Default::default()
+        };
+        assert!(db.query_rejections(&none, |_| true).unwrap().is_empty());
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cleanup_backoff() {
+        // Allow for the clock ticking between scheduling and checking
+        let delay_is = |c: &PendingCleanup, secs: u64| {
+            (secs - 1..=secs).contains(&c.next_attempt_at.saturating_sub(unix_now()))
+        };
+        let mut cleanup = PendingCleanup::new(CleanupKind::Deregister, "host-r0", "502".to_string());
+        assert_eq!(cleanup.attempts, 1);
+        assert!(delay_is(&cleanup, CLEANUP_BACKOFF_SECS));
+        assert!(!cleanup.is_due());
+
+        cleanup.failed("502".to_string());
+        assert!(delay_is(&cleanup, 2 * CLEANUP_BACKOFF_SECS));
+        for _ in 0..20 {
+            cleanup.failed("502".to_string());
+        }
+        assert!(delay_is(&cleanup, MAX_CLEANUP_BACKOFF_SECS));
+        assert_eq!(cleanup.key(), "deregister:host-r0");
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let base = std::env::temp_dir()
+            .join(format!("runner-controller-backup-{}", std::process::id()));
+        let (source, target) = (base.join("source"), base.join("target"));
+        let db = StateDb::open(&source, Durability::Immediate).unwrap();
+        db.put_container("r0", &ContainerState::new(0)).unwrap();
+        db.set_instance_id("0badcafe").unwrap();
+
+        let mut backup = db.backup().unwrap();
+        let path = base.join("backup.redb");
+        std::io::copy(&mut backup, &mut std::fs::File::create(&path).unwrap()).unwrap();
+        // Only the open handle is left of the snapshot
+        assert_eq!(std::fs::read_dir(&source).unwrap().count(), 1);
+
+        // The target database exists and is in use
+        let other = StateDb::open(&target, Durability::Immediate).unwrap();
+        assert!(matches!(StateDb::restore(&target, &path), Err(StateError::InUse { .. })));
+        drop(other);
+
+        StateDb::restore(&target, &path).unwrap();
+        let restored = StateDb::open(&target, Durability::Immediate).unwrap();
+        assert_eq!(restored.get_container("r0").unwrap().unwrap().slot, Some(0));
+        assert_eq!(restored.instance_id().unwrap(), "0badcafe");
+        assert!(target.join("state.redb.before-restore").exists());
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_compaction_shrinks_the_file() {
+        let dir = std::env::temp_dir()
+            .join(format!("runner-controller-compact-{}", std::process::id()));
+        let db = StateDb::open(&dir, Durability::Immediate).unwrap();
+        let mut state = ContainerState::new(0);
+        state.labels = vec!["x".repeat(4096)];
+        for i in 0..500 {
+            db.put_container(&format!("r{}", i), &state).unwrap();
+        }
+        for i in 0..500 {
+            db.remove_container(&format!("r{}", i)).unwrap();
+        }
+
+        let before = db.stats().unwrap();
+        assert!(before.fragmented_bytes > 0);
+        db.compact().unwrap();
+        let after = db.stats().unwrap();
+        assert!(after.file_bytes < before.file_bytes);
+        assert!(after.fragmented_bytes < before.fragmented_bytes);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_migrates_old_entries_on_open() {
+        let dir = std::env::temp_dir()
+            .join(format!("runner-controller-migrate-{}", std::process::id()));
+        let db = StateDb::open(&dir, Durability::Immediate).unwrap();
+
+        // Pretend the database was written before schema versioning
+        let txn = db.db().begin_write().unwrap();
+        {
+            let mut meta = txn.open_table(META_TABLE).unwrap();
+            meta.remove(SCHEMA_VERSION_KEY).unwrap();
+            let mut containers = txn.open_table(CONTAINERS_TABLE).unwrap();
+            let old = br#"{"slot": 0, "started_at": 1700000000}"#;
+            containers.insert("r0", old.as_slice()).unwrap();
+        }
+        txn.commit().unwrap();
+        drop(db);
+
+        let db = StateDb::open(&dir, Durability::Immediate).unwrap();
+        let state = db.get_container("r0").unwrap().unwrap();
+        assert_eq!(state.slot, Some(0));
+        assert_eq!(state.runner_id, None);
+        let raw = {
+            let txn = db.db().begin_read().unwrap();
+            let meta = txn.open_table(META_TABLE).unwrap();
+            let value = meta.get(SCHEMA_VERSION_KEY).unwrap().unwrap();
+            String::from_utf8_lossy(value.value()).to_string()
+        };
+        assert_eq!(raw, schema_version().to_string());
+
+        // A database written by a newer build is left alone
+        let txn = db.db().begin_write().unwrap();
+        {
+            let mut meta = txn.open_table(META_TABLE).unwrap();
+            let newer = (schema_version() + 1).to_string();
+            meta.insert(SCHEMA_VERSION_KEY, newer.as_bytes()).unwrap();
+        }
+        txn.commit().unwrap();
+        drop(db);
+        let reopened = StateDb::open(&dir, Durability::Immediate);
+        assert!(matches!(reopened, Err(StateError::SchemaTooNew { .. })));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
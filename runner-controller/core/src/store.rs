@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::config::Config;
+
+mod redis;
+
+pub use self::redis::{RedisConfig, RedisStore};
+
+/// Outcome of claiming a job
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claim {
+    /// The job is ours, newly or again
+    Acquired,
+    /// Another controller claimed the job first
+    HeldBy(String),
+}
+
+/// Job claims shared between the controllers serving one repository, so
+/// only one of them provisions a runner for each job. Claims expire after
+/// their TTL in case the controller holding one dies.
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    /// Short name for logs, e.g. `redis`
+    fn name(&self) -> &'static str;
+    /// Claim `job_id` for `ttl`, or extend our own claim
+    async fn claim_job(&self, job_id: u64, ttl: Duration) -> Result<Claim>;
+    /// Give up our claim on `job_id`; claims of other controllers stay
+    async fn release_job(&self, job_id: u64) -> Result<()>;
+}
+
+/// Claims kept in memory, for a controller without replicas
+pub struct LocalStore {
+    owner: String,
+    claims: Mutex<HashMap<u64, (String, Instant)>>,
+}
+
+impl LocalStore {
+    pub fn new(owner: impl Into<String>) -> Self {
+        Self {
+            owner: owner.into(),
+            claims: Mutex::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl StateStore for LocalStore {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn claim_job(&self, job_id: u64, ttl: Duration) -> Result<Claim> {
+        let now = Instant::now();
+        let mut claims = self.claims.lock().unwrap();
+        claims.retain(|_, (_, expires)| *expires > now);
+        match claims.get(&job_id) {
+            Some((owner, _)) if *owner != self.owner => Ok(Claim::HeldBy(owner.clone())),
+            _ => {
+                claims.insert(job_id, (self.owner.clone(), now + ttl));
+                Ok(Claim::Acquired)
+            }
+        }
+    }
+
+    async fn release_job(&self, job_id: u64) -> Result<()> {
+        let mut claims = self.claims.lock().unwrap();
+        if claims.get(&job_id).is_some_and(|(owner, _)| *owner == self.owner) {
+            claims.remove(&job_id);
+        }
+        Ok(())
+    }
+}
+
+/// The configured store, claiming jobs as `owner` (the instance ID)
+pub fn open(config: &Config, owner: &str) -> Result<Arc<dyn StateStore>> {
+    Ok(match &config.redis {
+        Some(redis) => Arc::new(RedisStore::new(redis, owner)?),
+        None => Arc::new(LocalStore::new(owner)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_claims() {
+        let store = LocalStore::new("a");
+        let ttl = Duration::from_secs(60);
+        let claim = |job_id| tokio_test::block_on(store.claim_job(job_id, ttl)).unwrap();
+        let release = |job_id| tokio_test::block_on(store.release_job(job_id)).unwrap();
+        assert_eq!(claim(1), Claim::Acquired);
+        assert_eq!(claim(1), Claim::Acquired);
+
+        store.claims.lock().unwrap().insert(2, ("b".into(), Instant::now() + ttl));
+        assert_eq!(claim(2), Claim::HeldBy("b".into()));
+        release(2);
+        assert_eq!(claim(2), Claim::HeldBy("b".into()));
+
+        release(1);
+        assert!(!store.claims.lock().unwrap().contains_key(&1));
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::{Client, Script};
+use serde::Serialize;
+use tokio::sync::OnceCell;
+
+use super::{Claim, StateStore};
+use crate::secrets::SecretString;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Takes a free claim or extends our own, returning the claim's holder
+const CLAIM_SCRIPT: &str = r#"
+local holder = redis.call("GET", KEYS[1])
+if not holder or holder == ARGV[1] then
+    redis.call("SET", KEYS[1], ARGV[1], "PX", ARGV[2])
+    return ARGV[1]
+end
+return holder
+"#;
+
+/// Deletes a claim only if it is still ours; it may have expired and been
+/// taken by another controller meanwhile
+const RELEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Redis shared by the replicas of a controller (`REDIS_URL`)
+#[derive(Debug, Clone, Serialize)]
+pub struct RedisConfig {
+    /// `redis://` or `rediss://` URL, possibly with a password
+    #[serde(skip)]
+    pub url: SecretString,
+    /// Prefix of every key, so several controllers can share one Redis
+    pub key_prefix: String,
+}
+
+/// Job claims in Redis, one key per job holding the owner's instance ID
+/// and expiring with the claim
+pub struct RedisStore {
+    client: Client,
+    key_prefix: String,
+    owner: String,
+    /// Connected on first use, so an unreachable Redis doesn't stop startup;
+    /// reconnects by itself afterwards
+    conn: OnceCell<ConnectionManager>,
+}
+
+impl RedisStore {
+    pub fn new(config: &RedisConfig, owner: &str) -> Result<Self> {
+        let client = Client::open(config.url.expose()).context("Invalid REDIS_URL")?;
+        Ok(Self {
+            client,
+            key_prefix: config.key_prefix.clone(),
+            owner: owner.to_string(),
+            conn: OnceCell::new(),
+        })
+    }
+
+    async fn conn(&self) -> Result<ConnectionManager> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| {
+                let config = ConnectionManagerConfig::new()
+                    .set_connection_timeout(CONNECT_TIMEOUT)
+                    .set_response_timeout(RESPONSE_TIMEOUT);
+                ConnectionManager::new_with_config(self.client.clone(), config)
+            })
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(conn.clone())
+    }
+
+    fn job_key(&self, job_id: u64) -> String {
+        format!("{}:job:{}", self.key_prefix, job_id)
+    }
+}
+
+#[async_trait]
+impl StateStore for RedisStore {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn claim_job(&self, job_id: u64, ttl: Duration) -> Result<Claim> {
+        let mut conn = self.conn().await?;
+        let holder: String = Script::new(CLAIM_SCRIPT)
+            .key(self.job_key(job_id))
+            .arg(&self.owner)
+            .arg(ttl.as_millis().max(1) as u64)
+            .invoke_async(&mut conn)
+            .await
+            .context("Redis claim failed")?;
+        Ok(match holder == self.owner {
+            true => Claim::Acquired,
+            false => Claim::HeldBy(holder),
+        })
+    }
+
+    async fn release_job(&self, job_id: u64) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let _: i64 = Script::new(RELEASE_SCRIPT)
+            .key(self.job_key(job_id))
+            .arg(&self.owner)
+            .invoke_async(&mut conn)
+            .await
+            .context("Redis claim release failed")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::secrets::SecretString;
+
+/// A team with read access to the activity of its own repositories
+#[derive(Debug, Clone, Serialize)]
+pub struct Tenant {
+    pub name: String,
+    #[serde(skip)]
+    pub token: SecretString,
+    /// `owner/repo` names whose jobs and containers this tenant may see
+    pub repositories: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct TenantEntry {
+    token: SecretString,
+    repositories: Vec<String>,
+}
+
+impl Tenant {
+    pub fn owns(&self, repository: &str) -> bool {
+        self.repositories
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(repository))
+    }
+}
+
+/// Load tenants from a JSON file of the form
+/// `{"team-a": {"token": "...", "repositories": ["org/a"]}}`
+pub fn load_tenants(path: &Path) -> Result<Vec<Tenant>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read tenants from {:?}", path))?;
+    let entries: BTreeMap<String, TenantEntry> =
+        serde_json::from_str(&contents).context("Failed to parse tenants file")?;
+
+    Ok(entries
+        .into_iter()
+        .map(|(name, entry)| Tenant {
+            name,
+            token: entry.token.expose().trim().into(),
+            repositories: entry.repositories,
+        })
+        .collect())
+}
+
+/// Who is reading an API view
+#[derive(Debug, Clone, Copy)]
+pub enum Viewer<'a> {
+    /// Sees everything
+    Admin,
+    Tenant(&'a Tenant),
+}
+
+impl Viewer<'_> {
+    /// Whether this viewer may see activity belonging to `repository`
+    pub fn can_see(&self, repository: Option<&str>) -> bool {
+        match self {
+            Self::Admin => true,
+            Self::Tenant(tenant) => repository.is_some_and(|r| tenant.owns(r)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_visibility() {
+        let tenant = Tenant {
+            name: "team-a".to_string(),
+            token: "secret".into(),
+            repositories: vec!["org/a".to_string()],
+        };
+
+        let viewer = Viewer::Tenant(&tenant);
+        assert!(viewer.can_see(Some("Org/A")));
+        assert!(!viewer.can_see(Some("org/b")));
+        assert!(!viewer.can_see(None));
+        assert!(Viewer::Admin.can_see(None));
+    }
+}
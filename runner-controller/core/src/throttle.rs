@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// A spawn refused because an hourly budget is used up
+#[derive(Debug, Clone, Error)]
+#[error("spawn throttled: {scope} budget of {limit} jobs per hour used up")]
+pub struct SpawnThrottled {
+    /// `global` or the repository whose budget is exhausted
+    pub scope: String,
+    pub limit: u32,
+}
+
+/// Refills `capacity` tokens per hour, continuously
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn full(capacity: u32, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(capacity),
+            refilled: now,
+        }
+    }
+
+    fn refill(&mut self, capacity: u32, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        let added = f64::from(capacity) * elapsed.as_secs_f64() / HOUR.as_secs_f64();
+        self.tokens = (self.tokens + added).min(f64::from(capacity));
+        self.refilled = now;
+    }
+}
+
+/// Caps spawns per hour, globally and per repository, so a matrix explosion
+/// or retry storm can't churn hundreds of containers on one host. Short bursts
+/// up to the hourly limit are allowed.
+#[derive(Debug)]
+pub struct SpawnThrottle {
+    /// Spawns per hour across all repositories; 0 disables
+    global_limit: u32,
+    /// Spawns per hour for a single repository; 0 disables
+    repo_limit: u32,
+    inner: Mutex<ThrottleInner>,
+}
+
+#[derive(Debug)]
+struct ThrottleInner {
+    global: TokenBucket,
+    repos: HashMap<String, TokenBucket>,
+}
+
+impl SpawnThrottle {
+    pub fn new(global_limit: u32, repo_limit: u32) -> Self {
+        Self {
+            global_limit,
+            repo_limit,
+            inner: Mutex::new(ThrottleInner {
+                global: TokenBucket::full(global_limit, Instant::now()),
+                repos: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Take one spawn from the global budget and, if known, the repository's.
+    /// Nothing is taken when either budget is exhausted.
+    pub fn try_acquire(&self, repository: Option<&str>) -> Result<(), SpawnThrottled> {
+        self.try_acquire_at(repository, Instant::now())
+    }
+
+    fn try_acquire_at(&self, repository: Option<&str>, now: Instant) -> Result<(), SpawnThrottled> {
+        let mut inner = self.inner.lock().unwrap();
+        let ThrottleInner { global, repos } = &mut *inner;
+
+        if self.global_limit > 0 {
+            global.refill(self.global_limit, now);
+            if global.tokens < 1.0 {
+                return Err(SpawnThrottled {
+                    scope: "global".to_string(),
+                    limit: self.global_limit,
+                });
+            }
+        }
+
+        let repo = match repository {
+            Some(repo) if self.repo_limit > 0 => {
+                let bucket = repos
+                    .entry(repo.to_ascii_lowercase())
+                    .or_insert_with(|| TokenBucket::full(self.repo_limit, now));
+                bucket.refill(self.repo_limit, now);
+                if bucket.tokens < 1.0 {
+                    return Err(SpawnThrottled {
+                        scope: repo.to_string(),
+                        limit: self.repo_limit,
+                    });
+                }
+                Some(bucket)
+            }
+            _ => None,
+        };
+
+        if let Some(bucket) = repo {
+            bucket.tokens -= 1.0;
+        }
+        if self.global_limit > 0 {
+            global.tokens -= 1.0;
+        }
+
+        // Full buckets carry no information; drop them to bound memory
+        repos.retain(|_, b| {
+            b.refill(self.repo_limit, now);
+            b.tokens < f64::from(self.repo_limit)
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_throttle() {
+        let throttle = SpawnThrottle::new(3, 2);
+        let now = Instant::now();
+
+        assert!(throttle.try_acquire_at(Some("org/a"), now).is_ok());
+        assert!(throttle.try_acquire_at(Some("Org/A"), now).is_ok());
+        let err = throttle.try_acquire_at(Some("org/a"), now).unwrap_err();
+        assert_eq!(err.scope, "org/a");
+
+        assert!(throttle.try_acquire_at(Some("org/b"), now).is_ok());
+        let err = throttle.try_acquire_at(None, now).unwrap_err();
+        assert_eq!(err.scope, "global");
+
+        // Half an hour refills half of each budget
+        let later = now + HOUR / 2;
+        assert!(throttle.try_acquire_at(Some("org/a"), later).is_ok());
+        assert!(throttle.try_acquire_at(Some("org/a"), later).is_err());
+
+        let unlimited = SpawnThrottle::new(0, 0);
+        for _ in 0..100 {
+            assert!(unlimited.try_acquire(Some("org/a")).is_ok());
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! The REST client against a mock GitHub API, through the `GitHubApi` trait
+//! the controller uses.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use runner_controller_core::config::{IpFamily, NetworkConfig};
+use runner_controller_core::github::{
+    http_client, GitHubApi, GitHubClient, GitHubError, RetryPolicy, RetrySettings,
+};
+use serde_json::json;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const REPO: &str = "owner/repo";
+
+fn network() -> NetworkConfig {
+    NetworkConfig {
+        ip_family: IpFamily::Auto,
+        resolve: Vec::new(),
+        connect_timeout: Duration::from_secs(5),
+        proxy: None,
+        ca_file: None,
+    }
+}
+
+fn client(server: &MockServer) -> Arc<dyn GitHubApi> {
+    client_via(&server.uri(), &network())
+}
+
+fn client_via(api_base: &str, network: &NetworkConfig) -> Arc<dyn GitHubApi> {
+    let retry = RetrySettings {
+        policy: RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(10),
+            jitter: 0.0,
+            deadline: None,
+        },
+        overrides: Vec::new(),
+    };
+    let client = GitHubClient::new(REPO.to_string(), "test-token".to_string(), network)
+        .unwrap()
+        .with_api_base(api_base)
+        .with_retry(retry);
+    Arc::new(client)
+}
+
+#[tokio::test]
+async fn lists_runners() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/runners"))
+        .and(header("authorization", "token test-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "total_count": 1,
+            "runners": [{
+                "id": 7,
+                "name": "runner-1",
+                "status": "online",
+                "busy": true,
+                "labels": [{ "name": "self-hosted" }, { "name": "nix" }]
+            }]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let runners = client(&server).list_runners().await.unwrap();
+    assert_eq!(runners.len(), 1);
+    assert_eq!(runners[0].id, 7);
+    assert!(runners[0].busy);
+    assert!(runners[0].has_label("NIX"));
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/jobs/42"))
+        .respond_with(ResponseTemplate::new(502))
+        .up_to_n_times(2)
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/jobs/42"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": 42,
+            "status": "in_progress",
+            "runner_name": "runner-1"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let job = client(&server).get_job(42).await.unwrap();
+    assert_eq!(job.status, "in_progress");
+    assert_eq!(job.runner_name.as_deref(), Some("runner-1"));
+}
+
+#[tokio::test]
+async fn gives_up_after_max_attempts() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/repos/owner/repo/actions/runners/registration-token"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let err = client(&server).get_registration_token().await.unwrap_err();
+    assert!(matches!(err, GitHubError::Transient(_)), "{err}");
+}
+
+#[tokio::test]
+async fn classifies_client_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/jobs/1"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/runners"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/runs/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+        .mount(&server)
+        .await;
+    // Sent once: repeating a request GitHub refuses can't succeed
+    Mock::given(method("POST"))
+        .and(path("/repos/owner/repo/actions/runners/registration-token"))
+        .respond_with(ResponseTemplate::new(422).set_body_string("Validation Failed"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    assert!(matches!(client.get_job(1).await, Err(GitHubError::NotFound(_))));
+    assert!(matches!(client.list_runners().await, Err(GitHubError::Unauthorized)));
+    assert!(matches!(client.list_runners().await, Err(GitHubError::Unauthorized)));
+    assert_eq!(client.consecutive_auth_failures(), 2);
+    assert!(matches!(client.get_run(1).await, Err(GitHubError::InvalidResponse(_))));
+    let err = client.get_registration_token().await.unwrap_err();
+    assert!(matches!(err, GitHubError::Rejected { status: 422, .. }), "{err}");
+}
+
+#[tokio::test]
+async fn deletes_runner_by_name() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/runners"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "runners": [{ "id": 3, "name": "runner-a" }, { "id": 4, "name": "runner-b" }]
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path("/repos/owner/repo/actions/runners/4"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+    // Already removed by GitHub, e.g. an ephemeral runner after its job
+    Mock::given(method("DELETE"))
+        .and(path("/repos/owner/repo/actions/runners/3"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    client.delete_runner_by_name("runner-b").await.unwrap();
+    client.delete_runner(3).await.unwrap();
+    // Not registered at all: nothing to delete
+    client.delete_runner_by_name("runner-c").await.unwrap();
+}
+
+#[tokio::test]
+async fn queued_jobs_oldest_first() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/runs"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "workflow_runs": [{ "id": 10 }, { "id": 11 }]
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/runs/10/jobs"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "jobs": [
+                { "id": 1, "status": "queued", "created_at": "2024-01-15T10:30:00Z" },
+                { "id": 2, "status": "completed", "created_at": "2024-01-15T10:00:00Z" }
+            ]
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/runs/11/jobs"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "jobs": [{ "id": 3, "status": "queued", "created_at": "2024-01-15T10:20:00Z" }]
+        })))
+        .mount(&server)
+        .await;
+
+    let jobs = client(&server).list_queued_jobs().await.unwrap();
+    let ids: Vec<u64> = jobs.iter().map(|j| j.id).collect();
+    assert_eq!(ids, [3, 1]);
+}
+
+#[tokio::test]
+async fn goes_through_the_proxy() {
+    // The mock answers as the proxy for an API host that doesn't resolve
+    let proxy = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/owner/repo/actions/runners"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "runners": [] })))
+        .expect(1)
+        .mount(&proxy)
+        .await;
+
+    let proxied = NetworkConfig {
+        proxy: Some(proxy.uri()),
+        ..network()
+    };
+    let runners = client_via("http://api.github.invalid", &proxied).list_runners().await;
+    assert!(runners.unwrap().is_empty());
+
+    // A CA bundle without certificates is refused rather than ignored
+    let ca_file = std::env::temp_dir().join(format!("github-ca-{}.pem", std::process::id()));
+    std::fs::write(&ca_file, "not a certificate\n").unwrap();
+    let extra_ca = NetworkConfig {
+        ca_file: Some(ca_file.clone()),
+        ..network()
+    };
+    assert!(http_client(&extra_ca).is_err());
+    std::fs::remove_file(ca_file).unwrap();
+}
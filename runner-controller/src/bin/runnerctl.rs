@@ -0,0 +1,441 @@
+//! Command-line client for the runner-controller HTTP API
+
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use reqwest::{Client, Method, RequestBuilder, StatusCode, Url};
+use runner_controller_core::state::StateDb;
+use serde_json::Value;
+
+const USAGE: &str = "\
+Usage: runnerctl [--url URL | --socket PATH] [--json] <command>
+
+Commands:
+  status                      Pool state and active containers
+  kill <container>            Tear down a container (pool slots are refilled)
+  drain                       Set the warm pool size to 0; busy runners finish their job
+  resize <size>               Set the warm pool size
+  pause [reason]              Stop spawning runners, e.g. for host maintenance;
+                              runners already up stay but aren't replaced
+  resume                      Spawn runners again after a pause
+  history [--limit N] [--event NAME]
+                              Recent lifecycle events, newest first
+  state backup <file>         Save a consistent copy of the state database
+  state restore <file> [--state-dir DIR]
+                              Replace the state database in DIR (default
+                              $STATE_DIR or /var/lib/runner-controller) with a
+                              backup; the controller must be stopped
+
+Environment:
+  RUNNERCTL_URL               Controller address (default http://127.0.0.1:8080)
+  RUNNERCTL_SOCKET            Controller Unix socket (HTTP_SOCKET); no token needed
+  RUNNERCTL_TOKEN_FILE        API token file, falls back to API_TOKEN_FILE";
+
+struct Cli {
+    url: String,
+    token: Option<String>,
+    json: bool,
+    client: Client,
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("runnerctl: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let url = take_option(&mut args, "--url")?
+        .or_else(|| std::env::var("RUNNERCTL_URL").ok())
+        .unwrap_or_else(|| "http://127.0.0.1:8080".to_string());
+    let socket = take_option(&mut args, "--socket")?
+        .or_else(|| std::env::var("RUNNERCTL_SOCKET").ok())
+        .filter(|path| !path.is_empty());
+    let json = take_flag(&mut args, "--json");
+
+    let token_file = std::env::var("RUNNERCTL_TOKEN_FILE")
+        .or_else(|_| std::env::var("API_TOKEN_FILE"))
+        .ok();
+    let token = match token_file {
+        Some(path) => Some(
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read token from {}", path))?
+                .trim()
+                .to_string(),
+        ),
+        None => None,
+    };
+
+    // Over the socket the host part of the URL is only sent as `Host`
+    let (url, client) = match &socket {
+        Some(path) => (
+            "http://localhost".to_string(),
+            Client::builder().unix_socket(path.as_str()).build()?,
+        ),
+        None => (url, Client::new()),
+    };
+    let cli = Cli {
+        url: url.trim_end_matches('/').to_string(),
+        token,
+        json,
+        client,
+    };
+
+    if args.is_empty() {
+        anyhow::bail!("{}", USAGE);
+    }
+    let command = args.remove(0);
+    if command == "history" {
+        let limit: usize = match take_option(&mut args, "--limit")? {
+            Some(limit) => limit.parse().context("--limit must be a number")?,
+            None => 20,
+        };
+        let event = take_option(&mut args, "--event")?;
+        if !args.is_empty() {
+            anyhow::bail!("unexpected arguments: {}\n\n{}", args.join(" "), USAGE);
+        }
+        return history(&cli, limit, event.as_deref()).await;
+    }
+    if command == "state" {
+        let state_dir = take_option(&mut args, "--state-dir")?
+            .or_else(|| std::env::var("STATE_DIR").ok())
+            .unwrap_or_else(|| "/var/lib/runner-controller".to_string());
+        return match args.as_slice() {
+            [action, file] if action == "backup" => backup(&cli, file).await,
+            [action, file] if action == "restore" => restore(&cli, &state_dir, file),
+            _ => anyhow::bail!("{}", USAGE),
+        };
+    }
+
+    match (command.as_str(), args.as_slice()) {
+        ("status", []) => status(&cli).await,
+        ("kill", [name]) => kill(&cli, name).await,
+        ("drain", []) => resize(&cli, 0).await,
+        ("pause", reason) => pause(&cli, &reason.join(" ")).await,
+        ("resume", []) => resume(&cli).await,
+        ("resize", [size]) => {
+            let size = size.parse().context("Pool size must be a number")?;
+            resize(&cli, size).await
+        }
+        ("help" | "--help" | "-h", _) => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => anyhow::bail!("{}", USAGE),
+    }
+}
+
+impl Cli {
+    /// Call the API, returning the JSON body (`Null` for empty responses)
+    async fn call(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value> {
+        let mut request = self.request(method, path);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        self.send(request).await
+    }
+
+    /// Send a request built with `request`, returning the JSON body
+    async fn send(&self, request: RequestBuilder) -> Result<Value> {
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach the controller at {}", self.url))?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            anyhow::bail!("{} ({})", text.trim(), status);
+        }
+        if status == StatusCode::NO_CONTENT || text.is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&text).context("Invalid JSON from the controller")
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.authorized(self.client.request(method, format!("{}{}", self.url, path)))
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn print_json(&self, value: &Value) -> Result<()> {
+        println!("{}", serde_json::to_string_pretty(value)?);
+        Ok(())
+    }
+}
+
+async fn status(cli: &Cli) -> Result<()> {
+    let status = cli.call(Method::GET, "/status", None).await?;
+    if cli.json {
+        return cli.print_json(&status);
+    }
+
+    println!(
+        "pool size {}, {} active, GitHub circuit {}",
+        status["pool_size"], status["active_containers"], text(&status["github_circuit"]),
+    );
+    if let Some(pause) = status["paused"].as_object() {
+        match pause.get("reason").and_then(Value::as_str) {
+            Some(reason) => println!("dispatch paused: {}", reason),
+            None => println!("dispatch paused"),
+        }
+    }
+    if let Some(window) = status["maintenance_window"].as_str() {
+        println!("in maintenance window {}", window);
+    }
+    let rows: Vec<Vec<String>> = status["containers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|c| {
+            let job = match (c["workflow_name"].as_str(), c["job_name"].as_str()) {
+                (Some(workflow), Some(job)) => format!("{} / {}", workflow, job),
+                (None, Some(job)) => job.to_string(),
+                _ => text(&c["job_id"]),
+            };
+            vec![
+                text(&c["name"]),
+                text(&c["slot"]),
+                job,
+                text(&c["repository"]),
+                format_duration(c["running_seconds"].as_u64().unwrap_or(0)),
+            ]
+        })
+        .collect();
+    print!("{}", render_table(&["NAME", "SLOT", "JOB", "REPOSITORY", "RUNNING"], &rows));
+    Ok(())
+}
+
+/// `DELETE /containers/<name>`, with the name encoded as a single path segment
+fn kill_request(cli: &Cli, name: &str) -> Result<RequestBuilder> {
+    let mut url = Url::parse(&cli.url).with_context(|| format!("Invalid URL {}", cli.url))?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("Invalid URL {}", cli.url))?
+        .pop_if_empty()
+        .extend(["containers", name]);
+    Ok(cli.authorized(cli.client.request(Method::DELETE, url)))
+}
+
+async fn kill(cli: &Cli, name: &str) -> Result<()> {
+    cli.send(kill_request(cli, name)?).await?;
+    if cli.json {
+        return cli.print_json(&serde_json::json!({ "removed": name }));
+    }
+    println!("Removed {}", name);
+    Ok(())
+}
+
+async fn resize(cli: &Cli, size: usize) -> Result<()> {
+    let spec = cli
+        .call(Method::PUT, "/pool", Some(serde_json::json!({ "size": size })))
+        .await?;
+    if cli.json {
+        return cli.print_json(&spec);
+    }
+    println!("Warm pool size set to {}", spec["size"]);
+    if size == 0 {
+        println!("Idle runners are removed now, busy ones after their job");
+    }
+    Ok(())
+}
+
+async fn pause(cli: &Cli, reason: &str) -> Result<()> {
+    let body = (!reason.is_empty()).then(|| serde_json::json!({ "reason": reason }));
+    let pause = cli.call(Method::POST, "/pause", body).await?;
+    if cli.json {
+        return cli.print_json(&pause);
+    }
+    println!("Dispatch paused; runners already up stay, finished ones aren't replaced");
+    Ok(())
+}
+
+async fn resume(cli: &Cli) -> Result<()> {
+    cli.call(Method::POST, "/resume", None).await?;
+    if cli.json {
+        return cli.print_json(&serde_json::json!({ "paused": false }));
+    }
+    println!("Dispatch resumed");
+    Ok(())
+}
+
+fn history_request(cli: &Cli, limit: usize, event: Option<&str>) -> RequestBuilder {
+    let mut query = vec![("limit", limit.to_string())];
+    query.extend(event.map(|event| ("event", event.to_string())));
+    cli.request(Method::GET, "/history").query(&query)
+}
+
+async fn history(cli: &Cli, limit: usize, event: Option<&str>) -> Result<()> {
+    let entries = cli.send(history_request(cli, limit, event)).await?;
+    if cli.json {
+        return cli.print_json(&entries);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let rows: Vec<Vec<String>> = entries
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|e| {
+            let at = e["timestamp"].as_u64().unwrap_or(0);
+            vec![
+                format!("{} ago", format_duration(now.saturating_sub(at))),
+                text(&e["event"]),
+                text(&e["container"]),
+                text(&e["detail"]),
+            ]
+        })
+        .collect();
+    print!("{}", render_table(&["WHEN", "EVENT", "CONTAINER", "DETAIL"], &rows));
+    Ok(())
+}
+
+/// Download the state database into `file`, via a temporary file so a failed
+/// download leaves no truncated backup behind
+async fn backup(cli: &Cli, file: &str) -> Result<()> {
+    let mut response = cli
+        .request(Method::GET, "/state/backup")
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach the controller at {}", cli.url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        anyhow::bail!("{} ({})", text.trim(), status);
+    }
+
+    let partial = format!("{}.partial", file);
+    let mut out = std::fs::File::create(&partial)
+        .with_context(|| format!("Failed to create {}", partial))?;
+    let mut bytes = 0;
+    while let Some(chunk) = response.chunk().await.context("Backup download failed")? {
+        out.write_all(&chunk).with_context(|| format!("Failed to write {}", partial))?;
+        bytes += chunk.len();
+    }
+    out.sync_all()?;
+    std::fs::rename(&partial, file).with_context(|| format!("Failed to write {}", file))?;
+
+    if cli.json {
+        return cli.print_json(&serde_json::json!({ "path": file, "bytes": bytes }));
+    }
+    println!("Saved state database backup to {} ({} bytes)", file, bytes);
+    Ok(())
+}
+
+/// Put a backup in place of the state database; runs on the controller host
+fn restore(cli: &Cli, state_dir: &str, file: &str) -> Result<()> {
+    StateDb::restore(Path::new(state_dir), Path::new(file))
+        .with_context(|| format!("Failed to restore {} into {}", file, state_dir))?;
+    if cli.json {
+        return cli.print_json(&serde_json::json!({ "restored": file, "state_dir": state_dir }));
+    }
+    println!("Restored {} into {}", file, state_dir);
+    println!("The previous database is kept as state.redb.before-restore");
+    Ok(())
+}
+
+/// Remove `flag` from the arguments, returning whether it was present
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|a| a != flag);
+    args.len() != before
+}
+
+/// Remove `name VALUE` from the arguments, returning the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>> {
+    let Some(index) = args.iter().position(|a| a == name) else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        anyhow::bail!("{} needs a value", name);
+    }
+    let value = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(value))
+}
+
+/// A JSON value as table cell text; `-` for null
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m{}s", s / 60, s % 60),
+        s => format!("{}h{}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+/// Left-aligned columns separated by two spaces
+fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let header: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table_and_args() {
+        let rows = vec![vec!["r0".to_string(), "12m3s".to_string()]];
+        assert_eq!(
+            render_table(&["NAME", "RUNNING"], &rows),
+            "NAME  RUNNING\nr0    12m3s\n"
+        );
+        assert_eq!(format_duration(3725), "1h2m");
+
+        let mut args: Vec<String> = ["history", "--limit", "5", "--json"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert!(take_flag(&mut args, "--json"));
+        assert_eq!(take_option(&mut args, "--limit").unwrap().as_deref(), Some("5"));
+        assert_eq!(args, vec!["history"]);
+
+        let cli = Cli {
+            url: "http://127.0.0.1:8080".to_string(),
+            token: None,
+            json: false,
+            client: Client::new(),
+        };
+        let request = history_request(&cli, 5, Some("spawned&limit=1")).build().unwrap();
+        assert_eq!(request.url().query(), Some("limit=5&event=spawned%26limit%3D1"));
+        let request = kill_request(&cli, "r0/../pool?x").unwrap().build().unwrap();
+        assert_eq!(request.url().path(), "/containers/r0%2F..%2Fpool%3Fx");
+    }
+}
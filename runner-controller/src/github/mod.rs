@@ -1,4 +0,0 @@
-mod client;
-mod types;
-
-pub use client::GitHubClient;
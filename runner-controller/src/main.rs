@@ -1,27 +1,365 @@
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
 
-use anyhow::Result;
-use tokio::sync::watch;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use tokio::sync::{mpsc, oneshot, watch};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use zeroize::Zeroizing;
 
-mod config;
-mod container;
-mod github;
-mod http;
-mod listener;
-mod state;
+use runner_controller_core::capabilities::HostCapabilities;
+use runner_controller_core::config::{parse_env_file, Config, ContainerBackend};
+use runner_controller_core::container::{
+    captured_logs_dir, check_nspawn_image, Backend, ContainerManager, Fleet, RunnerNaming,
+};
+use runner_controller_core::github::{GitHubApp, GitHubClient};
+use runner_controller_core::http::{self, AppState};
+use runner_controller_core::leader::LeaderElection;
+use runner_controller_core::listener::{PoolController, RunExit};
+use runner_controller_core::notify::{NotificationEvent, Notifier};
+use runner_controller_core::state::{PoolSpec, StateDb, DEFAULT_POOL};
+use runner_controller_core::secrets::{self, GitHubSecrets, SecretsProvider};
+use runner_controller_core::store::StateStore;
+use runner_controller_core::{check, doctor, rotation, store};
 
-use config::Config;
-use container::ContainerManager;
-use github::GitHubClient;
-use http::AppState;
-use listener::PoolController;
-use state::StateDb;
+/// GitHub Actions runner controller: keeps a warm pool of ephemeral runners in
+/// containers and provisions runners for queued jobs.
+///
+/// Configured through environment variables (see docs/runner-controller.md);
+/// the environment overrides `--config`, and flags override both.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Environment file with `KEY=value` lines, read before the environment
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Repository to serve (overrides GITHUB_REPO)
+    #[arg(long, value_name = "OWNER/REPO")]
+    repo: Option<String>,
+    /// Warm pool size (overrides MAX_CONCURRENT)
+    #[arg(long, value_name = "N")]
+    max_concurrent: Option<usize>,
+    /// Validate the configuration against this host and GitHub, print a
+    /// report and exit non-zero if anything failed
+    #[arg(long)]
+    check_config: bool,
+    /// Run a single poll and exit, leaving containers running; for cron-style
+    /// invocation and debugging
+    #[arg(long, conflicts_with = "check_config")]
+    once: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Check connectivity to GitHub with the service's configuration
+    Doctor,
+}
+
+impl Args {
+    /// Variables set by flags, taking precedence over the environment
+    fn overrides(&self) -> BTreeMap<&'static str, String> {
+        let mut vars = BTreeMap::new();
+        if let Some(repo) = &self.repo {
+            vars.insert("GITHUB_REPO", repo.clone());
+        }
+        if let Some(n) = self.max_concurrent {
+            vars.insert("MAX_CONCURRENT", n.to_string());
+        }
+        vars
+    }
+}
+
+/// Fetch the GitHub credentials from the configured secrets provider, if any
+async fn fetch_secrets(
+    config: &Config,
+) -> Result<Option<(Arc<dyn SecretsProvider>, GitHubSecrets)>> {
+    let Some(provider) = secrets::provider(config)? else {
+        return Ok(None);
+    };
+    let fetched = provider
+        .fetch()
+        .await
+        .with_context(|| format!("Failed to fetch GitHub credentials from {}", provider.name()))?;
+    tracing::info!(provider = provider.name(), "GitHub credentials fetched");
+    Ok(Some((provider, fetched)))
+}
+
+/// Build the GitHub API client, attaching GitHub App credentials when configured.
+/// Credentials from a secrets provider take precedence over files.
+fn build_github_client(config: &Config, secrets: Option<&GitHubSecrets>) -> Result<GitHubClient> {
+    let token = match secrets {
+        Some(secrets) => secrets.token.clone(),
+        None => config.github_token.clone(),
+    };
+    let mut github = GitHubClient::new(config.github_repo.clone(), token, &config.network)?
+    .with_circuit_breaker(
+        config.circuit_breaker_threshold,
+        config.circuit_breaker_cooldown,
+    )
+    .with_retry(config.github_retry.clone())
+    .with_rate_budget(config.github_rate_reserve, config.github_repo_rate_budget);
+
+    if let Some(app) = &config.github_app {
+        let key = match (secrets.and_then(|s| s.app_key.clone()), &app.private_key_file) {
+            (Some(key), _) => key,
+            (None, Some(path)) => std::fs::read(path)
+                .map(Zeroizing::new)
+                .with_context(|| format!("Failed to read GitHub App key from {:?}", path))?,
+            (None, None) => anyhow::bail!(
+                "No GitHub App key: GITHUB_APP_KEY_FILE is unset and Vault has none"
+            ),
+        };
+        github = github.with_app(GitHubApp::new(
+            app.app_id.clone(),
+            app.installation_id,
+            &key,
+            &config.network,
+        )?);
+        tracing::info!(app_id = %app.app_id, "Job-scoped GitHub tokens enabled");
+    }
+
+    Ok(github)
+}
+
+/// Load the configuration from flags, the environment and the `--config`
+/// file, appending labels derived from host capabilities
+fn load_config(args: &Args) -> Result<Config> {
+    let file = match &args.config {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| parse_env_file(&contents))
+            .with_context(|| format!("Failed to read configuration file {}", path.display()))?,
+        None => BTreeMap::new(),
+    };
+    let overrides = args.overrides();
+    let mut config = Config::from_vars(|name| {
+        overrides
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+            .or_else(|| file.get(name).cloned())
+    })?;
+
+    // Append labels derived from host capabilities (kvm, avx512, ...)
+    if config.auto_labels {
+        for label in HostCapabilities::detect().labels() {
+            if !config.runner_labels.contains(&label) {
+                config.runner_labels.push(label);
+            }
+        }
+    }
+
+    tracing::info!(
+        repo = %config.github_repo,
+        pool_size = config.max_concurrent_jobs,
+        poll_interval = ?config.poll_interval,
+        labels = ?config.runner_labels,
+        http_port = ?config.http_port,
+        http_socket = ?config.http_socket,
+        "Configuration loaded"
+    );
+
+    Ok(config)
+}
+
+/// Everything built from the configuration, rebuilt on `POST /admin/reload`.
+/// The state database, metrics recorder and running containers carry over.
+struct Wiring {
+    config: Config,
+    github: Arc<GitHubClient>,
+    containers: Arc<dyn Backend>,
+    /// Remote hosts the containers are spread over, when configured
+    fleet: Option<Arc<Fleet>>,
+    notifier: Notifier,
+    /// Lease renewal with the standby controller, when configured
+    leader: Option<LeaderElection>,
+    /// Job claims shared with the other replicas
+    store: Arc<dyn StateStore>,
+}
+
+impl Wiring {
+    async fn build(config: Config, state_db: &StateDb) -> Result<Self> {
+        // Initialize webhook/email notifications
+        let notifier = Notifier::new(&config)?;
+
+        // Initialize GitHub client
+        let secrets = fetch_secrets(&config).await?;
+        let github = Arc::new(build_github_client(&config, secrets.as_ref().map(|(_, s)| s))?);
+        tracing::info!(ip_family = ?config.network.ip_family, "GitHub client initialized");
+        rotation::spawn_watcher(&config, &github);
+        if let Some((provider, fetched)) = secrets {
+            secrets::spawn_refresher(provider, &github, fetched);
+        }
+
+        // Quick connectivity check
+        match github.list_runners().await {
+            Ok(runners) => {
+                tracing::info!(count = runners.len(), "Connected to GitHub API");
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to list runners (will retry in main loop)");
+            }
+        }
+
+        // Cache repository metadata for policy decisions
+        match github.repo_metadata(&config.github_repo).await {
+            Ok(repo) => tracing::info!(
+                default_branch = %repo.default_branch,
+                visibility = %repo.visibility,
+                actions_enabled = ?repo.actions_enabled,
+                "Fetched repository metadata"
+            ),
+            Err(e) => tracing::warn!(error = %e, "Failed to fetch repository metadata"),
+        }
+
+        // Initialize container manager
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+        let instance_id = match &config.instance_id {
+            Some(id) => {
+                state_db.set_instance_id(id)?;
+                id.clone()
+            }
+            None => state_db.instance_id()?,
+        };
+        tracing::info!(instance_id = %instance_id, "Controller instance");
+        let naming = RunnerNaming::new(&config.runner_name_template, hostname.trim())?
+            .with_instance(&instance_id);
+        // An image without the runner unit would fail every spawn
+        if let ContainerBackend::Nspawn { image } = &config.container_backend {
+            let machines = config.container_backend.containers_dir();
+            check_nspawn_image(image, machines)?;
+            for (_, template) in &config.label_templates {
+                check_nspawn_image(template, machines)?;
+            }
+        }
+        let local: Arc<dyn Backend> = Arc::new(
+            ContainerManager::new(
+                config.state_dir.clone(),
+                config.container_backend.clone(),
+                naming,
+            )
+            .with_root_tmpfs(config.root_tmpfs_size.clone())
+            .with_egress_allowlist(config.egress_allowlist.clone()),
+        );
+        tracing::info!("Container manager initialized");
+
+        let fleet = config.remote.as_ref().map(|remote| {
+            let hosts: Vec<&str> = remote.hosts.iter().map(|h| h.name.as_str()).collect();
+            tracing::info!(hosts = ?hosts, "Placing external runners on remote hosts");
+            let logs_dir = captured_logs_dir(&config.state_dir);
+            Arc::new(Fleet::new(Arc::clone(&local), remote, logs_dir))
+        });
+        let containers = match &fleet {
+            Some(fleet) => Arc::clone(fleet) as Arc<dyn Backend>,
+            None => local,
+        };
+        let store = store::open(&config, &instance_id)?;
+        if config.redis.is_some() {
+            tracing::info!("Sharing job claims with other replicas through Redis");
+        }
+
+        let leader = match &config.leader_lease {
+            Some(lease) => Some(
+                LeaderElection::start(lease, instance_id.clone())
+                    .await
+                    .context("Leader election failed")?,
+            ),
+            None => None,
+        };
+
+        // Record the effective configuration so history can be correlated with changes
+        let config_hash = config.hash();
+        let config_changed =
+            state_db.record_config(&config_hash, serde_json::to_value(&config)?)?;
+        if config_changed {
+            tracing::info!(config_hash = %config_hash, "Configuration changed since last run");
+        }
+
+        // Seed the warm pool's desired state from the configuration; changes made
+        // through the API persist until the configuration itself changes
+        let spec =
+            state_db.seed_pool_spec(DEFAULT_POOL, PoolSpec::from_config(&config), config_changed)?;
+        tracing::info!(size = spec.size, source = ?spec.source, "Using pool spec");
+
+        Ok(Self {
+            config,
+            github,
+            containers,
+            fleet,
+            notifier,
+            leader,
+            store,
+        })
+    }
+}
+
+/// Run the controller until shutdown, or until a requested reload has built
+/// new wiring. A reload that fails leaves the running controller in place.
+async fn run_until_reload(
+    controller: &mut PoolController,
+    state_db: &StateDb,
+    args: &Args,
+) -> Result<Option<(Wiring, oneshot::Sender<Result<String>>)>> {
+    let mut exit = controller.run().await?;
+
+    loop {
+        let RunExit::Reload(reply) = exit else {
+            return Ok(None);
+        };
+
+        tracing::info!("Reload requested, rebuilding from fresh configuration");
+        let wiring = match load_config(args) {
+            Ok(config) => Wiring::build(config, state_db).await,
+            Err(e) => Err(e),
+        };
+        match wiring {
+            Ok(wiring) => return Ok(Some((wiring, reply))),
+            Err(e) => {
+                tracing::warn!(error = %e, "Reload failed, keeping the current configuration");
+                let _ = reply.send(Err(e));
+                exit = controller.serve().await?;
+            }
+        }
+    }
+}
+
+/// `--once`: a single poll without the HTTP API. Containers stay up for the
+/// next invocation instead of being cleaned up as on shutdown.
+async fn run_once(mut wiring: Wiring, state_db: Arc<StateDb>) -> Result<()> {
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+    let (_control_tx, control_rx) = mpsc::channel(1);
+    let mut controller = PoolController::new(
+        wiring.config.clone(),
+        wiring.github.clone(),
+        wiring.containers.clone(),
+        state_db,
+        shutdown_rx,
+        control_rx,
+        wiring.notifier.clone(),
+    )
+    .with_store(Arc::clone(&wiring.store));
+    if let Some(leader) = &wiring.leader {
+        controller = controller.with_leader(leader.subscribe());
+    }
+
+    let result = controller.run_once().await;
+    if let Some(leader) = wiring.leader.take() {
+        leader.release().await;
+    }
+    wiring.notifier.flush().await;
+    tracing::info!("Single poll done");
+    result
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+
     // Initialize tracing
     tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer())
@@ -32,62 +370,58 @@ async fn main() -> Result<()> {
 
     let start_time = Instant::now();
 
+    // `--check-config` validates the configuration against the host and
+    // GitHub, reporting every problem rather than the first
+    if args.check_config {
+        let valid = match load_config(&args) {
+            Ok(config) => match fetch_secrets(&config)
+                .await
+                .and_then(|secrets| build_github_client(&config, secrets.as_ref().map(|(_, s)| s)))
+            {
+                Ok(github) => check::run(&config, &github).await,
+                Err(e) => {
+                    println!("[fail] GitHub client: {:#}", e);
+                    false
+                }
+            },
+            Err(e) => {
+                println!("[fail] configuration: {:#}", e);
+                false
+            }
+        };
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+
     // Load configuration
-    let config = Config::from_env()?;
-    tracing::info!(
-        repo = %config.github_repo,
-        pool_size = config.max_concurrent_jobs,
-        poll_interval = ?config.poll_interval,
-        labels = ?config.runner_labels,
-        http_port = config.http_port,
-        "Configuration loaded"
-    );
+    let config = load_config(&args)?;
+
+    // `runner-controller doctor` runs connectivity diagnostics and exits
+    if let Some(Command::Doctor) = args.command {
+        let secrets = fetch_secrets(&config).await?;
+        let github = build_github_client(&config, secrets.as_ref().map(|(_, s)| s))?;
+        let healthy = doctor::run(&config, &github).await;
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
 
     // Initialize state database
-    let state_db = Arc::new(StateDb::open(&config.state_dir)?);
+    let state_db = Arc::new(StateDb::open(&config.state_dir, config.state_durability)?);
     tracing::info!(state_dir = ?config.state_dir, "State database opened");
 
-    // Initialize GitHub client
-    let github = GitHubClient::new(config.github_repo.clone(), config.github_token.clone())?;
-    tracing::info!("GitHub client initialized");
+    let mut wiring = Wiring::build(config, &state_db).await?;
 
-    // Quick connectivity check
-    match github.list_runners().await {
-        Ok(runners) => {
-            tracing::info!(count = runners.len(), "Connected to GitHub API");
-        }
-        Err(e) => {
-            tracing::warn!(error = %e, "Failed to list runners (will retry in main loop)");
-        }
+    if args.once {
+        return run_once(wiring, state_db).await;
     }
 
-    // Initialize container manager
-    let containers = Arc::new(ContainerManager::new(config.state_dir.clone()));
-    tracing::info!("Container manager initialized");
+    // Install Prometheus metrics recorder (rendered by GET /metrics)
+    let metrics = metrics_exporter_prometheus::PrometheusBuilder::new()
+        .install_recorder()
+        .context("Failed to install metrics recorder")?;
 
     // Set up shutdown signal
     let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
-    // Start HTTP server
-    let http_state = AppState {
-        state_db: Arc::clone(&state_db),
-        start_time,
-        pool_size: config.max_concurrent_jobs,
-        poll_interval_seconds: config.poll_interval.as_secs(),
-        job_timeout_seconds: config.job_timeout.as_secs(),
-    };
-    let http_addr: SocketAddr = ([0, 0, 0, 0], config.http_port).into();
-    let http_shutdown_rx = shutdown_tx.subscribe();
-    tokio::spawn(http::run_server(http_addr, http_state, http_shutdown_rx));
-
-    // Create pool controller
-    let mut controller = PoolController::new(
-        config.clone(),
-        github,
-        Arc::clone(&containers),
-        Arc::clone(&state_db),
-        shutdown_rx,
-    );
+    wiring.notifier.notify(NotificationEvent::Startup);
 
     // Spawn signal handler
     let shutdown_tx_clone = shutdown_tx.clone();
@@ -109,11 +443,83 @@ async fn main() -> Result<()> {
         let _ = shutdown_tx_clone.send(true);
     });
 
-    // Run the main loop
-    let result = controller.run().await;
+    let (result, controller) = loop {
+        let config = &wiring.config;
+
+        // Channel for external scheduler requests (HTTP -> pool controller)
+        let (control_tx, control_rx) = mpsc::channel(16);
+
+        // Start HTTP server; it is restarted with the controller on reload
+        let http_state = AppState {
+            fleet: wiring.fleet.clone(),
+            leader: wiring.leader.as_ref().map(LeaderElection::subscribe),
+            start_time,
+            ..AppState::new(
+                config,
+                Arc::clone(&state_db),
+                wiring.containers.clone(),
+                wiring.github.clone(),
+                wiring.notifier.clone(),
+                control_tx,
+                metrics.clone(),
+            )
+        };
+        let http_addr = config
+            .http_port
+            .map(|port| SocketAddr::from(([0, 0, 0, 0], port)));
+        let http_socket = config
+            .http_socket
+            .clone()
+            .map(|path| (path, config.http_socket_mode));
+        let (http_stop_tx, http_stop_rx) = watch::channel(false);
+        let http_server = tokio::spawn(http::run_server(
+            http_addr,
+            http_socket,
+            http_state,
+            http_stop_rx,
+        ));
+
+        // Create pool controller
+        let mut controller = PoolController::new(
+            config.clone(),
+            wiring.github.clone(),
+            wiring.containers.clone(),
+            Arc::clone(&state_db),
+            shutdown_rx.clone(),
+            control_rx,
+            wiring.notifier.clone(),
+        )
+        .with_store(Arc::clone(&wiring.store));
+        if let Some(leader) = &wiring.leader {
+            controller = controller.with_leader(leader.subscribe());
+        }
+
+        // Run the main loop
+        match run_until_reload(&mut controller, &state_db, &args).await {
+            Ok(Some((next, reply))) => {
+                let _ = reply.send(Ok(next.config.hash()));
+                // Let the reload request finish before the port is bound again
+                let _ = http_stop_tx.send(true);
+                let _ = http_server.await;
+                wiring.notifier.flush().await;
+                wiring = next;
+                tracing::info!("Controller reloaded");
+            }
+            result => {
+                let _ = http_stop_tx.send(true);
+                break (result.map(|_| ()), controller);
+            }
+        }
+    };
 
-    // Graceful shutdown
-    controller.shutdown().await?;
+    // Graceful shutdown; the standby need not wait for the lease to expire
+    wiring.notifier.notify(NotificationEvent::Shutdown);
+    if let Some(leader) = wiring.leader.take() {
+        leader.release().await;
+    }
+    let shutdown_result = controller.shutdown().await;
+    wiring.notifier.flush().await;
+    shutdown_result?;
 
     tracing::info!("runner-controller stopped");
 